@@ -1,11 +1,17 @@
 use serde_derive::{Deserialize, Serialize};
-use std::{error::Error, fmt, process, thread, time};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error,
+    fmt, process, thread, time,
+};
 
 #[derive(Eq, PartialEq, Clone, Debug)]
 pub struct Card {
     pub front: String,
     pub back: String,
     pub tags: Vec<String>,
+    /// Anki note the card was imported from.
+    pub note_id: Option<NoteId>,
 }
 
 impl Card {
@@ -18,6 +24,7 @@ impl Card {
             front: front.into(),
             back: back.into(),
             tags: tags.into_iter().map(|c| c.into()).collect(),
+            note_id: None,
         }
     }
 }
@@ -32,17 +39,147 @@ impl fmt::Display for Card {
     }
 }
 
-impl From<NoteInfo> for Card {
-    fn from(note: NoteInfo) -> Card {
+impl Card {
+    /// Read a card from note info using the configured field names.
+    ///
+    /// Missing fields are read as empty.
+    pub fn from_note(config: &AnkiConfig, mut note: NoteInfo) -> Card {
+        let (_, front, back) = config.note_type();
+        let mut field = |name: &str| {
+            note.fields
+                .remove(name)
+                .map(|f| f.value)
+                .unwrap_or_default()
+        };
         Card {
-            front: note.fields.front.value,
-            back: note.fields.back.value,
+            front: field(front),
+            back: field(back),
             tags: note.tags,
+            note_id: Some(note.note_id),
         }
     }
 }
 
-const ANKI_SERVER_URL: &str = "http://127.0.0.1:8765";
+/// Where to find AnkiConnect and how to map cards to Anki notes.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AnkiConfig {
+    /// URL of the AnkiConnect server.
+    pub endpoint: String,
+    /// Deck to operate on, the current deck if not set.
+    pub deck: Option<String>,
+    /// Note type used for new notes.
+    pub model: String,
+    /// Note field for the front side of cards.
+    pub front_field: String,
+    /// Note field for the back side of cards.
+    pub back_field: String,
+    /// Make cloze notes where the front of a card is the text with cloze
+    /// deletion markup like `{{c1::deleted}}`, instead of basic notes.
+    pub cloze: bool,
+    /// Note type used for new notes in cloze mode.
+    pub cloze_model: String,
+    /// Note field for the cloze text.
+    pub text_field: String,
+    /// Note field for the extra information shown on the back of cloze
+    /// cards.
+    pub extra_field: String,
+    /// Deck and note type overrides for cards from sections with a `lang`
+    /// attribute, by language code.
+    pub languages: BTreeMap<String, LanguageConfig>,
+}
+
+/// Where to put the cards of one language.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LanguageConfig {
+    pub deck: Option<String>,
+    /// Note type for new notes, used in both basic and cloze mode.
+    pub model: Option<String>,
+}
+
+impl Default for AnkiConfig {
+    fn default() -> Self {
+        AnkiConfig {
+            endpoint: "http://127.0.0.1:8765".into(),
+            deck: None,
+            model: "Basic".into(),
+            front_field: "Front".into(),
+            back_field: "Back".into(),
+            cloze: false,
+            cloze_model: "Cloze".into(),
+            text_field: "Text".into(),
+            extra_field: "Back Extra".into(),
+            languages: Default::default(),
+        }
+    }
+}
+
+impl AnkiConfig {
+    /// Override values with the `ANKI_CONNECT_URL`, `ANKI_DECK`,
+    /// `ANKI_MODEL`, `ANKI_FRONT_FIELD` and `ANKI_BACK_FIELD` environment
+    /// variables when they are set.
+    pub fn with_env_overrides(mut self) -> Self {
+        let var = |name| std::env::var(name).ok().filter(|s| !s.is_empty());
+        if let Some(endpoint) = var("ANKI_CONNECT_URL") {
+            self.endpoint = endpoint;
+        }
+        if let Some(deck) = var("ANKI_DECK") {
+            self.deck = Some(deck);
+        }
+        if let Some(model) = var("ANKI_MODEL") {
+            self.model = model;
+        }
+        if let Some(field) = var("ANKI_FRONT_FIELD") {
+            self.front_field = field;
+        }
+        if let Some(field) = var("ANKI_BACK_FIELD") {
+            self.back_field = field;
+        }
+        self
+    }
+
+    /// Return the configuration for cards in a language.
+    pub fn for_lang(&self, lang: Option<&str>) -> AnkiConfig {
+        let mut ret = self.clone();
+        let Some(language) = lang.and_then(|l| self.languages.get(l)) else {
+            return ret;
+        };
+        if let Some(deck) = &language.deck {
+            ret.deck = Some(deck.clone());
+        }
+        if let Some(model) = &language.model {
+            if ret.cloze {
+                ret.cloze_model = model.clone();
+            } else {
+                ret.model = model.clone();
+            }
+        }
+        ret
+    }
+
+    /// Return the note type and the fields for card front and back.
+    fn note_type(&self) -> (&str, &str, &str) {
+        if self.cloze {
+            (&self.cloze_model, &self.text_field, &self.extra_field)
+        } else {
+            (&self.model, &self.front_field, &self.back_field)
+        }
+    }
+
+    /// Deck new notes are added to.
+    fn target_deck(&self) -> String {
+        self.deck.clone().unwrap_or_else(|| "Default".into())
+    }
+
+    fn fields(&self, front: String, back: String) -> Fields<String> {
+        let (_, front_field, back_field) = self.note_type();
+        let mut ret = Fields::new();
+        ret.insert(front_field.to_string(), front);
+        ret.insert(back_field.to_string(), back);
+        ret
+    }
+}
 
 pub type ErrBox = Box<dyn Error + Send + Sync + 'static>;
 
@@ -87,19 +224,22 @@ pub struct Note {
 }
 
 impl Note {
-    pub fn new(front: String, back: String, tags: Vec<String>) -> Note {
+    pub fn new(
+        config: &AnkiConfig,
+        front: String,
+        back: String,
+        tags: Vec<String>,
+    ) -> Note {
         Note {
-            deck_name: "Default".into(),
-            model_name: "Basic".into(),
-            fields: Fields { front, back },
+            deck_name: config.target_deck(),
+            model_name: config.note_type().0.to_string(),
+            fields: config.fields(front, back),
             tags,
         }
     }
-}
 
-impl From<Card> for Note {
-    fn from(c: Card) -> Self {
-        Note::new(c.front, c.back, c.tags)
+    pub fn from_card(config: &AnkiConfig, c: Card) -> Note {
+        Note::new(config, c.front, c.back, c.tags)
     }
 }
 
@@ -112,19 +252,28 @@ pub struct NoteInfo {
     pub tags: Vec<String>,
 }
 
+impl NoteInfo {
+    /// Return the value of a named field.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(|f| f.value.as_str())
+    }
+
+    /// Return the field values in the order the note type lists them.
+    pub fn field_values(&self) -> Vec<&str> {
+        let mut fields: Vec<&FieldData> = self.fields.values().collect();
+        fields.sort_by_key(|f| f.order);
+        fields.into_iter().map(|f| f.value.as_str()).collect()
+    }
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct NoteUpdate {
     pub id: NoteId,
     pub fields: Fields<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct Fields<T> {
-    #[serde(rename = "Front")]
-    pub front: T,
-    #[serde(rename = "Back")]
-    pub back: T,
-}
+/// Note field values by field name.
+pub type Fields<T> = BTreeMap<String, T>;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct FieldData {
@@ -150,7 +299,7 @@ pub struct Response<T> {
 }
 
 impl Response<()> {
-    fn to_result(self) -> AnkiResult<()> {
+    fn into_result(self) -> AnkiResult<()> {
         match self.error {
             Some(e) => Err(Box::new(ResponseError(e))),
             _ => Ok(()),
@@ -158,9 +307,9 @@ impl Response<()> {
     }
 }
 
-impl<T> Into<AnkiResult<T>> for Response<T> {
-    fn into(self) -> AnkiResult<T> {
-        match (self.result, self.error) {
+impl<T> From<Response<T>> for AnkiResult<T> {
+    fn from(response: Response<T>) -> Self {
+        match (response.result, response.error) {
             (None, None) => {
                 log::warn!("Received malformed response");
                 Err(Box::new(ResponseError("n/a".into())))
@@ -177,27 +326,84 @@ impl<T> Into<AnkiResult<T>> for Response<T> {
 
 pub struct AnkiConnection {
     anki_process: Option<process::Child>,
+    config: AnkiConfig,
+}
+
+/// Find the Anki program.
+///
+/// The Windows installer doesn't put Anki on PATH, so look in the usual
+/// install locations there first.
+fn anki_executable() -> std::path::PathBuf {
+    if cfg!(windows) {
+        let candidates = [
+            ("LOCALAPPDATA", "Programs\\Anki\\anki.exe"),
+            ("ProgramFiles", "Anki\\anki.exe"),
+        ];
+        for (var, path) in candidates {
+            if let Some(dir) = std::env::var_os(var) {
+                let exe = std::path::Path::new(&dir).join(path);
+                if exe.is_file() {
+                    return exe;
+                }
+            }
+        }
+    }
+    format!("anki{}", std::env::consts::EXE_SUFFIX).into()
+}
+
+/// Fail if offline mode is on and the endpoint isn't on this machine.
+///
+/// Offline mode is set with the `OTLBOOK_OFFLINE` environment variable,
+/// same as for the rest of otlbook.
+fn check_offline(endpoint: &str) -> AnkiResult<()> {
+    let is_offline =
+        std::env::var_os("OTLBOOK_OFFLINE").is_some_and(|v| !v.is_empty());
+    if !is_offline {
+        return Ok(());
+    }
+    let is_local = reqwest::Url::parse(endpoint).is_ok_and(|url| {
+        matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"))
+    });
+    if is_local {
+        Ok(())
+    } else {
+        Err(format!("Offline, not connecting to {}", endpoint).into())
+    }
+}
+
+/// Return whether an AnkiConnect server answers at the endpoint.
+pub fn is_running(endpoint: &str) -> bool {
+    if check_offline(endpoint).is_err() {
+        return false;
+    }
+    log::debug!("Probing for running Anki server...");
+    let res = reqwest::blocking::get(endpoint);
+    log::debug!("Response from server: {:?}", res);
+    res.is_ok()
 }
 
 impl AnkiConnection {
-    pub fn new() -> Result<AnkiConnection, ErrBox> {
-        log::debug!("Probing for running Anki server...");
-        let is_anki_running = {
-            let res = reqwest::blocking::get(ANKI_SERVER_URL);
-            log::debug!("Response from server: {:?}", res);
-            res.is_ok()
-        };
+    /// Use a different deck or note type for the following operations.
+    pub fn set_config(&mut self, config: AnkiConfig) {
+        self.config = config;
+    }
 
-        if is_anki_running {
-            Ok(AnkiConnection { anki_process: None })
+    pub fn new(config: AnkiConfig) -> Result<AnkiConnection, ErrBox> {
+        check_offline(&config.endpoint)?;
+        if is_running(&config.endpoint) {
+            Ok(AnkiConnection {
+                anki_process: None,
+                config,
+            })
         } else {
             log::info!("Anki not running, starting process");
-            match process::Command::new("anki").spawn() {
+            match process::Command::new(anki_executable()).spawn() {
                 Ok(proc) => {
                     log::debug!("Waiting for Anki to start...");
                     thread::sleep(time::Duration::from_secs(3));
                     Ok(AnkiConnection {
                         anki_process: Some(proc),
+                        config,
                     })
                 }
                 Err(e) => Err(Box::new(e)),
@@ -209,19 +415,27 @@ impl AnkiConnection {
         &self,
         query: impl Into<AnkiRequest>,
     ) -> AnkiResult<T> {
+        check_offline(&self.config.endpoint)?;
         let client = reqwest::blocking::Client::new();
         let query: AnkiRequest = query.into();
-        let ret: Response<T> =
-            client.post(ANKI_SERVER_URL).json(&query).send()?.json()?;
+        let ret: Response<T> = client
+            .post(&self.config.endpoint)
+            .json(&query)
+            .send()?
+            .json()?;
         ret.into()
     }
 
     fn command(&self, query: impl Into<AnkiRequest>) -> AnkiResult<()> {
+        check_offline(&self.config.endpoint)?;
         let client = reqwest::blocking::Client::new();
         let query: AnkiRequest = query.into();
-        let ret: Response<()> =
-            client.post(ANKI_SERVER_URL).json(&query).send()?.json()?;
-        ret.to_result()
+        let ret: Response<()> = client
+            .post(&self.config.endpoint)
+            .json(&query)
+            .send()?
+            .json()?;
+        ret.into_result()
     }
 
     fn _add_note(&self, note: Note) -> AnkiResult<()> {
@@ -235,10 +449,29 @@ impl AnkiConnection {
         self.request(Action::AddNotes { notes })
     }
 
+    /// Find the notes of the configured note type in the deck.
+    ///
+    /// Notes of other types are left alone so that basic and cloze cards
+    /// can share a deck.
     pub fn find_notes(&self) -> AnkiResult<Vec<NoteId>> {
-        self.request(Action::FindNotes {
-            query: "deck:current".into(),
-        })
+        let quote = |s: &str| format!("\"{}\"", s.replace('"', "\\\""));
+        let deck = match &self.config.deck {
+            Some(deck) => format!("deck:{}", quote(deck)),
+            None => "deck:current".into(),
+        };
+        let query =
+            format!("{} note:{}", deck, quote(self.config.note_type().0));
+        self.request(Action::FindNotes { query })
+    }
+
+    /// Find all the notes in a deck and its subdecks regardless of type.
+    pub fn deck_notes(&self, deck: &str) -> AnkiResult<Vec<NoteInfo>> {
+        let query = format!("deck:\"{}\"", deck.replace('"', "\\\""));
+        let ids: Vec<NoteId> = self.request(Action::FindNotes { query })?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.notes_info(ids)
     }
 
     pub fn notes_info(&self, notes: Vec<NoteId>) -> AnkiResult<Vec<NoteInfo>> {
@@ -262,7 +495,7 @@ impl AnkiConnection {
         self.command(Action::UpdateNoteFields {
             note: NoteUpdate {
                 id,
-                fields: Fields { front, back },
+                fields: self.config.fields(front, back),
             },
         })
     }
@@ -280,6 +513,178 @@ impl AnkiConnection {
     }
 }
 
+impl AnkiConnection {
+    /// Return the cards in the deck.
+    pub fn current_cards(&self) -> AnkiResult<Vec<(NoteId, Card)>> {
+        let ids = self.find_notes()?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .notes_info(ids)?
+            .into_iter()
+            .map(|n| (n.note_id, Card::from_note(&self.config, n)))
+            .collect())
+    }
+
+    /// Carry out an update plan and sync the collection.
+    pub fn apply(&self, plan: &UpdatePlan) -> AnkiResult<()> {
+        if !plan.delete.is_empty() {
+            self.delete_notes(plan.delete.iter().map(|(id, _)| *id).collect())?;
+        }
+
+        for (id, card) in &plan.update {
+            self.update_note_fields(
+                *id,
+                card.front.clone(),
+                card.back.clone(),
+            )?;
+        }
+
+        for (tag, notes) in &plan.add_tags {
+            self.add_tag(notes.clone(), tag.clone())?;
+        }
+
+        for (tag, notes) in &plan.remove_tags {
+            self.remove_tag(notes.clone(), tag.clone())?;
+        }
+
+        if !plan.add.is_empty() {
+            let notes = plan
+                .add
+                .iter()
+                .cloned()
+                .map(|card| Note::from_card(&self.config, card))
+                .collect();
+            let ids = self.add_notes(notes)?;
+            for (card, id) in plan.add.iter().zip(ids) {
+                if id.is_none() {
+                    log::warn!("Anki did not add note {}", card);
+                }
+            }
+        }
+
+        self.sync()
+    }
+}
+
+/// Changes needed to make an Anki deck match a set of cards.
+///
+/// Cards imported from Anki are matched to their original note by note id
+/// and the fields of the note are left as they are. Other cards are
+/// matched to existing notes by their front side.
+#[derive(Clone, Debug, Default)]
+pub struct UpdatePlan {
+    pub add: Vec<Card>,
+    /// Notes whose back side changed.
+    pub update: Vec<(NoteId, Card)>,
+    pub add_tags: BTreeMap<String, Vec<NoteId>>,
+    pub remove_tags: BTreeMap<String, Vec<NoteId>>,
+    pub delete: Vec<(NoteId, Card)>,
+}
+
+impl UpdatePlan {
+    pub fn new(existing: Vec<(NoteId, Card)>, cards: Vec<Card>) -> UpdatePlan {
+        let mut ret = UpdatePlan::default();
+
+        let ids_by_front: HashMap<String, NoteId> = existing
+            .iter()
+            .map(|(id, c)| (c.front.clone(), *id))
+            .collect();
+        let mut existing: HashMap<NoteId, Card> =
+            existing.into_iter().collect();
+
+        let mut seen = BTreeSet::new();
+        for card in cards {
+            if !seen.insert(card.front.clone()) {
+                log::warn!("Skipping card with duplicate front {}", card);
+                continue;
+            }
+
+            let (id, old, is_imported) = if let Some((id, old)) =
+                card.note_id.and_then(|id| existing.remove_entry(&id))
+            {
+                (id, old, true)
+            } else if let Some((id, old)) = ids_by_front
+                .get(&card.front)
+                .and_then(|id| existing.remove_entry(id))
+            {
+                (id, old, false)
+            } else {
+                ret.add.push(card);
+                continue;
+            };
+
+            let old_tags: BTreeSet<&String> = old.tags.iter().collect();
+            let new_tags: BTreeSet<&String> = card.tags.iter().collect();
+            for &tag in new_tags.difference(&old_tags) {
+                ret.add_tags.entry(tag.clone()).or_default().push(id);
+            }
+            for &tag in old_tags.difference(&new_tags) {
+                ret.remove_tags.entry(tag.clone()).or_default().push(id);
+            }
+
+            if !is_imported && old.back != card.back {
+                ret.update.push((id, card));
+            }
+        }
+
+        ret.delete = existing.into_iter().collect();
+        ret.delete.sort_by_key(|(id, _)| *id);
+        ret
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty()
+            && self.update.is_empty()
+            && self.add_tags.is_empty()
+            && self.remove_tags.is_empty()
+            && self.delete.is_empty()
+    }
+}
+
+impl fmt::Display for UpdatePlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for card in &self.add {
+            writeln!(f, "add {}", card)?;
+        }
+        for (_, card) in &self.update {
+            writeln!(f, "update {}", card)?;
+        }
+        for (tag, notes) in &self.add_tags {
+            writeln!(f, "add tag {} to {} note(s)", tag, notes.len())?;
+        }
+        for (tag, notes) in &self.remove_tags {
+            writeln!(f, "remove tag {} from {} note(s)", tag, notes.len())?;
+        }
+        for (_, card) in &self.delete {
+            writeln!(f, "delete {}", card)?;
+        }
+        Ok(())
+    }
+}
+
+/// Make the Anki deck match the given cards.
+///
+/// Notes in the deck that don't match any card are only deleted with
+/// `delete`. Returns the changes that were made, or with `dry_run` the
+/// changes that would be made without touching the deck.
+pub fn update_cards(
+    conn: &AnkiConnection,
+    cards: Vec<Card>,
+    dry_run: bool,
+    delete: bool,
+) -> AnkiResult<UpdatePlan> {
+    let mut plan = UpdatePlan::new(conn.current_cards()?, cards);
+    if !delete {
+        plan.delete.clear();
+    }
+    if !dry_run && !plan.is_empty() {
+        conn.apply(&plan)?;
+    }
+    Ok(plan)
+}
+
 impl Drop for AnkiConnection {
     fn drop(&mut self) {
         if let Some(ref mut proc) = self.anki_process {
@@ -287,3 +692,53 @@ impl Drop for AnkiConnection {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_plan() {
+        let existing = vec![
+            (1, Card::new("same", "back", vec!["a"])),
+            (2, Card::new("changed", "old", vec!["a", "b"])),
+            (3, Card::new("gone", "back", Vec::<String>::new())),
+        ];
+        let cards = vec![
+            Card::new("same", "back", vec!["a"]),
+            Card::new("changed", "new", vec!["a", "c"]),
+            Card::new("new", "back", Vec::<String>::new()),
+            Card::new("new", "duplicate", Vec::<String>::new()),
+        ];
+
+        let plan = UpdatePlan::new(existing.clone(), cards.clone());
+        assert_eq!(plan.add, vec![cards[2].clone()]);
+        assert_eq!(plan.update, vec![(2, cards[1].clone())]);
+        assert_eq!(plan.add_tags, BTreeMap::from([("c".to_string(), vec![2])]));
+        assert_eq!(
+            plan.remove_tags,
+            BTreeMap::from([("b".to_string(), vec![2])])
+        );
+        assert_eq!(plan.delete, vec![existing[2].clone()]);
+        assert!(!plan.is_empty());
+
+        assert!(UpdatePlan::new(existing[..1].to_vec(), cards[..1].to_vec())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_imported_card() {
+        let existing = vec![(1, Card::new("Question", "Answer", vec!["a"]))];
+        let mut card = Card::new("Question [...]", "<b>Answer</b>", vec!["a"]);
+        card.note_id = Some(1);
+
+        let plan = UpdatePlan::new(existing.clone(), vec![card.clone()]);
+        assert!(plan.is_empty());
+
+        // The original note is gone, add the card as a new note.
+        card.note_id = Some(2);
+        let plan = UpdatePlan::new(existing.clone(), vec![card.clone()]);
+        assert_eq!(plan.add, vec![card]);
+        assert_eq!(plan.delete, existing);
+    }
+}
@@ -0,0 +1,159 @@
+//! Copies of collection files from before they were overwritten.
+//!
+//! Backups are off unless `backup.idm` in the collection's config directory
+//! turns them on:
+//!
+//! ```notrust
+//! dir /mnt/backup/otlbook
+//! keep 10
+//! ```
+//!
+//! Before a save overwrites or deletes a file, the old version is copied to
+//! the backup directory under its collection path with a timestamp suffix,
+//! eg. `notes/Reading.otl.20240131T120000.123`. Only the `keep` latest
+//! copies of each file are kept. `dir` defaults to `backups` in the
+//! collection's state directory, a relative `dir` is relative to the
+//! collection root.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{xdg, Result};
+
+/// Name of the backup settings file in the config directory.
+pub const BACKUP_FILE: &str = "backup.idm";
+
+/// Default number of copies kept of each file.
+pub const DEFAULT_KEEP: usize = 5;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+struct BackupConfig {
+    dir: Option<PathBuf>,
+    keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            dir: None,
+            keep: DEFAULT_KEEP,
+        }
+    }
+}
+
+/// Backup settings of a collection.
+#[derive(Clone, Debug)]
+pub struct Backups {
+    /// Directory the copies are kept in.
+    pub dir: PathBuf,
+    /// Number of copies kept of each file.
+    pub keep: usize,
+}
+
+impl Backups {
+    /// Load the backup settings of the collection at the given root
+    /// directory.
+    ///
+    /// Return `None` if the collection has no backup settings file.
+    pub fn load(root_path: impl AsRef<Path>) -> Result<Option<Backups>> {
+        let root_path = root_path.as_ref();
+        let path = xdg::path(root_path, xdg::Dir::Config, BACKUP_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path)?;
+        let config: BackupConfig = idm::from_str(&text)
+            .map_err(|e| format!("Bad backup settings in {:?}: {}", path, e))?;
+
+        let dir = match config.dir {
+            Some(dir) => root_path.join(dir),
+            None => xdg::path(root_path, xdg::Dir::State, "backups"),
+        };
+        Ok(Some(Backups {
+            dir,
+            keep: config.keep,
+        }))
+    }
+
+    /// Copy the current version of a file at a collection path to the
+    /// backup directory and remove the oldest copies past the limit.
+    ///
+    /// Does nothing if the file doesn't exist yet.
+    pub fn back_up(&self, root_path: &Path, path: &Path) -> Result<()> {
+        let source = root_path.join(path);
+        if !source.exists() {
+            return Ok(());
+        }
+        let Some(name) = path.file_name().map(|s| s.to_string_lossy()) else {
+            return Ok(());
+        };
+
+        let target = self.dir.join(path);
+        let dir = target.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(dir)?;
+
+        let stamp = chrono::Local::now().format("%Y%m%dT%H%M%S%.3f");
+        fs::copy(&source, dir.join(format!("{}.{}", name, stamp)))?;
+
+        // Timestamps sort in chronological order.
+        let prefix = format!("{}.", name);
+        let mut copies: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|e| e.path())
+            .collect();
+        copies.sort();
+        for old in &copies[..copies.len().saturating_sub(self.keep)] {
+            log::info!("Removing old backup {:?}", old);
+            fs::remove_file(old)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_back_up() {
+        let root = std::env::temp_dir()
+            .join(format!("otlbook-backup-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("notes")).unwrap();
+        let backups = Backups {
+            dir: root.join("backups"),
+            keep: 2,
+        };
+        let path = Path::new("notes/Reading.otl");
+
+        // Nothing to back up yet.
+        backups.back_up(&root, path).unwrap();
+        assert!(!backups.dir.exists());
+
+        for i in 0..3 {
+            fs::write(root.join(path), format!("Version {}\n", i)).unwrap();
+            backups.back_up(&root, path).unwrap();
+            // Keep the timestamps apart.
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        let mut copies: Vec<PathBuf> = fs::read_dir(backups.dir.join("notes"))
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        copies.sort();
+        let texts: Vec<String> = copies
+            .iter()
+            .map(|p| fs::read_to_string(p).unwrap())
+            .collect();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(texts, vec!["Version 1\n", "Version 2\n"]);
+    }
+}
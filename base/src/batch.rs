@@ -0,0 +1,267 @@
+//! Batches of edit commands for scripts and other programs.
+//!
+//! A batch is newline-delimited JSON, one command object per line with the
+//! command name in `op`:
+//!
+//! ```notrust
+//! {"op": "insert", "idm": "Title\n\t:uri https://example.com/\n"}
+//! {"op": "insert", "under": "Reading", "idm": "..."}
+//! {"op": "set-attr", "path": "Reading/Title", "name": "rating", "value": "***"}
+//! {"op": "set-attr", "path": "Reading/Title", "name": "rating", "value": null}
+//! {"op": "tag", "path": "Reading/Title", "add": ["read"], "remove": ["toread"]}
+//! {"op": "delete", "path": "Reading/Title"}
+//! {"op": "replace", "path": "Reading/Title", "idm": "Title\n\t:uri ...\n"}
+//! ```
+//!
+//! Inserted sections are given as IDM text. They go under `under` if it's
+//! given, otherwise where the collection routes or the inbox says, and
+//! sections whose entity is already in the collection are skipped. A null
+//! attribute value removes the attribute. Deleted sections are moved to the
+//! trash. A replaced section is swapped for the section in the IDM text.
+//! Blank lines are ignored.
+//!
+//! Every command gets a result object, `{"ok": true, "changes": n}` or
+//! `{"ok": false, "error": "..."}`. The batch is all or nothing: the caller
+//! should only save the collection if every command succeeded.
+
+use std::collections::{BTreeSet, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    route::Routes, trash, Collection, Config, EntityIdentifier, Result, Section,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Command {
+    Insert {
+        under: Option<String>,
+        idm: String,
+    },
+    SetAttr {
+        path: String,
+        name: String,
+        value: Option<String>,
+    },
+    Tag {
+        path: String,
+        #[serde(default)]
+        add: Vec<String>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    Delete {
+        path: String,
+    },
+    Replace {
+        path: String,
+        idm: String,
+    },
+}
+
+/// Results of applying a batch.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// Result object of each command in order.
+    pub results: Vec<Value>,
+    /// Total number of changes made.
+    pub changes: usize,
+    /// Whether any command failed.
+    pub failed: bool,
+}
+
+/// Apply a batch of newline-delimited JSON commands to the collection.
+///
+/// Commands after a failed one are still tried so that all errors get
+/// reported.
+pub fn apply<'a>(
+    col: &mut Collection,
+    lines: impl IntoIterator<Item = &'a str>,
+) -> Result<Report> {
+    let mut batch = Batch::new(col)?;
+    let mut report = Report::default();
+
+    for line in lines.into_iter().filter(|s| !s.trim().is_empty()) {
+        let result = serde_json::from_str(line)
+            .map_err(|e| format!("Bad command: {}", e).into())
+            .and_then(|cmd| batch.run(&cmd));
+        report.results.push(match result {
+            Ok(n) => {
+                report.changes += n;
+                json!({"ok": true, "changes": n})
+            }
+            Err(e) => {
+                report.failed = true;
+                json!({"ok": false, "error": e.to_string()})
+            }
+        });
+    }
+
+    Ok(report)
+}
+
+/// State kept across the commands of a batch.
+struct Batch<'a> {
+    col: &'a mut Collection,
+    routes: Routes,
+    existing_entities: HashSet<EntityIdentifier>,
+}
+
+impl<'a> Batch<'a> {
+    fn new(col: &'a mut Collection) -> Result<Self> {
+        let routes = Routes::load(col.root_path())?;
+        // Things in the trash are free to be inserted again.
+        let existing_entities = col
+            .files()
+            .filter(|(path, _)| !trash::is_trash(path))
+            .flat_map(|(_, root)| root.iter())
+            .filter_map(|s| s.entity_identifier())
+            .collect();
+        Ok(Batch {
+            col,
+            routes,
+            existing_entities,
+        })
+    }
+
+    fn lookup(&self, path: &str) -> Result<Section> {
+        Ok(self
+            .col
+            .find(path)
+            .ok_or_else(|| format!("Section {:?} not found", path))?)
+    }
+
+    /// Run a command, return the number of changes made.
+    fn run(&mut self, cmd: &Command) -> Result<usize> {
+        match cmd {
+            Command::Insert { under, idm } => {
+                let items: Vec<Section> = idm::from_str(idm)?;
+                let mut count = 0;
+                for item in items {
+                    if let Some(id) = item.entity_identifier() {
+                        if !self.existing_entities.insert(id) {
+                            continue;
+                        }
+                    }
+                    let path = match under {
+                        Some(path) => path.clone(),
+                        None => self
+                            .routes
+                            .target(&item)
+                            .unwrap_or(&Config::get().inbox)
+                            .to_string(),
+                    };
+                    self.col.find_or_create(&path)?.append(item);
+                    count += 1;
+                }
+                Ok(count)
+            }
+            Command::SetAttr { path, name, value } => {
+                let mut section = self.lookup(path)?;
+                match value {
+                    Some(value) => section.set_raw_attr(name, value),
+                    None => section.remove_attr(name),
+                }
+                Ok(1)
+            }
+            Command::Tag { path, add, remove } => {
+                let mut section = self.lookup(path)?;
+                let mut tags: BTreeSet<String> = section
+                    .attr("tags")
+                    .map_err(|e| e.to_string())?
+                    .unwrap_or_default();
+                let old = tags.clone();
+                tags.extend(add.iter().cloned());
+                tags.retain(|t| !remove.contains(t));
+                if tags == old {
+                    return Ok(0);
+                }
+                if tags.is_empty() {
+                    section.remove_attr("tags");
+                } else {
+                    section
+                        .set_attr("tags", &tags)
+                        .map_err(|e| e.to_string())?;
+                }
+                Ok(1)
+            }
+            Command::Delete { path } => {
+                let section = self.lookup(path)?;
+                if section.parent().is_none() {
+                    return Err(format!("Can't delete file {:?}", path))?;
+                }
+                trash::move_to_trash(self.col, &section)?;
+                Ok(1)
+            }
+            Command::Replace { path, idm } => {
+                let section = self.lookup(path)?;
+                if section.parent().is_none() {
+                    return Err(format!("Can't replace file {:?}", path))?;
+                }
+                let new_section: Section = idm::from_str(idm)?;
+                section.insert_before(new_section);
+                section.detach();
+                Ok(1)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_parse_commands() {
+        let cmd: Command = serde_json::from_str(
+            r#"{"op": "set-attr", "path": "A/B", "name": "x", "value": null}"#,
+        )
+        .unwrap();
+        assert!(matches!(cmd, Command::SetAttr { value: None, .. }));
+
+        let cmd: Command =
+            serde_json::from_str(r#"{"op": "tag", "path": "A", "add": ["t"]}"#)
+                .unwrap();
+        let Command::Tag { add, remove, .. } = cmd else {
+            panic!("Not a tag command");
+        };
+        assert_eq!(add, vec!["t".to_string()]);
+        assert!(remove.is_empty());
+
+        assert!(serde_json::from_str::<Command>(r#"{"op": "frob"}"#).is_err());
+        assert!(serde_json::from_str::<Command>(
+            r#"{"op": "delete", "path": "A", "extra": 1}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_replace() {
+        let storage = Arc::new(MemoryStorage::new([(
+            "Notes.otl",
+            "Draft\n\tText\nOther\n",
+        )]));
+        let mut col = Collection::in_memory(&storage, |_| true);
+        let report = apply(
+            &mut col,
+            [
+                r#"{"op": "replace", "path": "Notes/Draft", "idm": "Final\n\tNew text\n"}"#,
+                r#"{"op": "replace", "path": "Notes", "idm": "Gone\n"}"#,
+            ],
+        )
+        .unwrap();
+        assert_eq!(report.results[0]["changes"], 1);
+        assert_eq!(report.results[1]["ok"], false);
+
+        let root = col.find("Notes").unwrap();
+        assert_eq!(
+            idm::to_string(&root.children().collect::<Vec<_>>()).unwrap(),
+            "Final\n  New text\nOther\n"
+        );
+    }
+}
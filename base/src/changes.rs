@@ -0,0 +1,170 @@
+//! Entities added, modified and removed by a collection save.
+//!
+//! Only files the save writes or deletes are compared, using their
+//! contents on disk before the save. An article also counts as modified
+//! when an entity inside it changes, and an entity that moved to another
+//! file is modified with `from` naming the old file.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{EntityIdentifier, Section};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Removed,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Change {
+    pub change: ChangeKind,
+    /// WikiWord title or URI of the entity.
+    pub entity: String,
+    pub title: String,
+    /// Collection path of the file the entity is in, or was in before it
+    /// was removed.
+    pub path: PathBuf,
+    /// Previous file of an entity that moved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<PathBuf>,
+}
+
+/// Entity contents by identifier.
+#[derive(Default)]
+pub(crate) struct Snapshot(BTreeMap<EntityIdentifier, Entity>);
+
+struct Entity {
+    title: String,
+    path: PathBuf,
+    text: String,
+}
+
+impl Snapshot {
+    /// Add the entities in a file to the snapshot.
+    ///
+    /// The file root isn't counted, files are listed in the saved paths.
+    pub fn add(&mut self, path: &Path, root: &Section) {
+        for section in root.iter().skip(1) {
+            let Some(id) = section.entity_identifier() else {
+                continue;
+            };
+            self.0.entry(id).or_insert_with(|| Entity {
+                title: section.title(),
+                path: path.to_owned(),
+                text: idm::to_string(&section).unwrap_or_default(),
+            });
+        }
+    }
+
+    /// List the changes from an older snapshot to this one.
+    pub fn changes_from(self, mut old: Snapshot) -> Vec<Change> {
+        let mut ret = Vec::new();
+        for (id, new) in self.0 {
+            let (change, from) = match old.0.remove(&id) {
+                None => (ChangeKind::Added, None),
+                Some(old) if old.path != new.path => {
+                    (ChangeKind::Modified, Some(old.path))
+                }
+                Some(old) if old.text != new.text => {
+                    (ChangeKind::Modified, None)
+                }
+                Some(_) => continue,
+            };
+            ret.push(Change {
+                change,
+                entity: name(&id),
+                title: new.title,
+                path: new.path,
+                from,
+            });
+        }
+        for (id, old) in old.0 {
+            ret.push(Change {
+                change: ChangeKind::Removed,
+                entity: name(&id),
+                title: old.title,
+                path: old.path,
+                from: None,
+            });
+        }
+        ret
+    }
+}
+
+fn name(id: &EntityIdentifier) -> String {
+    match id {
+        EntityIdentifier::WikiTitle(s) | EntityIdentifier::Uri(s) => s.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(files: &[(&str, &str)]) -> Snapshot {
+        let mut ret = Snapshot::default();
+        for (path, s) in files {
+            let items: Vec<Section> = idm::from_str(s).unwrap();
+            let root = Section::new(String::new(), Default::default());
+            for item in items {
+                root.append(item);
+            }
+            ret.add(Path::new(path), &root);
+        }
+        ret
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = snapshot(&[(
+            "a.otl",
+            "\
+KeptArticle
+  Text
+EditedArticle
+  Old text
+MovedArticle
+RemovedArticle
+",
+        )]);
+        let new = snapshot(&[
+            (
+                "a.otl",
+                "\
+KeptArticle
+  Text
+EditedArticle
+  New text
+NewArticle
+",
+            ),
+            ("b.otl", "MovedArticle\n"),
+        ]);
+
+        let changes: Vec<(ChangeKind, String, Option<PathBuf>)> = new
+            .changes_from(old)
+            .into_iter()
+            .map(|c| (c.change, c.entity, c.from))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (ChangeKind::Modified, "EditedArticle".into(), None),
+                (
+                    ChangeKind::Modified,
+                    "MovedArticle".into(),
+                    Some("a.otl".into())
+                ),
+                (ChangeKind::Added, "NewArticle".into(), None),
+                (ChangeKind::Removed, "RemovedArticle".into(), None),
+            ]
+        );
+    }
+}
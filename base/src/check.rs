@@ -0,0 +1,322 @@
+//! Consistency checks over the whole collection.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    parse, trash, typed_attribute::TypedAttribute, Collection, Rating, Section,
+};
+
+/// Files of the collection outside the trash.
+///
+/// Deleted material isn't checked, and articles in the trash don't count
+/// as link targets.
+fn files(collection: &Collection) -> impl Iterator<Item = (&Path, Section)> {
+    collection
+        .files()
+        .filter(|(path, _)| !trash::is_trash(path))
+}
+
+/// Sections of the collection outside the trash.
+fn sections(collection: &Collection) -> impl Iterator<Item = Section> + '_ {
+    files(collection).flat_map(|(_, root)| root.iter())
+}
+
+/// What kind of reference a broken link was made with.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub enum LinkKind {
+    /// WikiWord in the text of a headline.
+    WikiWord,
+    /// `[[Bracketed link]]` in the text of a headline.
+    Bracket,
+    /// Value of a `via` attribute.
+    Via,
+    /// Alias stub pointing to a file that doesn't have the section.
+    Alias,
+}
+
+impl fmt::Display for LinkKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkKind::WikiWord => write!(f, "WikiWord"),
+            LinkKind::Bracket => write!(f, "link"),
+            LinkKind::Via => write!(f, "via"),
+            LinkKind::Alias => write!(f, "alias"),
+        }
+    }
+}
+
+/// Reference to an article that does not exist in the collection.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct BrokenLink {
+    /// Collection file the reference was found in.
+    pub path: PathBuf,
+    /// Headline of the section containing the reference.
+    pub headline: String,
+    pub kind: LinkKind,
+    /// The link target that could not be found.
+    pub target: String,
+}
+
+impl fmt::Display for BrokenLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:?} in {:?}", self.kind, self.target, self.headline)
+    }
+}
+
+/// Find references to WikiWords, bracketed links and `via` entries that
+/// don't resolve to any section in the collection.
+pub fn broken_links(collection: &Collection) -> Vec<BrokenLink> {
+    // Any section title can be a link target.
+    let titles: BTreeSet<String> =
+        sections(collection).map(|s| s.title()).collect();
+
+    let mut ret = Vec::new();
+    for (path, root) in files(collection) {
+        for section in root.iter() {
+            check_section(collection, &titles, path, &section, &mut ret);
+        }
+    }
+    ret.sort();
+    ret
+}
+
+fn check_section(
+    collection: &Collection,
+    titles: &BTreeSet<String>,
+    path: &Path,
+    section: &Section,
+    output: &mut Vec<BrokenLink>,
+) {
+    let mut push = |kind, target: &str| {
+        output.push(BrokenLink {
+            path: path.to_owned(),
+            headline: section.headline(),
+            kind,
+            target: target.to_string(),
+        })
+    };
+
+    if let Ok(Some(alias)) = section.attr::<String>("alias") {
+        if collection.resolve_alias(section).is_none() {
+            push(LinkKind::Alias, &alias);
+        }
+    }
+
+    let mut report = |kind, target: &str| {
+        if !titles.contains(target) {
+            push(kind, target);
+        }
+    };
+
+    // A headline that's just the WikiWord defines the article instead of
+    // linking to it.
+    if section.wiki_title().is_none() {
+        let title = section.title();
+        for word in parse::wiki_words(&title) {
+            report(LinkKind::WikiWord, word);
+        }
+        for target in parse::bracket_links(&title) {
+            report(LinkKind::Bracket, target);
+        }
+    }
+
+    // Via values can also be URLs or free-form descriptions, only check the
+    // ones that look like links to local articles.
+    if let Some(via) = section.borrow().attributes.get("via") {
+        let via = via.trim();
+        if parse::only(parse::wiki_word)(via).is_ok() {
+            report(LinkKind::Via, via);
+        }
+        for target in parse::bracket_links(via) {
+            report(LinkKind::Bracket, target);
+        }
+    }
+}
+
+/// Attribute whose value isn't valid for its type.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct BadValue {
+    /// Collection file the attribute was found in.
+    pub path: PathBuf,
+    /// Headline of the section with the attribute.
+    pub headline: String,
+    /// Why the value is invalid.
+    pub error: String,
+}
+
+impl fmt::Display for BadValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in {:?}", self.error, self.headline)
+    }
+}
+
+/// Find `rating` attributes that aren't whole numbers from 1 to 5.
+pub fn bad_ratings(collection: &Collection) -> Vec<BadValue> {
+    let mut ret = Vec::new();
+    for (path, root) in files(collection) {
+        for section in root.iter() {
+            if let Err(e) = section.attr::<Rating>("rating") {
+                ret.push(BadValue {
+                    path: path.to_owned(),
+                    headline: section.headline(),
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+    ret
+}
+
+/// Collection file that has grown large enough to be unwieldy.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct LargeFile {
+    /// Path of the file relative to collection root.
+    pub path: PathBuf,
+    /// Size of the file on disk.
+    pub bytes: u64,
+    /// Number of sections in the file.
+    pub sections: usize,
+}
+
+impl fmt::Display for LargeFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "large file, {} bytes and {} sections",
+            self.bytes, self.sections
+        )
+    }
+}
+
+/// Find files that exceed either the byte size or the section count limit.
+pub fn large_files(
+    collection: &Collection,
+    max_bytes: u64,
+    max_sections: usize,
+) -> Vec<LargeFile> {
+    let mut ret = Vec::new();
+    for (path, root) in files(collection) {
+        // Files that haven't been saved yet have no size on disk.
+        let bytes = std::fs::metadata(collection.root_path().join(path))
+            .map(|m| m.len())
+            .unwrap_or(0);
+        // Don't count the root node, it's the file itself.
+        let sections = root.iter().count() - 1;
+
+        if bytes > max_bytes || sections > max_sections {
+            ret.push(LargeFile {
+                path: path.to_owned(),
+                bytes,
+                sections,
+            });
+        }
+    }
+    ret
+}
+
+/// A WikiWord that is spelled in several ways differing only by letter case
+/// or digits, like `HttpServer` and `HTTPServer`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SpellingVariants {
+    /// Spellings and their number of uses. Spellings that are proper
+    /// WikiWords come first, then the most used ones.
+    pub spellings: Vec<(String, usize)>,
+}
+
+impl fmt::Display for SpellingVariants {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WikiWord spelled as")?;
+        for (i, (word, count)) in self.spellings.iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(f, "{}{} ({})", sep, word, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Find WikiWords that are also spelled differently elsewhere.
+pub fn wiki_word_variants(collection: &Collection) -> Vec<SpellingVariants> {
+    fn key(word: &str) -> String {
+        word.chars()
+            .filter(|c| !c.is_ascii_digit())
+            .flat_map(|c| c.to_lowercase())
+            .collect()
+    }
+
+    // Anything CamelCased can be a misspelled WikiWord.
+    fn is_candidate(word: &str) -> bool {
+        let mut chars = word.chars();
+        chars.next().is_some_and(|c| c.is_uppercase())
+            && chars.any(|c| c.is_uppercase())
+    }
+
+    let mut counts: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+    for section in sections(collection) {
+        // Look at the same text `rename::rename_word` changes.
+        let data = section.borrow();
+        let text = std::iter::once(&data.headline)
+            .chain(
+                data.attributes
+                    .iter()
+                    .filter(|(k, v)| TypedAttribute::is_text(k, v))
+                    .map(|(_, v)| v),
+            )
+            .chain(std::iter::once(&data.body));
+        let words = text
+            .flat_map(|t| t.split_whitespace())
+            .filter(|t| !t.contains("://"))
+            .flat_map(|t| t.split(|c: char| !c.is_alphanumeric()));
+        for word in words {
+            if is_candidate(word) {
+                *counts
+                    .entry(key(word))
+                    .or_default()
+                    .entry(word.to_string())
+                    .or_default() += 1;
+            }
+        }
+    }
+
+    let is_wiki_word = |w: &str| parse::only(parse::wiki_word)(w).is_ok();
+    let mut ret = Vec::new();
+    for spellings in counts.into_values() {
+        if spellings.len() < 2 || !spellings.keys().any(|w| is_wiki_word(w)) {
+            continue;
+        }
+        let mut spellings: Vec<(String, usize)> =
+            spellings.into_iter().collect();
+        spellings
+            .sort_by_key(|(w, n)| (!is_wiki_word(w), std::cmp::Reverse(*n)));
+        ret.push(SpellingVariants { spellings });
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_skip_trash() {
+        let storage = Arc::new(MemoryStorage::new([
+            ("Notes.otl", "See OldNote\nReview\n\t:rating 3\n"),
+            (trash::TRASH_FILE, "OldNote\n\tSee GoneNote\n\t:rating 9\n"),
+        ]));
+        let col = Collection::in_memory(&storage, |_| true);
+
+        let links: Vec<String> =
+            broken_links(&col).into_iter().map(|l| l.target).collect();
+        assert_eq!(links, vec!["OldNote"]);
+        assert!(bad_ratings(&col).is_empty());
+        assert!(large_files(&col, 0, 0)
+            .iter()
+            .all(|f| !trash::is_trash(&f.path)));
+    }
+}
@@ -3,6 +3,8 @@ use std::{
     ffi::OsStr,
     fs,
     path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    time::SystemTime,
 };
 
 use idm::ser::Indentation;
@@ -10,8 +12,11 @@ use rayon::prelude::*;
 use walkdir::WalkDir;
 
 use crate::{
-    section::{RawOutline, RawSection, SectionData},
-    Result, Section,
+    backup::Backups,
+    changes::{Change, Snapshot},
+    section::{split_body, RawOutline, RawSection, SectionData},
+    storage::Storage,
+    Config, ParseError, Result, Section,
 };
 
 /// Representation of a collection of otl files that makes up the knowledge
@@ -19,77 +24,304 @@ use crate::{
 pub struct Collection {
     /// Path the collection was loaded from.
     root_path: PathBuf,
+    storage: Arc<dyn Storage>,
 
     /// Last seen set of paths, used to determine if files need to be created
     /// or deleted when saving the collection.
     previous_paths: BTreeSet<PathBuf>,
     files: BTreeMap<PathBuf, File>,
+    /// Where to copy files before saves overwrite them, if anywhere.
+    backups: Option<Backups>,
+    /// Paths written or deleted by the last save.
+    saved_paths: Vec<PathBuf>,
+    /// Whether saves list the entities they change.
+    track_changes: bool,
+    /// Entities changed by the last save if tracking changes.
+    changes: Vec<Change>,
+    /// Lock files that don't reserialize to their stored text, from the
+    /// `fidelity` setting.
+    fidelity: bool,
 }
 
-/// Metadata and contents for a single file in the collection.
+/// A single file in the collection.
+///
+/// Files outside the prefix given to `Collection::load_subset` are parsed
+/// when their contents are first needed.
 struct File {
+    /// Collection path of the file.
+    path: PathBuf,
+    storage: Arc<dyn Storage>,
+    /// Collection path headline of the file.
+    headline: String,
+    contents: OnceLock<Contents>,
+    /// Lock the file when it's loaded if it doesn't reserialize cleanly.
+    fidelity: bool,
+}
+
+/// Parsed contents and metadata of a file.
+struct Contents {
     section: Section,
     style: Indentation,
+    /// Modification time of the file in storage when it was last loaded or
+    /// saved.
+    mtime: Option<SystemTime>,
+    /// Why deferred loading failed if it did, the section is an empty
+    /// placeholder then.
+    error: Option<String>,
+    /// The file doesn't reserialize to the text it was loaded from and the
+    /// `fidelity` setting keeps it from being rewritten.
+    locked: bool,
 }
 
 impl File {
-    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
-        let outline = RawSection::from(&self.section).outline();
-
-        fs::write(
+    /// File that is parsed on first use.
+    fn deferred(
+        storage: Arc<dyn Storage>,
+        path: PathBuf,
+        fidelity: bool,
+    ) -> Self {
+        File {
+            headline: file_headline(&path),
             path,
-            idm::to_string_styled(self.style, &outline)
-                .expect("Failed to serialize outline"),
-        )?;
+            storage,
+            contents: OnceLock::new(),
+            fidelity,
+        }
+    }
+
+    /// File with contents already in memory.
+    fn loaded(
+        storage: Arc<dyn Storage>,
+        path: PathBuf,
+        contents: Contents,
+    ) -> Self {
+        // Already loaded, so fidelity doesn't matter.
+        let ret = File::deferred(storage, path, false);
+        let _ = ret.contents.set(contents);
+        ret
+    }
+
+    fn contents(&self) -> &Contents {
+        self.contents.get_or_init(|| {
+            log::info!("Collection: Loading deferred file {:?}", self.path);
+            match Contents::read(&*self.storage, &self.path, self.fidelity) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    log::error!("Failed to load {:?}: {}", self.path, e);
+                    Contents {
+                        section: Section::new(
+                            self.headline.clone(),
+                            Default::default(),
+                        ),
+                        style: Config::get().default_indentation(),
+                        mtime: self.storage.modified(&self.path),
+                        error: Some(e.to_string()),
+                        locked: false,
+                    }
+                }
+            }
+        })
+    }
+
+    fn section(&self) -> Section {
+        self.contents().section.clone()
+    }
+
+    /// Headline of the root section, without loading a deferred file.
+    fn root_headline(&self) -> String {
+        match self.contents.get() {
+            Some(contents) => contents.section.headline(),
+            None => self.headline.clone(),
+        }
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let Some(contents) = self.contents.get_mut() else {
+            return Ok(());
+        };
+        if let Some(e) = &contents.error {
+            return Err(format!("Not saving, failed to load: {}", e))?;
+        }
+
+        self.storage.write(&self.path, &contents.text())?;
+        contents.section.cleanse();
+        contents.mtime = self.storage.modified(&self.path);
         Ok(())
     }
 }
 
-/// Load file into raw sections.
-///
-/// Return path converted into headline as well.
+impl Contents {
+    /// Contents of a new file that isn't in storage yet.
+    fn new(section: Section, style: Indentation) -> Contents {
+        Contents {
+            section,
+            style,
+            mtime: None,
+            error: None,
+            locked: false,
+        }
+    }
+
+    /// Read and parse a file from storage.
+    ///
+    /// With `fidelity`, the file is locked if it doesn't reserialize to the
+    /// text it was read from.
+    fn read(
+        storage: &dyn Storage,
+        path: &Path,
+        fidelity: bool,
+    ) -> Result<Contents> {
+        let mtime = storage.modified(path);
+        let text = storage.read(path)?;
+        let (style, outline) = parse_outline(path, &text)?;
+        let mut ret = Contents {
+            section: build_section(file_headline(path), outline),
+            style,
+            mtime,
+            error: None,
+            locked: false,
+        };
+        // Reserializing every file is only worth it if it's asked for.
+        ret.locked = fidelity && ret.text() != text;
+        if ret.locked {
+            log::info!("{:?} doesn't reserialize cleanly, locking it", path);
+        }
+        Ok(ret)
+    }
+
+    /// Text of the file as saving would write it.
+    fn text(&self) -> String {
+        let outline = RawSection::from(&self.section).outline();
+        idm::to_string_styled(self.style, &outline)
+            .expect("Failed to serialize outline")
+    }
+}
+
+/// Read and parse a file from storage into a section.
+fn load_contents(
+    storage: &dyn Storage,
+    path: &Path,
+) -> Result<(Indentation, Section)> {
+    let (style, outline) = parse_outline(path, &storage.read(path)?)?;
+    Ok((style, build_section(file_headline(path), outline)))
+}
+
+/// Parse the text of a file.
 ///
-/// Parallelizable helper function for file. Currently tree nodes can't be
-/// parallelized.
-fn load_outline(
-    root_path: impl AsRef<Path>,
-    path: impl Into<PathBuf>,
-) -> Result<(Indentation, String, RawOutline)> {
-    let path = path.into();
-    log::debug!("load_outline from {:?}", path);
-    let headline = path
-        .strip_prefix(root_path.as_ref())
-        .unwrap()
-        .with_extension("") // Strip out the ".otl"
-        .to_string_lossy()
-        .to_string();
-
-    let contents = fs::read_to_string(path.clone())?;
-    // NB. Currently using tabs as the default otlbook style to go with
-    // VimOutliner conventions. This should be made customizable somewhere
-    // eventually.
-    let style = Indentation::infer(&contents).unwrap_or(Indentation::Tabs);
+/// Failures are `ParseError`s with the path and line of the problem.
+pub(crate) fn parse_outline(
+    path: &Path,
+    contents: &str,
+) -> Result<(Indentation, RawOutline)> {
+    // Tabs are the default style to go with VimOutliner conventions.
+    let style = Indentation::infer(contents)
+        .unwrap_or(Config::get().default_indentation());
 
     Ok((
         style,
-        headline,
-        // FIXME: Remove the final .to_string() when IDM is updated to version with more generic file name setter.
-        idm::from_str::<RawOutline>(&contents).map_err(|e| {
-            e.with_file_name(path.to_string_lossy().to_string())
-        })?,
+        idm::from_str::<RawOutline>(contents)
+            .map_err(|e| ParseError::from_idm(path, contents, e))?,
     ))
 }
 
-fn build_section(headline: String, outline: RawOutline) -> Section {
-    let RawOutline((attributes,), mut body) = outline;
+/// Show the lines that differ between two texts.
+///
+/// Only the block between the common start and end is shown, cut short
+/// after a few lines.
+fn diff(old: &str, new: &str) -> String {
+    const MAX_LINES: usize = 10;
+
+    let (old, new): (Vec<&str>, Vec<&str>) =
+        (old.lines().collect(), new.lines().collect());
+    let start = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let end = old[start..]
+        .iter()
+        .rev()
+        .zip(new[start..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut ret = format!("@@ line {}\n", start + 1);
+    for (sign, lines) in [('-', &old), ('+', &new)] {
+        let changed = &lines[start..lines.len() - end];
+        for line in changed.iter().take(MAX_LINES) {
+            ret.push_str(&format!("{}{}\n", sign, line));
+        }
+        if changed.len() > MAX_LINES {
+            ret.push_str(&format!(
+                "{} ... {} more lines\n",
+                sign,
+                changed.len() - MAX_LINES
+            ));
+        }
+    }
+    ret
+}
+
+/// Return a filter for path headlines that lie on a path prefix or above
+/// it, see `Collection::load_subset`.
+fn matches_prefix(prefix: &str) -> impl Fn(&str) -> bool + '_ {
+    let prefix = prefix.trim_matches('/');
+    move |headline| {
+        let (short, long) = if headline.len() < prefix.len() {
+            (headline, prefix)
+        } else {
+            (prefix, headline)
+        };
+        long.strip_prefix(short)
+            .is_some_and(|s| s.is_empty() || s.starts_with('/'))
+    }
+}
+
+/// Headline for a collection-relative file path.
+///
+/// The headline is the path without the `.otl` extension and with `/` as the
+/// separator on every platform, so headline paths look the same on Windows.
+pub(crate) fn file_headline(path: &Path) -> String {
+    path.with_extension("")
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// List the .otl files of a collection.
+pub(crate) fn otl_files(root_path: &Path) -> Vec<PathBuf> {
+    let otl_extension = OsStr::new("otl");
+    WalkDir::new(root_path)
+        .into_iter()
+        // Skip hidden directories like .git and .otlbook.
+        .filter_entry(|e| {
+            e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.')
+        })
+        .filter_map(|e| e.map(|e| e.path().to_path_buf()).ok())
+        .filter(|e| e.extension() == Some(otl_extension))
+        .collect()
+}
+
+/// Load a single collection file as a section without loading the rest of
+/// the collection.
+pub(crate) fn load_file(root_path: &Path, path: &Path) -> Result<Section> {
+    let headline = file_headline(path.strip_prefix(root_path).unwrap());
+    let (_, outline) = parse_outline(path, &fs::read_to_string(path)?)?;
+    Ok(build_section(headline, outline))
+}
+
+pub(crate) fn build_section(headline: String, outline: RawOutline) -> Section {
+    let RawOutline((attributes,), children) = outline;
+    let (body, mut children) = split_body(children);
 
     // XXX: Reverse-prepend optimization to get around nodes having
     // inefficient append. Nicer approach would be to fix tree node to track
     // last child pointer and have O(1) append op.
-    body.reverse();
+    children.reverse();
 
-    let ret = Section::from(SectionData::new(headline, attributes));
-    for child in body {
+    let ret = Section::from(SectionData {
+        headline,
+        attributes,
+        body,
+    });
+    for child in children {
         ret.prepend(child.into());
     }
     ret.cleanse();
@@ -97,29 +329,83 @@ fn build_section(headline: String, outline: RawOutline) -> Section {
 }
 
 impl Collection {
+    /// Return the path of the collection `load` would load.
+    ///
+    /// This is `OTLBOOK_PATH` if set, then `path` in the user's settings and
+    /// `~/otlbook` if neither is set. On Windows,
+    /// where the home directory isn't the usual place for documents,
+    /// `otlbook` in the Documents folder is used unless `~/otlbook` exists.
+    pub fn default_path() -> Result<PathBuf> {
+        if let Ok(path) = std::env::var("OTLBOOK_PATH") {
+            return Ok(PathBuf::from(path));
+        }
+        if let Some(path) = &Config::get().path {
+            return Ok(path.clone());
+        }
+
+        let home = dirs::home_dir().map(|p| p.join("otlbook"));
+        if cfg!(windows) && !home.as_ref().is_some_and(|p| p.exists()) {
+            if let Some(docs) = dirs::document_dir() {
+                return Ok(docs.join("otlbook"));
+            }
+        }
+
+        home.ok_or_else(|| {
+            "Cannot find otlbook collection, set env var OTLBOOK_PATH".into()
+        })
+    }
+
     pub fn load() -> Result<Collection> {
-        log::info!("Collection::load: Determining collection path");
-        let root_path = if let Ok(path) = std::env::var("OTLBOOK_PATH") {
-            PathBuf::from(path)
-        } else if let Some(mut path) = dirs::home_dir() {
-            path.push("otlbook");
-            path
-        } else {
-            return Err(
-                "Cannot find otlbook collection, set env var OTLBOOK_PATH",
-            )?;
-        };
+        Self::load_filtered(Self::default_path()?, |_| true)
+    }
 
-        log::info!("Collection::load: Collecting .otl files");
+    /// Load the collection in a directory instead of the default one.
+    pub fn load_from(root_path: impl Into<PathBuf>) -> Result<Collection> {
+        Self::load_filtered(root_path.into(), |_| true)
+    }
 
-        let otl_extension = OsStr::new("otl");
-        let file_paths: Vec<_> = WalkDir::new(root_path.clone())
+    /// Load a collection parsing only the files under a path prefix.
+    ///
+    /// The prefix is a slash-separated collection path like `notes/ToRead`.
+    /// Files whose path headline matches it up to the shorter of the two
+    /// are parsed right away, the rest when their contents are first
+    /// needed. Commands that only work on one subtree can use this to skip
+    /// parsing the rest of the collection. Errors in the deferred files are
+    /// logged instead of returned and the files can't be saved.
+    pub fn load_subset(prefix: &str) -> Result<Collection> {
+        Self::load_filtered(Self::default_path()?, matches_prefix(prefix))
+    }
+
+    fn load_filtered(
+        root_path: PathBuf,
+        eager: impl Fn(&str) -> bool,
+    ) -> Result<Collection> {
+        let backups = Backups::load(&root_path)?;
+        let storage = Config::get().storage.open(&root_path)?;
+        let fidelity = Config::get().fidelity;
+        Self::open(root_path, storage, backups, fidelity, eager)
+    }
+
+    /// Load a collection from storage, parsing the files whose path
+    /// headline `eager` accepts right away.
+    fn open(
+        root_path: PathBuf,
+        storage: Arc<dyn Storage>,
+        backups: Option<Backups>,
+        fidelity: bool,
+        eager: impl Fn(&str) -> bool,
+    ) -> Result<Collection> {
+        log::info!("Collection::load: Collecting .otl files");
+        let (file_paths, deferred): (Vec<_>, Vec<_>) = storage
+            .paths()?
             .into_iter()
-            .filter_map(|e| e.map(|e| e.path().to_path_buf()).ok())
-            .filter(|e| e.extension() == Some(otl_extension))
-            .collect();
+            .partition(|p| eager(&file_headline(p)));
 
-        log::info!("Collection::load: Loading {} .otl files", file_paths.len());
+        log::info!(
+            "Collection::load: Loading {} .otl files, deferring {}",
+            file_paths.len(),
+            deferred.len()
+        );
 
         let mut files = BTreeMap::new();
         let mut seen_paths = BTreeSet::new();
@@ -127,67 +413,357 @@ impl Collection {
         // Load outlines in parallel with rayon.
         for (path, res) in file_paths
             .par_iter()
-            .map(|p| (p.clone(), load_outline(&root_path, p)))
+            .map(|p| (p.clone(), Contents::read(&*storage, p, fidelity)))
             .collect::<Vec<_>>()
             .into_iter()
         {
-            let (style, headline, raw_outline) = res?;
-            let section = build_section(headline, raw_outline);
+            let file = File::loaded(storage.clone(), path.clone(), res?);
+            files.insert(path.clone(), file);
+            seen_paths.insert(path);
+        }
 
-            let path = path.strip_prefix(&root_path).unwrap().to_owned();
-            files.insert(path.clone(), File { style, section });
+        for path in deferred {
+            let file = File::deferred(storage.clone(), path.clone(), fidelity);
+            files.insert(path.clone(), file);
             seen_paths.insert(path);
         }
 
         Ok(Collection {
             root_path,
+            storage,
             previous_paths: seen_paths,
             files,
+            backups,
+            saved_paths: Vec::new(),
+            track_changes: false,
+            changes: Vec::new(),
+            fidelity,
         })
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = Section> {
-        // Construct a mutant iterator that has no current next item but the
-        // roots of all the file sections as pending items.
-        crate::tree::BreadthFirstNodes {
+    /// Path of the directory the collection was loaded from.
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
+    }
+
+    /// Iterate all sections of the collection breadth-first.
+    ///
+    /// Deferred files are loaded as the iteration reaches their roots.
+    pub fn iter(&self) -> impl Iterator<Item = Section> + '_ {
+        // Yield the file roots first and queue their children in a
+        // breadth-first iterator that has no current next item.
+        let mut roots = self.roots();
+        let mut rest = crate::tree::BreadthFirstNodes {
             next: None,
-            pending: self
-                .files
+            pending: Default::default(),
+        };
+        std::iter::from_fn(move || match roots.next() {
+            Some(root) => {
+                if let Some(child) = root.child() {
+                    rest.pending.push_back(child);
+                }
+                Some(root)
+            }
+            None => rest.next(),
+        })
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = Section> + '_ {
+        self.files.values().map(|file| file.section())
+    }
+
+    /// Iterate root sections of files along with their paths relative to
+    /// collection root.
+    pub fn files(&self) -> impl Iterator<Item = (&Path, Section)> + '_ {
+        self.files
+            .iter()
+            .map(|(path, file)| (path.as_path(), file.section()))
+    }
+
+    /// Return root section of the file at path relative to collection root.
+    pub fn root(&self, path: impl AsRef<Path>) -> Option<Section> {
+        self.files.get(path.as_ref()).map(|file| file.section())
+    }
+
+    /// Return the first file root with the given headline.
+    ///
+    /// Roots come first in `iter`, so this is what searching it would find,
+    /// but deferred files aren't loaded unless they match.
+    fn find_root(&self, headline: &str) -> Option<Section> {
+        self.files
+            .values()
+            .find(|file| file.root_headline() == headline)
+            .map(|file| file.section())
+    }
+
+    /// Add a new file to the collection.
+    ///
+    /// The headline of the section is replaced with the collection path of
+    /// the file. The file will be written to disk on the next save with the
+    /// indentation from `new_file_style`.
+    pub fn insert_file(
+        &mut self,
+        path: impl Into<PathBuf>,
+        section: Section,
+    ) -> Result<()> {
+        let path = path.into();
+        if path.extension() != Some(OsStr::new("otl")) {
+            return Err(format!(
+                "insert_file: {:?} is not an .otl file",
+                path
+            ))?;
+        }
+        if self.files.contains_key(&path) {
+            return Err(format!("insert_file: {:?} already exists", path))?;
+        }
+
+        section.detach();
+        section.set_headline(file_headline(&path));
+        let file = File::loaded(
+            self.storage.clone(),
+            path.clone(),
+            Contents::new(section, self.new_file_style()),
+        );
+        self.files.insert(path, file);
+        Ok(())
+    }
+
+    /// Indentation style for new files.
+    ///
+    /// This is the `indentation` setting if there is one, otherwise the
+    /// style most of the loaded files use.
+    pub fn new_file_style(&self) -> Indentation {
+        if let Some(indent) = Config::get().indentation {
+            return indent.0;
+        }
+
+        let mut counts: Vec<(Indentation, usize)> = Vec::new();
+        for contents in self.files.values().filter_map(|f| f.contents.get()) {
+            match counts
+                .iter_mut()
+                .find(|(style, _)| *style == contents.style)
+            {
+                Some((_, n)) => *n += 1,
+                None => counts.push((contents.style, 1)),
+            }
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(_, n)| *n)
+            .map_or(Indentation::Tabs, |(style, _)| style)
+    }
+
+    /// Change the indentation style of a file, the file is rewritten on the
+    /// next save.
+    pub fn set_style(
+        &mut self,
+        path: impl AsRef<Path>,
+        style: Indentation,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let Some(file) = self.files.get_mut(path) else {
+            return Err(format!("{:?} is not in the collection", path))?;
+        };
+        file.contents();
+        if let Some(contents) = file.contents.get_mut() {
+            if contents.style != style {
+                contents.style = style;
+                contents.section.taint();
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the collection path of the file a section is in.
+    pub fn file_path(&self, section: &Section) -> Option<&Path> {
+        self.files
+            .iter()
+            .filter_map(|(path, file)| Some((path, file.contents.get()?)))
+            .find(|(_, contents)| contents.section.contains(section))
+            .map(|(path, _)| path.as_path())
+    }
+
+    /// Return the stored text of the file at a collection path.
+    pub fn file_text(&self, path: impl AsRef<Path>) -> Result<String> {
+        self.storage.read(path.as_ref())
+    }
+
+    /// Return whether the file at a collection path is stored exactly the
+    /// way saving it would write it.
+    pub fn is_normalized(&self, path: impl AsRef<Path>) -> Result<bool> {
+        let path = path.as_ref();
+        match self.files.get(path) {
+            Some(file) => {
+                Ok(self.storage.read(path)? == file.contents().text())
+            }
+            None => Err(format!("{:?} is not in the collection", path))?,
+        }
+    }
+
+    /// Return when the file at a collection path was last written in
+    /// storage.
+    pub fn modified(&self, path: impl AsRef<Path>) -> Option<SystemTime> {
+        self.storage.modified(path.as_ref())
+    }
+
+    /// Return whether the file at a collection path was changed in storage
+    /// after the collection loaded or saved it.
+    pub fn is_changed_on_disk(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        match self.files.get(path).and_then(|file| file.contents.get()) {
+            Some(Contents {
+                mtime: Some(loaded),
+                ..
+            }) => self.storage.modified(path) != Some(*loaded),
+            _ => false,
+        }
+    }
+
+    /// Return whether the file at a collection path was added, changed or
+    /// removed in storage after the collection loaded or saved it.
+    pub fn is_stale(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        match (self.files.contains_key(path), self.storage.exists(path)) {
+            (true, true) => self.is_changed_on_disk(path),
+            (is_known, exists) => is_known != exists,
+        }
+    }
+
+    /// Return collection paths of all the stale files.
+    pub fn stale_files(&self) -> Vec<PathBuf> {
+        let mut paths: BTreeSet<PathBuf> = match self.storage.paths() {
+            Ok(paths) => paths.into_iter().collect(),
+            Err(e) => {
+                log::warn!("Failed to list collection files: {}", e);
+                Default::default()
+            }
+        };
+        paths.extend(self.files.keys().cloned());
+        paths.into_iter().filter(|p| self.is_stale(p)).collect()
+    }
+
+    /// Reload a file at a collection path if it is stale.
+    ///
+    /// Return whether the collection changed. Unsaved changes to the file
+    /// are lost.
+    pub fn reload_file(&mut self, path: impl AsRef<Path>) -> Result<bool> {
+        let path = path.as_ref();
+
+        if !self.is_stale(path) {
+            return Ok(false);
+        }
+        if !self.storage.exists(path) {
+            self.previous_paths.remove(path);
+            return Ok(self.files.remove(path).is_some());
+        }
+
+        log::info!("Collection::reload_file: Loading {:?}", path);
+        let contents = Contents::read(&*self.storage, path, self.fidelity)?;
+        self.files.insert(
+            path.to_owned(),
+            File::loaded(self.storage.clone(), path.to_owned(), contents),
+        );
+        self.previous_paths.insert(path.to_owned());
+        Ok(true)
+    }
+
+    /// Throw away changes made after the collection was loaded or saved.
+    ///
+    /// Changed and removed files are loaded again from disk and new files
+    /// are dropped.
+    pub fn revert(&mut self) -> Result<()> {
+        let mut paths: Vec<PathBuf> = self
+            .files
+            .iter()
+            .filter(|(path, file)| {
+                !self.previous_paths.contains(*path)
+                    || file
+                        .contents
+                        .get()
+                        .is_some_and(|contents| contents.section.is_dirty())
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        paths.extend(
+            self.previous_paths
                 .iter()
-                .map(|(_, file)| file.section.clone())
-                .collect(),
+                .filter(|p| !self.files.contains_key(*p))
+                .cloned(),
+        );
+
+        for path in paths {
+            self.files.remove(&path);
+            self.reload_file(&path)?;
         }
+        Ok(())
     }
 
-    pub fn roots(&self) -> impl Iterator<Item = Section> + '_ {
-        self.files.iter().map(|(_, file)| file.section.clone())
+    /// If section is an alias stub, return the section it points to.
+    ///
+    /// Alias stubs are left behind when sections are moved to another file.
+    /// They have an `alias` attribute naming the file the section was moved
+    /// to, and the section has the same headline as the stub under the root
+    /// of the target file.
+    pub fn resolve_alias(&self, section: &Section) -> Option<Section> {
+        let target: String = section.attr("alias").ok()??;
+        let root = self.root(format!("{}.otl", target))?;
+        let headline = section.headline();
+        root.children().find(|c| c.headline() == headline)
     }
 
     /// Save changes after creating the collection or the previous save to
     /// disk to path where the collection was loaded from.
     pub fn save(&mut self) -> Result<()> {
         log::info!("Collection::save started");
-        let abs = |relative_path: &PathBuf| self.root_path.join(relative_path);
+        self.saved_paths.clear();
+        self.changes.clear();
+        let current_paths = self.files.keys().cloned().collect::<BTreeSet<_>>();
+        let (mut old, mut new) = (Snapshot::default(), Snapshot::default());
 
-        let current_paths = self
-            .files
-            .iter()
-            .map(|(p, _)| p)
-            .cloned()
-            .collect::<BTreeSet<_>>();
+        // Refuse before writing anything so the collection isn't left half
+        // saved.
+        let mut refused = Vec::new();
+        for (path, file) in &self.files {
+            let Some(contents) = file.contents.get() else {
+                continue;
+            };
+            if contents.locked && contents.section.is_dirty() {
+                let stored = self.storage.read(path).unwrap_or_default();
+                refused.push(format!(
+                    "{} doesn't reserialize cleanly, not rewriting it:\n{}",
+                    path.to_string_lossy(),
+                    diff(&stored, &contents.text())
+                ));
+            }
+        }
+        if !refused.is_empty() {
+            return Err(refused.join("\n"))?;
+        }
 
         // Delete files that were removed from current set.
         for deleted in self.previous_paths.difference(&current_paths) {
-            let path = abs(deleted);
-            log::info!("Collection::save deleting removed file {:?}", path);
-            fs::remove_file(path)?;
+            if self.track_changes {
+                if let Ok((_, root)) = load_contents(&*self.storage, deleted) {
+                    old.add(deleted, &root);
+                }
+            }
+            if let Some(backups) = &self.backups {
+                backups.back_up(&self.root_path, deleted)?;
+            }
+            log::info!("Collection::save deleting removed file {:?}", deleted);
+            self.storage.remove(deleted)?;
+            self.saved_paths.push(deleted.clone());
         }
 
-        for (path, file) in self.files.iter() {
+        for (path, file) in self.files.iter_mut() {
             let do_write = if !self.previous_paths.contains(path) {
                 log::info!("Collection::save creating new file {:?}", path);
                 true
-            } else if file.section.is_dirty() {
+            } else if file
+                .contents
+                .get()
+                .is_some_and(|contents| contents.section.is_dirty())
+            {
                 log::info!("Collection::save writing changed file {:?}", path);
                 true
             } else {
@@ -195,15 +771,88 @@ impl Collection {
             };
 
             if do_write {
-                file.save(abs(path))?;
-                file.section.cleanse();
+                if self.track_changes {
+                    if self.previous_paths.contains(path) {
+                        if let Ok((_, root)) =
+                            load_contents(&*self.storage, path)
+                        {
+                            old.add(path, &root);
+                        }
+                    }
+                    new.add(path, &file.section());
+                }
+                if let Some(backups) = &self.backups {
+                    backups.back_up(&self.root_path, path)?;
+                }
+                file.save()?;
+                self.saved_paths.push(path.clone());
             }
         }
 
         self.previous_paths = current_paths;
+        self.changes = new.changes_from(old);
         Ok(())
     }
 
+    /// Return whether the `fidelity` setting keeps a file from being
+    /// rewritten because it doesn't reserialize to its stored text.
+    pub fn is_locked(&self, path: impl AsRef<Path>) -> bool {
+        self.files
+            .get(path.as_ref())
+            .and_then(|file| file.contents.get())
+            .is_some_and(|contents| contents.locked)
+    }
+
+    /// Allow saving to rewrite a locked file in the normal form.
+    pub fn allow_rewrite(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let Some(file) = self.files.get_mut(path) else {
+            return Err(format!("{:?} is not in the collection", path))?;
+        };
+        file.contents();
+        if let Some(contents) = file.contents.get_mut() {
+            contents.locked = false;
+        }
+        Ok(())
+    }
+
+    /// Return collection paths of the files the last save wrote or deleted.
+    pub fn saved_paths(&self) -> &[PathBuf] {
+        &self.saved_paths
+    }
+
+    /// Make saves list the entities they add, modify and remove.
+    ///
+    /// This reads the old versions of the files being saved from disk.
+    pub fn set_track_changes(&mut self, track_changes: bool) {
+        self.track_changes = track_changes;
+    }
+
+    /// Return the entities changed by the last save if tracking changes.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Return the node at a slash-separated path of headlines if it exists.
+    ///
+    /// The first element is matched against any headline in the collection
+    /// like in `find_or_create`.
+    pub fn find(&self, path: &str) -> Option<Section> {
+        let mut elts = path.split('/');
+        let first = elts.next()?;
+        let root = self
+            .find_root(first)
+            .or_else(|| self.iter().find(|n| n.headline() == first))?;
+        let mut node = self.resolve_alias(&root).unwrap_or(root);
+
+        for headline in elts {
+            let c = node.children().find(|c| c.headline() == headline)?;
+            node = self.resolve_alias(&c).unwrap_or(c);
+        }
+
+        Some(node)
+    }
+
     /// Return a node with the given title.
     ///
     /// If the node isn't found in the collection, create a new toplevel item
@@ -218,41 +867,30 @@ impl Collection {
             return Err("find_or_create: Bad path")?;
         }
 
-        let mut root = None;
-
         // Look for existing section.
         // XXX: Ineffective O(n) lookup.
-        for node in self.iter() {
-            if &node.headline() == elts[0] {
-                root = Some(node);
-                break;
-            }
-        }
+        let root = self
+            .find_root(elts[0])
+            .or_else(|| self.iter().find(|n| n.headline() == elts[0]));
 
         let mut node = if let Some(root) = root {
-            root
+            self.resolve_alias(&root).unwrap_or(root)
         } else {
             log::info!("Section {:?} not found, creating toplevel item", path);
 
-            let headline = format!("{}.otl", path);
+            let headline = elts[0].to_string();
             let section = Section::from(SectionData::new(
                 headline.clone(),
                 Default::default(),
             ));
-            self.files.insert(
-                headline.into(),
-                File {
-                    section: section.clone(),
-                    style: Indentation::Tabs,
-                },
-            );
+            self.insert_file(format!("{}.otl", headline), section.clone())?;
             section
         };
 
         'path: for headline in elts[1..].iter() {
             for c in node.children() {
                 if &c.headline() == headline {
-                    node = c;
+                    node = self.resolve_alias(&c).unwrap_or(c);
                     continue 'path;
                 }
             }
@@ -266,3 +904,266 @@ impl Collection {
         Ok(node)
     }
 }
+
+#[cfg(test)]
+impl Collection {
+    /// Load a collection from in-memory storage, parsing the files whose
+    /// path headline `eager` accepts right away.
+    pub(crate) fn in_memory(
+        storage: &Arc<crate::storage::MemoryStorage>,
+        eager: impl Fn(&str) -> bool,
+    ) -> Collection {
+        let root_path = std::env::temp_dir().join("otlbook-test");
+        Collection::open(root_path, storage.clone(), None, false, eager)
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn storage() -> Arc<MemoryStorage> {
+        Arc::new(MemoryStorage::new([
+            ("Inbox.otl", "Note\n\tDetail\n"),
+            ("notes/Books.otl", "Dune\n\tChapter\nEmma\n"),
+            ("notes/ToRead.otl", "Ulysses\n"),
+            ("notes/ToReadMore.otl", "Walden\n"),
+            ("other.otl", "Other\n"),
+        ]))
+    }
+
+    #[test]
+    fn test_matches_prefix() {
+        let eager = matches_prefix("/notes/ToRead/");
+        assert!(eager("notes/ToRead"));
+        assert!(eager("notes"));
+        assert!(eager("notes/ToRead/Fiction"));
+        assert!(!eager("notes/ToReadMore"));
+        assert!(!eager("notes/Books"));
+        assert!(!eager("other"));
+    }
+
+    #[test]
+    fn test_load_subset() {
+        let storage = storage();
+        let all = Collection::in_memory(&storage, |_| true);
+        let subset =
+            Collection::in_memory(&storage, matches_prefix("notes/ToRead"));
+        let is_loaded = |col: &Collection| -> Vec<bool> {
+            col.files
+                .values()
+                .map(|f| f.contents.get().is_some())
+                .collect()
+        };
+        assert_eq!(is_loaded(&subset), vec![false, false, true, false, false]);
+
+        // Deferred files are loaded as iteration reaches them and the
+        // sections come in the same order.
+        let headlines = |col: &Collection| {
+            col.iter().map(|s| s.headline()).collect::<Vec<_>>()
+        };
+        assert_eq!(headlines(&subset), headlines(&all));
+        assert!(is_loaded(&subset).iter().all(|&b| b));
+    }
+
+    #[test]
+    fn test_save_subset() {
+        let storage = storage();
+        let mut col =
+            Collection::in_memory(&storage, matches_prefix("notes/ToRead"));
+        col.root("notes/ToRead.otl")
+            .and_then(|root| root.child())
+            .unwrap()
+            .append(Section::new("Chapter".into(), Default::default()));
+        col.save().unwrap();
+        assert_eq!(col.saved_paths(), &[PathBuf::from("notes/ToRead.otl")]);
+        assert_eq!(
+            storage.read(Path::new("notes/ToRead.otl")).unwrap(),
+            "Ulysses\n\tChapter\n"
+        );
+        assert!(col.files[Path::new("other.otl")].contents.get().is_none());
+    }
+
+    #[test]
+    fn test_failed_deferred_load() {
+        let storage = storage();
+        storage
+            .write(Path::new("other.otl"), "A\n\tB\n\t  C\n")
+            .unwrap();
+        let mut col =
+            Collection::in_memory(&storage, matches_prefix("notes/ToRead"));
+
+        // The placeholder is empty and can't be saved over the file.
+        let root = col.root("other.otl").unwrap();
+        assert_eq!(root.headline(), "other");
+        assert!(root.child().is_none());
+        root.append(Section::new("New".into(), Default::default()));
+        let err = col.save().unwrap_err().to_string();
+        assert!(err.contains("failed to load"), "{}", err);
+        assert_eq!(
+            storage.read(Path::new("other.otl")).unwrap(),
+            "A\n\tB\n\t  C\n"
+        );
+    }
+
+    #[test]
+    fn test_revert() {
+        let storage = storage();
+        let mut col = Collection::in_memory(&storage, |_| true);
+        col.root("Inbox.otl")
+            .and_then(|root| root.child())
+            .unwrap()
+            .set_headline("Changed");
+        col.insert_file(
+            "New.otl",
+            Section::new(String::new(), Default::default()),
+        )
+        .unwrap();
+
+        col.revert().unwrap();
+        assert_eq!(
+            col.root("Inbox.otl")
+                .and_then(|root| root.child())
+                .map(|s| s.headline()),
+            Some("Note".into())
+        );
+        assert!(col.root("New.otl").is_none());
+        col.save().unwrap();
+        assert!(col.saved_paths().is_empty());
+        assert_eq!(storage.paths().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_reload_file() {
+        let storage = storage();
+        let mut col = Collection::in_memory(&storage, |_| true);
+        assert!(!col.reload_file("Inbox.otl").unwrap());
+
+        storage.write(Path::new("Inbox.otl"), "Edited\n").unwrap();
+        storage.write(Path::new("New.otl"), "New\n").unwrap();
+        storage.remove(Path::new("other.otl")).unwrap();
+
+        assert!(col.reload_file("Inbox.otl").unwrap());
+        assert_eq!(
+            col.root("Inbox.otl")
+                .and_then(|root| root.child())
+                .map(|s| s.headline()),
+            Some("Edited".into())
+        );
+        assert!(col.reload_file("New.otl").unwrap());
+        assert!(col.root("New.otl").is_some());
+        assert!(col.reload_file("other.otl").unwrap());
+        assert!(col.root("other.otl").is_none());
+
+        // Everything is up to date now.
+        assert!(col.stale_files().is_empty());
+        assert!(!col.reload_file("Inbox.otl").unwrap());
+    }
+
+    #[test]
+    fn test_new_file_style() {
+        let storage = Arc::new(MemoryStorage::new([
+            ("a.otl", "A\n  B\n"),
+            ("b.otl", "A\n  B\n"),
+            ("c.otl", "A\n\tB\n"),
+        ]));
+        let col = Collection::in_memory(&storage, |_| true);
+        assert_eq!(col.new_file_style(), Indentation::Spaces(2));
+
+        // Deferred files aren't loaded just to count their styles.
+        let col = Collection::in_memory(&storage, |p| p == "c");
+        assert_eq!(col.new_file_style(), Indentation::Tabs);
+    }
+
+    #[test]
+    fn test_set_style() {
+        let storage = storage();
+        let mut col = Collection::in_memory(&storage, |_| true);
+        assert!(col.set_style("Missing.otl", Indentation::Tabs).is_err());
+
+        // Setting the style a file already has changes nothing.
+        col.set_style("Inbox.otl", Indentation::Tabs).unwrap();
+        col.save().unwrap();
+        assert!(col.saved_paths().is_empty());
+
+        col.set_style("Inbox.otl", Indentation::Spaces(2)).unwrap();
+        col.save().unwrap();
+        assert_eq!(col.saved_paths(), &[PathBuf::from("Inbox.otl")]);
+        assert_eq!(
+            storage.read(Path::new("Inbox.otl")).unwrap(),
+            "Note\n  Detail\n"
+        );
+    }
+
+    /// Load a collection with the `fidelity` setting on.
+    fn with_fidelity(storage: &Arc<MemoryStorage>) -> Collection {
+        let root_path = std::env::temp_dir().join("otlbook-test");
+        Collection::open(root_path, storage.clone(), None, true, |_| true)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_locked_file() {
+        let storage = storage();
+        storage.write(Path::new("other.otl"), "Other  \n").unwrap();
+        let mut col = with_fidelity(&storage);
+        assert!(col.is_locked("other.otl"));
+        assert!(!col.is_locked("Inbox.otl"));
+        assert!(
+            !Collection::in_memory(&storage, |_| true).is_locked("other.otl")
+        );
+
+        // An unchanged locked file doesn't keep others from being saved.
+        let inbox = col.root("Inbox.otl").unwrap();
+        inbox.append(Section::new("First".into(), Default::default()));
+        col.save().unwrap();
+        assert_eq!(col.saved_paths(), &[PathBuf::from("Inbox.otl")]);
+
+        // Nothing is written if a locked file was changed.
+        inbox.append(Section::new("Second".into(), Default::default()));
+        col.root("other.otl").unwrap().taint();
+        let err = col.save().unwrap_err().to_string();
+        assert!(err.contains("doesn't reserialize cleanly"), "{}", err);
+        assert!(col.saved_paths().is_empty());
+        assert_eq!(
+            storage.read(Path::new("Inbox.otl")).unwrap(),
+            "Note\n\tDetail\nFirst\n"
+        );
+        assert_eq!(storage.read(Path::new("other.otl")).unwrap(), "Other  \n");
+
+        // Normalizing a file unlocks it.
+        col.allow_rewrite("other.otl").unwrap();
+        assert!(!col.is_locked("other.otl"));
+        col.save().unwrap();
+        assert_eq!(storage.read(Path::new("other.otl")).unwrap(), "Other\n");
+    }
+
+    #[test]
+    fn test_lint_fix_unlocks() {
+        let storage = storage();
+        storage.write(Path::new("other.otl"), "Other  \n").unwrap();
+        let mut col = with_fidelity(&storage);
+
+        // What olt lint --fix does.
+        let problems = crate::lint::lint(&col);
+        for p in &problems {
+            if p.fix == Some(crate::lint::Fix::Rewrite) {
+                col.allow_rewrite(&p.path).unwrap();
+            }
+        }
+        assert!(crate::lint::fix(&problems) > 0);
+        col.save().unwrap();
+        assert_eq!(storage.read(Path::new("other.otl")).unwrap(), "Other\n");
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(
+            diff("A\n\tB\nC\n\t\tD\nE\n", "A\n\tB\nC\n\tD\nE\n"),
+            "@@ line 4\n-\t\tD\n+\tD\n"
+        );
+        assert_eq!(diff("A\n", "A\nB\n"), "@@ line 2\n+B\n");
+    }
+}
@@ -0,0 +1,219 @@
+//! User settings shared by all collections.
+//!
+//! Read from `config.idm` in the otlbook directory under the user's config
+//! directory, eg. `~/.config/otlbook/config.idm`. Every setting is optional:
+//!
+//! ```notrust
+//! path ~/notes
+//! indentation tabs
+//! inbox InBox
+//! journal Journal
+//! http-timeout 10
+//! host-interval 1
+//! wayback-interval 5
+//! webserver-port 8080
+//! anki-deck Japanese
+//! telegram-token 123456:ABC-DEF
+//! telegram-users 11111111
+//! matrix-homeserver https://matrix.example.org
+//! matrix-token syt_abc
+//! matrix-users @me:example.org
+//! storage files
+//! extra-attributes rss-title isbn
+//! fidelity true
+//! ```
+//!
+//! `path` is the collection loaded when `OTLBOOK_PATH` isn't set.
+//! `indentation` is `tabs` or a number of spaces and is used for new files
+//! and files whose indentation can't be inferred. Without it new files use
+//! the style most of the collection uses and other files use tabs.
+//! `inbox` is where inserted
+//! items go when no route matches them. `journal` is where `olt journal`
+//! keeps daily entries. The timeouts and intervals are in seconds.
+//! `anki-deck` is used when the collection's Anki settings don't name a
+//! deck. The `telegram-` and `matrix-` settings are for `olt bot`,
+//! which only takes messages from the listed user IDs. `storage` is
+//! `files`, `mirror` or `sqlite`, see the `storage` module.
+//! `extra-attributes` lists attribute names of your own that `olt lint`
+//! shouldn't report as unknown. With `fidelity` set, files that saving
+//! would write differently from how they are stored aren't rewritten until
+//! you run `olt normalize` on them.
+//!
+//! Settings specific to one collection are in the collection's own config
+//! directory, see the `xdg` module.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+    time::Duration,
+};
+
+use idm::ser::Indentation;
+use serde::Deserialize;
+use serde_with::DeserializeFromStr;
+
+use crate::{storage::StorageKind, Result};
+
+/// Name of the settings file in the otlbook config directory.
+pub const CONFIG_FILE: &str = "config.idm";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// Collection to load when `OTLBOOK_PATH` isn't set.
+    pub path: Option<PathBuf>,
+    /// Indentation for new files, the collection's most common style if
+    /// not set.
+    pub indentation: Option<Indent>,
+    /// Where inserted items go if no route matches them.
+    pub inbox: String,
+    /// Where `olt journal` keeps daily entries.
+    pub journal: String,
+    /// Seconds to wait for a web page.
+    pub http_timeout: u64,
+    /// Seconds to wait between requests to the same host.
+    pub host_interval: u64,
+    /// Seconds to wait between Wayback Machine save requests.
+    pub wayback_interval: u64,
+    pub webserver_port: u32,
+    /// Anki deck for collections that don't set one.
+    pub anki_deck: Option<String>,
+    /// Telegram bot API token.
+    pub telegram_token: Option<String>,
+    /// Telegram user IDs the bot takes messages from.
+    pub telegram_users: Vec<String>,
+    /// Matrix homeserver URL of the bot account.
+    pub matrix_homeserver: Option<String>,
+    /// Matrix access token of the bot account.
+    pub matrix_token: Option<String>,
+    /// Matrix user IDs the bot takes messages from.
+    pub matrix_users: Vec<String>,
+    /// Where collections are kept.
+    pub storage: StorageKind,
+    /// Attribute names `olt lint` accepts in addition to the known ones.
+    pub extra_attributes: Vec<String>,
+    /// Refuse to rewrite files that don't reserialize to their stored text.
+    pub fidelity: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            path: None,
+            indentation: None,
+            inbox: "InBox".into(),
+            journal: "Journal".into(),
+            http_timeout: 10,
+            host_interval: 1,
+            wayback_interval: 5,
+            webserver_port: 8080,
+            anki_deck: None,
+            telegram_token: None,
+            telegram_users: Vec::new(),
+            matrix_homeserver: None,
+            matrix_token: None,
+            matrix_users: Vec::new(),
+            storage: StorageKind::Files,
+            extra_attributes: Vec::new(),
+            fidelity: false,
+        }
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+impl Config {
+    /// Path of the settings file.
+    pub fn file_path() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join("otlbook").join(CONFIG_FILE))
+    }
+
+    /// Read the settings file, return default settings if there isn't
+    /// one.
+    pub fn load() -> Result<Config> {
+        let Some(path) = Config::file_path().filter(|p| p.exists()) else {
+            return Ok(Default::default());
+        };
+        let text = fs::read_to_string(&path)?;
+        let mut config: Config = idm::from_str(&text)
+            .map_err(|e| format!("Bad settings in {:?}: {}", path, e))?;
+        config.path = config.path.map(|p| expand_home(&p));
+        Ok(config)
+    }
+
+    /// Return the settings, loading them on first use.
+    ///
+    /// A broken settings file is logged and default settings are used
+    /// instead, call `load` at startup to report it properly.
+    pub fn get() -> &'static Config {
+        CONFIG.get_or_init(|| {
+            Config::load().unwrap_or_else(|e| {
+                log::error!("{}", e);
+                Default::default()
+            })
+        })
+    }
+
+    /// Indentation for files whose style can't be inferred.
+    pub fn default_indentation(&self) -> Indentation {
+        self.indentation.map_or(Indentation::Tabs, |i| i.0)
+    }
+
+    pub fn http_timeout(&self) -> Duration {
+        Duration::from_secs(self.http_timeout)
+    }
+
+    pub fn host_interval(&self) -> Duration {
+        Duration::from_secs(self.host_interval)
+    }
+
+    pub fn wayback_interval(&self) -> Duration {
+        Duration::from_secs(self.wayback_interval)
+    }
+}
+
+/// Replace a leading `~` with the home directory.
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Indentation style written as `tabs`, a number of spaces or `spaces:N`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, DeserializeFromStr)]
+pub struct Indent(pub Indentation);
+
+impl FromStr for Indent {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            "tabs" => Ok(Indent(Indentation::Tabs)),
+            n => match n.strip_prefix("spaces:").unwrap_or(n).parse() {
+                Ok(n) if n > 0 => Ok(Indent(Indentation::Spaces(n))),
+                _ => Err(format!(
+                    "Bad indentation {:?}, must be tabs or spaces:N",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indent() {
+        assert_eq!("tabs".parse(), Ok(Indent(Indentation::Tabs)));
+        assert_eq!("spaces:2".parse(), Ok(Indent(Indentation::Spaces(2))));
+        assert_eq!(" 4 ".parse(), Ok(Indent(Indentation::Spaces(4))));
+        assert!("spaces:0".parse::<Indent>().is_err());
+        assert!("0".parse::<Indent>().is_err());
+        assert!("spaces".parse::<Indent>().is_err());
+    }
+}
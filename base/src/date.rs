@@ -35,7 +35,12 @@ use VagueDate::*;
 
 impl VagueDate {
     pub fn from_timestamp(seconds_since_epoch: i64) -> VagueDate {
-        DateTime(FixedOffset::east(0).timestamp(seconds_since_epoch, 0))
+        DateTime(
+            FixedOffset::east_opt(0)
+                .unwrap()
+                .timestamp_opt(seconds_since_epoch, 0)
+                .unwrap(),
+        )
     }
 
     pub fn now() -> VagueDate {
@@ -44,20 +49,58 @@ impl VagueDate {
         let local = chrono::offset::Local::now();
         let tz = local.offset().fix();
 
-        DateTime(DateTime::<FixedOffset>::from_utc(local.naive_utc(), tz))
+        DateTime(tz.from_utc_datetime(&local.naive_utc()))
+    }
+
+    /// Return the year of the date, available at every level of precision.
+    pub fn year(&self) -> i32 {
+        match self {
+            Year(y) => *y,
+            YearMonth(y, _) => *y,
+            Date(d) => d.year(),
+            DateTime(dt) => dt.year(),
+        }
+    }
+
+    /// Return the first day covered by the date.
+    pub fn first_day(&self) -> NaiveDate {
+        match self {
+            Year(y) => NaiveDate::from_ymd_opt(*y, 1, 1).unwrap(),
+            YearMonth(y, m) => NaiveDate::from_ymd_opt(*y, *m, 1).unwrap(),
+            Date(d) => *d,
+            DateTime(dt) => dt.date_naive(),
+        }
+    }
+
+    /// Return the last day covered by the date.
+    pub fn last_day(&self) -> NaiveDate {
+        match self {
+            Year(y) => NaiveDate::from_ymd_opt(*y, 12, 31).unwrap(),
+            YearMonth(y, 12) => NaiveDate::from_ymd_opt(*y, 12, 31).unwrap(),
+            YearMonth(y, m) => NaiveDate::from_ymd_opt(*y, m + 1, 1)
+                .unwrap()
+                .pred_opt()
+                .unwrap(),
+            Date(d) => *d,
+            DateTime(dt) => dt.date_naive(),
+        }
     }
 
     /// Reduce precision to the level of the other date.
     ///
     /// Ie if the other date is YearMonth, 2006-01-02 becomes 2006-01.
     fn reduce_precision_to(&self, other: &VagueDate) -> VagueDate {
+        if other.precision() >= self.precision() {
+            return *self;
+        }
+
         // Hack: Use the string representation and the fixed lenghts of the less precise types to
         // do this.
         match other {
             DateTime(_) => *self,
-            Date(_) => (&format!("{}", self)[..10]).parse().unwrap(),
-            YearMonth(_, _) => (&format!("{}", self)[..7]).parse().unwrap(),
-            Year(_) => (&format!("{}", self)[..4]).parse().unwrap(),
+            Date(_) => format!("{}", self)[..10].parse().unwrap(),
+            YearMonth(_, _) => format!("{}", self)[..7].parse().unwrap(),
+            Year(_) => format!("{}", self)[..4].parse().unwrap(),
         }
     }
 
@@ -130,7 +173,7 @@ impl fmt::Display for VagueDate {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Year(y) => write!(f, "{}", y),
-            YearMonth(y, m) => write!(f, "{}-{}", y, m),
+            YearMonth(y, m) => write!(f, "{}-{:02}", y, m),
             Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
             DateTime(date_time) => {
                 write!(f, "{}", date_time.format("%Y-%m-%dT%H:%M:%S%z"))
@@ -147,9 +190,10 @@ mod tests {
 
     fn example_date() -> VagueDate {
         VagueDate::DateTime(
-            FixedOffset::west(7 * 3600)
-                .ymd(2006, 1, 2)
-                .and_hms(15, 4, 5),
+            FixedOffset::west_opt(7 * 3600)
+                .unwrap()
+                .with_ymd_and_hms(2006, 1, 2, 15, 4, 5)
+                .unwrap(),
         )
     }
 
@@ -162,12 +206,39 @@ mod tests {
         assert_eq!(EXAMPLE_DATE_STR.parse(), Ok(example_date()));
         assert_eq!(
             "2006-01-02".parse(),
-            Ok(Date(chrono::naive::NaiveDate::from_ymd(2006, 1, 2)))
+            Ok(Date(
+                chrono::naive::NaiveDate::from_ymd_opt(2006, 1, 2).unwrap()
+            ))
         );
         assert_eq!("2006-01".parse(), Ok(YearMonth(2006, 1)));
         assert_eq!("2006".parse(), Ok(Year(2006)));
     }
 
+    #[test]
+    fn test_ordering() {
+        let date = |s: &str| s.parse::<VagueDate>().unwrap();
+
+        assert!(date("2006") < date("2007"));
+        assert!(date("2006") < date("2006-01"));
+        assert!(date("2006-01") < date("2006-01-02"));
+        assert!(date("2006-01-02") < date(EXAMPLE_DATE_STR));
+        assert!(date("2006-02") > date("2006-01-31"));
+        assert!(date("2005-12-31") < date("2006"));
+        assert_eq!(date("2006-01").to_string(), "2006-01");
+    }
+
+    #[test]
+    fn test_day_range() {
+        let date = |s: &str| s.parse::<VagueDate>().unwrap();
+        let day = |s: &str| s.parse::<chrono::NaiveDate>().unwrap();
+
+        assert_eq!(date("2006").first_day(), day("2006-01-01"));
+        assert_eq!(date("2006").last_day(), day("2006-12-31"));
+        assert_eq!(date("2008-02").last_day(), day("2008-02-29"));
+        assert_eq!(date("2006-12").last_day(), day("2006-12-31"));
+        assert_eq!(date(EXAMPLE_DATE_STR).first_day(), day("2006-01-02"));
+    }
+
     #[test]
     fn test_serialization() {
         let example_date = example_date();
@@ -0,0 +1,183 @@
+//! Errors that point to where in the notebook something went wrong.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Failure to read an outline file or an attribute value.
+///
+/// Displayed like compiler errors, `notes/Books.otl:12:3: message`, so
+/// editors can jump to the place.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct ParseError {
+    /// Collection file the error is in, if known.
+    pub path: Option<PathBuf>,
+    /// Line number, starting from 1.
+    pub line: Option<usize>,
+    /// Column number, starting from 1.
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(message: impl Into<String>) -> ParseError {
+        ParseError {
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Convert an error from parsing the text of the file at path.
+    ///
+    /// The line numbers IDM reports are sometimes relative to a nested
+    /// block, so the problem is looked up in the text when the kind of
+    /// error allows it.
+    pub fn from_idm(path: &Path, text: &str, err: idm::Error) -> ParseError {
+        let mut message = err.to_string();
+        if let Some(n) = err.line_num() {
+            if let Some(rest) = message.strip_prefix(&format!("line {}: ", n)) {
+                message = rest.to_string();
+            }
+        }
+
+        let lines: Vec<&str> = text.lines().collect();
+        let (line, column, message) = if message.ends_with('\n') {
+            // Unexpected indentation errors are the offending line.
+            let bad = message.trim_end_matches('\n');
+            match lines.iter().position(|s| *s == bad) {
+                Some(i) => (
+                    Some(i),
+                    Some(indentation_error(&lines, i)),
+                    "Unexpected indentation".to_string(),
+                ),
+                None => (
+                    err.line_num().map(|n| n - 1),
+                    None,
+                    "Unexpected indentation".to_string(),
+                ),
+            }
+        } else if message == "Inconsistent indentation" {
+            match (0..lines.len())
+                .map(|i| (i, indentation_error(&lines, i)))
+                .find(|&(i, col)| col <= indent_len(lines[i]))
+            {
+                Some((i, col)) => (Some(i), Some(col), message),
+                None => (err.line_num().map(|n| n - 1), None, message),
+            }
+        } else if message == "Out of input" {
+            match (0..lines.len()).find(|&i| is_empty_attribute(&lines, i)) {
+                Some(i) => (
+                    Some(i),
+                    Some(indent_len(lines[i]) + 1),
+                    "Attribute without a value".to_string(),
+                ),
+                None => (None, None, message),
+            }
+        } else {
+            (err.line_num().map(|n| n - 1), None, message)
+        };
+
+        ParseError {
+            path: Some(path.to_owned()),
+            line: line.map(|i| i + 1),
+            column,
+            message,
+        }
+    }
+}
+
+fn indent_len(line: &str) -> usize {
+    line.chars().take_while(|c| c.is_whitespace()).count()
+}
+
+/// Return the column of the first indentation character on a line that
+/// doesn't match the file's style, or the start of the content if all do.
+///
+/// The style is set by the first indented line.
+fn indentation_error(lines: &[&str], i: usize) -> usize {
+    let style = lines
+        .iter()
+        .find_map(|s| s.chars().next().filter(|c| *c == '\t' || *c == ' '));
+    let indent: Vec<char> =
+        lines[i].chars().take_while(|c| c.is_whitespace()).collect();
+    match indent.iter().position(|c| Some(*c) != style) {
+        Some(pos) => pos + 1,
+        None => indent.len() + 1,
+    }
+}
+
+/// Return whether line i is an attribute name with no value after it or
+/// indented under it.
+fn is_empty_attribute(lines: &[&str], i: usize) -> bool {
+    let content = lines[i].trim();
+    let is_name = content.len() > 1
+        && content.starts_with(':')
+        && !content.contains(' ')
+        && content[1..]
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-');
+    is_name
+        && lines
+            .get(i + 1)
+            .is_none_or(|next| indent_len(next) <= indent_len(lines[i]))
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.to_string_lossy())?;
+            if let Some(line) = self.line {
+                write!(f, "{}:", line)?;
+                if let Some(column) = self.column {
+                    write!(f, "{}:", column)?;
+                }
+            }
+            write!(f, " ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collection::parse_outline;
+
+    #[test]
+    fn test_parse_error() {
+        let path = Path::new("notes/Books.otl");
+        let err = |text| {
+            let Err(e) = parse_outline(path, text) else {
+                panic!("Bad outline parsed");
+            };
+            let e = e.downcast_ref::<ParseError>().unwrap().clone();
+            assert_eq!(e.path.as_deref(), Some(path));
+            (e.line, e.column, e.message)
+        };
+
+        assert_eq!(
+            err("A\n\tB\n\t  C\n"),
+            (Some(3), Some(2), "Unexpected indentation".into())
+        );
+        assert_eq!(
+            err("A\n\tB\n  C\n"),
+            (Some(3), Some(1), "Inconsistent indentation".into())
+        );
+        assert_eq!(
+            err("A\n\t:rating\nB\n"),
+            (Some(2), Some(2), "Attribute without a value".into())
+        );
+
+        let e = ParseError {
+            path: Some(path.into()),
+            line: Some(3),
+            column: Some(2),
+            message: "Bad".into(),
+        };
+        assert_eq!(e.to_string(), "notes/Books.otl:3:2: Bad");
+        assert_eq!(ParseError::new("Bad").to_string(), "Bad");
+    }
+}
@@ -0,0 +1,148 @@
+//! Ledger-style expense lines.
+//!
+//! Expenses are written as headlines like `2024-05-01 groceries 23.50 EUR`,
+//! with an optional free-form description after the currency, in sections
+//! tagged `expenses`.
+
+use std::{fmt, str::FromStr};
+
+use crate::{Collection, Section, VagueDate};
+
+/// Tag that marks a section as containing expense lines.
+pub const EXPENSES_TAG: &str = "expenses";
+
+/// Money amount in hundredths of the currency unit.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct Amount(pub i64);
+
+impl FromStr for Amount {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sign, s) = match s.strip_prefix('-') {
+            Some(s) => (-1, s),
+            None => (1, s),
+        };
+        let (whole, frac) = s.split_once('.').unwrap_or((s, ""));
+        if whole.is_empty()
+            || frac.len() > 2
+            || !whole
+                .chars()
+                .chain(frac.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(());
+        }
+        let whole: i64 = whole.parse().map_err(|_| ())?;
+        let frac: i64 = format!("{:0<2}", frac).parse().map_err(|_| ())?;
+        whole
+            .checked_mul(100)
+            .and_then(|n| n.checked_add(frac))
+            .map(|n| Amount(sign * n))
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let s =
+            format!("{}{}.{:02}", sign, self.0.abs() / 100, self.0.abs() % 100);
+        f.pad(&s)
+    }
+}
+
+impl std::ops::AddAssign for Amount {
+    fn add_assign(&mut self, rhs: Amount) {
+        self.0 += rhs.0;
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Expense {
+    pub date: VagueDate,
+    pub category: String,
+    pub amount: Amount,
+    pub currency: String,
+    pub description: String,
+}
+
+impl FromStr for Expense {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let date = words.next().ok_or(())?.parse().map_err(|_| ())?;
+        let category = words.next().ok_or(())?.to_string();
+        let amount = words.next().ok_or(())?.parse()?;
+        let currency = words.next().ok_or(())?.to_string();
+        if !currency.chars().all(|c| c.is_alphabetic()) {
+            return Err(());
+        }
+        let description = words.collect::<Vec<_>>().join(" ");
+
+        Ok(Expense {
+            date,
+            category,
+            amount,
+            currency,
+            description,
+        })
+    }
+}
+
+impl Expense {
+    /// Year and month of the expense as `YYYY-MM`.
+    pub fn month(&self) -> String {
+        self.date.first_day().format("%Y-%m").to_string()
+    }
+}
+
+/// Return whether the section is an expense section or inside one.
+pub fn is_expense_section(section: &Section) -> bool {
+    section.tags().contains(EXPENSES_TAG)
+}
+
+/// Collect all expense lines in the collection, ordered by date.
+pub fn expenses(collection: &Collection) -> Vec<Expense> {
+    let mut ret: Vec<Expense> = collection
+        .iter()
+        .filter(is_expense_section)
+        .filter_map(|s| s.headline().parse().ok())
+        .collect();
+    ret.sort_by_key(|e| e.date);
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("23.50".parse(), Ok(Amount(2350)));
+        assert_eq!("23.5".parse(), Ok(Amount(2350)));
+        assert_eq!("-4".parse(), Ok(Amount(-400)));
+        assert!("1.234".parse::<Amount>().is_err());
+        assert!("x".parse::<Amount>().is_err());
+        assert!("92233720368547759".parse::<Amount>().is_err());
+        assert!("-92233720368547758.08".parse::<Amount>().is_err());
+        assert_eq!(
+            "92233720368547758.07".parse(),
+            Ok(Amount(9223372036854775807))
+        );
+        assert_eq!(Amount(-1205).to_string(), "-12.05");
+
+        let e: Expense = "2024-05-01 groceries 23.50 EUR milk and bread"
+            .parse()
+            .unwrap();
+        assert_eq!(e.category, "groceries");
+        assert_eq!(e.amount, Amount(2350));
+        assert_eq!(e.currency, "EUR");
+        assert_eq!(e.description, "milk and bread");
+        assert_eq!(e.month(), "2024-05");
+
+        assert!("Groceries for May".parse::<Expense>().is_err());
+        assert!("2024-05-01 rent 800 €".parse::<Expense>().is_err());
+    }
+}
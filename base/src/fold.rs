@@ -0,0 +1,66 @@
+//! Remembered fold state of articles in outline viewers.
+//!
+//! Stored in `folds.json` in the collection's state directory. Folded
+//! sections are identified by their headline path relative to the viewed
+//! article, and the state is kept separately for each viewer session.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{xdg, Result};
+
+/// Name of the fold state file in the state directory.
+pub const FOLDS_FILE: &str = "folds.json";
+
+/// Folded section paths by session and article.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct FoldState(BTreeMap<String, BTreeMap<String, BTreeSet<String>>>);
+
+impl FoldState {
+    /// Load the fold state of a collection, start with nothing folded if
+    /// there's no state file or it can't be read.
+    pub fn load(root: impl AsRef<Path>) -> FoldState {
+        fs::read_to_string(xdg::path(root, xdg::Dir::State, FOLDS_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, root: impl AsRef<Path>) -> Result<()> {
+        let path = xdg::path(root, xdg::Dir::State, FOLDS_FILE);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Return the folded section paths of an article.
+    pub fn folded(&self, session: &str, article: &str) -> BTreeSet<String> {
+        self.0
+            .get(session)
+            .and_then(|s| s.get(article))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Set the folded section paths of an article.
+    pub fn set(
+        &mut self,
+        session: &str,
+        article: &str,
+        folded: BTreeSet<String>,
+    ) {
+        let articles = self.0.entry(session.to_string()).or_default();
+        if folded.is_empty() {
+            articles.remove(article);
+        } else {
+            articles.insert(article.to_string(), folded);
+        }
+    }
+}
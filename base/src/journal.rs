@@ -0,0 +1,268 @@
+//! Journal of edits that haven't been saved yet.
+//!
+//! Servers and bots write each edit to `journal.log` in the collection's
+//! state directory as a batch command before making it, and clear the
+//! journal once the collection is saved. If the process dies in between,
+//! the next one to start replays the journal so the edits aren't lost.
+//!
+//! A replaced section is written to the journal whole, so a replace is
+//! only replayed if the file it is in hasn't been written since it was
+//! journaled. Otherwise the replace would undo edits made after the
+//! crash, or the replace was already saved.
+//!
+//! Replaying an edit that did get saved before the crash is harmless for
+//! most commands, attribute changes end up the same and inserts skip
+//! existing entities. Inserted sections without an entity, like plain
+//! notes, are skipped if the same section is already where the insert
+//! puts it. Commands that fail on replay are logged and dropped.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    batch::{self, Command},
+    xdg, Collection, Result, Section,
+};
+
+/// Name of the journal file in the state directory.
+pub const JOURNAL_FILE: &str = "journal.log";
+
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn new(root: impl AsRef<Path>) -> Journal {
+        Journal {
+            path: xdg::path(root, xdg::Dir::State, JOURNAL_FILE),
+        }
+    }
+
+    /// Write a command to disk before it's applied.
+    pub fn record(&self, command: &Command) -> Result<()> {
+        self.record_lines([serde_json::to_string(command)?.as_str()])
+    }
+
+    /// Write lines of a command batch to disk before they are applied.
+    pub fn record_lines<'a>(
+        &self,
+        lines: impl IntoIterator<Item = &'a str>,
+    ) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        for line in lines.into_iter().filter(|s| !s.trim().is_empty()) {
+            writeln!(file, "{}", line.trim())?;
+        }
+        // Make sure the edit is on disk before the collection is touched.
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Forget the recorded commands after they've been saved or reverted.
+    pub fn clear(&self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Apply commands left in the journal by a process that didn't get to
+    /// save them.
+    ///
+    /// The caller saves the collection and then clears the journal. Return
+    /// the number of changes recovered.
+    pub fn recover(&self, col: &mut Collection) -> Result<usize> {
+        if !self.path.exists() {
+            return Ok(0);
+        }
+        let text = fs::read_to_string(&self.path)?;
+        let journaled = fs::metadata(&self.path)?.modified()?;
+        replay(col, &text, journaled)
+    }
+}
+
+/// Apply journal text that was last written at `journaled`.
+fn replay(
+    col: &mut Collection,
+    text: &str,
+    journaled: SystemTime,
+) -> Result<usize> {
+    let lines: Vec<String> = text
+        .lines()
+        .filter(|line| !is_stale_replace(col, line, journaled))
+        .filter_map(|line| unapplied(col, line))
+        .collect();
+    let report = batch::apply(col, lines.iter().map(|s| s.as_str()))?;
+    for result in report.results.iter().filter(|r| r["ok"] != true) {
+        log::warn!("Dropped unsaved edit: {}", result["error"]);
+    }
+    Ok(report.changes)
+}
+
+/// Return whether a journal line replaces a section in a file that was
+/// written after the journal.
+fn is_stale_replace(
+    col: &Collection,
+    line: &str,
+    journaled: SystemTime,
+) -> bool {
+    let Ok(Command::Replace { path, .. }) = serde_json::from_str(line) else {
+        return false;
+    };
+    let Some(section) = col.find(&path) else {
+        return false;
+    };
+    let is_stale = col
+        .file_path(&section)
+        .and_then(|file| col.modified(file))
+        .is_some_and(|modified| modified > journaled);
+    if is_stale {
+        log::warn!("Dropped unsaved replace of {:?}, file changed since", path);
+    }
+    is_stale
+}
+
+/// Return a journal line with inserts that are already in the collection
+/// left out, or `None` if nothing is left.
+///
+/// Lines that can't be read are returned as they are for `batch::apply`
+/// to report.
+fn unapplied(col: &Collection, line: &str) -> Option<String> {
+    let Ok(Command::Insert {
+        under: Some(under),
+        idm,
+    }) = serde_json::from_str(line)
+    else {
+        return Some(line.to_string());
+    };
+    let (Ok(items), Some(parent)) =
+        (idm::from_str::<Vec<Section>>(&idm), col.find(&under))
+    else {
+        return Some(line.to_string());
+    };
+
+    let existing: Vec<String> = parent
+        .children()
+        .filter_map(|c| idm::to_string(&c).ok())
+        .collect();
+    let count = items.len();
+    let items: Vec<Section> = items
+        .into_iter()
+        .filter(|item| {
+            idm::to_string(item).map_or(true, |text| !existing.contains(&text))
+        })
+        .collect();
+    if items.is_empty() {
+        return None;
+    } else if items.len() == count {
+        return Some(line.to_string());
+    }
+    let command = Command::Insert {
+        under: Some(under),
+        idm: idm::to_string(&items).ok()?,
+    };
+    serde_json::to_string(&command).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn collection() -> Collection {
+        let storage = Arc::new(MemoryStorage::new([
+            ("InBox.otl", "Note\n\t:added 2024-01-01\n"),
+            ("Notes.otl", "Draft\n\tText\n"),
+        ]));
+        Collection::in_memory(&storage, |_| true)
+    }
+
+    fn headlines(section: Section) -> Vec<String> {
+        section.children().map(|c| c.headline()).collect()
+    }
+
+    #[test]
+    fn test_recover() {
+        let journal = Journal {
+            path: std::env::temp_dir()
+                .join(format!("otlbook-journal-{}.log", std::process::id())),
+        };
+        let mut col = collection();
+        journal.clear().unwrap();
+        assert_eq!(journal.recover(&mut col).unwrap(), 0);
+
+        journal
+            .record(&Command::Insert {
+                under: Some("InBox".into()),
+                idm: "New\n".into(),
+            })
+            .unwrap();
+        journal
+            .record_lines([
+                "",
+                r#"{"op": "replace", "path": "Notes/Draft", "idm": "Final\n"}"#,
+                r#"{"op": "delete", "path": "Notes/Missing"}"#,
+            ])
+            .unwrap();
+        assert_eq!(journal.recover(&mut col).unwrap(), 2);
+        assert_eq!(headlines(col.find("InBox").unwrap()), vec!["Note", "New"]);
+        assert_eq!(headlines(col.find("Notes").unwrap()), vec!["Final"]);
+
+        // The caller saves and clears.
+        assert!(journal.path.exists());
+        journal.clear().unwrap();
+        assert!(!journal.path.exists());
+        assert_eq!(journal.recover(&mut col).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_stale_replace() {
+        let mut col = collection();
+        let line =
+            r#"{"op": "replace", "path": "Notes/Draft", "idm": "Final\n"}"#;
+        // Memory storage files are written at whole seconds from the epoch,
+        // Notes.otl at the second one.
+        let at = |secs| {
+            SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs)
+        };
+
+        assert_eq!(replay(&mut col, line, at(1)).unwrap(), 0);
+        assert_eq!(headlines(col.find("Notes").unwrap()), vec!["Draft"]);
+        assert_eq!(replay(&mut col, line, at(2)).unwrap(), 1);
+        assert_eq!(headlines(col.find("Notes").unwrap()), vec!["Final"]);
+    }
+
+    #[test]
+    fn test_unapplied() {
+        let col = collection();
+        let insert = |idm: &str| {
+            serde_json::to_string(&Command::Insert {
+                under: Some("InBox".into()),
+                idm: idm.into(),
+            })
+            .unwrap()
+        };
+
+        assert_eq!(
+            unapplied(&col, &insert("Note\n\t:added 2024-01-01\n")),
+            None
+        );
+        let line = insert("Note\n\t:added 2024-01-01\nOther\n");
+        assert_eq!(unapplied(&col, &line), Some(insert("Other\n")));
+        let line = insert("Other\n");
+        assert_eq!(unapplied(&col, &line), Some(line.clone()));
+        let line = insert("Note\n\t:added 2024-01-02\n");
+        assert_eq!(unapplied(&col, &line), Some(line.clone()));
+    }
+}
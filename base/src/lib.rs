@@ -1,21 +1,83 @@
+pub mod backup;
+
+pub mod batch;
+
+pub mod changes;
+
+pub mod check;
+
+mod config;
+pub use config::{Config, Indent};
+
 mod collection;
 pub use collection::Collection;
 
 mod date;
 pub use date::VagueDate;
 
+mod error;
+pub use error::ParseError;
+
+pub mod expense;
+
+pub mod fold;
+
+pub mod journal;
+
+pub mod lint;
+
+pub mod minhash;
+
 pub mod parse;
 
+pub mod quantity;
+
+pub mod publish;
+
+pub mod query;
+
+mod rating;
+pub use rating::{Rating, MAX_RATING};
+
+pub mod rename;
+
+pub mod route;
+
+pub mod search;
+
 mod section;
 pub use section::{EntityIdentifier, Section};
 
+pub mod shared;
+
+pub mod split;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub mod srs;
+
+pub mod storage;
+
 mod symbol;
 pub use symbol::{Sym, Uri};
 pub type Symbol = Sym<String>;
 
+pub mod task;
+
 pub type Result<T> =
     std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+pub mod token;
+
+pub mod trash;
+
 mod tree;
 
-mod typed_attribute;
+pub mod typed_attribute;
+
+pub mod vcs;
+
+pub mod view;
+
+pub mod xdg;
@@ -0,0 +1,407 @@
+//! Consistency checks for `olt lint`.
+//!
+//! Problems are reported with the file and, when it can be found, the line
+//! they are on:
+//!
+//! - WikiWords and uris defined more than once
+//! - attribute values that aren't valid for the attribute's type, like
+//!   malformed dates and tags that aren't plain words
+//! - attributes that otlbook doesn't know, names listed in the
+//!   `extra-attributes` setting are accepted
+//! - empty attribute values
+//! - `read` dates before `added` dates
+//! - files that would be written differently if saved
+//!
+//! Empty attributes, dates that parse but aren't in the normal form and
+//! files that aren't in the normal form can be fixed automatically.
+//! The trash isn't checked.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    trash,
+    typed_attribute::{Added, Read, TypedAttribute},
+    Collection, Config, Section, VagueDate,
+};
+
+/// Change that fixes a problem.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Fix {
+    RemoveAttr(String),
+    SetAttr(String, String),
+    /// Rewrite the file in the normal form.
+    Rewrite,
+}
+
+pub struct Problem {
+    /// Collection file the problem is in.
+    pub path: PathBuf,
+    /// Line number in the file, starting from 1.
+    pub line: Option<usize>,
+    /// Section with the problem, the file root for whole-file problems.
+    pub section: Section,
+    pub message: String,
+    pub fix: Option<Fix>,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "{}: ", line)?;
+        }
+        write!(f, "{}", self.message)?;
+        if self.fix.is_some() {
+            write!(f, " (fixable)")?;
+        }
+        Ok(())
+    }
+}
+
+/// A WikiWord or uri that more than one section defines.
+pub struct Duplicate {
+    /// `"WikiWord"` or `"uri"`.
+    pub kind: &'static str,
+    pub key: String,
+    /// Positions of the defining sections in the checked sections.
+    pub positions: Vec<usize>,
+}
+
+/// Find WikiWords and uris that more than one of the sections define.
+///
+/// WikiWords come first, then uris, both sorted. Alias stubs don't define
+/// anything.
+pub fn duplicates<'a>(
+    sections: impl IntoIterator<Item = &'a Section>,
+) -> Vec<Duplicate> {
+    let mut titles: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut uris: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, section) in sections.into_iter().enumerate() {
+        if section.is_alias() {
+            continue;
+        }
+        if let Some(title) = section.wiki_title() {
+            titles.entry(title).or_default().push(i);
+        }
+        if let Some(uri) = section.uri() {
+            uris.entry(uri).or_default().push(i);
+        }
+    }
+
+    let mut ret = Vec::new();
+    for (kind, map) in [("WikiWord", titles), ("uri", uris)] {
+        for (key, positions) in map {
+            if positions.len() > 1 {
+                ret.push(Duplicate {
+                    kind,
+                    key,
+                    positions,
+                });
+            }
+        }
+    }
+    ret
+}
+
+/// Check the whole collection.
+pub fn lint(collection: &Collection) -> Vec<Problem> {
+    let mut ret = Vec::new();
+    // Every checked section with where it is, for finding duplicates.
+    let mut checked: Vec<(PathBuf, Option<usize>, Section)> = Vec::new();
+
+    for (path, root) in collection.files() {
+        if trash::is_trash(path) {
+            continue;
+        }
+        let text = collection.file_text(path).unwrap_or_default();
+        let mut lines = Lines::new(&text);
+
+        match collection.is_normalized(path) {
+            Ok(true) => {}
+            Ok(false) => ret.push(Problem {
+                path: path.to_owned(),
+                line: None,
+                section: root.clone(),
+                message: "file is not in normal form".into(),
+                fix: Some(Fix::Rewrite),
+            }),
+            Err(e) => log::warn!("Failed to read {:?}: {}", path, e),
+        }
+
+        // Go in file order so headlines are found on the following lines.
+        for section in root.iter_preorder() {
+            // The root has no headline line, its attributes are at the top.
+            let line = if section.parent().is_none() {
+                Some(0)
+            } else {
+                lines.find_headline(&section.headline())
+            };
+            check_attributes(path, line, &lines, &section, &mut ret);
+            checked.push((path.to_owned(), line, section));
+        }
+    }
+
+    for dupe in duplicates(checked.iter().map(|(_, _, s)| s)) {
+        for &i in &dupe.positions {
+            let (path, line, section) = &checked[i];
+            ret.push(problem(
+                path,
+                *line,
+                section,
+                format!(
+                    "duplicate {} {}, defined {} times",
+                    dupe.kind,
+                    dupe.key,
+                    dupe.positions.len()
+                ),
+            ));
+        }
+    }
+    ret
+}
+
+fn check_attributes(
+    path: &Path,
+    line: Option<usize>,
+    lines: &Lines,
+    section: &Section,
+    output: &mut Vec<Problem>,
+) {
+    let attributes = section.borrow().attributes.clone();
+    let extra = &Config::get().extra_attributes;
+    for (name, value) in &attributes {
+        let line = line.and_then(|l| lines.find_attribute(l, name));
+        let mut push = |message: String, fix| {
+            let mut p = problem(path, line, section, message);
+            p.fix = fix;
+            output.push(p);
+        };
+
+        if value.trim().is_empty() {
+            push(
+                format!("empty :{} in {:?}", name, section.headline()),
+                Some(Fix::RemoveAttr(name.clone())),
+            );
+            continue;
+        }
+
+        match TypedAttribute::parse(name, value) {
+            Some(Ok(typed)) => {
+                // Dates like 2024-1-5 parse but aren't written that way.
+                if let Some(date) = typed.date() {
+                    let normal = date.to_string();
+                    if normal != value.trim() {
+                        push(
+                            format!(
+                                ":{} {} should be written as {}",
+                                name, value, normal
+                            ),
+                            Some(Fix::SetAttr(name.clone(), normal)),
+                        );
+                    }
+                }
+            }
+            Some(Err(e)) => {
+                let fix = fix_date(value).filter(|_| {
+                    TypedAttribute::parse(name, "2000")
+                        .is_some_and(|a| a.is_ok_and(|a| a.date().is_some()))
+                });
+                push(
+                    format!(":{} {} in {:?}", name, e, section.headline()),
+                    fix.map(|d| Fix::SetAttr(name.clone(), d.to_string())),
+                );
+            }
+            None if !TypedAttribute::is_known(name)
+                && !extra.iter().any(|n| n == name) =>
+            {
+                push(
+                    format!(
+                        "unknown attribute :{} in {:?}",
+                        name,
+                        section.headline()
+                    ),
+                    None,
+                );
+            }
+            None => {}
+        }
+    }
+
+    if let (Ok(Some(Read(read))), Ok(Some(Added(added)))) =
+        (section.typed_attr::<Read>(), section.typed_attr::<Added>())
+    {
+        // A vague read date like 2023 is fine if any day of it can be
+        // after the added date.
+        if read.last_day() < added.first_day() {
+            output.push(problem(
+                path,
+                line,
+                section,
+                format!(
+                    "read {} before added {} in {:?}",
+                    read,
+                    added,
+                    section.headline()
+                ),
+            ));
+        }
+    }
+}
+
+fn problem(
+    path: &Path,
+    line: Option<usize>,
+    section: &Section,
+    message: String,
+) -> Problem {
+    Problem {
+        path: path.to_owned(),
+        // Lines are counted from 1 in messages.
+        line: line.map(|l| l + 1),
+        section: section.clone(),
+        message,
+        fix: None,
+    }
+}
+
+/// Read dates written with other separators, like 2024/01/31.
+fn fix_date(value: &str) -> Option<VagueDate> {
+    value.trim().replace(['/', '.'], "-").parse().ok()
+}
+
+/// Apply the fixes of problems.
+///
+/// Return the number of problems fixed.
+pub fn fix(problems: &[Problem]) -> usize {
+    let mut count = 0;
+    for p in problems {
+        let mut section = p.section.clone();
+        match &p.fix {
+            Some(Fix::RemoveAttr(name)) => section.remove_attr(name),
+            Some(Fix::SetAttr(name, value)) => {
+                section.set_raw_attr(name, value)
+            }
+            Some(Fix::Rewrite) => section.taint(),
+            None => continue,
+        }
+        count += 1;
+    }
+    count
+}
+
+/// Line finder that moves forward through a file as sections are visited
+/// in order.
+struct Lines<'a> {
+    lines: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> Lines<'a> {
+    fn new(text: &'a str) -> Self {
+        Lines {
+            lines: text.lines().collect(),
+            pos: 0,
+        }
+    }
+
+    fn find_headline(&mut self, headline: &str) -> Option<usize> {
+        let i = (self.pos..self.lines.len())
+            .find(|&i| self.lines[i].trim() == headline.trim())?;
+        self.pos = i + 1;
+        Some(i)
+    }
+
+    /// Find an attribute line after a headline line.
+    fn find_attribute(&self, headline: usize, name: &str) -> Option<usize> {
+        let prefix = format!(":{}", name);
+        self.lines
+            .iter()
+            .enumerate()
+            .skip(headline)
+            .find(|(_, line)| {
+                let line = line.trim();
+                line.strip_prefix(&prefix).is_some_and(|rest| {
+                    rest.is_empty() || rest.starts_with(' ')
+                })
+            })
+            .map(|(i, _)| i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let text = "\
+:tags foo
+Item
+\t:added 2024-01-01
+\tItem
+\t\t:added 2024/1/2
+";
+        let mut lines = Lines::new(text);
+        assert_eq!(lines.find_attribute(0, "tags"), Some(0));
+        assert_eq!(lines.find_headline("Item"), Some(1));
+        assert_eq!(lines.find_attribute(1, "added"), Some(2));
+        assert_eq!(lines.find_headline("Item"), Some(3));
+        assert_eq!(lines.find_attribute(3, "added"), Some(4));
+        assert_eq!(lines.find_attribute(3, "add"), None);
+
+        assert_eq!(fix_date("2024/1/2").unwrap().to_string(), "2024-01-02");
+    }
+
+    #[test]
+    fn test_read_before_added() {
+        let check = |read: &str, added: &str| {
+            let section: Section = idm::from_str(&format!(
+                "Book\n\t:read {}\n\t:added {}\n",
+                read, added
+            ))
+            .unwrap();
+            let mut problems = Vec::new();
+            check_attributes(
+                Path::new("Books.otl"),
+                None,
+                &Lines::new(""),
+                &section,
+                &mut problems,
+            );
+            problems.len()
+        };
+        assert_eq!(check("2023", "2023-05-01"), 0);
+        assert_eq!(check("2023-05", "2023-05-31"), 0);
+        assert_eq!(check("2023-06-01", "2023-05-01"), 0);
+        assert_eq!(check("2023-04", "2023-05-01"), 1);
+        assert_eq!(check("2022", "2023"), 1);
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let sections: Vec<Section> = idm::from_str(
+            "\
+FooBar
+  :uri https://example.com/
+BazQux
+FooBar
+  :alias BazQux
+FooBar
+https://example.com/
+",
+        )
+        .unwrap();
+        let dupes = duplicates(&sections);
+        assert_eq!(dupes.len(), 2);
+        assert_eq!(
+            (dupes[0].kind, dupes[0].key.as_str()),
+            ("WikiWord", "FooBar")
+        );
+        assert_eq!(dupes[0].positions, vec![0, 3]);
+        assert_eq!(dupes[1].kind, "uri");
+        assert_eq!(dupes[1].positions, vec![0, 4]);
+    }
+}
@@ -0,0 +1,114 @@
+//! Near-duplicate text detection with MinHash.
+//!
+//! A text is reduced to a signature of minimum hash values over its words
+//! under different hash functions. The fraction of matching values between
+//! two signatures estimates the Jaccard similarity of the texts' word sets.
+
+use std::collections::{BTreeSet, HashMap};
+
+/// Number of hash functions in a signature.
+const SIGNATURE_LEN: usize = 64;
+
+/// Signature values per band when looking for candidate pairs.
+const BAND_ROWS: usize = 4;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Signature([u64; SIGNATURE_LEN]);
+
+impl Signature {
+    pub fn new(text: &str) -> Signature {
+        let words: BTreeSet<String> = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_lowercase())
+            .collect();
+
+        let mut ret = [u64::MAX; SIGNATURE_LEN];
+        for word in &words {
+            let h = fnv1a(word);
+            for (i, min) in ret.iter_mut().enumerate() {
+                *min = (*min).min(mix(h, i as u64));
+            }
+        }
+        Signature(ret)
+    }
+
+    /// Estimated Jaccard similarity of the word sets, between 0 and 1.
+    pub fn similarity(&self, other: &Signature) -> f64 {
+        let same = self.0.iter().zip(&other.0).filter(|(a, b)| a == b).count();
+        same as f64 / SIGNATURE_LEN as f64
+    }
+}
+
+/// Return index pairs of signatures with similarity at least `threshold`.
+///
+/// Only signatures that are identical in some band of values are compared.
+/// With the band size used, pairs above 0.5 similarity are very likely to
+/// share a band.
+pub fn near_duplicates(
+    signatures: &[Signature],
+    threshold: f64,
+) -> Vec<(usize, usize)> {
+    let mut ret = BTreeSet::new();
+
+    for band in 0..SIGNATURE_LEN / BAND_ROWS {
+        let rows = band * BAND_ROWS..(band + 1) * BAND_ROWS;
+        let mut buckets: HashMap<&[u64], Vec<usize>> = HashMap::new();
+        for (i, sig) in signatures.iter().enumerate() {
+            buckets.entry(&sig.0[rows.clone()]).or_default().push(i);
+        }
+
+        for bucket in buckets.values() {
+            for (n, &i) in bucket.iter().enumerate() {
+                for &j in &bucket[n + 1..] {
+                    if signatures[i].similarity(&signatures[j]) >= threshold {
+                        ret.insert((i, j));
+                    }
+                }
+            }
+        }
+    }
+
+    ret.into_iter().collect()
+}
+
+/// Stable 64-bit FNV-1a hash, std's hashers may change between releases.
+pub(crate) fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Derive the `i`th hash function's value from a base hash (SplitMix64).
+fn mix(h: u64, i: u64) -> u64 {
+    let mut z = h.wrapping_add(i.wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minhash() {
+        let a = Signature::new(
+            "Outliners are a good way to keep notes because the structure \
+             is flexible.",
+        );
+        let b = Signature::new(
+            "Outliners are a good way to keep notes, because structure is \
+             flexible.",
+        );
+        let c = Signature::new(
+            "Completely unrelated text about compilers, type systems and the \
+             borrow checker.",
+        );
+
+        assert_eq!(a, Signature::new("OUTLINERS are a good way to keep notes because the structure is flexible"));
+        assert!(a.similarity(&b) > 0.7);
+        assert!(a.similarity(&c) < 0.3);
+        assert_eq!(near_duplicates(&[a, c, b], 0.7), vec![(0, 2)]);
+    }
+}
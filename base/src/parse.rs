@@ -63,6 +63,152 @@ pub fn url(i: &str) -> IResult<&str, Url> {
     Ok((rest, url))
 }
 
+/// Recognize a `[[bracketed link]]`, return the link target.
+pub fn bracket_link(i: &str) -> IResult<&str, &str> {
+    let Some(rest) = i.strip_prefix("[[") else {
+        return Err(err(i));
+    };
+    let Some(end) = rest.find("]]") else {
+        return Err(err(i));
+    };
+    let target = rest[..end].trim();
+    if target.is_empty() || target.contains('\n') {
+        return Err(err(i));
+    }
+
+    Ok((&rest[end + 2..], target))
+}
+
+/// Find all WikiWords that occur as separate words in a line of text.
+pub fn wiki_words(i: &str) -> Vec<&str> {
+    let mut ret = Vec::new();
+    for word in i.split_whitespace() {
+        let word = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+        if let Ok((_, wiki_word)) = wiki_word(word) {
+            ret.push(wiki_word);
+        }
+    }
+    ret
+}
+
+/// Find the targets of all `[[bracketed links]]` in a line of text.
+pub fn bracket_links(mut i: &str) -> Vec<&str> {
+    let mut ret = Vec::new();
+    while let Some(pos) = i.find("[[") {
+        match bracket_link(&i[pos..]) {
+            Ok((rest, target)) => {
+                ret.push(target);
+                i = rest;
+            }
+            Err(_) => i = &i[pos + 2..],
+        }
+    }
+    ret
+}
+
+/// Split a line of text into plain and `{cloze deletion}` segments.
+///
+/// Segments are returned as (text, is-deletion) pairs. Returns an empty vector
+/// if the line has no cloze deletions. Braces that are unmatched, empty or
+/// padded with whitespace are treated as plain text, and so are `漢字{かんじ}`
+/// ruby annotations.
+pub fn cloze(i: &str) -> Vec<(&str, bool)> {
+    let mut ret = Vec::new();
+    let mut has_deletions = false;
+    let mut start = 0;
+    let mut pos = 0;
+    while let Some(open) = i[pos..].find('{').map(|p| p + pos) {
+        if let Some((_, end, _)) = ruby_at(i, open) {
+            pos = end;
+            continue;
+        }
+        pos = open + 1;
+        let Some(len) = i[pos..].find(['{', '}']) else {
+            break;
+        };
+        let span = &i[pos..pos + len];
+        if !i[pos + len..].starts_with('}')
+            || span.is_empty()
+            || span.trim() != span
+        {
+            continue;
+        }
+
+        if open > start {
+            ret.push((&i[start..open], false));
+        }
+        ret.push((span, true));
+        has_deletions = true;
+        pos += len + 1;
+        start = pos;
+    }
+
+    if !has_deletions {
+        return Vec::new();
+    }
+    if start < i.len() {
+        ret.push((&i[start..], false));
+    }
+    ret
+}
+
+/// Split a line of text into plain and `漢字{かんじ}` ruby annotated segments.
+///
+/// Segments are returned as (text, reading) pairs. The annotated text is the
+/// run of kanji right before the braces and the reading in the braces must be
+/// kana. Returns an empty vector if the line has no ruby annotations.
+pub fn ruby(i: &str) -> Vec<(&str, Option<&str>)> {
+    let mut ret = Vec::new();
+    let mut start = 0;
+    let mut pos = 0;
+    while let Some(open) = i[pos..].find('{').map(|p| p + pos) {
+        let Some((base, end, reading)) = ruby_at(i, open) else {
+            pos = open + 1;
+            continue;
+        };
+        if base > start {
+            ret.push((&i[start..base], None));
+        }
+        ret.push((&i[base..open], Some(reading)));
+        pos = end;
+        start = end;
+    }
+
+    if ret.is_empty() {
+        return Vec::new();
+    }
+    if start < i.len() {
+        ret.push((&i[start..], None));
+    }
+    ret
+}
+
+/// If there's a ruby annotation with the opening brace at `open`, return
+/// the start position of the annotated text, the end position of the
+/// annotation and the reading.
+fn ruby_at(i: &str, open: usize) -> Option<(usize, usize, &str)> {
+    fn is_kanji(c: char) -> bool {
+        matches!(c, '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' | '々')
+    }
+
+    fn is_kana(c: char) -> bool {
+        matches!(c, '\u{3040}'..='\u{30FF}')
+    }
+
+    let len = i[open + 1..].find('}')?;
+    let reading = &i[open + 1..open + 1 + len];
+    if reading.is_empty() || !reading.chars().all(is_kana) {
+        return None;
+    }
+    let base = i[..open]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_kanji(*c))
+        .last()
+        .map(|(p, _)| p)?;
+    Some((base, open + len + 2, reading))
+}
+
 /// Combinator for parsing with no trailing input left.
 pub fn only<'a, T>(
     p: impl Fn(&'a str) -> IResult<&'a str, T>,
@@ -70,7 +216,7 @@ pub fn only<'a, T>(
     &'a str,
 ) -> std::result::Result<T, nom::Err<nom::error::Error<&'a str>>> {
     move |i| match p(i) {
-        Ok((rest, ret)) if rest.is_empty() => Ok(ret),
+        Ok(("", ret)) => Ok(ret),
         Ok(_) => Err(err(i)),
         Err(e) => Err(e),
     }
@@ -113,6 +259,71 @@ mod tests {
         assert!(only(wiki_word)("WikiWord ").is_err());
     }
 
+    #[test]
+    fn test_wiki_words() {
+        assert!(wiki_words("").is_empty());
+        assert!(wiki_words("no wiki words here").is_empty());
+        assert_eq!(wiki_words("WikiWord"), vec!["WikiWord"]);
+        assert_eq!(
+            wiki_words("See WikiWord, and (OtherWord)."),
+            vec!["WikiWord", "OtherWord"]
+        );
+        assert!(wiki_words("aWikiWord http://example.com/WikiWord").is_empty());
+    }
+
+    #[test]
+    fn test_bracket_links() {
+        assert_eq!(bracket_link("[[foo]]"), Ok(("", "foo")));
+        assert_eq!(bracket_link("[[ foo bar ]] baz"), Ok((" baz", "foo bar")));
+        assert!(bracket_link("[[]]").is_err());
+        assert!(bracket_link("[[foo").is_err());
+        assert!(bracket_link("foo").is_err());
+
+        assert!(bracket_links("").is_empty());
+        assert_eq!(
+            bracket_links("see [[foo]] and [[bar baz]] but not [[ ]]"),
+            vec!["foo", "bar baz"]
+        );
+    }
+
+    #[test]
+    fn test_cloze() {
+        assert!(cloze("").is_empty());
+        assert!(cloze("no deletions {} { x } {a").is_empty());
+        assert_eq!(
+            cloze("{Helsinki} is the capital of {Finland}."),
+            vec![
+                ("Helsinki", true),
+                (" is the capital of ", false),
+                ("Finland", true),
+                (".", false)
+            ]
+        );
+        assert_eq!(
+            cloze("fn {main}() {}"),
+            vec![("fn ", false), ("main", true), ("() {}", false)]
+        );
+        assert_eq!(
+            cloze("{東京}は日本{にほん}の首都"),
+            vec![("東京", true), ("は日本{にほん}の首都", false)]
+        );
+    }
+
+    #[test]
+    fn test_ruby() {
+        assert!(ruby("").is_empty());
+        assert!(ruby("{cloze} かな{かな} 漢字{kanji} 漢字{}").is_empty());
+        assert_eq!(
+            ruby("これは漢字{かんじ}です、{東京}の東京{トウキョウ}"),
+            vec![
+                ("これは", None),
+                ("漢字", Some("かんじ")),
+                ("です、{東京}の", None),
+                ("東京", Some("トウキョウ")),
+            ]
+        );
+    }
+
     #[test]
     fn test_title() {
         assert_eq!(title(""), Ok(("", ("", false))));
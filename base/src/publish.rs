@@ -0,0 +1,175 @@
+//! Choosing what goes in a published copy of the collection.
+//!
+//! Static exports like `olt export-html` and `olt export --gemini` make a
+//! page for every article outside the trash. Sections tagged `private` are
+//! left out, and since tags are inherited, tagging an article or a whole
+//! file section keeps everything in it private. Links to articles that
+//! don't get a page should be shown as plain text.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{trash, Collection, Section};
+
+/// Tag for sections that aren't published.
+pub const PRIVATE_TAG: &str = "private";
+
+/// File name of the index page that lists the published articles.
+pub const INDEX_NAME: &str = "index";
+
+/// Return whether a section is kept out of published exports.
+pub fn is_private(section: &Section) -> bool {
+    section.tags().contains(PRIVATE_TAG)
+}
+
+/// Return a detached copy of a section without its private descendants.
+pub fn public_copy(section: &Section) -> Section {
+    let ret = section.deep_clone();
+    let private: Vec<Section> = ret.iter().skip(1).filter(is_private).collect();
+    for s in private {
+        s.detach();
+    }
+    ret
+}
+
+/// Published article.
+#[derive(Clone)]
+pub struct Page {
+    /// Public copy of the article.
+    pub article: Section,
+    /// File name of the page without an extension.
+    pub name: String,
+    /// Tags of the article including the inherited ones.
+    pub tags: BTreeSet<String>,
+}
+
+/// Pages of a published collection.
+#[derive(Clone, Default)]
+pub struct Site {
+    /// Pages in collection order.
+    pub pages: Vec<Page>,
+    /// Collection files with the titles of their published articles.
+    pub files: Vec<(String, Vec<String>)>,
+    /// Index of page by article title.
+    by_title: HashMap<String, usize>,
+}
+
+impl Site {
+    pub fn new(col: &Collection) -> Site {
+        Site::from_files(
+            col.files()
+                .filter(|(path, _)| !trash::is_trash(path))
+                .map(|(path, root)| (path.display().to_string(), root)),
+        )
+    }
+
+    /// Build a site from the root sections of files.
+    pub fn from_files(
+        files: impl IntoIterator<Item = (String, Section)>,
+    ) -> Site {
+        let mut ret = Site::default();
+        // Don't let an article page overwrite the index.
+        let mut used = BTreeSet::from([INDEX_NAME.to_string()]);
+
+        for (path, root) in files {
+            let mut titles = Vec::new();
+            for article in articles(&root) {
+                if is_private(&article) {
+                    continue;
+                }
+                let title = article.title();
+                titles.push(title.clone());
+                // First article with each title gets the page, like on the
+                // server.
+                if ret.by_title.contains_key(&title) {
+                    continue;
+                }
+
+                // Different titles can map to the same file name.
+                let mut name = page_name(&title);
+                let mut n = 1;
+                while used.contains(&name) {
+                    n += 1;
+                    name = format!("{}-{}", page_name(&title), n);
+                }
+                used.insert(name.clone());
+
+                ret.by_title.insert(title, ret.pages.len());
+                ret.pages.push(Page {
+                    article: public_copy(&article),
+                    name,
+                    tags: article.tags(),
+                });
+            }
+            ret.files.push((path, titles));
+        }
+        ret
+    }
+
+    /// Return the page of the article with a title.
+    pub fn page(&self, title: &str) -> Option<&Page> {
+        self.by_title.get(title).map(|&i| &self.pages[i])
+    }
+
+    /// Return all tags of the published articles.
+    pub fn tags(&self) -> BTreeSet<String> {
+        self.pages
+            .iter()
+            .flat_map(|p| p.tags.iter().cloned())
+            .collect()
+    }
+}
+
+/// Articles under a section in document order.
+fn articles(section: &Section) -> Vec<Section> {
+    let mut ret = Vec::new();
+    for child in section.children() {
+        if child.is_article() {
+            ret.push(child.clone());
+        }
+        ret.extend(articles(&child));
+    }
+    ret
+}
+
+/// File name for a page without the extension.
+///
+/// Characters that aren't safe in file names on every system become
+/// dashes.
+pub fn page_name(title: &str) -> String {
+    title
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_names() {
+        let root: Section = idm::from_str(
+            "\
+Notes
+  index
+    :uri https://example.com/index
+  FooBar
+  Foo?Bar
+    :uri https://example.com/foo
+  SecretPage
+    :tags private
+",
+        )
+        .unwrap();
+        let site = Site::from_files([("Notes.otl".to_string(), root)]);
+        let names: Vec<&str> =
+            site.pages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["index-2", "FooBar", "Foo-Bar"]);
+    }
+}
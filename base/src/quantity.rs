@@ -0,0 +1,168 @@
+//! Numeric values with units, like `82 kg` or `5 km`.
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+/// Known units as (name, canonical unit, size in canonical units).
+const UNITS: &[(&str, &str, f64)] = &[
+    // Length
+    ("mm", "m", 0.001),
+    ("cm", "m", 0.01),
+    ("m", "m", 1.0),
+    ("km", "m", 1000.0),
+    ("in", "m", 0.0254),
+    ("ft", "m", 0.3048),
+    ("yd", "m", 0.9144),
+    ("mi", "m", 1609.344),
+    // Mass
+    ("mg", "kg", 0.000_001),
+    ("g", "kg", 0.001),
+    ("kg", "kg", 1.0),
+    ("t", "kg", 1000.0),
+    ("oz", "kg", 0.028_349_523_125),
+    ("lb", "kg", 0.453_592_37),
+    // Time
+    ("ms", "s", 0.001),
+    ("s", "s", 1.0),
+    ("min", "s", 60.0),
+    ("h", "s", 3600.0),
+    // Volume
+    ("ml", "l", 0.001),
+    ("cl", "l", 0.01),
+    ("dl", "l", 0.1),
+    ("l", "l", 1.0),
+    // Energy
+    ("kJ", "kcal", 1.0 / 4.184),
+    ("kcal", "kcal", 1.0),
+];
+
+/// Number with an optional unit.
+///
+/// Units not in the built-in table are allowed, they just can't be
+/// converted to anything else. A quantity without a unit is a plain
+/// number.
+#[derive(Clone, PartialEq, Debug, SerializeDisplay, DeserializeFromStr)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: String,
+}
+
+impl Quantity {
+    pub fn new(value: f64, unit: impl Into<String>) -> Quantity {
+        Quantity {
+            value,
+            unit: unit.into(),
+        }
+    }
+
+    /// Return the canonical unit and the scale of this quantity's unit.
+    fn unit_info(&self) -> (&str, f64) {
+        match UNITS.iter().find(|(name, _, _)| *name == self.unit) {
+            Some((_, canonical, scale)) => (canonical, *scale),
+            None => (&self.unit, 1.0),
+        }
+    }
+
+    /// Return the same quantity in the canonical unit of its dimension, eg.
+    /// meters for lengths and kilograms for masses.
+    pub fn canonical(&self) -> Quantity {
+        let (unit, scale) = self.unit_info();
+        Quantity::new(self.value * scale, unit)
+    }
+
+    /// Return whether the quantities can be converted to each other.
+    pub fn is_compatible(&self, other: &Quantity) -> bool {
+        self.unit_info().0 == other.unit_info().0
+    }
+
+    /// Convert the quantity to another unit, if they measure the same thing.
+    pub fn convert_to(&self, unit: &str) -> Option<Quantity> {
+        let target = Quantity::new(1.0, unit);
+        if !self.is_compatible(&target) {
+            return None;
+        }
+        Some(Quantity::new(
+            self.canonical().value / target.unit_info().1,
+            unit,
+        ))
+    }
+
+    /// Compare quantities that can be converted to each other.
+    pub fn partial_cmp_converted(&self, other: &Quantity) -> Option<Ordering> {
+        if !self.is_compatible(other) {
+            return None;
+        }
+        self.canonical().value.partial_cmp(&other.canonical().value)
+    }
+}
+
+impl FromStr for Quantity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        // Number is everything up to the first character that can't be in
+        // a number, so both "5 km" and "5km" work.
+        let split = s
+            .find(|c: char| {
+                !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+')
+            })
+            .unwrap_or(s.len());
+        let (value, unit) = s.split_at(split);
+        let value: f64 =
+            value.parse().map_err(|_| format!("Bad quantity {:?}", s))?;
+        let unit = unit.trim();
+        if unit
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_ascii_digit())
+        {
+            return Err(format!("Bad quantity unit {:?}", s));
+        }
+        Ok(Quantity::new(value, unit))
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Hide floating point noise from unit conversions.
+        let value = (self.value * 1e6).round() / 1e6;
+        if self.unit.is_empty() {
+            write!(f, "{}", value)
+        } else {
+            write!(f, "{} {}", value, self.unit)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn q(s: &str) -> Quantity {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_quantity() {
+        assert_eq!(q("82 kg"), Quantity::new(82.0, "kg"));
+        assert_eq!(q("5km"), Quantity::new(5.0, "km"));
+        assert_eq!(q("-1.5"), Quantity::new(-1.5, ""));
+        assert_eq!(q("3 reps"), Quantity::new(3.0, "reps"));
+        assert!("kg".parse::<Quantity>().is_err());
+        assert!("5 k g".parse::<Quantity>().is_err());
+
+        assert_eq!(q("5 km").canonical().to_string(), "5000 m");
+        assert_eq!(
+            q("1 lb").convert_to("g").unwrap().to_string(),
+            "453.59237 g"
+        );
+        assert_eq!(q("1 lb").convert_to("km"), None);
+        assert_eq!(
+            q("1 mi").partial_cmp_converted(&q("1600 m")),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(q("1 kg").partial_cmp_converted(&q("1 m")), None);
+        assert_eq!(q("82 kg").to_string(), "82 kg");
+    }
+}
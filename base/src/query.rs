@@ -0,0 +1,711 @@
+//! Queries for selecting sections by attribute values.
+//!
+//! A query is a boolean expression of terms combined with `AND`, `OR`, `NOT`
+//! and parentheses:
+//!
+//! ```notrust
+//! tag:junk AND added<2015
+//! tags:rust AND published>=2020 AND (read:none OR NOT rating>3)
+//! ```
+//!
+//! `NOT` binds tightest and `OR` loosest. Terms are either `name:value`
+//! matches or comparisons using `=`, `<`, `<=`, `>` or `>=`. The `tag` and
+//! `tags` names match against the tags of a section, including tags
+//! inherited from parent sections. `name:none` matches sections that don't
+//! have the attribute, or have no tags for `tag:none`. Comparisons are
+//! done as dates if both values are dates, as quantities if both are numbers
+//! with convertible units (`weight>80kg` matches `weight: 180 lb`) and as
+//! text otherwise. A plain number compares against the number part of a
+//! quantity.
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use crate::{quantity::Quantity, Section, VagueDate};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Op {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Eq => write!(f, "="),
+            Op::Lt => write!(f, "<"),
+            Op::Le => write!(f, "<="),
+            Op::Gt => write!(f, ">"),
+            Op::Ge => write!(f, ">="),
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Term {
+    /// Attribute has value, or section has tag.
+    Has(String, String),
+    /// Attribute value compares to the given value.
+    Cmp(String, Op, String),
+    /// Section doesn't have the attribute, or doesn't have tags.
+    Missing(String),
+}
+
+impl Term {
+    pub fn matches(&self, section: &Section) -> bool {
+        match self {
+            Term::Has(name, value) if is_tag_name(name) => {
+                section.tags().contains(value)
+            }
+            Term::Has(name, value) => attribute(section, name)
+                .is_some_and(|a| a.trim() == value.as_str()),
+            Term::Missing(name) if is_tag_name(name) => {
+                section.tags().is_empty()
+            }
+            Term::Missing(name) => attribute(section, name).is_none(),
+            Term::Cmp(name, op, value) => attribute(section, name)
+                .is_some_and(|a| op.accepts(compare(a.trim(), value))),
+        }
+    }
+}
+
+impl FromStr for Term {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Look for the first operator character, the name can't contain any
+        // of them.
+        let Some(pos) = s.find([':', '=', '<', '>']) else {
+            return Err(format!("Bad query term {:?}", s));
+        };
+        let name = &s[..pos];
+        let rest = &s[pos..];
+
+        let (op, value) = if let Some(v) = rest.strip_prefix(':') {
+            (None, v)
+        } else if let Some(v) = rest.strip_prefix("<=") {
+            (Some(Op::Le), v)
+        } else if let Some(v) = rest.strip_prefix(">=") {
+            (Some(Op::Ge), v)
+        } else if let Some(v) = rest.strip_prefix('<') {
+            (Some(Op::Lt), v)
+        } else if let Some(v) = rest.strip_prefix('>') {
+            (Some(Op::Gt), v)
+        } else if let Some(v) = rest.strip_prefix('=') {
+            (Some(Op::Eq), v)
+        } else {
+            unreachable!()
+        };
+
+        if name.is_empty() || value.is_empty() {
+            return Err(format!("Bad query term {:?}", s));
+        }
+
+        let (name, value) = (name.to_string(), value.to_string());
+        Ok(match op {
+            None if value == "none" => Term::Missing(name),
+            None => Term::Has(name, value),
+            Some(op) => Term::Cmp(name, op, value),
+        })
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Has(name, value) => write!(f, "{}:{}", name, value),
+            Term::Cmp(name, op, value) => write!(f, "{}{}{}", name, op, value),
+            Term::Missing(name) => write!(f, "{}:none", name),
+        }
+    }
+}
+
+/// Boolean expression over query terms.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Query {
+    Term(Term),
+    Not(Box<Query>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+impl Query {
+    pub fn matches(&self, section: &Section) -> bool {
+        match self {
+            Query::Term(t) => t.matches(section),
+            Query::Not(q) => !q.matches(section),
+            Query::And(qs) => qs.iter().all(|q| q.matches(section)),
+            Query::Or(qs) => qs.iter().any(|q| q.matches(section)),
+        }
+    }
+
+    /// Binding strength for deciding where `Display` needs parentheses.
+    fn precedence(&self) -> usize {
+        match self {
+            Query::Or(_) => 0,
+            Query::And(_) => 1,
+            Query::Not(_) | Query::Term(_) => 2,
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s);
+        let mut pos = 0;
+        let ret = parse_or(&tokens, &mut pos)
+            .map_err(|e| format!("{} in query {:?}", e, s))?;
+        if let Some(token) = tokens.get(pos) {
+            return Err(format!("Unexpected {:?} in query {:?}", token, s));
+        }
+        Ok(ret)
+    }
+}
+
+/// Split query into words and parentheses.
+fn tokenize(s: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    let mut word = String::new();
+    for c in s.chars() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if !word.is_empty() {
+                ret.push(std::mem::take(&mut word));
+            }
+            if !c.is_whitespace() {
+                ret.push(c.to_string());
+            }
+        } else {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        ret.push(word);
+    }
+    ret
+}
+
+type ParseResult = Result<Query, String>;
+
+fn parse_or(tokens: &[String], pos: &mut usize) -> ParseResult {
+    parse_chain(tokens, pos, "OR", parse_and, Query::Or)
+}
+
+fn parse_and(tokens: &[String], pos: &mut usize) -> ParseResult {
+    parse_chain(tokens, pos, "AND", parse_not, Query::And)
+}
+
+/// Parse operands separated by a binary operator.
+fn parse_chain(
+    tokens: &[String],
+    pos: &mut usize,
+    op: &str,
+    operand: fn(&[String], &mut usize) -> ParseResult,
+    join: fn(Vec<Query>) -> Query,
+) -> ParseResult {
+    let mut items = vec![operand(tokens, pos)?];
+    while tokens.get(*pos).map(|t| t.as_str()) == Some(op) {
+        *pos += 1;
+        items.push(operand(tokens, pos)?);
+    }
+    Ok(if items.len() == 1 {
+        items.pop().unwrap()
+    } else {
+        join(items)
+    })
+}
+
+fn parse_not(tokens: &[String], pos: &mut usize) -> ParseResult {
+    let Some(token) = tokens.get(*pos) else {
+        return Err("Unexpected end".into());
+    };
+    *pos += 1;
+    match token.as_str() {
+        "NOT" => Ok(Query::Not(Box::new(parse_not(tokens, pos)?))),
+        "(" => {
+            let ret = parse_or(tokens, pos)?;
+            if tokens.get(*pos).map(|t| t.as_str()) != Some(")") {
+                return Err("Missing ')'".into());
+            }
+            *pos += 1;
+            Ok(ret)
+        }
+        "AND" | "OR" | ")" => Err(format!("Unexpected {:?}", token)),
+        _ => token.parse().map(Query::Term),
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let write_operand = |f: &mut fmt::Formatter<'_>, q: &Query| {
+            if q.precedence() <= self.precedence() {
+                write!(f, "({})", q)
+            } else {
+                write!(f, "{}", q)
+            }
+        };
+
+        match self {
+            Query::Term(t) => write!(f, "{}", t),
+            Query::Not(q) => {
+                write!(f, "NOT ")?;
+                // NOT NOT x doesn't need parentheses.
+                if q.precedence() < self.precedence() {
+                    write!(f, "({})", q)
+                } else {
+                    write!(f, "{}", q)
+                }
+            }
+            Query::And(qs) | Query::Or(qs) => {
+                let op = if matches!(self, Query::And(_)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                for (i, q) in qs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{}", op)?;
+                    }
+                    write_operand(f, q)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn is_tag_name(name: &str) -> bool {
+    name == "tag" || name == "tags"
+}
+
+fn attribute(section: &Section, name: &str) -> Option<String> {
+    section.borrow().attributes.get(name).cloned()
+}
+
+/// Compare as dates, numbers or text, whichever works for both values.
+///
+/// This is for comparing against a single query value and is not a total
+/// order over many values, use `SortKey` for sorting.
+pub(crate) fn compare(a: &str, b: &str) -> Ordering {
+    if let (Ok(a), Ok(b)) = (a.parse::<VagueDate>(), b.parse::<VagueDate>()) {
+        // Plain numbers parse as years, so don't use dates unless one of
+        // the values actually looks like a date.
+        if a.to_string().contains('-') || b.to_string().contains('-') {
+            return a.cmp(&b);
+        }
+    }
+
+    if let (Ok(a), Ok(b)) = (a.parse::<Quantity>(), b.parse::<Quantity>()) {
+        let ordering = if a.unit.is_empty() || b.unit.is_empty() {
+            a.value.partial_cmp(&b.value)
+        } else {
+            a.partial_cmp_converted(&b)
+        };
+        if let Some(ordering) = ordering {
+            return ordering;
+        }
+    }
+
+    a.cmp(b)
+}
+
+/// Attribute value in a sortable form.
+///
+/// Dates come before quantities and quantities before text. Quantities are
+/// ordered by their canonical unit first, so all values are ordered
+/// consistently even when they can't be converted to each other.
+#[derive(Clone, Debug)]
+enum SortKey {
+    Date(VagueDate),
+    Quantity(Quantity),
+    Text(String),
+}
+
+impl SortKey {
+    /// Make a key for the value.
+    ///
+    /// Plain numbers parse as years, they are only read as dates with
+    /// `has_dates` when the values being sorted include actual dates.
+    fn new(value: &str, has_dates: bool) -> SortKey {
+        let value = value.trim();
+        if let Ok(date) = value.parse::<VagueDate>() {
+            if has_dates || looks_like_date(value) {
+                return SortKey::Date(date);
+            }
+        }
+        if let Ok(quantity) = value.parse::<Quantity>() {
+            return SortKey::Quantity(quantity.canonical());
+        }
+        SortKey::Text(value.to_string())
+    }
+
+    fn rank(&self) -> usize {
+        match self {
+            SortKey::Date(_) => 0,
+            SortKey::Quantity(_) => 1,
+            SortKey::Text(_) => 2,
+        }
+    }
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (SortKey::Date(a), SortKey::Date(b)) => a.cmp(b),
+            (SortKey::Quantity(a), SortKey::Quantity(b)) => a
+                .unit
+                .cmp(&b.unit)
+                .then_with(|| a.value.total_cmp(&b.value)),
+            (SortKey::Text(a), SortKey::Text(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SortKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortKey {}
+
+fn looks_like_date(value: &str) -> bool {
+    value
+        .parse::<VagueDate>()
+        .is_ok_and(|d| d.to_string().contains('-'))
+}
+
+/// Sort sections by the value of an attribute.
+///
+/// Values are compared as dates, quantities or text like in queries, but
+/// values of different kinds are ordered by kind so that the order is
+/// consistent. Sections without the attribute go last.
+pub fn sort_by_attribute(
+    sections: &mut [Section],
+    name: &str,
+    descending: bool,
+) {
+    let value = |s: &Section| s.borrow().attributes.get(name).cloned();
+    let has_dates = sections
+        .iter()
+        .filter_map(value)
+        .any(|v| looks_like_date(v.trim()));
+    let key = |s: &Section| value(s).map(|v| SortKey::new(&v, has_dates));
+    sections.sort_by(|a, b| match (key(a), key(b)) {
+        (Some(a), Some(b)) if descending => b.cmp(&a),
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+}
+
+/// Return whether a date overlaps the range between `from` and `to`.
+///
+/// The ends of the range are included and cover all their days, so a
+/// range to 2023-06 includes 2023-06-30. A vague date like 2023 is in any
+/// range that overlaps the year.
+pub fn in_date_range(
+    date: &VagueDate,
+    from: Option<&VagueDate>,
+    to: Option<&VagueDate>,
+) -> bool {
+    from.is_none_or(|from| date.last_day() >= from.first_day())
+        && to.is_none_or(|to| date.first_day() <= to.last_day())
+}
+
+/// Collect sections whose date attribute `name` is in a range, in
+/// chronological order.
+///
+/// Sections without the attribute or with a value that isn't a date are
+/// skipped.
+pub fn by_date(
+    sections: impl IntoIterator<Item = Section>,
+    name: &str,
+    from: Option<&VagueDate>,
+    to: Option<&VagueDate>,
+) -> Vec<(VagueDate, Section)> {
+    let mut ret: Vec<(VagueDate, Section)> = sections
+        .into_iter()
+        .filter_map(|s| {
+            let date = attribute(&s, name)?.parse::<VagueDate>().ok()?;
+            in_date_range(&date, from, to).then_some((date, s))
+        })
+        .collect();
+    // Stable sort, sections with the same date stay in collection order.
+    ret.sort_by_key(|(date, _)| *date);
+    ret
+}
+
+/// Ways to combine attribute values from multiple sections.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Mean,
+    Min,
+    Max,
+}
+
+impl FromStr for Aggregate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "count" => Ok(Aggregate::Count),
+            "sum" => Ok(Aggregate::Sum),
+            "mean" => Ok(Aggregate::Mean),
+            "min" => Ok(Aggregate::Min),
+            "max" => Ok(Aggregate::Max),
+            _ => Err(format!("Unknown aggregate {:?}", s)),
+        }
+    }
+}
+
+/// Combine the quantity values of attribute `name` in the sections.
+///
+/// Sections without the attribute are skipped. The values must all have
+/// units that convert to each other, and the result is given in the unit
+/// of the first value. Returns `None` if no section has the attribute.
+pub fn aggregate(
+    sections: impl IntoIterator<Item = Section>,
+    name: &str,
+    aggregate: Aggregate,
+) -> Result<Option<Quantity>, String> {
+    let mut values = Vec::new();
+    for section in sections {
+        if let Some(a) = attribute(&section, name) {
+            let q: Quantity = a.parse()?;
+            if let Some(first) = values.first() {
+                if !q.is_compatible(first) {
+                    return Err(format!(
+                        "Can't combine {} with {} in {:?}",
+                        q,
+                        first,
+                        section.headline()
+                    ));
+                }
+            }
+            values.push(q);
+        }
+    }
+
+    let Some(unit) = values.first().map(|q| q.unit.clone()) else {
+        return Ok(None);
+    };
+    let canonical: Vec<Quantity> =
+        values.iter().map(Quantity::canonical).collect();
+    let total: f64 = canonical.iter().map(|q| q.value).sum();
+    let value = match aggregate {
+        Aggregate::Count => {
+            return Ok(Some(Quantity::new(values.len() as f64, "")))
+        }
+        Aggregate::Sum => total,
+        Aggregate::Mean => total / values.len() as f64,
+        Aggregate::Min => {
+            canonical.iter().map(|q| q.value).fold(f64::MAX, f64::min)
+        }
+        Aggregate::Max => {
+            canonical.iter().map(|q| q.value).fold(f64::MIN, f64::max)
+        }
+    };
+    Ok(Quantity::new(value, &canonical[0].unit).convert_to(&unit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(s: &str) -> Section {
+        idm::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "tag:junk AND added<2015".parse(),
+            Ok(Query::And(vec![
+                Query::Term(Term::Has("tag".into(), "junk".into())),
+                Query::Term(Term::Cmp("added".into(), Op::Lt, "2015".into()))
+            ]))
+        );
+        assert_eq!(
+            "read:none".parse(),
+            Ok(Query::Term(Term::Missing("read".into())))
+        );
+        assert!("".parse::<Query>().is_err());
+        assert!("tag:junk AND".parse::<Query>().is_err());
+        assert!("tag:junk OR".parse::<Query>().is_err());
+        assert!("(tag:junk".parse::<Query>().is_err());
+        assert!("tag:junk)".parse::<Query>().is_err());
+        assert!("tag:junk tag:x".parse::<Query>().is_err());
+        assert!("junk".parse::<Query>().is_err());
+        assert!("tag:".parse::<Query>().is_err());
+
+        let roundtrip = |q: &str| q.parse::<Query>().unwrap().to_string();
+        assert_eq!(roundtrip("a:b AND c>=2"), "a:b AND c>=2");
+        assert_eq!(roundtrip("a:b OR c:d AND e:f"), "a:b OR c:d AND e:f");
+        assert_eq!(roundtrip("(a:b OR c:d) AND e:f"), "(a:b OR c:d) AND e:f");
+        assert_eq!(roundtrip("NOT (a:b AND c:d)"), "NOT (a:b AND c:d)");
+        assert_eq!(roundtrip("NOT(a:none)"), "NOT a:none");
+    }
+
+    #[test]
+    fn test_match() {
+        let s = section(
+            "\
+Item
+\t:tags junk stuff
+\t:added 2014-05-01
+\t:rating 3
+",
+        );
+
+        let matches = |q: &str| q.parse::<Query>().unwrap().matches(&s);
+
+        assert!(matches("tag:junk"));
+        assert!(matches("tags:stuff AND added<2015"));
+        assert!(!matches("tag:junk AND added>=2015"));
+        assert!(matches("added>2014-04"));
+        assert!(matches("rating>=3 AND rating<10"));
+        assert!(matches("added:2014-05-01"));
+        assert!(!matches("missing:value"));
+
+        assert!(matches("tag:nope OR rating:3"));
+        assert!(!matches("NOT tag:junk"));
+        assert!(matches("NOT (tag:junk AND rating>3)"));
+        assert!(!matches("tag:nope OR rating:3 AND added>2015"));
+        assert!(matches("(tag:nope OR rating:3) AND NOT added>2015"));
+        assert!(matches("read:none AND NOT rating:none"));
+        assert!(!matches("tag:none"));
+    }
+
+    #[test]
+    fn test_quantity() {
+        let s = section(
+            "\
+Log
+\t:tags workout
+\tMonday
+\t\t:distance 5 km
+\tTuesday
+\t\t:distance 2 mi
+\tWednesday
+",
+        );
+        let run = s.children().next().unwrap();
+        let matches = |q: &str| q.parse::<Query>().unwrap().matches(&run);
+        assert!(matches("distance>=5000m"));
+        assert!(matches("distance<4mi"));
+        assert!(matches("distance>4"));
+        assert!(!matches("distance>5km"));
+
+        let agg = |a| {
+            aggregate(s.iter(), "distance", a)
+                .unwrap()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(agg(Aggregate::Count), "2");
+        assert_eq!(agg(Aggregate::Sum), "8.218688 km");
+        assert_eq!(agg(Aggregate::Max), "5 km");
+        assert_eq!(aggregate(s.iter(), "weight", Aggregate::Sum), Ok(None));
+    }
+
+    #[test]
+    fn test_sort_mixed() {
+        let s = section(
+            "\
+Items
+\tA
+\t\t:value 10
+\tB
+\t\t:value 9
+\tC
+\t\t:value n/a
+\tD
+\t\t:value 2 km
+\tE
+\t\t:value 500 m
+\tF
+\tG
+\t\t:value 3 kg
+",
+        );
+        let mut items: Vec<Section> = s.children().collect();
+        let titles = |items: &[Section]| {
+            items.iter().map(|s| s.headline()).collect::<String>()
+        };
+        sort_by_attribute(&mut items, "value", false);
+        assert_eq!(titles(&items), "BAGEDCF");
+        sort_by_attribute(&mut items, "value", true);
+        assert_eq!(titles(&items), "CDEGABF");
+
+        let s = section(
+            "\
+Books
+\tA
+\t\t:read 2023-06
+\tB
+\t\t:read 2022
+\tC
+\t\t:read unknown
+\tD
+\t\t:read 2023-01-15
+",
+        );
+        let mut items: Vec<Section> = s.children().collect();
+        sort_by_attribute(&mut items, "read", false);
+        assert_eq!(titles(&items), "BDAC");
+    }
+
+    #[test]
+    fn test_by_date() {
+        let s = section(
+            "\
+Books
+\tA
+\t\t:read 2023-06-30
+\tB
+\t\t:read 2023-01
+\tC
+\t\t:read 2023
+\tD
+\t\t:read 2022-12-31
+\tE
+",
+        );
+        let date = |d: &str| d.parse::<VagueDate>().unwrap();
+        let titles = |from: &str, to: &str| {
+            by_date(s.iter(), "read", Some(&date(from)), Some(&date(to)))
+                .into_iter()
+                .map(|(_, s)| s.headline())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles("2023-01", "2023-06"), vec!["C", "B", "A"]);
+        assert_eq!(titles("2023-02", "2023-05"), vec!["C"]);
+        assert_eq!(titles("2022", "2022"), vec!["D"]);
+    }
+}
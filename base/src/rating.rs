@@ -0,0 +1,63 @@
+//! Ratings of books, articles and other entries, from 1 to 5.
+
+use std::{fmt, str::FromStr};
+
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+/// Highest possible rating.
+pub const MAX_RATING: u8 = 5;
+
+/// Rating from 1 to `MAX_RATING` stored in the `rating` attribute.
+#[derive(
+    Copy,
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Debug,
+    SerializeDisplay,
+    DeserializeFromStr,
+)]
+pub struct Rating(u8);
+
+impl Rating {
+    pub fn new(value: u8) -> Option<Rating> {
+        (1..=MAX_RATING).contains(&value).then_some(Rating(value))
+    }
+
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+}
+
+impl FromStr for Rating {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim().parse().ok().and_then(Rating::new).ok_or_else(|| {
+            format!("Bad rating {:?}, must be from 1 to {}", s, MAX_RATING)
+        })
+    }
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rating() {
+        assert_eq!("4".parse::<Rating>(), Ok(Rating(4)));
+        assert_eq!(" 1 ".parse::<Rating>(), Ok(Rating(1)));
+        assert!("0".parse::<Rating>().is_err());
+        assert!("6".parse::<Rating>().is_err());
+        assert!("4.5".parse::<Rating>().is_err());
+        assert!("good".parse::<Rating>().is_err());
+    }
+}
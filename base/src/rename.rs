@@ -0,0 +1,145 @@
+//! Rewriting words across the whole collection.
+
+use crate::{typed_attribute::TypedAttribute, Collection, Section};
+
+/// Replace whole-word occurrences of `from` in text with `to`.
+///
+/// Words inside web addresses are left alone so that links keep working.
+/// Return `None` if the text has no occurrences.
+pub fn replace_word(text: &str, from: &str, to: &str) -> Option<String> {
+    if from.is_empty() {
+        return None;
+    }
+
+    let is_word_char = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric());
+
+    let mut ret = String::new();
+    let mut rest = text;
+    let mut found = false;
+    while let Some(pos) = rest.find(from) {
+        let end = pos + from.len();
+        let before = rest[..pos].chars().next_back();
+        let after = rest[end..].chars().next();
+        ret.push_str(&rest[..pos]);
+        // The whitespace-separated token the occurrence is in.
+        let token_start =
+            rest[..pos].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let token_end = rest[end..]
+            .find(char::is_whitespace)
+            .map_or(rest.len(), |i| end + i);
+        let is_in_url = rest[token_start..token_end].contains("://");
+        if is_word_char(before) || is_word_char(after) || is_in_url {
+            // Part of a longer word or an address, leave it alone.
+            ret.push_str(from);
+        } else {
+            ret.push_str(to);
+            found = true;
+        }
+        rest = &rest[end..];
+    }
+    ret.push_str(rest);
+
+    found.then_some(ret)
+}
+
+/// Replace whole-word occurrences of `from` with `to` in all headlines,
+/// body texts and text attribute values of the collection.
+///
+/// Attributes like `uri` are left alone, see `TypedAttribute::is_text`.
+/// Return the number of sections that were changed.
+pub fn rename_word(collection: &Collection, from: &str, to: &str) -> usize {
+    collection
+        .iter()
+        .filter(|section| rename_in_section(section, from, to))
+        .count()
+}
+
+/// Rename a word in a single section, return whether it changed.
+fn rename_in_section(section: &Section, from: &str, to: &str) -> bool {
+    let mut changed = false;
+
+    if let Some(headline) = replace_word(&section.headline(), from, to) {
+        section.set_headline(headline);
+        changed = true;
+    }
+
+    if let Some(body) = replace_word(&section.body(), from, to) {
+        section.set_body(body);
+        changed = true;
+    }
+
+    let attributes = section.borrow().attributes.clone();
+    for (name, value) in attributes {
+        if !TypedAttribute::is_text(&name, &value) {
+            continue;
+        }
+        if let Some(value) = replace_word(&value, from, to) {
+            section.borrow_mut().attributes.insert(name, value);
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_word() {
+        assert_eq!(
+            replace_word(
+                "See HTTPServer, HTTPServers and HTTPServer.",
+                "HTTPServer",
+                "HttpServer"
+            ),
+            Some("See HttpServer, HTTPServers and HttpServer.".into())
+        );
+        assert_eq!(
+            replace_word("[[HTTPServer]]", "HTTPServer", "HttpServer"),
+            Some("[[HttpServer]]".into())
+        );
+        assert_eq!(
+            replace_word("MyHTTPServer", "HTTPServer", "HttpServer"),
+            None
+        );
+        assert_eq!(replace_word("", "HTTPServer", "HttpServer"), None);
+        assert_eq!(
+            replace_word(
+                "HTTPServer at https://example.com/HTTPServer/",
+                "HTTPServer",
+                "HttpServer"
+            ),
+            Some("HttpServer at https://example.com/HTTPServer/".into())
+        );
+    }
+
+    #[test]
+    fn test_rename_keeps_urls() {
+        let section: Section = idm::from_str(
+            "\
+HTTPServer notes
+  :uri https://example.com/HTTPServer/
+  :via https://example.com/HTTPServer/list
+  :links https://a.com/HTTPServer/ https://b.com/
+  :mirror https://archive.org/HTTPServer/
+  :author HTTPServer team
+  : See https://example.com/HTTPServer/ and HTTPServer.",
+        )
+        .unwrap();
+        assert!(rename_in_section(&section, "HTTPServer", "HttpServer"));
+
+        let attr = |name| section.attr::<String>(name).unwrap().unwrap();
+        assert_eq!(section.headline(), "HttpServer notes");
+        assert_eq!(attr("uri"), "https://example.com/HTTPServer/");
+        assert_eq!(attr("via"), "https://example.com/HTTPServer/list");
+        assert_eq!(attr("links"), "https://a.com/HTTPServer/ https://b.com/");
+        assert_eq!(attr("mirror"), "https://archive.org/HTTPServer/");
+        assert_eq!(attr("author"), "HttpServer team");
+        assert_eq!(
+            section.body(),
+            "See https://example.com/HTTPServer/ and HttpServer."
+        );
+    }
+}
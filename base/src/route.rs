@@ -0,0 +1,158 @@
+//! Rules for automatically placing new entries in the collection.
+//!
+//! Routes are read from `routes.idm` in the collection's config directory. The
+//! file is an outline where each headline is a destination path and the
+//! attributes are conditions that must all match for an entry to go there:
+//!
+//! ```notrust
+//! Papers
+//!     :host arxiv.org
+//! Recipes
+//!     :tag recipe
+//! ```
+//!
+//! Routes are tried in order and the first match wins.
+
+use std::{convert::TryFrom, fs, path::Path};
+
+use crate::{xdg, Result, Section};
+
+/// Name of the routing rules file in the config directory.
+pub const ROUTES_FILE: &str = "routes.idm";
+
+/// A single routing rule.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Route {
+    /// Collection path where matching entries are inserted.
+    pub target: String,
+    /// Match entries whose uri host contains this string.
+    pub host: Option<String>,
+    /// Match entries that have this tag.
+    pub tag: Option<String>,
+}
+
+impl Route {
+    pub fn matches(&self, section: &Section) -> bool {
+        if let Some(host) = &self.host {
+            let Some(uri) = section.uri() else {
+                return false;
+            };
+            let Ok(url) = url::Url::parse(&uri) else {
+                return false;
+            };
+            if !url.host_str().is_some_and(|h| h.contains(host.as_str())) {
+                return false;
+            }
+        }
+
+        if let Some(tag) = &self.tag {
+            if !section.tags().contains(tag) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl TryFrom<&Section> for Route {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn try_from(section: &Section) -> Result<Self> {
+        let target = section.headline();
+        let get = |name| {
+            section
+                .attr::<String>(name)
+                .map_err(|e| format!("Route {:?}: {}", target, e))
+        };
+
+        let ret = Route {
+            host: get("host")?,
+            tag: get("tag")?,
+            target: target.clone(),
+        };
+
+        if ret.host.is_none() && ret.tag.is_none() {
+            return Err(format!("Route {:?} has no conditions", target))?;
+        }
+
+        Ok(ret)
+    }
+}
+
+/// Ordered set of routing rules.
+#[derive(Clone, Default, Debug)]
+pub struct Routes(pub Vec<Route>);
+
+impl Routes {
+    /// Load routes of the collection at the given root directory.
+    ///
+    /// Return an empty set of routes if the collection has no routes file.
+    pub fn load(root_path: impl AsRef<Path>) -> Result<Routes> {
+        let path = xdg::path(root_path, xdg::Dir::Config, ROUTES_FILE);
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+        Routes::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Routes> {
+        let sections: Vec<Section> = idm::from_str(text)?;
+        Ok(Routes(
+            sections
+                .iter()
+                .map(Route::try_from)
+                .collect::<Result<Vec<_>>>()?,
+        ))
+    }
+
+    /// Return destination path for section if any route matches it.
+    pub fn target(&self, section: &Section) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|r| r.matches(section))
+            .map(|r| r.target.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes() {
+        let routes = Routes::parse(
+            "\
+Papers
+\t:host arxiv.org
+Recipes
+\t:tag recipe
+",
+        )
+        .unwrap();
+
+        let paper: Section = idm::from_str(
+            "\
+Some paper
+\t:uri https://arxiv.org/abs/1234
+",
+        )
+        .unwrap();
+        assert_eq!(routes.target(&paper), Some("Papers"));
+
+        let recipe: Section = idm::from_str(
+            "\
+Soup
+\t:uri https://example.com/soup
+\t:tags food recipe
+",
+        )
+        .unwrap();
+        assert_eq!(routes.target(&recipe), Some("Recipes"));
+
+        let other: Section = idm::from_str("Something else\n").unwrap();
+        assert_eq!(routes.target(&other), None);
+
+        assert!(Routes::parse("Anything\n").is_err());
+    }
+}
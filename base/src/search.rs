@@ -0,0 +1,279 @@
+//! Full-text search over the collection.
+//!
+//! The index is kept in `index` in the collection's cache directory. Each
+//! file's postings are stored separately along with the file's modification
+//! time, so updating the index only needs to parse files that have changed
+//! since the last search instead of loading the whole collection.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collection::{load_file, otl_files},
+    xdg, Result, Section,
+};
+
+/// Name of the search index in the cache directory.
+pub const INDEX_FILE: &str = "index";
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Index {
+    files: BTreeMap<PathBuf, FileIndex>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FileIndex {
+    /// Modification time of the file when it was indexed.
+    mtime: u128,
+    /// Paths of the indexed sections and the positions of their parents.
+    sections: Vec<(String, Option<usize>)>,
+    /// Positions of sections that have the word in their headline or
+    /// attributes.
+    words: BTreeMap<String, Vec<usize>>,
+}
+
+impl FileIndex {
+    fn new(mtime: u128, root: &Section) -> FileIndex {
+        let mut ret = FileIndex {
+            mtime,
+            sections: Vec::new(),
+            words: BTreeMap::new(),
+        };
+        // The file root section is the file name, don't index it.
+        for child in root.children() {
+            ret.add(&child, None);
+        }
+        ret
+    }
+
+    fn add(&mut self, section: &Section, parent: Option<usize>) {
+        let idx = self.sections.len();
+        self.sections.push((section.path(), parent));
+
+        let data = section.borrow();
+        let text = std::iter::once(&data.headline)
+            .chain(data.attributes.values())
+            .chain(std::iter::once(&data.body));
+        let words: BTreeSet<String> = text.flat_map(|t| words(t)).collect();
+        drop(data);
+
+        for word in words {
+            self.words.entry(word).or_default().push(idx);
+        }
+
+        for child in section.children() {
+            self.add(&child, Some(idx));
+        }
+    }
+
+    /// Return positions of sections that have the term in their own text.
+    fn lookup(&self, term: &str) -> BTreeSet<usize> {
+        match term.strip_suffix('*') {
+            Some(prefix) => self
+                .words
+                .range(prefix.to_string()..)
+                .take_while(|(w, _)| w.starts_with(prefix))
+                .flat_map(|(_, ps)| ps.iter().copied())
+                .collect(),
+            None => self
+                .words
+                .get(term)
+                .map(|ps| ps.iter().copied().collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Return the positions of all ancestors of the given sections.
+    fn ancestors(&self, sections: &BTreeSet<usize>) -> BTreeSet<usize> {
+        let mut ret = BTreeSet::new();
+        for &i in sections {
+            let mut n = self.sections[i].1;
+            while let Some(p) = n {
+                if !ret.insert(p) {
+                    break;
+                }
+                n = self.sections[p].1;
+            }
+        }
+        ret
+    }
+
+    /// Return positions of matching sections in document order.
+    ///
+    /// A section matches if all the terms are in its own text, or if they
+    /// are all in its subtree but no smaller subtree inside it.
+    fn search(&self, terms: &[String]) -> BTreeSet<usize> {
+        let mut own: Option<BTreeSet<usize>> = None;
+        let mut subtree: Option<BTreeSet<usize>> = None;
+        for term in terms {
+            let hits = self.lookup(term);
+            let mut hits_below = self.ancestors(&hits);
+            hits_below.extend(hits.iter().copied());
+
+            own = Some(match own {
+                Some(own) => own.intersection(&hits).copied().collect(),
+                None => hits,
+            });
+            subtree = Some(match subtree {
+                Some(sub) => sub.intersection(&hits_below).copied().collect(),
+                None => hits_below,
+            });
+        }
+
+        let subtree = subtree.unwrap_or_default();
+        let ancestors = self.ancestors(&subtree);
+        let mut ret: BTreeSet<usize> =
+            subtree.difference(&ancestors).copied().collect();
+        ret.extend(own.unwrap_or_default());
+        ret
+    }
+}
+
+impl Index {
+    /// Load index of collection at root, return an empty index if there is
+    /// none or it can't be read.
+    pub fn load(root: impl AsRef<Path>) -> Index {
+        fs::read_to_string(xdg::path(root, xdg::Dir::Cache, INDEX_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, root: impl AsRef<Path>) -> Result<()> {
+        let path = xdg::path(root, xdg::Dir::Cache, INDEX_FILE);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Reindex changed and new files and drop removed ones.
+    ///
+    /// Return the number of files that were reindexed.
+    pub fn update(&mut self, root: impl AsRef<Path>) -> Result<usize> {
+        let root = root.as_ref();
+        let mut seen = BTreeSet::new();
+        let mut count = 0;
+
+        for path in otl_files(root) {
+            let mtime = fs::metadata(&path)?
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0);
+            let key = path.strip_prefix(root).unwrap().to_owned();
+            seen.insert(key.clone());
+
+            if self.files.get(&key).map(|f| f.mtime) != Some(mtime) {
+                log::info!("Index::update: Indexing {:?}", key);
+                let section = load_file(root, &path)?;
+                self.files.insert(key, FileIndex::new(mtime, &section));
+                count += 1;
+            }
+        }
+
+        let before = self.files.len();
+        self.files.retain(|path, _| seen.contains(path));
+        Ok(count + before - self.files.len())
+    }
+
+    /// Return paths of sections that contain all the words in the query.
+    ///
+    /// Sections whose words are spread out in their body are found too, but
+    /// only the innermost such section is returned.
+    ///
+    /// A word ending in `*` matches all words starting with it. Query words
+    /// are split like indexed text, so "e-mail" needs both "e" and "mail".
+    pub fn search(&self, query: &str) -> Vec<String> {
+        let mut terms: Vec<String> = Vec::new();
+        for t in query.split_whitespace() {
+            let (t, is_prefix) = match t.strip_suffix('*') {
+                Some(prefix) => (prefix, true),
+                None => (t, false),
+            };
+            terms.extend(words(t));
+            if is_prefix && t.ends_with(char::is_alphanumeric) {
+                if let Some(last) = terms.last_mut() {
+                    last.push('*');
+                }
+            }
+        }
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        let mut ret = Vec::new();
+        for file in self.files.values() {
+            ret.extend(
+                file.search(&terms)
+                    .into_iter()
+                    .map(|i| file.sections[i].0.clone()),
+            );
+        }
+        ret
+    }
+}
+
+/// Split text into lowercase words.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        let root: Section = idm::from_str(
+            "\
+Notes
+  Thoughts on outliners
+    Outliners are flexible.
+    Another line.
+  Rust
+    :tags programming
+    Ownership and borrowing.",
+        )
+        .unwrap();
+        let index = Index {
+            files: BTreeMap::from([(
+                PathBuf::from("Notes.otl"),
+                FileIndex::new(0, &root),
+            )]),
+        };
+
+        assert_eq!(
+            index.search("outliners"),
+            vec![
+                "Notes/Thoughts on outliners",
+                "Notes/Thoughts on outliners/Outliners are flexible."
+            ]
+        );
+        assert_eq!(
+            index.search("Thoughts FLEXIBLE"),
+            vec!["Notes/Thoughts on outliners"]
+        );
+        assert_eq!(index.search("programming borrow*"), vec!["Notes/Rust"]);
+        assert!(index.search("nothing").is_empty());
+        assert_eq!(
+            index.search("Outliners-flexible"),
+            vec!["Notes/Thoughts on outliners/Outliners are flexible."]
+        );
+        assert_eq!(
+            index.search("OWNERSHIP, bor*"),
+            vec!["Notes/Rust/Ownership and borrowing."]
+        );
+        assert!(index.search("*").is_empty());
+        assert!(index.search("").is_empty());
+    }
+}
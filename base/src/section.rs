@@ -1,6 +1,10 @@
 use std::collections::BTreeSet;
 
-use crate::parse::{self, only};
+use crate::{
+    parse::{self, only},
+    typed_attribute::TypedAttr,
+    ParseError, VagueDate,
+};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
@@ -20,6 +24,8 @@ pub enum EntityIdentifier {
 pub struct SectionData {
     pub headline: String,
     pub attributes: IndexMap<String, String>,
+    /// Free text after the attributes, lines separated by newlines.
+    pub body: String,
 }
 
 impl SectionData {
@@ -27,6 +33,7 @@ impl SectionData {
         SectionData {
             headline,
             attributes,
+            body: Default::default(),
         }
     }
 }
@@ -37,7 +44,7 @@ impl From<String> for SectionData {
     }
 }
 
-// Headline and attributes.
+// Headline, attributes and body.
 pub type Section = crate::tree::NodeRef<SectionData>;
 
 #[derive(Serialize, Deserialize)]
@@ -59,13 +66,67 @@ impl RawSection {
     }
 }
 
+impl RawSection {
+    /// Return whether this is a `: text` body line.
+    fn is_body_line(&self) -> bool {
+        let RawSection((headline,), RawOutline((attributes,), children)) = self;
+        (headline == ":" || headline.starts_with(": "))
+            && attributes.is_empty()
+            && children.is_empty()
+    }
+}
+
+/// Split the leading `: text` body lines from outline children.
+///
+/// Body text is written in the VimOutliner style, each line prefixed with
+/// `: ` and placed before the child sections:
+///
+/// ```notrust
+/// Title
+///   :uri https://example.com
+///   : First line of body text
+///   : and the second one.
+///   Child section
+/// ```
+pub(crate) fn split_body(
+    mut children: Vec<RawSection>,
+) -> (String, Vec<RawSection>) {
+    let n = children.iter().take_while(|c| c.is_body_line()).count();
+    let body = children
+        .drain(..n)
+        .map(|c| {
+            // Only the separator space goes, indentation in the text stays.
+            let line = &c.0 .0[1..];
+            line.strip_prefix(' ').unwrap_or(line).to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    (body, children)
+}
+
+fn body_lines(body: &str) -> impl Iterator<Item = RawSection> + '_ {
+    body.lines().map(|line| {
+        let line = line.trim_end();
+        RawSection(
+            (if line.is_empty() {
+                ":".to_string()
+            } else {
+                format!(": {}", line)
+            },),
+            RawOutline((Default::default(),), Vec::new()),
+        )
+    })
+}
+
 impl From<&Section> for RawSection {
     fn from(sec: &Section) -> Self {
         RawSection(
             (sec.borrow().headline.clone(),),
             RawOutline(
                 (sec.borrow().attributes.clone(),),
-                sec.children().map(|c| RawSection::from(&c)).collect(),
+                body_lines(&sec.borrow().body)
+                    .chain(sec.children().map(|c| RawSection::from(&c)))
+                    .collect(),
             ),
         )
     }
@@ -73,8 +134,14 @@ impl From<&Section> for RawSection {
 
 impl From<RawSection> for Section {
     fn from(sec: RawSection) -> Self {
-        let root = Section::from(SectionData::new(sec.0 .0, sec.1 .0 .0));
-        for s in sec.1 .1.into_iter() {
+        let RawSection((headline,), RawOutline((attributes,), children)) = sec;
+        let (body, children) = split_body(children);
+        let root = Section::from(SectionData {
+            headline,
+            attributes,
+            body,
+        });
+        for s in children.into_iter() {
             root.append(Section::from(s));
         }
         root
@@ -128,7 +195,8 @@ impl Section {
     /// Return error if attribute value was found but could not be
     /// deserialized to given type.
     ///
-    /// Return Ok(None) if attribute was not found in outline.
+    /// Return Ok(None) if attribute was not found in outline. Errors are
+    /// `ParseError`s that name the attribute.
     pub fn attr<T: serde::de::DeserializeOwned>(
         &self,
         name: &str,
@@ -136,7 +204,10 @@ impl Section {
         self.borrow()
             .attributes
             .get(name)
-            .map(|s| idm::from_str(s).map_err(Into::into))
+            .map(|s| {
+                idm::from_str(s)
+                    .map_err(|e| attr_error(name, e.to_string()).into())
+            })
             .transpose()
     }
 
@@ -155,12 +226,65 @@ impl Section {
     where
         T: serde::Serialize + Default + PartialEq,
     {
-        self.borrow_mut()
-            .attributes
-            .insert(name.to_string(), idm::to_string(value)?);
+        if *value == T::default() {
+            self.remove_attr(name);
+        } else {
+            self.borrow_mut()
+                .attributes
+                .insert(name.to_string(), attr_text(&idm::to_string(value)?));
+        }
         Ok(())
     }
 
+    /// Read a known attribute as its type, see the `typed_attribute`
+    /// module.
+    pub fn typed_attr<T: TypedAttr>(&self) -> crate::Result<Option<T>> {
+        self.borrow()
+            .attributes
+            .get(T::NAME)
+            .map(|s| {
+                T::parse(s)
+                    .map_err(|e| attr_error(T::NAME, e.to_string()).into())
+            })
+            .transpose()
+    }
+
+    /// Write a known attribute from its type.
+    pub fn set_typed_attr<T: TypedAttr>(&mut self, value: &T) {
+        self.set_raw_attr(T::NAME, &value.to_string());
+    }
+
+    /// Write a raw IDM text value to a named attribute.
+    ///
+    /// Multi-line values are stored as an indented block under the
+    /// attribute. The text is dedented and blank lines around it are
+    /// dropped so it's stored the same way it reads back from a file. An
+    /// empty value removes the attribute.
+    pub fn set_raw_attr(&mut self, name: &str, value: &str) {
+        let value = attr_text(value);
+        if value.is_empty() {
+            self.remove_attr(name);
+        } else {
+            self.borrow_mut().attributes.insert(name.to_string(), value);
+        }
+    }
+
+    /// Remove a named attribute, keeping the order of the remaining ones.
+    pub fn remove_attr(&mut self, name: &str) {
+        if self.borrow().attributes.contains_key(name) {
+            self.borrow_mut().attributes.shift_remove(name);
+        }
+    }
+
+    /// Return the slash-separated headlines from the root of the file down
+    /// to this section.
+    pub fn path(&self) -> String {
+        match self.parent() {
+            Some(parent) => format!("{}/{}", parent.path(), self.headline()),
+            None => self.headline(),
+        }
+    }
+
     /// Return section headline.
     pub fn headline(&self) -> String {
         self.borrow().headline.clone()
@@ -170,6 +294,17 @@ impl Section {
         self.borrow_mut().headline = input.into();
     }
 
+    /// Return the free text body of the section.
+    pub fn body(&self) -> String {
+        self.borrow().body.clone()
+    }
+
+    /// Replace the body text of the section, an empty text removes it.
+    pub fn set_body(&self, text: impl Into<String>) {
+        let text = text.into();
+        self.borrow_mut().body = text.trim_end().to_string();
+    }
+
     /// Extract the title part of the headline
     ///
     /// This omits the important item tag.
@@ -196,6 +331,64 @@ impl Section {
         tags
     }
 
+    /// Return the section as a JSON object with the title, inherited tags
+    /// and attributes, the way `olt dump` lists articles.
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::{Map, Value};
+
+        let mut entry = Map::default();
+        // Initially override title with the headline.
+        //
+        // Currently headline will just be thrown out if the title is
+        // redefined, might put it in a separate field in the future in the
+        // case the values redefine title.
+        entry.insert("title".into(), self.title().into());
+
+        // Tags can be inherited from parent nodes, so add them explicitly.
+        entry.insert(
+            "tags".into(),
+            Value::Array(self.tags().into_iter().map(|a| a.into()).collect()),
+        );
+
+        for (key, val) in self.borrow().attributes.iter() {
+            if key == "tags" {
+                // Skip tags when processing the remaining attrs
+                continue;
+            } else {
+                entry.insert(key.into(), val.clone().into());
+            }
+        }
+
+        let body = self.body();
+        if !body.is_empty() {
+            entry.insert("body".into(), body.into());
+        }
+        Value::Object(entry)
+    }
+
+    /// Return the section and everything under it as a JSON object with
+    /// the headline, attributes, body and an array of children.
+    pub fn to_json_tree(&self) -> serde_json::Value {
+        let section = self.borrow();
+        serde_json::json!({
+            "headline": section.headline,
+            "attributes": section.attributes,
+            "body": section.body,
+            "children": self
+                .children()
+                .map(|c| c.to_json_tree())
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Get language code of current node, inheriting from parents.
+    pub fn lang(&self) -> Option<String> {
+        match self.attr::<String>("lang") {
+            Ok(Some(lang)) => Some(lang),
+            _ => self.parent().and_then(|p| p.lang()),
+        }
+    }
+
     pub fn set_title(&mut self, new_title: impl Into<String>) {
         let is_important = self.is_important();
         let mut title: String = new_title.into();
@@ -220,6 +413,12 @@ impl Section {
         }
     }
 
+    /// If the whole headline is a date, like in journal entries, return
+    /// that.
+    pub fn headline_date(&self) -> Option<VagueDate> {
+        self.title().trim().parse().ok()
+    }
+
     /// If headline resolves to URL, return that.
     pub fn url_title(&self) -> Option<String> {
         if let Ok(url) = only(parse::url)(&self.title()) {
@@ -238,26 +437,158 @@ impl Section {
     pub fn uri(&self) -> Option<String> {
         if let Ok(Some(uri)) = self.attr::<String>("uri") {
             Some(uri)
-        } else if let Some(uri) = self.url_title() {
-            Some(uri)
         } else {
-            None
+            self.url_title()
         }
     }
 
     pub fn entity_identifier(&self) -> Option<EntityIdentifier> {
-        if let Ok(Some(uri)) = self.attr("uri") {
+        if self.is_alias() {
+            // Stubs stand in for the real entity elsewhere.
+            None
+        } else if let Ok(Some(uri)) = self.attr("uri") {
             Some(EntityIdentifier::Uri(uri))
         } else if let Some(url_title) = self.url_title() {
             Some(EntityIdentifier::Uri(url_title))
-        } else if let Some(wiki_title) = self.wiki_title() {
-            Some(EntityIdentifier::WikiTitle(wiki_title))
         } else {
-            None
+            self.wiki_title().map(EntityIdentifier::WikiTitle)
         }
     }
 
+    /// Return whether this is an alias stub for a section that was moved
+    /// to another file.
+    pub fn is_alias(&self) -> bool {
+        self.borrow().attributes.contains_key("alias")
+    }
+
     pub fn has_attributes(&self) -> bool {
         !self.borrow().attributes.is_empty()
     }
 }
+
+/// Error for an attribute value that doesn't parse.
+fn attr_error(name: &str, message: String) -> ParseError {
+    ParseError::new(format!(":{} {}", name, message))
+}
+
+/// Normalize attribute text into the form the outline parser produces.
+///
+/// IDM serialization ends multi-line values with a newline that isn't
+/// there when the value is read back from a file, and pasted blocks may
+/// carry indentation that would be taken as outline structure.
+fn attr_text(text: &str) -> String {
+    let mut lines: Vec<&str> =
+        text.lines().skip_while(|s| s.trim().is_empty()).collect();
+    while lines.last().is_some_and(|s| s.trim().is_empty()) {
+        lines.pop();
+    }
+
+    // Strip the longest run of spaces and tabs that starts every line.
+    let indent = lines
+        .iter()
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| &s[..s.len() - s.trim_start_matches([' ', '\t']).len()])
+        .reduce(|a, b| {
+            let n = a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y);
+            &a[..n.count()]
+        })
+        .unwrap_or("");
+
+    lines
+        .iter()
+        .map(|s| {
+            if s.trim().is_empty() {
+                ""
+            } else {
+                s[indent.len()..].trim_end()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multiline_attr() {
+        let mut sec = Section::new("Title".into(), Default::default());
+        sec.set_attr("notes", &"First line\n  nested\n\nLast".to_string())
+            .unwrap();
+        sec.set_raw_attr("summary", "\n    Pasted\n      block\n\n");
+        sec.set_attr("uri", &"https://example.com".to_string())
+            .unwrap();
+
+        let text = idm::to_string(&RawOutline(
+            (Default::default(),),
+            vec![RawSection::from(&sec)],
+        ))
+        .unwrap();
+        assert_eq!(
+            text,
+            "\
+Title
+  :notes
+    First line
+      nested
+    \n    Last
+  :summary
+    Pasted
+      block
+  :uri https://example.com
+"
+        );
+
+        let outline: RawOutline = idm::from_str(&text).unwrap();
+        let reloaded = Section::from(outline.1.into_iter().next().unwrap());
+        assert_eq!(reloaded.borrow().attributes, sec.borrow().attributes);
+        assert_eq!(
+            reloaded.attr::<String>("notes").unwrap().as_deref(),
+            Some("First line\n  nested\n\nLast")
+        );
+    }
+
+    #[test]
+    fn attr_text_indentation() {
+        assert_eq!(attr_text("  a\n    b\n"), "a\n  b");
+        assert_eq!(attr_text("\ta\n\t  b"), "a\n  b");
+        // Only spaces and tabs are stripped.
+        assert_eq!(attr_text(" x\n\u{3000}y"), " x\n\u{3000}y");
+        assert_eq!(attr_text("\u{3000}x\n\u{3000}y"), "\u{3000}x\n\u{3000}y");
+    }
+
+    #[test]
+    fn test_body_text() {
+        let text = "\
+Title
+  :uri https://example.com
+  : Some prose
+  :
+  : and more.
+  Child
+  : Not body after children
+";
+        let sec: Section = idm::from_str(text).unwrap();
+        assert_eq!(sec.body(), "Some prose\n\nand more.");
+        assert_eq!(
+            sec.children().map(|c| c.headline()).collect::<Vec<_>>(),
+            vec!["Child", ": Not body after children"]
+        );
+        assert_eq!(idm::to_string(&sec).unwrap(), text);
+
+        sec.set_body("");
+        assert_eq!(
+            idm::to_string(&sec).unwrap(),
+            "Title\n  :uri https://example.com\n  Child\n  : Not body after children\n"
+        );
+    }
+
+    #[test]
+    fn indented_body_text() {
+        let text = "Title\n  :  indented\n  : plain\n";
+        let sec: Section = idm::from_str(text).unwrap();
+        assert_eq!(sec.body(), " indented\nplain");
+        assert_eq!(idm::to_string(&sec).unwrap(), text);
+    }
+}
@@ -0,0 +1,107 @@
+//! Collection handle for long-running processes.
+//!
+//! Servers and bots keep one collection loaded and use it from several
+//! threads. Every `read` call sees the collection as it was when the call
+//! started and writes are serialized. Sections are shared nodes, so they
+//! must only be modified inside `write`, and sections taken out of a
+//! `read` call may change under a later writer.
+//!
+//! A panic inside a `read` or `write` call doesn't make the collection
+//! unusable, later calls see whatever state the panicking call left it in.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, RwLock,
+};
+
+use crate::{Collection, Result};
+
+#[derive(Clone)]
+pub struct SharedCollection {
+    collection: Arc<RwLock<Collection>>,
+    /// Number of writes so far, for rebuilding data derived from the
+    /// collection.
+    version: Arc<AtomicU64>,
+}
+
+impl SharedCollection {
+    pub fn new(collection: Collection) -> SharedCollection {
+        SharedCollection {
+            collection: Arc::new(RwLock::new(collection)),
+            version: Default::default(),
+        }
+    }
+
+    /// Look at the collection while holding off writers.
+    pub fn read<T>(&self, f: impl FnOnce(&Collection) -> T) -> T {
+        f(&self.collection.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Change the collection while holding off readers and other writers.
+    pub fn write<T>(&self, f: impl FnOnce(&mut Collection) -> T) -> T {
+        let mut collection =
+            self.collection.write().unwrap_or_else(|e| e.into_inner());
+        // Bump the version after the write even if it panics halfway, so
+        // that derived data is rebuilt.
+        let _bump = Bump(&self.version);
+        f(&mut collection)
+    }
+
+    /// Return a number that changes whenever the collection is written to.
+    ///
+    /// Data derived from the collection during a `read` call is current as
+    /// long as the version read during the same call stays the same.
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    /// Reload files changed on disk by other programs.
+    ///
+    /// Only takes the write lock if there is something to reload. Return
+    /// whether the collection changed.
+    pub fn refresh(&self) -> Result<bool> {
+        let stale = self.read(|col| col.stale_files());
+        if stale.is_empty() {
+            return Ok(false);
+        }
+        self.write(|col| {
+            let mut is_changed = false;
+            for path in stale {
+                is_changed |= col.reload_file(path)?;
+            }
+            Ok(is_changed)
+        })
+    }
+}
+
+/// Increment a version counter when dropped.
+struct Bump<'a>(&'a AtomicU64);
+
+impl Drop for Bump<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_panic_in_write() {
+        let storage = Arc::new(MemoryStorage::new([("Inbox.otl", "Note\n")]));
+        let shared =
+            SharedCollection::new(Collection::in_memory(&storage, |_| true));
+
+        let version = shared.version();
+        let result = std::panic::catch_unwind(|| {
+            shared.write(|_| panic!("Bad request"));
+        });
+        assert!(result.is_err());
+        assert!(shared.version() > version);
+
+        assert_eq!(shared.read(|col| col.files().count()), 1);
+        shared.write(|col| col.root("Inbox.otl").unwrap().set_body("Text"));
+    }
+}
@@ -0,0 +1,146 @@
+//! Splitting oversized files into smaller ones.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use indexmap::IndexMap;
+
+use crate::{Collection, Result, Section, VagueDate};
+
+/// How to divide the toplevel sections of a file into new files.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SplitBy {
+    /// One new file for each year of the `added` or `read` date of the
+    /// sections. Sections without dates are left in place.
+    Year,
+    /// One new file for each toplevel section.
+    Toplevel,
+}
+
+impl FromStr for SplitBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "year" => Ok(SplitBy::Year),
+            "toplevel" => Ok(SplitBy::Toplevel),
+            _ => Err(format!("Unknown split mode {:?}, use year|toplevel", s)),
+        }
+    }
+}
+
+impl fmt::Display for SplitBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitBy::Year => write!(f, "year"),
+            SplitBy::Toplevel => write!(f, "toplevel"),
+        }
+    }
+}
+
+/// Split the toplevel sections of a collection file into new files.
+///
+/// New files go into a directory named after the split file. Each moved
+/// section leaves behind an alias stub with its headline and an `alias`
+/// attribute pointing to the new file, so paths into the old file keep
+/// resolving.
+///
+/// Return the paths of the created files.
+pub fn split_file(
+    collection: &mut Collection,
+    path: impl AsRef<Path>,
+    by: SplitBy,
+) -> Result<Vec<PathBuf>> {
+    let path = path.as_ref();
+    let root = collection
+        .root(path)
+        .ok_or_else(|| format!("File {:?} not found in collection", path))?;
+    let dir = path.with_extension("");
+
+    // Group sections by the name of the new file they're going to.
+    let mut groups: BTreeMap<String, Vec<Section>> = BTreeMap::new();
+    for section in root.children() {
+        if section.is_alias() {
+            // Already split off.
+            continue;
+        }
+
+        let name = match by {
+            SplitBy::Year => match date(&section) {
+                Some(date) => date.year().to_string(),
+                None => continue,
+            },
+            SplitBy::Toplevel => file_name(&section.title()),
+        };
+        groups.entry(name).or_default().push(section);
+    }
+
+    let mut ret = Vec::new();
+    for (name, sections) in groups {
+        let mut new_path = dir.join(format!("{}.otl", name));
+        let mut n = 1;
+        while collection.root(&new_path).is_some() {
+            n += 1;
+            new_path = dir.join(format!("{} {}.otl", name, n));
+        }
+        let alias = crate::collection::file_headline(&new_path);
+
+        let new_root = Section::new(Default::default(), Default::default());
+        for section in sections {
+            let stub = Section::new(
+                section.headline(),
+                IndexMap::from([("alias".to_string(), alias.clone())]),
+            );
+            // Put the stub where the section used to be.
+            section.insert_before(stub);
+            new_root.append(section);
+        }
+
+        collection.insert_file(&new_path, new_root)?;
+        ret.push(new_path);
+    }
+
+    Ok(ret)
+}
+
+fn date(section: &Section) -> Option<VagueDate> {
+    ["added", "read"]
+        .iter()
+        .find_map(|name| section.attr::<VagueDate>(name).ok().flatten())
+}
+
+/// Turn a section title into something usable as a file name.
+fn file_name(title: &str) -> String {
+    let ret: String = title
+        .trim()
+        .chars()
+        .map(|c| {
+            if c == '/' || c == '\\' || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    match ret.as_str() {
+        "" | "." | ".." => "_".into(),
+        _ => ret,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("Foo"), "Foo");
+        assert_eq!(file_name(" a/b "), "a_b");
+        assert_eq!(file_name(".."), "_");
+        assert_eq!(file_name(""), "_");
+    }
+}
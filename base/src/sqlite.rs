@@ -0,0 +1,429 @@
+//! SQLite storage for collections.
+//!
+//! The database has the parsed sections of every file so that it can be
+//! queried with SQL:
+//!
+//! ```notrust
+//! files(path, modified, indentation)
+//! sections(id, file, parent, position, depth, headline, body)
+//! attributes(section, position, name, value)
+//! links(section, target)
+//! ```
+//!
+//! Sections are numbered in pre-order within their file by `position`,
+//! the file's root section has depth 0. `links` has the WikiWords and
+//! `[[bracketed links]]` in headlines and bodies. Files are rebuilt from
+//! their sections when they are read.
+//!
+//! `SqliteStorage` keeps a collection in the database alone. `Mirror` keeps
+//! the `.otl` files as the collection and updates the database whenever
+//! they are saved or found to have been changed by another program.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use idm::ser::Indentation;
+use indexmap::IndexMap;
+use rusqlite::{params, types::ValueRef, Connection, OpenFlags, Transaction};
+
+use crate::{
+    collection::{build_section, file_headline, parse_outline},
+    parse,
+    section::{RawSection, SectionData},
+    storage::{FileStorage, Storage, DATABASE_FILE},
+    xdg, Result, Section,
+};
+
+const SCHEMA: &str = "
+PRAGMA foreign_keys = ON;
+
+CREATE TABLE IF NOT EXISTS files (
+    path TEXT PRIMARY KEY,
+    -- Nanoseconds since the Unix epoch.
+    modified INTEGER NOT NULL,
+    -- Number of spaces per level, 0 for tabs.
+    indentation INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sections (
+    id INTEGER PRIMARY KEY,
+    file TEXT NOT NULL REFERENCES files(path) ON DELETE CASCADE,
+    parent INTEGER REFERENCES sections(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    depth INTEGER NOT NULL,
+    headline TEXT NOT NULL,
+    body TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sections_file ON sections(file, position);
+CREATE INDEX IF NOT EXISTS sections_headline ON sections(headline);
+
+CREATE TABLE IF NOT EXISTS attributes (
+    section INTEGER NOT NULL REFERENCES sections(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS attributes_section ON attributes(section);
+CREATE INDEX IF NOT EXISTS attributes_name ON attributes(name, value);
+
+CREATE TABLE IF NOT EXISTS links (
+    section INTEGER NOT NULL REFERENCES sections(id) ON DELETE CASCADE,
+    target TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS links_section ON links(section);
+CREATE INDEX IF NOT EXISTS links_target ON links(target);
+";
+
+/// Collection kept in a SQLite database.
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: impl AsRef<Path>) -> Result<SqliteStorage> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(SqliteStorage {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Write a file with the given modification time.
+    fn put(&self, path: &Path, text: &str, modified: SystemTime) -> Result<()> {
+        let (style, outline) = parse_outline(path, text)?;
+        let root = build_section(file_headline(path), outline);
+        let indentation = match style {
+            Indentation::Tabs => 0,
+            Indentation::Spaces(n) => n as i64,
+        };
+        let path = path_key(path);
+
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection.transaction()?;
+        tx.execute("DELETE FROM files WHERE path = ?1", [&path])?;
+        tx.execute(
+            "INSERT INTO files (path, modified, indentation)
+             VALUES (?1, ?2, ?3)",
+            params![path, nanos(modified), indentation],
+        )?;
+        insert_section(&tx, &path, None, 0, &mut 0, &root)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn paths(&self) -> Result<Vec<PathBuf>> {
+        let connection = self.connection.lock().unwrap();
+        let mut stmt = connection.prepare("SELECT path FROM files")?;
+        let paths = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|p| p.map(PathBuf::from))
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(paths)
+    }
+
+    fn read(&self, path: &Path) -> Result<String> {
+        let key = path_key(path);
+        let connection = self.connection.lock().unwrap();
+        let indentation: i64 = connection
+            .query_row(
+                "SELECT indentation FROM files WHERE path = ?1",
+                [&key],
+                |row| row.get(0),
+            )
+            .map_err(|_| format!("{:?} not found in database", path))?;
+
+        let mut attributes: HashMap<i64, IndexMap<String, String>> =
+            HashMap::new();
+        let mut stmt = connection.prepare(
+            "SELECT a.section, a.name, a.value FROM attributes a
+             JOIN sections s ON a.section = s.id
+             WHERE s.file = ?1 ORDER BY a.section, a.position",
+        )?;
+        let mut rows = stmt.query([&key])?;
+        while let Some(row) = rows.next()? {
+            attributes
+                .entry(row.get(0)?)
+                .or_default()
+                .insert(row.get(1)?, row.get(2)?);
+        }
+
+        // Sections come in pre-order, keep the path of open ancestors.
+        let mut stack: Vec<Section> = Vec::new();
+        let mut stmt = connection.prepare(
+            "SELECT id, depth, headline, body FROM sections
+             WHERE file = ?1 ORDER BY position",
+        )?;
+        let mut rows = stmt.query([&key])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let depth: i64 = row.get(1)?;
+            let section = Section::from(SectionData {
+                headline: row.get(2)?,
+                attributes: attributes.remove(&id).unwrap_or_default(),
+                body: row.get(3)?,
+            });
+            stack.truncate(depth as usize);
+            if let Some(parent) = stack.last() {
+                parent.append(section.clone());
+            }
+            stack.push(section);
+        }
+        let root = stack
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("{:?} has no sections in database", path))?;
+
+        let style = match indentation {
+            0 => Indentation::Tabs,
+            n => Indentation::Spaces(n as usize),
+        };
+        Ok(idm::to_string_styled(
+            style,
+            &RawSection::from(&root).outline(),
+        )?)
+    }
+
+    fn write(&self, path: &Path, text: &str) -> Result<()> {
+        self.put(path, text, SystemTime::now())
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM files WHERE path = ?1", [path_key(path)])?;
+        Ok(())
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        let nanos: i64 = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT modified FROM files WHERE path = ?1",
+                [path_key(path)],
+                |row| row.get(0),
+            )
+            .ok()?;
+        Some(UNIX_EPOCH + Duration::from_nanos(nanos as u64))
+    }
+}
+
+/// `.otl` files with a database kept in sync with them.
+///
+/// The database is in the collection's cache directory.
+pub struct Mirror {
+    files: FileStorage,
+    db: SqliteStorage,
+}
+
+impl Mirror {
+    pub fn open(root: &Path) -> Result<Mirror> {
+        let path = xdg::path(root, xdg::Dir::Cache, DATABASE_FILE);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let ret = Mirror {
+            files: FileStorage::new(root),
+            db: SqliteStorage::open(path)?,
+        };
+        let count = ret.sync()?;
+        if count > 0 {
+            log::info!("Mirror: Updated {} files in database", count);
+        }
+        Ok(ret)
+    }
+
+    /// Update the database for files that were changed, added or removed
+    /// by other programs.
+    ///
+    /// Return the number of files updated.
+    pub fn sync(&self) -> Result<usize> {
+        let paths = self.files.paths()?;
+        let mut count = 0;
+        for path in &paths {
+            let Some(modified) = self.files.modified(path) else {
+                continue;
+            };
+            if self.db.modified(path) != Some(modified) {
+                self.db.put(path, &self.files.read(path)?, modified)?;
+                count += 1;
+            }
+        }
+        for path in self.db.paths()? {
+            if !paths.contains(&path) {
+                self.db.remove(&path)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+impl Storage for Mirror {
+    fn paths(&self) -> Result<Vec<PathBuf>> {
+        self.files.paths()
+    }
+
+    fn read(&self, path: &Path) -> Result<String> {
+        self.files.read(path)
+    }
+
+    fn write(&self, path: &Path, text: &str) -> Result<()> {
+        self.files.write(path, text)?;
+        // The next sync fixes the database if this fails.
+        let modified = self.files.modified(path).unwrap_or(UNIX_EPOCH);
+        if let Err(e) = self.db.put(path, text, modified) {
+            log::warn!("Failed to update {:?} in database: {}", path, e);
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        self.files.remove(path)?;
+        if let Err(e) = self.db.remove(path) {
+            log::warn!("Failed to remove {:?} from database: {}", path, e);
+        }
+        Ok(())
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        self.files.modified(path)
+    }
+}
+
+/// Run a read-only SQL query on a collection database.
+///
+/// Return the column names and the rows with values formatted as text.
+pub fn query(
+    path: impl AsRef<Path>,
+    sql: &str,
+) -> Result<(Vec<String>, Vec<Vec<String>>)> {
+    let connection =
+        Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = connection.prepare(sql)?;
+    let columns: Vec<String> =
+        stmt.column_names().iter().map(|s| s.to_string()).collect();
+
+    let mut ret = Vec::new();
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let mut values = Vec::new();
+        for i in 0..columns.len() {
+            values.push(match row.get_ref(i)? {
+                ValueRef::Null => String::new(),
+                ValueRef::Integer(n) => n.to_string(),
+                ValueRef::Real(x) => x.to_string(),
+                ValueRef::Text(s) => String::from_utf8_lossy(s).to_string(),
+                ValueRef::Blob(b) => format!("<{} bytes>", b.len()),
+            });
+        }
+        ret.push(values);
+    }
+    Ok((columns, ret))
+}
+
+/// Insert a section and its descendants, numbering them from position.
+fn insert_section(
+    tx: &Transaction,
+    file: &str,
+    parent: Option<i64>,
+    depth: usize,
+    position: &mut i64,
+    section: &Section,
+) -> Result<()> {
+    let headline = section.headline();
+    let body = section.body();
+    tx.execute(
+        "INSERT INTO sections (file, parent, position, depth, headline, body)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![file, parent, *position, depth as i64, headline, body],
+    )?;
+    let id = tx.last_insert_rowid();
+    *position += 1;
+
+    for (i, (name, value)) in section.borrow().attributes.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO attributes (section, position, name, value)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![id, i as i64, name, value],
+        )?;
+    }
+
+    let text = format!("{}\n{}", headline, body);
+    for line in text.lines() {
+        for target in parse::wiki_words(line)
+            .into_iter()
+            .chain(parse::bracket_links(line))
+        {
+            tx.execute(
+                "INSERT INTO links (section, target) VALUES (?1, ?2)",
+                params![id, target],
+            )?;
+        }
+    }
+
+    for child in section.children() {
+        insert_section(tx, file, Some(id), depth + 1, position, &child)?;
+    }
+    Ok(())
+}
+
+/// Database key for a collection path, with `/` separators everywhere.
+fn path_key(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn nanos(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let db = SqliteStorage::open(":memory:").unwrap();
+        let text = "\
+:tags reading
+Reading
+  :uri https://example.com/
+  Notes on the ReadingList
+  and [[another page]].
+  Child
+Other
+";
+        db.write(Path::new("notes/Books.otl"), text).unwrap();
+        assert_eq!(db.paths().unwrap(), vec![PathBuf::from("notes/Books.otl")]);
+        assert!(db.exists(Path::new("notes/Books.otl")));
+        assert_eq!(db.read(Path::new("notes/Books.otl")).unwrap(), text);
+
+        let connection = db.connection.lock().unwrap();
+        let links: Vec<String> = connection
+            .prepare("SELECT target FROM links ORDER BY target")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(links, vec!["ReadingList", "another page"]);
+        drop(connection);
+
+        db.remove(Path::new("notes/Books.otl")).unwrap();
+        assert!(db.paths().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+//! Spaced repetition of cloze cards without Anki.
+//!
+//! Cards are made from `{braced}` cloze deletions in headlines the same way
+//! as for `olt anki`, with one card for each deletion. The review state of
+//! the cards is kept in `srs.json` in the collection's state directory, so
+//! reviewing doesn't change the notebook. Cards are keyed by their text, so
+//! editing a line starts its cards over.
+//!
+//! Reviews are graded from 0 to 5 and the next review is scheduled with the
+//! SM-2 algorithm: grades below 3 start the card over from a one day
+//! interval, the interval grows by the card's easiness factor otherwise.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{minhash::fnv1a, parse, trash, xdg, Collection, Result, Section};
+
+/// Name of the review state file in the state directory.
+pub const SRS_FILE: &str = "srs.json";
+
+/// Easiness factor of new cards.
+const INITIAL_EASINESS: f64 = 2.5;
+
+/// Lowest easiness factor, cards don't get scheduled more often than this.
+const MIN_EASINESS: f64 = 1.3;
+
+/// Highest review grade.
+pub const MAX_GRADE: u32 = 5;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Card {
+    /// Key of the card in the review state.
+    pub id: String,
+    /// The line with the deletion replaced by `[...]`.
+    pub front: String,
+    /// The line with the deletion in brackets.
+    pub back: String,
+}
+
+/// Make a card for each cloze deletion in the section's headline.
+pub fn cards(section: &Section) -> Vec<Card> {
+    let title = section.title();
+    let segments = parse::cloze(&title);
+
+    let mut ret = Vec::new();
+    for (i, _) in segments.iter().enumerate().filter(|(_, (_, h))| *h) {
+        let show = |blank: &dyn Fn(&str) -> String| -> String {
+            segments
+                .iter()
+                .enumerate()
+                .map(|(j, &(text, _))| {
+                    if i == j {
+                        blank(text)
+                    } else {
+                        text.to_string()
+                    }
+                })
+                .collect()
+        };
+        let front = show(&|_| "[...]".to_string());
+        let back = show(&|text| format!("[{}]", text));
+        ret.push(Card {
+            id: format!("{:016x}", fnv1a(&back)),
+            front,
+            back,
+        });
+    }
+    ret
+}
+
+/// Return the cards of the whole collection, not including the trash.
+pub fn collection_cards(collection: &Collection) -> Vec<Card> {
+    let mut ret = Vec::new();
+    for (path, root) in collection.files() {
+        if !trash::is_trash(path) {
+            for section in root.iter() {
+                ret.extend(cards(&section));
+            }
+        }
+    }
+    ret
+}
+
+/// Review schedule of a card.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Schedule {
+    pub easiness: f64,
+    /// Days from the last review to the next one.
+    pub interval: u64,
+    /// Number of successful reviews in a row.
+    pub repetitions: u32,
+    pub due: NaiveDate,
+}
+
+impl Schedule {
+    /// Schedule for a new card that is due right away.
+    pub fn new(today: NaiveDate) -> Schedule {
+        Schedule {
+            easiness: INITIAL_EASINESS,
+            interval: 0,
+            repetitions: 0,
+            due: today,
+        }
+    }
+
+    /// Update the schedule with a review grade from 0 to 5.
+    pub fn review(&mut self, grade: u32, today: NaiveDate) {
+        let grade = grade.min(MAX_GRADE);
+        if grade >= 3 {
+            self.interval = match self.repetitions {
+                0 => 1,
+                1 => 6,
+                _ => (self.interval as f64 * self.easiness).round() as u64,
+            };
+            self.repetitions += 1;
+        } else {
+            self.interval = 1;
+            self.repetitions = 0;
+        }
+
+        let miss = (MAX_GRADE - grade) as f64;
+        self.easiness = (self.easiness + 0.1 - miss * (0.08 + miss * 0.02))
+            .max(MIN_EASINESS);
+        self.due = today + Days::new(self.interval);
+    }
+}
+
+/// Review schedules of a collection's cards.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct State(BTreeMap<String, Schedule>);
+
+impl State {
+    /// Load the state of the collection at root, an empty state if there
+    /// isn't one yet.
+    pub fn load(root: impl AsRef<Path>) -> Result<State> {
+        let path = xdg::path(root, xdg::Dir::State, SRS_FILE);
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, root: impl AsRef<Path>) -> Result<()> {
+        let path = xdg::path(root, xdg::Dir::State, SRS_FILE);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Saved after every card, don't leave a truncated file behind if
+        // the program dies mid-write.
+        crate::storage::write_atomic(
+            &path,
+            &serde_json::to_string_pretty(self)?,
+        )
+    }
+
+    /// Return the schedule of a card, new cards get one that is due today.
+    pub fn schedule(&self, card: &Card, today: NaiveDate) -> Schedule {
+        self.0
+            .get(&card.id)
+            .cloned()
+            .unwrap_or_else(|| Schedule::new(today))
+    }
+
+    pub fn is_new(&self, card: &Card) -> bool {
+        !self.0.contains_key(&card.id)
+    }
+
+    /// Record a review of a card.
+    pub fn review(&mut self, card: &Card, grade: u32, today: NaiveDate) {
+        let mut schedule = self.schedule(card, today);
+        schedule.review(grade, today);
+        self.0.insert(card.id.clone(), schedule);
+    }
+
+    /// Return the cards due for review by today, most overdue first.
+    ///
+    /// New cards come after the cards already being reviewed.
+    pub fn due(&self, cards: Vec<Card>, today: NaiveDate) -> Vec<Card> {
+        let mut ret: Vec<(bool, NaiveDate, Card)> = cards
+            .into_iter()
+            .map(|c| (self.is_new(&c), self.schedule(&c, today).due, c))
+            .filter(|(_, due, _)| *due <= today)
+            .collect();
+        // Stable sort, new cards stay in collection order.
+        ret.sort_by_key(|(new, due, _)| (*new, *due));
+        ret.into_iter().map(|(_, _, c)| c).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cards() {
+        let s: Section = idm::from_str("A {cat} is a {mammal}").unwrap();
+        let cards = cards(&s);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].front, "A [...] is a mammal");
+        assert_eq!(cards[0].back, "A [cat] is a mammal");
+        assert_eq!(cards[1].front, "A cat is a [...]");
+        assert_ne!(cards[0].id, cards[1].id);
+    }
+
+    #[test]
+    fn test_schedule() {
+        let day = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        let mut s = Schedule::new(day(1));
+        s.review(5, day(1));
+        assert_eq!((s.interval, s.due), (1, day(2)));
+        s.review(4, day(2));
+        assert_eq!((s.interval, s.due), (6, day(8)));
+        s.review(4, day(8));
+        assert_eq!((s.interval, s.due), (16, day(24)));
+        assert_eq!(s.easiness, 2.6);
+
+        s.review(1, day(24));
+        assert_eq!((s.interval, s.repetitions, s.due), (1, 0, day(25)));
+        assert!(s.easiness < 2.6);
+
+        for _ in 0..10 {
+            s.review(0, day(25));
+        }
+        assert_eq!(s.easiness, MIN_EASINESS);
+    }
+}
@@ -0,0 +1,316 @@
+//! Where the files of a collection are kept.
+//!
+//! A collection is normally a directory of `.otl` files. The `storage`
+//! setting can also keep a SQLite database in sync with the files or keep
+//! the collection in a database only, see the `sqlite` module:
+//!
+//! ```notrust
+//! storage files
+//! storage mirror
+//! storage sqlite
+//! ```
+//!
+//! The collection works on the text of whole files either way and uses
+//! collection-relative paths like `notes/Reading.otl` for them.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use serde_with::DeserializeFromStr;
+
+use crate::{collection::otl_files, xdg, Result};
+
+/// Name of the SQLite database, in the collection directory if there are
+/// no `.otl` files and in the cache directory if it mirrors them.
+pub const DATABASE_FILE: &str = "collection.db";
+
+/// Backing store for the files of a collection.
+pub trait Storage: Send + Sync {
+    /// List the collection paths of the files in storage.
+    fn paths(&self) -> Result<Vec<PathBuf>>;
+
+    /// Read the text of a file.
+    fn read(&self, path: &Path) -> Result<String>;
+
+    /// Replace the text of a file.
+    ///
+    /// The file has either the old or the new text even if the program is
+    /// interrupted.
+    fn write(&self, path: &Path, text: &str) -> Result<()>;
+
+    fn remove(&self, path: &Path) -> Result<()>;
+
+    /// Return when a file was last written, or `None` if there is no such
+    /// file.
+    fn modified(&self, path: &Path) -> Option<SystemTime>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.modified(path).is_some()
+    }
+}
+
+/// Storage setting value.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, DeserializeFromStr)]
+pub enum StorageKind {
+    /// `.otl` files in the collection directory.
+    #[default]
+    Files,
+    /// `.otl` files with a SQLite database that is kept in sync with them.
+    Mirror,
+    /// SQLite database in the collection directory with no `.otl` files.
+    Sqlite,
+}
+
+impl FromStr for StorageKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            "files" => Ok(StorageKind::Files),
+            "mirror" => Ok(StorageKind::Mirror),
+            "sqlite" => Ok(StorageKind::Sqlite),
+            _ => Err(format!(
+                "Bad storage {:?}, must be files, mirror or sqlite",
+                s
+            )),
+        }
+    }
+}
+
+impl StorageKind {
+    /// Open the storage of a collection at root.
+    pub fn open(self, root: &Path) -> Result<Arc<dyn Storage>> {
+        match self {
+            StorageKind::Files => Ok(Arc::new(FileStorage::new(root))),
+            #[cfg(feature = "sqlite")]
+            StorageKind::Mirror => {
+                Ok(Arc::new(crate::sqlite::Mirror::open(root)?))
+            }
+            #[cfg(feature = "sqlite")]
+            StorageKind::Sqlite => {
+                fs::create_dir_all(root)?;
+                Ok(Arc::new(crate::sqlite::SqliteStorage::open(
+                    root.join(DATABASE_FILE),
+                )?))
+            }
+            #[cfg(not(feature = "sqlite"))]
+            _ => Err("otlbook was built without SQLite support")?,
+        }
+    }
+
+    /// Path of the SQLite database of a collection at root, if it has one.
+    pub fn database_path(self, root: &Path) -> Option<PathBuf> {
+        match self {
+            StorageKind::Files => None,
+            StorageKind::Mirror => {
+                Some(xdg::path(root, xdg::Dir::Cache, DATABASE_FILE))
+            }
+            StorageKind::Sqlite => Some(root.join(DATABASE_FILE)),
+        }
+    }
+}
+
+/// Directory of `.otl` files.
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(root: impl Into<PathBuf>) -> FileStorage {
+        FileStorage { root: root.into() }
+    }
+}
+
+impl Storage for FileStorage {
+    fn paths(&self) -> Result<Vec<PathBuf>> {
+        Ok(otl_files(&self.root)
+            .into_iter()
+            .filter_map(|p| p.strip_prefix(&self.root).ok().map(Path::to_owned))
+            .collect())
+    }
+
+    fn read(&self, path: &Path) -> Result<String> {
+        Ok(fs::read_to_string(self.root.join(path))?)
+    }
+
+    fn write(&self, path: &Path, text: &str) -> Result<()> {
+        let path = self.root.join(path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomic(&path, text)
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        Ok(fs::remove_file(self.root.join(path))?)
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        fs::metadata(self.root.join(path))
+            .and_then(|m| m.modified())
+            .ok()
+    }
+}
+
+/// Write a file so that it has either the old or the new contents even if
+/// the program is interrupted.
+///
+/// The contents are written to a temporary file in the same directory that
+/// is then renamed over the target. If the path is a symlink, the file it
+/// points to is replaced instead of the link, and the new file keeps the
+/// permissions of the old one.
+pub(crate) fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    let path = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(path)?,
+        _ => path.to_owned(),
+    };
+    // Writers in other threads and processes, like a running server, get
+    // temporary files of their own.
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let name = path.file_name().ok_or("write_atomic: Bad path")?;
+    let tmp = path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        name.to_string_lossy(),
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut file = fs::File::create_new(&tmp)?;
+    let written = file
+        .write_all(contents.as_bytes())
+        .and_then(|_| file.sync_all())
+        .and_then(|_| match fs::metadata(&path) {
+            Ok(meta) => fs::set_permissions(&tmp, meta.permissions()),
+            Err(_) => Ok(()),
+        });
+    if let Err(e) = written.and_then(|_| fs::rename(&tmp, &path)) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Storage that keeps files in memory, for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MemoryStorage {
+    /// Text of each file and the write count when it was last written.
+    files: std::sync::Mutex<std::collections::BTreeMap<PathBuf, (String, u64)>>,
+    writes: std::sync::atomic::AtomicU64,
+}
+
+#[cfg(test)]
+impl MemoryStorage {
+    pub fn new<'a>(
+        files: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> MemoryStorage {
+        let ret = MemoryStorage::default();
+        for (path, text) in files {
+            ret.write(Path::new(path), text).unwrap();
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+impl Storage for MemoryStorage {
+    fn paths(&self) -> Result<Vec<PathBuf>> {
+        Ok(self.files.lock().unwrap().keys().cloned().collect())
+    }
+
+    fn read(&self, path: &Path) -> Result<String> {
+        match self.files.lock().unwrap().get(path) {
+            Some((text, _)) => Ok(text.clone()),
+            None => Err(format!("{:?} not found", path))?,
+        }
+    }
+
+    fn write(&self, path: &Path, text: &str) -> Result<()> {
+        // Every write gets a later modification time.
+        let n = self
+            .writes
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+            + 1;
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), (text.to_string(), n));
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        match self.files.lock().unwrap().remove(path) {
+            Some(_) => Ok(()),
+            None => Err(format!("{:?} not found", path))?,
+        }
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        self.files.lock().unwrap().get(path).map(|(_, n)| {
+            SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(*n)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "otlbook-storage-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = test_dir("write");
+        let path = dir.join("file.otl");
+        write_atomic(&path, "old\n").unwrap();
+        write_atomic(&path, "new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        // The temporary file is gone.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_symlink() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("symlink");
+        let target = dir.join("target.otl");
+        let link = dir.join("link.otl");
+        fs::write(&target, "old\n").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600))
+            .unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomic(&link, "new\n").unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new\n");
+        assert_eq!(
+            fs::metadata(&target).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
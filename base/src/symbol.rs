@@ -29,6 +29,7 @@ impl Default for Sym<String> {
 }
 
 impl<T: AsRef<str>> Sym<T> {
+    #[allow(clippy::result_unit_err)]
     pub fn new<U: Into<T>>(value: U) -> Result<Self, ()> {
         let value = value.into();
 
@@ -42,20 +43,14 @@ impl<T: AsRef<str>> Sym<T> {
     }
 }
 
-impl<'a, T: AsRef<str> + FromStr<Err = E>, E: Error + 'static> FromStr
-    for Sym<T>
-{
+impl<T: AsRef<str> + FromStr<Err = E>, E: Error + 'static> FromStr for Sym<T> {
     type Err = Box<dyn Error>;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let inner = T::from_str(s)?;
         match Sym::new(inner) {
-            Err(_) => {
-                return Err("err")?;
-            }
-            Ok(ok) => {
-                return Ok(ok);
-            }
+            Err(_) => Err("err")?,
+            Ok(ok) => Ok(ok),
         }
     }
 }
@@ -130,7 +125,6 @@ impl fmt::Display for Uri {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::sym;
 
     #[test]
     fn test_symbol() {
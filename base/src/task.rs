@@ -0,0 +1,246 @@
+//! Tasks marked with checkboxes in headlines.
+//!
+//! A task headline starts with `[_]` when it's pending or `[X]` when it's
+//! done, optionally followed by a completion percentage:
+//!
+//! ```notrust
+//! [_] 50% Move house
+//!     [X] Pack books
+//!     [_] Hire a van
+//! ```
+//!
+//! The percentage of a task with subtasks is the average completion of the
+//! subtasks, and the task is done when all of them are, see `update`.
+
+use std::fmt;
+
+use crate::Section;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum State {
+    Pending,
+    Done,
+}
+
+/// Task parsed from a headline.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Task {
+    pub state: State,
+    /// Completion percentage if the headline shows one.
+    pub percent: Option<u32>,
+    /// Rest of the headline.
+    pub title: String,
+}
+
+impl Task {
+    /// Parse a task headline, return `None` if the headline isn't a task.
+    pub fn parse(headline: &str) -> Option<Task> {
+        let (state, rest) = if let Some(rest) = headline.strip_prefix("[_]") {
+            (State::Pending, rest)
+        } else if let Some(rest) = headline
+            .strip_prefix("[X]")
+            .or_else(|| headline.strip_prefix("[x]"))
+        {
+            (State::Done, rest)
+        } else {
+            return None;
+        };
+
+        // The marker must be a word of its own.
+        if !rest.is_empty() && !rest.starts_with(' ') {
+            return None;
+        }
+        let rest = rest.trim_start();
+
+        let mut percent = None;
+        let mut title = rest;
+        let (word, tail) = rest.split_once(' ').unwrap_or((rest, ""));
+        if let Some(n) = word
+            .strip_suffix('%')
+            .and_then(|n| n.parse::<u32>().ok())
+            .filter(|&n| n <= 100)
+        {
+            percent = Some(n);
+            title = tail.trim_start();
+        }
+
+        Some(Task {
+            state,
+            percent,
+            title: title.to_string(),
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Completion from 0 to 100.
+    pub fn completion(&self) -> u32 {
+        match self.state {
+            State::Done => 100,
+            State::Pending => self.percent.unwrap_or(0),
+        }
+    }
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.state {
+            State::Pending => write!(f, "[_]")?,
+            State::Done => write!(f, "[X]")?,
+        }
+        if let Some(percent) = self.percent {
+            write!(f, " {}%", percent)?;
+        }
+        if !self.title.is_empty() {
+            write!(f, " {}", self.title)?;
+        }
+        Ok(())
+    }
+}
+
+impl Section {
+    /// Return the task in the headline if it has one.
+    pub fn task(&self) -> Option<Task> {
+        Task::parse(&self.headline())
+    }
+
+    /// Write a task into the headline.
+    ///
+    /// The section is only marked as changed if the headline changes.
+    pub fn set_task(&self, task: &Task) {
+        let headline = task.to_string();
+        if headline != self.headline() {
+            self.set_headline(headline);
+        }
+    }
+}
+
+/// Return the tasks in and under a section in the order they're written.
+pub fn tasks(section: &Section) -> Vec<(Section, Task)> {
+    fn walk(section: Section, output: &mut Vec<(Section, Task)>) {
+        if let Some(task) = section.task() {
+            output.push((section.clone(), task));
+        }
+        for c in section.children() {
+            walk(c, output);
+        }
+    }
+
+    let mut ret = Vec::new();
+    walk(section.clone(), &mut ret);
+    ret
+}
+
+/// Update the percentages and states of tasks with subtasks under a
+/// section.
+///
+/// Tasks without subtasks are left as they are. Return the number of tasks
+/// that changed.
+pub fn update(section: &Section) -> usize {
+    let mut count = 0;
+    for c in section.children() {
+        count += update(&c);
+    }
+
+    let Some(task) = section.task() else {
+        return count;
+    };
+    let subtasks: Vec<Task> =
+        section.children().filter_map(|c| c.task()).collect();
+    if subtasks.is_empty() {
+        return count;
+    }
+
+    let total: u32 = subtasks.iter().map(Task::completion).sum();
+    let percent = total / subtasks.len() as u32;
+    let new = Task {
+        state: if subtasks.iter().all(Task::is_done) {
+            State::Done
+        } else {
+            State::Pending
+        },
+        percent: Some(percent),
+        ..task.clone()
+    };
+    if new != task {
+        section.set_task(&new);
+        count += 1;
+    }
+    count
+}
+
+/// Flip a task between pending and done and update the tasks above it.
+///
+/// Return `false` if the section isn't a task.
+pub fn toggle(section: &Section) -> bool {
+    let Some(mut task) = section.task() else {
+        return false;
+    };
+    task.state = match task.state {
+        State::Pending => State::Done,
+        State::Done => State::Pending,
+    };
+    // Keep a percentage on a task without subtasks consistent with it.
+    if task.percent.is_some() {
+        task.percent = Some(if task.is_done() { 100 } else { 0 });
+    }
+    section.set_task(&task);
+
+    let mut root = section.clone();
+    while let Some(parent) = root.parent() {
+        root = parent;
+    }
+    update(&root);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Task::parse("Not a task"), None);
+        assert_eq!(Task::parse("[_]x"), None);
+        assert_eq!(
+            Task::parse("[_] 40% Move house"),
+            Some(Task {
+                state: State::Pending,
+                percent: Some(40),
+                title: "Move house".into()
+            })
+        );
+        assert_eq!(
+            Task::parse("[x] 200% done").unwrap().to_string(),
+            "[X] 200% done"
+        );
+        assert_eq!(Task::parse("[_]").unwrap().to_string(), "[_]");
+    }
+
+    #[test]
+    fn test_update() {
+        let top: Section = idm::from_str(
+            "\
+[_] Move house
+  [X] Pack books
+  [_] 50% Hire a van
+  Notes
+  [_] Clean",
+        )
+        .unwrap();
+        assert_eq!(update(&top), 1);
+        assert_eq!(top.headline(), "[_] 50% Move house");
+
+        let clean = top.children().last().unwrap();
+        assert!(toggle(&clean));
+        assert_eq!(clean.headline(), "[X] Clean");
+        assert_eq!(top.headline(), "[_] 83% Move house");
+
+        let van = top.children().nth(1).unwrap();
+        assert!(toggle(&van));
+        assert_eq!(van.headline(), "[X] 100% Hire a van");
+        assert_eq!(top.headline(), "[X] 100% Move house");
+    }
+}
@@ -0,0 +1,124 @@
+//! API tokens for programs that edit the collection over HTTP.
+//!
+//! Tokens are kept in `api-tokens.idm` in the collection's config
+//! directory, one name and token per line:
+//!
+//! ```notrust
+//! phone 3f9c0e6d1b2a4f5e8c7d6b5a4f3e2d1c
+//! ```
+//!
+//! The name only tells the tokens apart, removing a line revokes the token.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use rand::Rng;
+
+use crate::{xdg, Result};
+
+/// Name of the token file in the config directory.
+pub const TOKENS_FILE: &str = "api-tokens.idm";
+
+/// Named API tokens of a collection.
+#[derive(Clone, Debug, Default)]
+pub struct Tokens(BTreeMap<String, String>);
+
+impl Tokens {
+    pub fn load(root: impl AsRef<Path>) -> Result<Tokens> {
+        let path = xdg::path(root, xdg::Dir::Config, TOKENS_FILE);
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+        let text = fs::read_to_string(&path)?;
+        let tokens = idm::from_str(&text)
+            .map_err(|e| format!("Bad API tokens in {:?}: {}", path, e))?;
+        Ok(Tokens(tokens))
+    }
+
+    pub fn save(&self, root: impl AsRef<Path>) -> Result<()> {
+        let path = xdg::path(root, xdg::Dir::Config, TOKENS_FILE);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, idm::to_string(&self.0)?)?;
+        Ok(())
+    }
+
+    /// Iterate names of the tokens.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(|s| s.as_str())
+    }
+
+    /// Make a new random token with the given name, replacing any old
+    /// token with that name.
+    pub fn generate(&mut self, name: &str) -> Result<String> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("Bad token name {:?}", name))?;
+        }
+        let bytes: [u8; 16] = rand::thread_rng().gen();
+        let token: String =
+            bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        self.0.insert(name.to_string(), token.clone());
+        Ok(token)
+    }
+
+    /// Remove a token, return whether it existed.
+    pub fn revoke(&mut self, name: &str) -> bool {
+        self.0.remove(name).is_some()
+    }
+
+    /// Return the name of the token if it's valid.
+    pub fn check(&self, token: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(_, t)| same_bytes(t.as_bytes(), token.as_bytes()))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Compare strings in time that doesn't depend on where they differ.
+fn same_bytes(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens() {
+        let mut tokens = Tokens::default();
+        assert!(tokens.generate("").is_err());
+        assert!(tokens.generate("my phone").is_err());
+
+        let phone = tokens.generate("phone").unwrap();
+        assert_eq!(phone.len(), 32);
+        assert!(phone.chars().all(|c| c.is_ascii_hexdigit()));
+        let laptop = tokens.generate("laptop").unwrap();
+        assert_ne!(phone, laptop);
+        assert_eq!(tokens.names().collect::<Vec<_>>(), vec!["laptop", "phone"]);
+
+        assert_eq!(tokens.check(&phone), Some("phone"));
+        assert_eq!(tokens.check(&laptop), Some("laptop"));
+        assert_eq!(tokens.check(&phone[..31]), None);
+        assert_eq!(tokens.check(""), None);
+
+        // A new token replaces the old one.
+        let new_phone = tokens.generate("phone").unwrap();
+        assert_eq!(tokens.check(&phone), None);
+        assert_eq!(tokens.check(&new_phone), Some("phone"));
+
+        assert!(tokens.revoke("phone"));
+        assert!(!tokens.revoke("phone"));
+        assert_eq!(tokens.check(&new_phone), None);
+    }
+
+    #[test]
+    fn test_same_bytes() {
+        assert!(same_bytes(b"", b""));
+        assert!(same_bytes(b"abc", b"abc"));
+        assert!(!same_bytes(b"abc", b"abd"));
+        assert!(!same_bytes(b"abc", b"ab"));
+        assert!(!same_bytes(b"xbc", b"abc"));
+    }
+}
@@ -0,0 +1,182 @@
+//! Trash for deleted sections.
+//!
+//! Deleted sections are moved into a trash file in the collection instead of
+//! being destroyed outright. The trash file has a toplevel section for each
+//! day something was deleted, and the deleted sections under it are stamped
+//! with `deleted` and `trashed-from` attributes so they can be restored
+//! later.
+
+use std::path::Path;
+
+use crate::{Collection, Result, Section, VagueDate};
+
+/// Collection-relative path of the trash file.
+pub const TRASH_FILE: &str = "Trash.otl";
+
+/// Return whether the collection file at path is the trash.
+pub fn is_trash(path: impl AsRef<Path>) -> bool {
+    path.as_ref() == Path::new(TRASH_FILE)
+}
+
+/// Detach section from the collection and move it into the trash.
+pub fn move_to_trash(col: &mut Collection, section: &Section) -> Result<()> {
+    let trash = match col.root(TRASH_FILE) {
+        Some(trash) => trash,
+        None => {
+            col.insert_file(TRASH_FILE, Section::default())?;
+            col.root(TRASH_FILE).expect("Failed to create trash")
+        }
+    };
+
+    let now = VagueDate::now();
+    let today = now.to_string()[..10].to_string();
+    let day = match trash.children().find(|c| c.headline() == today) {
+        Some(day) => day,
+        None => {
+            let day = Section::new(today, Default::default());
+            trash.append(day.clone());
+            day
+        }
+    };
+
+    let mut section = section.clone();
+    let original_path = section.parent().map(|p| p.path());
+    section.detach();
+    section
+        .set_attr("deleted", &now.to_string())
+        .map_err(|e| e.to_string())?;
+    if let Some(path) = original_path {
+        section
+            .set_attr("trashed-from", &path)
+            .map_err(|e| e.to_string())?;
+    }
+    day.append(section);
+    Ok(())
+}
+
+/// Return the sections in the trash, oldest first.
+pub fn entries(col: &Collection) -> Vec<Section> {
+    let Some(trash) = col.root(TRASH_FILE) else {
+        return Vec::new();
+    };
+    trash.children().flat_map(|day| day.children()).collect()
+}
+
+/// Return when a trash entry was deleted.
+pub fn deleted(entry: &Section) -> Option<VagueDate> {
+    entry.attr("deleted").ok().flatten()
+}
+
+/// Move a trash entry back to where it was deleted from.
+///
+/// Return the path the entry was restored to.
+pub fn restore(col: &mut Collection, entry: &Section) -> Result<String> {
+    let mut entry = entry.clone();
+    let path = entry
+        .attr::<String>("trashed-from")
+        .ok()
+        .flatten()
+        // Restore to the inbox if the original path isn't known.
+        .unwrap_or_else(|| crate::Config::get().inbox.clone());
+
+    let day = entry.parent();
+    entry.remove_attr("deleted");
+    entry.remove_attr("trashed-from");
+    let parent = match find_or_create_in_file(col, &path) {
+        Some(parent) => parent,
+        None => col.find_or_create(&path)?,
+    };
+    parent.append(entry);
+    remove_if_empty(day);
+
+    Ok(path)
+}
+
+/// Find a section by a path that starts with a collection file path, like
+/// `notes/Books/Dune`, creating the sections missing in the file.
+///
+/// Return `None` if no file in the collection matches the path.
+fn find_or_create_in_file(col: &Collection, path: &str) -> Option<Section> {
+    let elts: Vec<&str> = path.split('/').collect();
+    // Headlines of nested file roots contain slashes, try the longest file
+    // path first.
+    let (i, mut node) = (1..=elts.len()).rev().find_map(|i| {
+        let file = format!("{}.otl", elts[..i].join("/"));
+        col.root(file).map(|root| (i, root))
+    })?;
+
+    for &headline in &elts[i..] {
+        node = match node.children().find(|c| c.headline() == headline) {
+            Some(child) => child,
+            None => {
+                let child =
+                    Section::new(headline.to_string(), Default::default());
+                node.append(child.clone());
+                child
+            }
+        };
+    }
+    Some(node)
+}
+
+/// Permanently delete trash entries deleted before the cutoff date, or all
+/// entries if there is no cutoff.
+///
+/// Return the number of deleted entries.
+pub fn empty(col: &mut Collection, before: Option<VagueDate>) -> usize {
+    let mut count = 0;
+    for entry in entries(col) {
+        let is_old = match (before, deleted(&entry)) {
+            (None, _) => true,
+            (Some(before), Some(deleted)) => deleted < before,
+            // Keep undated entries around when emptying by age.
+            (Some(_), None) => false,
+        };
+
+        if is_old {
+            let day = entry.parent();
+            entry.detach();
+            remove_if_empty(day);
+            count += 1;
+        }
+    }
+    count
+}
+
+fn remove_if_empty(day: Option<Section>) {
+    if let Some(day) = day {
+        if day.child().is_none() {
+            day.detach();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_restore_nested_file() {
+        let storage = Arc::new(MemoryStorage::new([(
+            "notes/Books.otl",
+            "Dune\n\tChapter\nEmma\n",
+        )]));
+        let mut col = Collection::in_memory(&storage, |_| true);
+        let chapter = |col: &Collection| {
+            let dune = col.root("notes/Books.otl")?.child()?;
+            dune.children().find(|c| c.headline() == "Chapter")
+        };
+        let section = chapter(&col).unwrap();
+        move_to_trash(&mut col, &section).unwrap();
+        assert!(chapter(&col).is_none());
+
+        let entry = entries(&col).pop().unwrap();
+        assert_eq!(restore(&mut col, &entry).unwrap(), "notes/Books/Dune");
+        assert!(chapter(&col).is_some());
+        assert!(entries(&col).is_empty());
+        assert_eq!(col.files().count(), 2);
+    }
+}
@@ -81,7 +81,7 @@ impl<T> NodeRef<T> {
     /// Immutable access to node data.
     ///
     /// Will panic if node is already mutably borrowed.
-    pub fn borrow(&self) -> Ref<T> {
+    pub fn borrow(&self) -> Ref<'_, T> {
         Ref(self.0.read().unwrap())
     }
 
@@ -91,7 +91,7 @@ impl<T> NodeRef<T> {
     ///
     /// Calling this will mark the node as dirty, regardless of whether node
     /// data is actually changed.
-    pub fn borrow_mut(&self) -> RefMut<T> {
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
         let mut node = self.0.write().unwrap();
         node.dirty = true;
         RefMut(node)
@@ -117,30 +117,19 @@ impl<T> NodeRef<T> {
             .unwrap()
             .parent
             .as_ref()
-            .map(|w| NodeRef::try_from(w).ok())
-            .flatten()
+            .and_then(|w| NodeRef::try_from(w).ok())
     }
 
     /// Return first child of node, if any.
     pub fn child(&self) -> Option<NodeRef<T>> {
-        self.0
-            .read()
-            .unwrap()
-            .child
-            .as_ref()
-            .map(|r| NodeRef::from(r))
+        self.0.read().unwrap().child.as_ref().map(NodeRef::from)
     }
 
     /// Return next sibling of node, if any.
     pub fn sibling(&self) -> Option<NodeRef<T>> {
         // Only report sibling if parent is still valid.
         if self.parent().is_some() {
-            self.0
-                .read()
-                .unwrap()
-                .sibling
-                .as_ref()
-                .map(|r| NodeRef::from(r))
+            self.0.read().unwrap().sibling.as_ref().map(NodeRef::from)
         } else {
             None
         }
@@ -149,6 +138,9 @@ impl<T> NodeRef<T> {
     /// Detach node from its parent and sibling.
     pub fn detach(&self) {
         if let Some(parent) = self.parent() {
+            // Parent's contents change, make sure it gets marked.
+            parent.taint();
+
             if parent.child().expect("Invalid tree state").ptr() == self.ptr() {
                 // Detaching first child, second child is new first child.
                 parent.0.write().unwrap().child =
@@ -204,19 +196,65 @@ impl<T> NodeRef<T> {
         }
     }
 
-    /// Breadth first iteration of a tree's nodes.
-    pub fn iter(&self) -> BreadthFirstNodes<T> {
-        BreadthFirstNodes {
-            next: Some(self.clone()),
-            pending: Default::default(),
+    /// Insert node as the previous sibling of this node.
+    ///
+    /// Does nothing if this node has no parent.
+    pub fn insert_before(&self, node: NodeRef<T>) {
+        let Some(parent) = self.parent() else {
+            return;
+        };
+
+        node.detach();
+        parent.taint();
+        {
+            let mut n = node.0.write().unwrap();
+            n.parent = Some(Arc::downgrade(&parent.0));
+            n.sibling = Some(self.0.clone());
         }
+
+        if parent.child().expect("Invalid tree state").ptr() == self.ptr() {
+            parent.0.write().unwrap().child = Some(node.0.clone());
+        } else {
+            let mut n = parent.child();
+            while let Some(prev) = n {
+                if prev.sibling().map(|n| n.ptr()) == Some(self.ptr()) {
+                    prev.0.write().unwrap().sibling = Some(node.0.clone());
+                    break;
+                }
+                n = prev.sibling();
+            }
+        }
+    }
+
+    /// Breadth first iteration of this node and its descendants.
+    pub fn iter(&self) -> impl Iterator<Item = NodeRef<T>> {
+        // Start from the children so the iteration doesn't wander off to
+        // this node's siblings.
+        std::iter::once(self.clone()).chain(BreadthFirstNodes {
+            next: self.child(),
+            pending: Default::default(),
+        })
+    }
+
+    /// Iterate this node and its descendants in pre-order, the order they
+    /// are written in an outline.
+    pub fn iter_preorder(&self) -> impl Iterator<Item = NodeRef<T>> {
+        // Siblings of this node are not visited.
+        let mut stack = vec![self.clone()];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            let first = stack.len();
+            stack.extend(node.children());
+            stack[first..].reverse();
+            Some(node)
+        })
     }
 
     /// Iterate through immediate children of this node.
     pub fn children(&self) -> impl Iterator<Item = NodeRef<T>> {
         let mut n = self.child();
         std::iter::from_fn(move || {
-            if let Some(node) = n.as_ref().map(|n| n.clone()) {
+            if let Some(node) = n.clone() {
                 n = node.sibling();
                 Some(node)
             } else {
@@ -229,7 +267,7 @@ impl<T> NodeRef<T> {
     ///
     /// Can be expensive to query as long as tree isn't dirty.
     pub fn is_dirty(&self) -> bool {
-        if self.0.write().unwrap().dirty == true {
+        if self.0.write().unwrap().dirty {
             return true;
         }
 
@@ -244,6 +282,12 @@ impl<T> NodeRef<T> {
         false
     }
 
+    /// Return whether `other` is this node or one of its descendants.
+    pub fn contains(&self, other: &NodeRef<T>) -> bool {
+        std::iter::successors(Some(other.clone()), |n| n.parent())
+            .any(|n| n.ptr() == self.ptr())
+    }
+
     /// Helper method for comparing by pointer identity.
     fn ptr(&self) -> *const RwLock<Node<T>> {
         &*(self.0)
@@ -280,7 +324,7 @@ impl<T: Hash> Hash for NodeRef<T> {
 
 impl<T: fmt::Display> fmt::Display for NodeRef<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fn print<'a, T: fmt::Display>(
+        fn print<T: fmt::Display>(
             f: &mut fmt::Formatter<'_>,
             indent: usize,
             node: &NodeRef<T>,
@@ -347,7 +391,7 @@ impl<T> Iterator for BreadthFirstNodes<T> {
 
         // Push next node's first child to pending list, move next node cursor
         // to its next sibling and yield the next node.
-        if let Some(node) = self.next.as_ref().map(|n| n.clone()) {
+        if let Some(node) = self.next.clone() {
             self.next = node.sibling();
             if let Some(child) = node.child() {
                 self.pending.push_back(child);
@@ -358,3 +402,33 @@ impl<T> Iterator for BreadthFirstNodes<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iter() {
+        let root = NodeRef::from("root");
+        let a = NodeRef::from("a");
+        let b = NodeRef::from("b");
+        root.append(a.clone());
+        root.append(b.clone());
+        a.append(NodeRef::from("a1"));
+        b.append(NodeRef::from("b1"));
+
+        fn names(n: &NodeRef<&'static str>) -> Vec<&'static str> {
+            n.iter().map(|n| *n.borrow()).collect()
+        }
+        assert_eq!(names(&root), vec!["root", "a", "b", "a1", "b1"]);
+        assert_eq!(
+            root.iter_preorder()
+                .map(|n| *n.borrow())
+                .collect::<Vec<_>>(),
+            vec!["root", "a", "a1", "b", "b1"]
+        );
+        assert_eq!(names(&a), vec!["a", "a1"]);
+        assert!(root.contains(&b.child().unwrap()));
+        assert!(!a.contains(&b));
+    }
+}
@@ -1,20 +1,463 @@
-use crate::{Uri, VagueDate};
+//! Typed values of the attributes with established meanings.
+//!
+//! ```notrust
+//! uri        URL or isbn:number
+//! tags       space-separated symbols
+//! added      date
+//! read       date
+//! published  date
+//! due        date
+//! deleted    date
+//! start      date
+//! end        date
+//! date       date
+//! via        URL, WikiWord, [[bracketed link]] or a free-form source
+//! links      space-separated http(s) URLs
+//! mirror     space-separated http(s) URLs of archived copies
+//! rating     whole number from 1 to 5
+//! ```
+//!
+//! Every known attribute has a type that implements `TypedAttr` and can be
+//! read with `Section::typed_attr`. `TypedAttribute::parse` looks up the
+//! type of an attribute by name for checking values. The attributes in
+//! `TEXT_ATTRIBUTES` are free-form text.
+
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+use crate::{Rating, Result, Symbol, Uri, VagueDate};
 
 // TODO: Make this a separate type, needs custom HtmlFmt handling.
-pub type Tag = crate::Symbol;
+pub type Tag = Symbol;
+
+/// Attributes used by otlbook that can have any text value.
+pub const TEXT_ATTRIBUTES: &[&str] = &[
+    "alias",
+    "anki-note",
+    "author",
+    "chart",
+    "cite-key",
+    "feed",
+    "journal",
+    "lang",
+    "location",
+    "notes",
+    "page",
+    "publisher",
+    "query",
+    "show",
+    "sort",
+    "status",
+    "title",
+    "trashed-from",
+    "view",
+    "wayback",
+];
+
+/// Value of an attribute with a known type.
+pub trait TypedAttr: Sized + fmt::Display {
+    /// Name of the attribute.
+    const NAME: &'static str;
+
+    fn parse(s: &str) -> Result<Self>;
+}
+
+/// Web address with a http or https scheme.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct Http(String);
+
+impl FromStr for Http {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match url::Url::parse(s) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+                Ok(Http(s.to_string()))
+            }
+            _ => Err(format!("Bad web address {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Http {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Where an entry was found.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Link {
+    Http(Http),
+    /// WikiWord or `[[bracketed link]]` to a local article.
+    Article(String),
+    /// Free-form description like a site name.
+    Text(String),
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Link::Http(url) => write!(f, "{}", url),
+            Link::Article(s) | Link::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// Whitespace-separated list of values.
+fn parse_list<T: FromStr<Err = String>>(s: &str) -> Result<Vec<T>> {
+    Ok(s.split_whitespace()
+        .map(T::from_str)
+        .collect::<std::result::Result<_, _>>()?)
+}
+
+fn write_list<T: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    items: impl IntoIterator<Item = T>,
+) -> fmt::Result {
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+fn parse_date(s: &str) -> Result<VagueDate> {
+    Ok(s.trim()
+        .parse()
+        .map_err(|_| format!("Bad date {:?}, must be like 2024-01-31", s))?)
+}
+
+impl TypedAttr for Uri {
+    const NAME: &'static str = "uri";
+
+    fn parse(s: &str) -> Result<Self> {
+        let uri: Uri = s.parse().map_err(|_| "Empty uri")?;
+        if let Uri::Http(url) = &uri {
+            if url::Url::parse(url).is_err() {
+                return Err(format!("Bad uri {:?}", url))?;
+            }
+        }
+        Ok(uri)
+    }
+}
+
+impl TypedAttr for Rating {
+    const NAME: &'static str = "rating";
+
+    fn parse(s: &str) -> Result<Self> {
+        Ok(s.parse()?)
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Tags(pub BTreeSet<Tag>);
+
+impl TypedAttr for Tags {
+    const NAME: &'static str = "tags";
+
+    fn parse(s: &str) -> Result<Self> {
+        let mut ret = BTreeSet::new();
+        for tag in s.split_whitespace() {
+            // Tags are written in queries and URLs, keep them to plain
+            // words.
+            if tag.starts_with('-')
+                || !tag
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "-_.".contains(c))
+            {
+                return Err(format!("Bad tag {:?}", tag))?;
+            }
+            ret.insert(Symbol::new(tag).map_err(|_| "Bad tag")?);
+        }
+        Ok(Tags(ret))
+    }
+}
+
+impl fmt::Display for Tags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_list(f, &self.0)
+    }
+}
+
+macro_rules! date_attr {
+    ($(#[$meta:meta])* $name:ident, $attr:literal) => {
+        $(#[$meta])*
+        #[derive(Clone, Eq, PartialEq, Debug)]
+        pub struct $name(pub VagueDate);
 
-// TODO: Separate type
-// TODO: This type can be a http URL or a local WikiWord.
-pub type Link = String;
+        impl TypedAttr for $name {
+            const NAME: &'static str = $attr;
 
-// TODO: Parsing
-pub type Http = String;
+            fn parse(s: &str) -> Result<Self> {
+                Ok($name(parse_date(s)?))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    };
+}
+
+date_attr!(
+    /// When the entry was added to the collection.
+    Added,
+    "added"
+);
+date_attr!(
+    /// When the book or article was read.
+    Read,
+    "read"
+);
+date_attr!(
+    /// When the work was published.
+    Published,
+    "published"
+);
+date_attr!(
+    /// When a task should be done.
+    Due,
+    "due"
+);
+date_attr!(
+    /// When the section was moved to the trash.
+    Deleted,
+    "deleted"
+);
+date_attr!(
+    /// When the event began, shown on timelines.
+    Start,
+    "start"
+);
+date_attr!(
+    /// When the event that began at `start` ended.
+    End,
+    "end"
+);
+date_attr!(
+    /// When a single-day event happened.
+    Date,
+    "date"
+);
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Via(pub Link);
+
+impl TypedAttr for Via {
+    const NAME: &'static str = "via";
+
+    fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("Empty via")?;
+        }
+        Ok(Via(if let Ok(url) = s.parse() {
+            Link::Http(url)
+        } else if crate::parse::only(crate::parse::wiki_word)(s).is_ok()
+            || (s.starts_with("[[") && s.ends_with("]]"))
+        {
+            Link::Article(s.to_string())
+        } else {
+            Link::Text(s.to_string())
+        }))
+    }
+}
+
+impl fmt::Display for Via {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Related web pages.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Links(pub Vec<Http>);
+
+impl TypedAttr for Links {
+    const NAME: &'static str = "links";
+
+    fn parse(s: &str) -> Result<Self> {
+        Ok(Links(parse_list(s)?))
+    }
+}
+
+impl fmt::Display for Links {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_list(f, &self.0)
+    }
+}
+
+/// Archived copies of the entry's web page.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Mirror(pub Vec<Http>);
+
+impl TypedAttr for Mirror {
+    const NAME: &'static str = "mirror";
+
+    fn parse(s: &str) -> Result<Self> {
+        Ok(Mirror(parse_list(s)?))
+    }
+}
+
+impl fmt::Display for Mirror {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_list(f, &self.0)
+    }
+}
 
 /// Enumeration of established typed attributes.
+#[derive(Clone, Eq, PartialEq, Debug)]
 pub enum TypedAttribute {
     Uri(Uri),
-    Tags(Vec<Tag>),
-    Via(Vec<Link>),
-    Added(VagueDate),
-    Links(Vec<Http>),
+    Tags(Tags),
+    Added(Added),
+    Read(Read),
+    Published(Published),
+    Due(Due),
+    Deleted(Deleted),
+    Start(Start),
+    End(End),
+    Date(Date),
+    Via(Via),
+    Links(Links),
+    Mirror(Mirror),
+    Rating(Rating),
+}
+
+type Parser = fn(&str) -> Result<TypedAttribute>;
+
+/// Known attribute names and the parsers of their values.
+const REGISTRY: &[(&str, Parser)] = &[
+    (Uri::NAME, |s| Ok(TypedAttribute::Uri(Uri::parse(s)?))),
+    (Tags::NAME, |s| Ok(TypedAttribute::Tags(Tags::parse(s)?))),
+    (Added::NAME, |s| Ok(TypedAttribute::Added(Added::parse(s)?))),
+    (Read::NAME, |s| Ok(TypedAttribute::Read(Read::parse(s)?))),
+    (Published::NAME, |s| {
+        Ok(TypedAttribute::Published(Published::parse(s)?))
+    }),
+    (Due::NAME, |s| Ok(TypedAttribute::Due(Due::parse(s)?))),
+    (Deleted::NAME, |s| {
+        Ok(TypedAttribute::Deleted(Deleted::parse(s)?))
+    }),
+    (Start::NAME, |s| Ok(TypedAttribute::Start(Start::parse(s)?))),
+    (End::NAME, |s| Ok(TypedAttribute::End(End::parse(s)?))),
+    (Date::NAME, |s| Ok(TypedAttribute::Date(Date::parse(s)?))),
+    (Via::NAME, |s| Ok(TypedAttribute::Via(Via::parse(s)?))),
+    (Links::NAME, |s| Ok(TypedAttribute::Links(Links::parse(s)?))),
+    (Mirror::NAME, |s| {
+        Ok(TypedAttribute::Mirror(Mirror::parse(s)?))
+    }),
+    (Rating::NAME, |s| {
+        Ok(TypedAttribute::Rating(Rating::parse(s)?))
+    }),
+];
+
+impl TypedAttribute {
+    /// Parse the value of an attribute by its name.
+    ///
+    /// Return `None` if the attribute has no known type.
+    pub fn parse(name: &str, value: &str) -> Option<Result<TypedAttribute>> {
+        REGISTRY
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, parse)| parse(value))
+    }
+
+    /// Return whether an attribute has a known type or is a known text
+    /// attribute.
+    pub fn is_known(name: &str) -> bool {
+        REGISTRY.iter().any(|(n, _)| *n == name)
+            || TEXT_ATTRIBUTES.contains(&name)
+    }
+
+    /// Return whether an attribute value is text that words can be renamed
+    /// in.
+    ///
+    /// Values with a known type like uris and dates aren't, except `via`
+    /// when it isn't a web address. Attributes without a known type are
+    /// text unless the value is a web address.
+    pub fn is_text(name: &str, value: &str) -> bool {
+        match TypedAttribute::parse(name, value) {
+            Some(Ok(TypedAttribute::Via(Via(link)))) => {
+                !matches!(link, Link::Http(_))
+            }
+            Some(_) => false,
+            None => {
+                TEXT_ATTRIBUTES.contains(&name)
+                    || value.trim().parse::<Http>().is_err()
+            }
+        }
+    }
+
+    /// Return the date if the attribute is a date.
+    pub fn date(&self) -> Option<&VagueDate> {
+        match self {
+            TypedAttribute::Added(Added(d))
+            | TypedAttribute::Read(Read(d))
+            | TypedAttribute::Published(Published(d))
+            | TypedAttribute::Due(Due(d))
+            | TypedAttribute::Deleted(Deleted(d))
+            | TypedAttribute::Start(Start(d))
+            | TypedAttribute::End(End(d))
+            | TypedAttribute::Date(Date(d)) => Some(d),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TypedAttribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypedAttribute::Uri(a) => write!(f, "{}", a),
+            TypedAttribute::Tags(a) => write!(f, "{}", a),
+            TypedAttribute::Added(a) => write!(f, "{}", a),
+            TypedAttribute::Read(a) => write!(f, "{}", a),
+            TypedAttribute::Published(a) => write!(f, "{}", a),
+            TypedAttribute::Due(a) => write!(f, "{}", a),
+            TypedAttribute::Deleted(a) => write!(f, "{}", a),
+            TypedAttribute::Start(a) => write!(f, "{}", a),
+            TypedAttribute::End(a) => write!(f, "{}", a),
+            TypedAttribute::Date(a) => write!(f, "{}", a),
+            TypedAttribute::Via(a) => write!(f, "{}", a),
+            TypedAttribute::Links(a) => write!(f, "{}", a),
+            TypedAttribute::Mirror(a) => write!(f, "{}", a),
+            TypedAttribute::Rating(a) => write!(f, "{}", a),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let parse = |name, value| {
+            TypedAttribute::parse(name, value).map(|a| a.map(|a| a.to_string()))
+        };
+
+        assert_eq!(parse("nickname", "anything").map(|a| a.ok()), None);
+        assert_eq!(parse("tags", "b  a").unwrap().unwrap(), "a b".to_string());
+        assert!(parse("added", "2024-13-01").unwrap().is_err());
+        assert!(parse("read", "2024-02").unwrap().is_ok());
+        assert!(parse("start", "2006").unwrap().is_ok());
+        assert!(parse("date", "someday").unwrap().is_err());
+        assert!(parse("uri", "isbn:9780262510875").unwrap().is_ok());
+        assert!(parse("uri", "not a uri").unwrap().is_err());
+        assert!(parse("links", "https://a.com/ ftp://b.com/")
+            .unwrap()
+            .is_err());
+        assert!(matches!(
+            Via::parse("HackerNews").unwrap().0,
+            Link::Article(_)
+        ));
+        assert!(matches!(
+            Via::parse("goodreads.com").unwrap().0,
+            Link::Text(_)
+        ));
+    }
 }
@@ -0,0 +1,111 @@
+//! Version control for collections kept in a git repository.
+//!
+//! The `git` command line tool is used to stage and commit files and to
+//! read their history. Nothing here does anything unless the collection
+//! root is the top of a git work tree.
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use crate::Result;
+
+/// Return whether the collection at root is a git repository.
+pub fn is_repo(root: impl AsRef<Path>) -> bool {
+    root.as_ref().join(".git").exists()
+}
+
+fn git(root: &Path, args: &[&str]) -> Result<Output> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    Ok(output)
+}
+
+fn check(output: Output) -> Result<String> {
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(format!(
+            "git failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into())
+    }
+}
+
+/// Stage and commit changes to the given collection paths.
+///
+/// Other changes in the work tree are left alone. Return whether a commit
+/// was made, there may be nothing to commit.
+pub fn commit(
+    root: impl AsRef<Path>,
+    paths: &[PathBuf],
+    message: &str,
+) -> Result<bool> {
+    let root = root.as_ref();
+    // Files that were deleted without ever being committed are nothing
+    // git knows about.
+    let paths: Vec<&str> = paths
+        .iter()
+        .filter(|p| root.join(p).exists() || is_tracked(root, p))
+        .filter_map(|p| p.to_str())
+        .collect();
+    if paths.is_empty() {
+        return Ok(false);
+    }
+    commit_paths(root, &paths, message)
+}
+
+/// Stage and commit every change in the collection's work tree.
+///
+/// Return whether a commit was made.
+pub fn commit_all(root: impl AsRef<Path>, message: &str) -> Result<bool> {
+    commit_paths(root.as_ref(), &["."], message)
+}
+
+fn commit_paths(root: &Path, paths: &[&str], message: &str) -> Result<bool> {
+    let mut add = vec!["add", "-A", "--"];
+    add.extend(paths);
+    check(git(root, &add)?)?;
+
+    // Exits with 1 when there are staged changes to the paths.
+    let mut diff = vec!["diff", "--cached", "--quiet", "--"];
+    diff.extend(paths);
+    if git(root, &diff)?.status.success() {
+        return Ok(false);
+    }
+
+    let mut commit = vec!["commit", "--quiet", "-m", message, "--"];
+    commit.extend(paths);
+    check(git(root, &commit)?)?;
+    Ok(true)
+}
+
+fn is_tracked(root: &Path, path: &Path) -> bool {
+    let Some(path) = path.to_str() else {
+        return false;
+    };
+    git(root, &["ls-files", "--error-unmatch", "--", path])
+        .is_ok_and(|o| o.status.success())
+}
+
+/// Return the commit log of a collection path, one commit per line.
+pub fn log(root: impl AsRef<Path>, path: &Path) -> Result<String> {
+    let path = path.to_str().ok_or("log: Bad path")?;
+    check(git(
+        root.as_ref(),
+        &[
+            "log",
+            "--follow",
+            "--date=short",
+            "--format=%h %ad %s",
+            "--",
+            path,
+        ],
+    )?)
+}
@@ -0,0 +1,158 @@
+//! Named views that select and reshape sections from the collection.
+//!
+//! A view is declared by a section with a `view` attribute naming it:
+//!
+//! ```notrust
+//! Reading list
+//!   :view reading
+//!   :query tag:book AND rating>=4
+//!   :show author rating
+//!   :sort -rating
+//! ```
+//!
+//! The view shows the sections matching `query`, with only the attributes
+//! listed in `show` and without their children. Sections are ordered by the
+//! `sort` attribute, a `-` prefix sorts in descending order. `show` and
+//! `sort` are optional.
+
+use indexmap::IndexMap;
+
+use crate::{
+    query::{self, Query},
+    trash, Collection, Result, Section,
+};
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct View {
+    pub name: String,
+    pub title: String,
+    pub query: Query,
+    pub show: Vec<String>,
+    /// Attribute to sort by and whether the order is descending.
+    pub sort: Option<(String, bool)>,
+}
+
+impl View {
+    /// Read a view declaration from a section.
+    ///
+    /// Return `Ok(None)` if the section doesn't declare a view.
+    pub fn from_section(section: &Section) -> Result<Option<View>> {
+        let attr = |name| section.borrow().attributes.get(name).cloned();
+        let Some(name) = attr("view") else {
+            return Ok(None);
+        };
+        let query = attr("query")
+            .ok_or_else(|| format!("View {:?} has no query", name))?
+            .parse()?;
+        let show = attr("show")
+            .map(|s| s.split_whitespace().map(|s| s.to_string()).collect())
+            .unwrap_or_default();
+        let sort = attr("sort").map(|s| match s.strip_prefix('-') {
+            Some(s) => (s.to_string(), true),
+            None => (s, false),
+        });
+
+        Ok(Some(View {
+            name,
+            title: section.title(),
+            query,
+            show,
+            sort,
+        }))
+    }
+
+    /// Collect the sections the view shows from the collection.
+    ///
+    /// The returned sections are new detached sections, changing them does
+    /// not change the collection.
+    pub fn apply(&self, collection: &Collection) -> Vec<Section> {
+        let mut ret: Vec<Section> = collection
+            .files()
+            .filter(|(path, _)| !trash::is_trash(path))
+            .flat_map(|(_, root)| root.iter())
+            // Don't match view declarations themselves.
+            .filter(|s| !s.borrow().attributes.contains_key("view"))
+            .filter(|s| self.query.matches(s))
+            .collect();
+
+        if let Some((name, descending)) = &self.sort {
+            query::sort_by_attribute(&mut ret, name, *descending);
+        }
+
+        ret.iter().map(|s| self.project(s)).collect()
+    }
+
+    /// Make a copy of the section's headline and the shown attributes.
+    fn project(&self, section: &Section) -> Section {
+        let data = section.borrow();
+        let attributes: IndexMap<String, String> = if self.show.is_empty() {
+            data.attributes.clone()
+        } else {
+            self.show
+                .iter()
+                .filter_map(|a| {
+                    Some((a.clone(), data.attributes.get(a)?.clone()))
+                })
+                .collect()
+        };
+        Section::new(data.headline.clone(), attributes)
+    }
+}
+
+/// Find all view declarations in the collection.
+pub fn views(collection: &Collection) -> Vec<View> {
+    collection
+        .iter()
+        .filter_map(|s| match View::from_section(&s) {
+            Ok(view) => view,
+            Err(e) => {
+                log::warn!("Bad view in {:?}: {}", s.headline(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Find the view with the given name.
+pub fn find(collection: &Collection, name: &str) -> Option<View> {
+    views(collection).into_iter().find(|v| v.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view() {
+        let s: Section = idm::from_str(
+            "\
+Good books
+\t:view books
+\t:query tag:book AND rating>=4
+\t:show rating
+\t:sort -rating
+",
+        )
+        .unwrap();
+        let view = View::from_section(&s).unwrap().unwrap();
+        assert_eq!(view.name, "books");
+        assert_eq!(view.title, "Good books");
+        assert_eq!(view.show, vec!["rating".to_string()]);
+        assert_eq!(view.sort, Some(("rating".into(), true)));
+
+        let book: Section = idm::from_str(
+            "\
+Book
+\t:author Someone
+\t:rating 5
+\tNotes
+",
+        )
+        .unwrap();
+        let p = view.project(&book);
+        assert_eq!(p.headline(), "Book");
+        assert!(p.child().is_none());
+        assert_eq!(p.attr::<String>("rating").unwrap(), Some("5".into()));
+        assert_eq!(p.attr::<String>("author").unwrap(), None);
+    }
+}
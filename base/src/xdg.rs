@@ -0,0 +1,146 @@
+//! Locations for files that aren't notebook content.
+//!
+//! Settings, caches and program state for a collection live under the XDG
+//! base directories, eg. `$XDG_CONFIG_HOME/otlbook/notes-1a2b3c4d5e6f7a8b/`
+//! for a collection at `~/notes`, so that the collection directory only
+//! holds the notebook itself. The platform equivalents are used on macOS
+//! and Windows.
+//!
+//! Files from older versions that kept everything in `.otlbook/` inside
+//! the collection are moved to the new location when first looked up.
+//! Legacy directories, like `hooks/`, keep being used where they are.
+
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// Directory inside the collection where files used to be kept.
+pub const LEGACY_DIR: &str = ".otlbook";
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Dir {
+    /// Settings written by the user.
+    Config,
+    /// Data that can be recomputed from the collection.
+    Cache,
+    /// Program state like history that can't be recomputed but isn't
+    /// worth keeping with the notebook.
+    State,
+}
+
+impl Dir {
+    fn base(self) -> Option<PathBuf> {
+        match self {
+            Dir::Config => dirs::config_dir(),
+            Dir::Cache => dirs::cache_dir(),
+            Dir::State => dirs::state_dir().or_else(dirs::data_local_dir),
+        }
+    }
+}
+
+/// Name of the per-collection directory under the base directories.
+///
+/// The collection directory name is included to make the directories easy
+/// to tell apart, the hash of the full path keeps collections with the same
+/// name apart.
+fn collection_id(root: &Path) -> String {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let name = root
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "root".into());
+    format!(
+        "{}-{:016x}",
+        name,
+        crate::minhash::fnv1a(&root.to_string_lossy())
+    )
+}
+
+/// Return a collection's directory in the given base directory.
+///
+/// Unlike [`path`], nothing is moved over from the legacy directory. The
+/// directory is not created. Returns `None` if the platform has no such
+/// base directory.
+pub fn dir(root: impl AsRef<Path>, dir: Dir) -> Option<PathBuf> {
+    Some(
+        dir.base()?
+            .join("otlbook")
+            .join(collection_id(root.as_ref())),
+    )
+}
+
+/// Return the path of a collection's file or directory in the given base
+/// directory.
+///
+/// If the file isn't there yet but exists in the collection's legacy
+/// `.otlbook/` directory, it is moved over first. Only a single file is
+/// ever moved, a legacy directory with the name is left in place and keeps
+/// being used. The parent directory is not created.
+///
+/// Panics if the name isn't a single file name.
+pub fn path(root: impl AsRef<Path>, dir: Dir, name: &str) -> PathBuf {
+    let mut components = Path::new(name).components();
+    assert!(
+        matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ),
+        "Bad file name {:?}",
+        name
+    );
+
+    let root = root.as_ref();
+    let legacy = root.join(LEGACY_DIR).join(name);
+    let Some(path) = self::dir(root, dir) else {
+        // Nowhere else to put things, keep using the collection directory.
+        return legacy;
+    };
+    let path = path.join(name);
+
+    if !path.exists() && legacy.is_dir() {
+        return legacy;
+    }
+
+    if !path.exists() && legacy.exists() {
+        if let Err(e) = migrate(&legacy, &path) {
+            log::warn!("Failed to move {:?} to {:?}: {}", legacy, path, e);
+            return legacy;
+        }
+        log::info!("Moved {:?} to {:?}", legacy, path);
+        // Clean up the legacy directory once everything is moved out.
+        let _ = fs::remove_dir(root.join(LEGACY_DIR));
+    }
+
+    path
+}
+
+fn migrate(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    // Renaming doesn't work across file systems, copy instead.
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic]
+    fn test_empty_name() {
+        path("/tmp/notes", Dir::Cache, "");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_nested_name() {
+        path("/tmp/notes", Dir::Cache, "../hooks");
+    }
+}
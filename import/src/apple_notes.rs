@@ -0,0 +1,96 @@
+//! Apple Notes exports
+//
+// Notes doesn't have an export format of its own. The iCloud data download
+// has notes as text files whose first line is the title, and the common
+// export scripts and apps write a HTML file per note. Either way the
+// export is a directory of files that gets imported one file at a time.
+
+use base::{Result, Section};
+use select::{document::Document, predicate::Name};
+
+use crate::{html::html_to_outline, note_headline};
+
+/// Import a note exported as HTML.
+///
+/// The title is taken from the first heading or the page title, falling
+/// back to `default_title`, which should be the file name.
+pub fn import_html(s: &str, default_title: &str) -> Result<Vec<Section>> {
+    let doc = Document::from(s);
+    let title = doc
+        .find(Name("h1"))
+        .chain(doc.find(Name("title")))
+        .map(|n| n.text().trim().to_string())
+        .find(|t| !t.is_empty())
+        .unwrap_or_else(|| default_title.to_string());
+
+    let mut lines = html_to_outline(s, &Default::default());
+    // Notes shows the first line as the title, don't repeat it in the body.
+    if lines
+        .first()
+        .is_some_and(|l| l.headline().trim_start_matches("# ") == title)
+    {
+        lines.remove(0);
+    }
+
+    Ok(vec![note(&title, lines)])
+}
+
+/// Import a note exported as plain text.
+pub fn import_text(s: &str, default_title: &str) -> Result<Vec<Section>> {
+    let mut lines = s.lines().map(|s| s.trim_end());
+    let title = lines
+        .by_ref()
+        .map(|s| s.trim())
+        .find(|s| !s.is_empty())
+        .unwrap_or(default_title);
+
+    let mut body: Vec<Section> = lines
+        .map(|s| Section::new(s.to_string(), Default::default()))
+        .collect();
+    while body.first().is_some_and(|l| l.headline().is_empty()) {
+        body.remove(0);
+    }
+
+    Ok(vec![note(title, body)])
+}
+
+fn note(title: &str, body: Vec<Section>) -> Section {
+    let mut attributes = Default::default();
+    let headline = note_headline(title, &mut attributes);
+    let section = Section::new(headline, attributes);
+    for line in body {
+        section.append(line);
+    }
+    section
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        let html = "<html><head><title>Travel plans</title></head><body>\
+            <div><h1>Travel plans</h1></div>\
+            <div>Pack:</div><ul><li>Passport</li><li>Charger</li></ul>\
+            </body></html>";
+        assert_eq!(
+            idm::to_string(&import_html(html, "x").unwrap()).unwrap(),
+            "\
+TravelPlans
+  :title Travel plans
+  Pack:
+    - Passport
+    - Charger
+"
+        );
+
+        assert_eq!(
+            idm::to_string(
+                &import_text("Recipes\n\nFlour\nWater\n", "x").unwrap()
+            )
+            .unwrap(),
+            "Recipes\n  Flour\n  Water\n"
+        );
+    }
+}
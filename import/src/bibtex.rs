@@ -0,0 +1,364 @@
+//! BibTeX bibliography file, eg. from Calibre, Zotero or JabRef
+//
+// The uri is made from the DOI if there is one, then from the ISBN and the
+// URL. Entries without any are skipped since they couldn't be told apart
+// from other entries.
+
+use std::collections::BTreeMap;
+
+use base::{Result, Section};
+use indexmap::IndexMap;
+
+use crate::goodreads::isbn_10_to_13;
+
+/// A parsed `@type{key, field = value, ...}` entry.
+#[derive(Debug, Default)]
+struct Entry {
+    kind: String,
+    key: String,
+    fields: BTreeMap<String, String>,
+}
+
+pub fn import(s: &str) -> Result<Vec<Section>> {
+    let mut ret = Vec::new();
+    for entry in parse(s)? {
+        let field = |name: &str| {
+            entry
+                .fields
+                .get(name)
+                .map(|s| s.as_str())
+                .filter(|s| !s.is_empty())
+        };
+
+        let Some(title) = field("title") else {
+            log::info!("Skipping BibTeX entry {} with no title", entry.key);
+            continue;
+        };
+        let uri = if let Some(doi) = field("doi") {
+            format!("https://doi.org/{}", doi.trim_start_matches("doi:"))
+        } else if let Some(isbn) = field("isbn") {
+            let isbn = isbn.replace(['-', ' '], "");
+            if isbn.len() == 10 {
+                format!("isbn:{}", isbn_10_to_13(&isbn))
+            } else {
+                format!("isbn:{}", isbn)
+            }
+        } else if let Some(url) = field("url") {
+            url.to_string()
+        } else {
+            log::info!(
+                "Skipping BibTeX entry {} with no identifier",
+                entry.key
+            );
+            continue;
+        };
+
+        let mut attributes = IndexMap::new();
+        let mut set = |name: &str, value: Option<String>| {
+            if let Some(value) = value.filter(|v| !v.is_empty()) {
+                attributes.insert(name.to_string(), value);
+            }
+        };
+        set("uri", Some(uri));
+        set("author", field("author").map(authors));
+        set("published", field("year").map(|s| s.to_string()));
+        set("publisher", field("publisher").map(|s| s.to_string()));
+        set("journal", field("journal").map(|s| s.to_string()));
+        // Calibre writes tags in a field of its own.
+        set(
+            "tags",
+            field("keywords").or(field("tags")).map(|keywords| {
+                keywords
+                    .split([',', ';'])
+                    .map(|k| k.trim().to_lowercase().replace(' ', "-"))
+                    .filter(|k| !k.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }),
+        );
+        set("cite-key", Some(entry.key.clone()));
+
+        ret.push(Section::new(title.to_string(), attributes));
+    }
+    Ok(ret)
+}
+
+/// Turn a BibTeX author list like "Knuth, Donald E. and Graham, Ronald"
+/// into "Donald E. Knuth, Ronald Graham".
+fn authors(s: &str) -> String {
+    s.split(" and ")
+        .map(|name| match name.split_once(',') {
+            Some((last, first)) => format!("{} {}", first.trim(), last.trim()),
+            None => name.trim().to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn parse(s: &str) -> Result<Vec<Entry>> {
+    let mut ret = Vec::new();
+    let mut strings: BTreeMap<String, String> = BTreeMap::new();
+    let mut p = Parser(s);
+
+    while p.skip_to_entry() {
+        let kind = p.word().to_lowercase();
+        p.skip_space();
+        let close = match p.next() {
+            Some('{') => '}',
+            Some('(') => ')',
+            _ => return Err(format!("Bad BibTeX entry @{}", kind))?,
+        };
+
+        match kind.as_str() {
+            "comment" | "preamble" => {
+                p.skip_group(close);
+                continue;
+            }
+            "string" => {
+                let (name, value) = p.field(&strings)?;
+                strings.insert(name, value);
+                p.skip_group(close);
+                continue;
+            }
+            _ => {}
+        }
+
+        p.skip_space();
+        let key = p.until([',', close]).trim().to_string();
+        let mut entry = Entry {
+            kind,
+            key,
+            ..Default::default()
+        };
+        loop {
+            p.skip_space();
+            match p.peek() {
+                Some(',') => {
+                    p.next();
+                }
+                Some(c) if c == close => {
+                    p.next();
+                    break;
+                }
+                Some(_) => {
+                    let (name, value) = p.field(&strings)?;
+                    entry.fields.insert(name, value);
+                }
+                None => {
+                    return Err(format!("Unclosed BibTeX entry {}", entry.key))?
+                }
+            }
+        }
+        log::debug!("Parsed BibTeX {} entry {}", entry.kind, entry.key);
+        ret.push(entry);
+    }
+    Ok(ret)
+}
+
+struct Parser<'a>(&'a str);
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.0.chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.0 = &self.0[c.len_utf8()..];
+        Some(c)
+    }
+
+    fn skip_space(&mut self) {
+        self.0 = self.0.trim_start();
+    }
+
+    /// Skip text outside entries, return whether an entry follows.
+    fn skip_to_entry(&mut self) -> bool {
+        match self.0.find('@') {
+            Some(i) => {
+                self.0 = &self.0[i + 1..];
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn word(&mut self) -> &str {
+        let end = self
+            .0
+            .find(|c: char| !(c.is_alphanumeric() || "_-:.".contains(c)))
+            .unwrap_or(self.0.len());
+        let (ret, rest) = self.0.split_at(end);
+        self.0 = rest;
+        ret
+    }
+
+    fn until<const N: usize>(&mut self, ends: [char; N]) -> &str {
+        let end = self.0.find(ends).unwrap_or(self.0.len());
+        let (ret, rest) = self.0.split_at(end);
+        self.0 = rest;
+        ret
+    }
+
+    /// Skip the rest of a group, with nested braces.
+    fn skip_group(&mut self, close: char) {
+        let mut depth = 0;
+        while let Some(c) = self.next() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth > 0 => depth -= 1,
+                c if c == close && depth == 0 => return,
+                _ => {}
+            }
+        }
+    }
+
+    /// Parse `name = value`, where the value can be a `#`-concatenation of
+    /// braced and quoted strings, numbers and `@string` names.
+    fn field(
+        &mut self,
+        strings: &BTreeMap<String, String>,
+    ) -> Result<(String, String)> {
+        self.skip_space();
+        let name = self.word().to_lowercase();
+        self.skip_space();
+        if self.next() != Some('=') {
+            return Err(format!("Bad BibTeX field {:?}", name))?;
+        }
+
+        let mut value = String::new();
+        loop {
+            self.skip_space();
+            match self.peek() {
+                Some('{') => {
+                    self.next();
+                    value.push_str(&self.braced('}'));
+                }
+                Some('"') => {
+                    self.next();
+                    value.push_str(&self.braced('"'));
+                }
+                Some(_) => {
+                    let word = self.word();
+                    match strings.get(&word.to_lowercase()) {
+                        Some(s) => value.push_str(s),
+                        None => value.push_str(word),
+                    }
+                }
+                None => break,
+            }
+            self.skip_space();
+            if self.peek() == Some('#') {
+                self.next();
+            } else {
+                break;
+            }
+        }
+        Ok((name, clean(&value)))
+    }
+
+    /// Read text up to an unnested closing character.
+    fn braced(&mut self, close: char) -> String {
+        let mut ret = String::new();
+        let mut depth = 0;
+        while let Some(c) = self.next() {
+            match c {
+                '\\' => {
+                    ret.push(c);
+                    if let Some(c) = self.next() {
+                        ret.push(c);
+                    }
+                }
+                '{' => {
+                    depth += 1;
+                    ret.push(c);
+                }
+                '}' if depth > 0 => {
+                    depth -= 1;
+                    ret.push(c);
+                }
+                c if c == close && depth == 0 => break,
+                c => ret.push(c),
+            }
+        }
+        ret
+    }
+}
+
+/// Remove TeX grouping and escapes from a field value.
+fn clean(s: &str) -> String {
+    let mut ret = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {}
+            '\\' => match chars.next() {
+                Some(c @ ('&' | '%' | '$' | '#' | '_' | '{' | '}')) => {
+                    ret.push(c)
+                }
+                Some(c) => {
+                    ret.push('\\');
+                    ret.push(c);
+                }
+                None => {}
+            },
+            '~' => ret.push(' '),
+            c => ret.push(c),
+        }
+    }
+    ret.replace("---", "—")
+        .replace("--", "–")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        let bib = r#"
+% Comment line
+@string{mit = "MIT Press"}
+
+@Book{sicp,
+  title = {Structure and Interpretation of {Computer} Programs},
+  author = "Abelson, Harold and Sussman, Gerald Jay",
+  publisher = mit,
+  year = 1996,
+  isbn = {978-0-262-51087-5},
+  keywords = {Lisp, Computer science},
+}
+
+@article{knuth84,
+  title = {Literate Programming},
+  author = {Donald E. Knuth},
+  journal = {The Computer Journal},
+  year = {1984},
+  doi = {10.1093/comjnl/27.2.97}
+}
+
+@misc{nothing, title = {No identifier}}
+"#;
+        assert_eq!(
+            idm::to_string(&import(bib).unwrap()).unwrap(),
+            "\
+Structure and Interpretation of Computer Programs
+  :uri isbn:9780262510875
+  :author Harold Abelson, Gerald Jay Sussman
+  :published 1996
+  :publisher MIT Press
+  :tags lisp computer-science
+  :cite-key sicp
+Literate Programming
+  :uri https://doi.org/10.1093/comjnl/27.2.97
+  :author Donald E. Knuth
+  :published 1984
+  :journal The Computer Journal
+  :cite-key knuth84
+"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Chrome and Chromium `Bookmarks` file
+
+use std::collections::BTreeSet;
+
+use base::{Result, Section, Symbol, VagueDate};
+use serde::Deserialize;
+
+use crate::Entry;
+
+/// Seconds between the Windows epoch Chrome uses and the Unix epoch.
+const WINDOWS_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+#[derive(Debug, Deserialize)]
+struct Bookmarks {
+    roots: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Node {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    url: String,
+    /// Microseconds since 1601-01-01 as a string.
+    #[serde(default)]
+    date_added: String,
+    #[serde(default)]
+    children: Vec<Node>,
+}
+
+/// Read bookmarks from the JSON `Bookmarks` file of a Chrome profile.
+///
+/// Chrome has no bookmark tags, so the names of the folders a bookmark is
+/// in are used as tags instead.
+pub fn import(s: &str) -> Result<Vec<Section>> {
+    let bookmarks: Bookmarks = serde_json::from_str(s)?;
+
+    // There are also non-folder values like "sync_transaction_version"
+    // among the roots.
+    let roots: Vec<Node> = bookmarks
+        .roots
+        .into_iter()
+        .filter_map(|(_, v)| serde_json::from_value(v).ok())
+        .collect();
+
+    // Roots are the toolbar and other built-in folders, don't make tags
+    // from them.
+    let mut items = Vec::new();
+    for child in roots.iter().flat_map(|r| &r.children) {
+        collect(child, &mut Vec::new(), &mut items);
+    }
+
+    items.sort_by_key(|(added, _, _)| *added);
+    let mut seen = BTreeSet::new();
+    let mut ret = Vec::new();
+    for (added, node, tags) in items {
+        if !seen.insert(node.url.clone()) {
+            continue;
+        }
+        let title = if node.name.trim().is_empty() {
+            node.url.clone()
+        } else {
+            node.name.trim().to_string()
+        };
+        ret.push(
+            Entry {
+                uri: node.url.clone(),
+                tags,
+                added,
+                via: None,
+            }
+            .into_section(title)?,
+        );
+    }
+    Ok(ret)
+}
+
+type Item<'a> = (Option<VagueDate>, &'a Node, BTreeSet<Symbol>);
+
+fn collect<'a>(
+    node: &'a Node,
+    folders: &mut Vec<&'a str>,
+    output: &mut Vec<Item<'a>>,
+) {
+    match node.kind.as_str() {
+        "url" => {
+            if !node.url.starts_with("http:") && !node.url.starts_with("https:")
+            {
+                return;
+            }
+            let added =
+                node.date_added.parse::<i64>().ok().filter(|&t| t > 0).map(
+                    |t| {
+                        VagueDate::from_timestamp(
+                            t / 1_000_000 - WINDOWS_EPOCH_OFFSET,
+                        )
+                    },
+                );
+            let tags = folders
+                .iter()
+                .filter_map(|f| Symbol::new(f.trim().replace(' ', "-")).ok())
+                .collect();
+            output.push((added, node, tags));
+        }
+        "folder" => {
+            folders.push(&node.name);
+            for child in &node.children {
+                collect(child, folders, output);
+            }
+            folders.pop();
+        }
+        _ => {}
+    }
+}
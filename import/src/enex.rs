@@ -0,0 +1,220 @@
+//! Evernote ENEX export file
+//
+// https://evernote.com/blog/how-evernotes-xml-export-format-works
+//
+// Note content is ENML, an XHTML subset, that gets converted into outline
+// body lines with Markdown style markup. Attachments are stored as base64
+// resources and referenced from the content by the MD5 hash of their data.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use base::{Result, Section, Symbol, VagueDate};
+use base64::Engine;
+use indexmap::IndexMap;
+
+use crate::{html::html_to_outline, note_headline, ATTACHMENTS_DIR};
+
+/// Import notes and save their attachments under the collection root.
+pub fn import(s: &str, collection_root: &Path) -> Result<Vec<Section>> {
+    let doc = roxmltree::Document::parse_with_options(
+        s,
+        roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        },
+    )?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "en-export" {
+        return Err("Not an Evernote export file".into());
+    }
+
+    let mut ret = Vec::new();
+    for note in root.children().filter(|n| n.has_tag_name("note")) {
+        let attachments = save_resources(note, collection_root)?;
+        ret.push(import_note(note, &attachments)?);
+    }
+    Ok(ret)
+}
+
+fn child_text<'a>(
+    node: roxmltree::Node<'a, '_>,
+    name: &str,
+) -> Option<&'a str> {
+    node.children()
+        .find(|n| n.has_tag_name(name))
+        .and_then(|n| n.text())
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+}
+
+fn import_note(
+    note: roxmltree::Node,
+    attachments: &HashMap<String, String>,
+) -> Result<Section> {
+    let title = child_text(note, "title").unwrap_or("Untitled");
+
+    let mut attributes = IndexMap::new();
+    let headline = note_headline(title, &mut attributes);
+
+    if let Some(uri) = note
+        .children()
+        .find(|n| n.has_tag_name("note-attributes"))
+        .and_then(|n| child_text(n, "source-url"))
+    {
+        attributes.insert("uri".to_string(), uri.to_string());
+    }
+
+    let tags: Vec<String> = note
+        .children()
+        .filter(|n| n.has_tag_name("tag"))
+        .filter_map(|n| n.text())
+        .filter_map(|t| {
+            Symbol::new(t.split_whitespace().collect::<Vec<_>>().join("-")).ok()
+        })
+        .map(|t| t.to_string())
+        .collect();
+    if !tags.is_empty() {
+        attributes.insert("tags".to_string(), tags.join(" "));
+    }
+
+    if let Some(added) = child_text(note, "created").and_then(parse_date) {
+        attributes.insert("added".to_string(), added.to_string());
+    }
+
+    let section = Section::new(headline, attributes);
+    let content = child_text(note, "content").unwrap_or("");
+    for line in html_to_outline(content, attachments) {
+        section.append(line);
+    }
+    Ok(section)
+}
+
+/// Parse ENEX timestamps like "20200131T120000Z".
+fn parse_date(s: &str) -> Option<VagueDate> {
+    if s.len() != 16 || !s.is_ascii() || !s.ends_with('Z') {
+        return None;
+    }
+    format!(
+        "{}-{}-{}T{}:{}:{}+0000",
+        &s[0..4],
+        &s[4..6],
+        &s[6..8],
+        &s[9..11],
+        &s[11..13],
+        &s[13..15]
+    )
+    .parse()
+    .ok()
+}
+
+/// Write the resources of a note into the attachments directory.
+///
+/// Returns a map from the MD5 hashes used to refer to the resources to
+/// their collection-relative paths.
+fn save_resources(
+    note: roxmltree::Node,
+    collection_root: &Path,
+) -> Result<HashMap<String, String>> {
+    let mut ret = HashMap::new();
+    for resource in note.children().filter(|n| n.has_tag_name("resource")) {
+        let Some(data) = child_text(resource, "data") else {
+            continue;
+        };
+        let data: String = data.split_whitespace().collect();
+        let data = base64::engine::general_purpose::STANDARD.decode(data)?;
+        let hash = format!("{:x}", md5::compute(&data));
+
+        let file_name = resource
+            .children()
+            .find(|n| n.has_tag_name("resource-attributes"))
+            .and_then(|n| child_text(n, "file-name"))
+            .map(|s| s.replace(['/', '\\'], "_"))
+            .unwrap_or_else(|| {
+                let mime = child_text(resource, "mime").unwrap_or("");
+                let ext = mime.split('/').nth(1).unwrap_or("bin");
+                format!("attachment.{}", ext)
+            });
+
+        // Prefix with part of the hash so same-named attachments of
+        // different notes don't overwrite each other.
+        let path = format!("{}/{}-{}", ATTACHMENTS_DIR, &hash[..8], file_name);
+        let full_path = collection_root.join(&path);
+        if let Some(dir) = full_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&full_path, &data)?;
+
+        ret.insert(hash, path);
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        assert!(parse_date("20200131T120000Z").is_some());
+        assert!(parse_date("2020é131T12000Z").is_none());
+        assert!(parse_date("20200131T120000").is_none());
+    }
+
+    #[test]
+    fn test_import() {
+        let enex = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE en-export SYSTEM "http://xml.evernote.com/pub/evernote-export3.dtd">
+<en-export export-date="20200201T100000Z" application="Evernote">
+  <note>
+    <title>Meeting notes</title>
+    <content><![CDATA[<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE en-note SYSTEM "http://xml.evernote.com/pub/enml2.dtd">
+<en-note><div>Agenda for <b>today</b>:</div>
+<ul><li>Budget</li><li><en-todo checked="true"/>Hiring
+<ul><li>Interviews</li></ul></li></ul>
+<div><br/></div>
+<div>See <a href="https://example.com">the site</a></div>
+<div><en-media hash="5d41402abc4b2a76b9719d911017c592" type="text/plain"/></div>
+</en-note>]]></content>
+    <created>20200131T120000Z</created>
+    <tag>work</tag>
+    <tag>weekly sync</tag>
+    <note-attributes>
+      <source-url>https://example.com/meeting</source-url>
+    </note-attributes>
+    <resource>
+      <data encoding="base64">aGVs
+bG8=</data>
+      <mime>text/plain</mime>
+      <resource-attributes><file-name>hello.txt</file-name></resource-attributes>
+    </resource>
+  </note>
+</en-export>"#;
+
+        let dir = std::env::temp_dir()
+            .join(format!("otlbook-enex-test-{}", std::process::id()));
+        let sections = import(enex, &dir).unwrap();
+        let attachment =
+            fs::read_to_string(dir.join("attachments/5d41402a-hello.txt"));
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(attachment.unwrap(), "hello");
+
+        assert_eq!(
+            idm::to_string(&sections).unwrap(),
+            "\
+MeetingNotes
+  :title Meeting notes
+  :uri https://example.com/meeting
+  :tags work weekly-sync
+  :added 2020-01-31T12:00:00+0000
+  Agenda for today:
+    - Budget
+    - [x] Hiring
+      - Interviews
+
+  See [the site](https://example.com)
+  [5d41402a-hello.txt](attachments/5d41402a-hello.txt)
+"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Firefox bookmarks database
+//
+// https://firefox-source-docs.mozilla.org/browser/places/
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+};
+
+use base::{Result, Section, Symbol, VagueDate};
+use rusqlite::{Connection, OpenFlags};
+
+use crate::Entry;
+
+/// Read bookmarks from a `places.sqlite` file of a Firefox profile.
+///
+/// The database is opened as immutable so it can be read while Firefox is
+/// running and holding a lock on it.
+pub fn import(path: &Path) -> Result<Vec<Section>> {
+    // Characters like `?` and `#` in the path must be escaped in the URI.
+    let uri = url::Url::from_file_path(path.canonicalize()?)
+        .map_err(|_| format!("Bad database path {:?}", path))?;
+    let uri = format!("{}?immutable=1", uri);
+    let conn = Connection::open_with_flags(
+        uri,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+    )?;
+
+    // Tags are folders under the tags root that hold bookmarks pointing to
+    // the tagged places.
+    let mut tags: HashMap<i64, BTreeSet<Symbol>> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT b.fk, t.title FROM moz_bookmarks b
+         JOIN moz_bookmarks t ON b.parent = t.id
+         JOIN moz_bookmarks r ON t.parent = r.id
+         WHERE r.guid = 'tagsRoot________' AND b.fk IS NOT NULL",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?))
+    })?;
+    for row in rows {
+        let (place, tag) = row?;
+        if let Some(tag) =
+            tag.and_then(|t| Symbol::new(t.replace(' ', "-")).ok())
+        {
+            tags.entry(place).or_default().insert(tag);
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT b.fk, b.title, p.url, b.dateAdded FROM moz_bookmarks b
+         JOIN moz_places p ON b.fk = p.id
+         JOIN moz_bookmarks f ON b.parent = f.id
+         LEFT JOIN moz_bookmarks r ON f.parent = r.id
+         WHERE b.type = 1
+           AND (r.guid IS NULL OR r.guid != 'tagsRoot________')
+         ORDER BY b.dateAdded",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<i64>>(3)?,
+        ))
+    })?;
+
+    let mut ret = Vec::new();
+    let mut seen = BTreeSet::new();
+    for row in rows {
+        let (place, title, uri, added) = row?;
+        // Skip smart bookmarks and the like.
+        if !uri.starts_with("http:") && !uri.starts_with("https:") {
+            continue;
+        }
+        // Same page can be bookmarked in several folders.
+        if !seen.insert(uri.clone()) {
+            continue;
+        }
+
+        let title = title
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| uri.clone());
+        // Timestamps are in microseconds.
+        let added = added.map(|t| VagueDate::from_timestamp(t / 1_000_000));
+
+        ret.push(
+            Entry {
+                uri,
+                tags: tags.remove(&place).unwrap_or_default(),
+                added,
+                via: None,
+            }
+            .into_section(title)?,
+        );
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        // The path needs escaping in the database URI.
+        let dir = std::env::temp_dir()
+            .join(format!("otlbook-firefox-test-{}/a #1?", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("places.sqlite");
+        let _ = std::fs::remove_file(&path);
+
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE moz_places (id INTEGER PRIMARY KEY, url TEXT);
+             CREATE TABLE moz_bookmarks (
+                 id INTEGER PRIMARY KEY, type INTEGER, fk INTEGER,
+                 parent INTEGER, title TEXT, dateAdded INTEGER, guid TEXT);
+             INSERT INTO moz_places VALUES (1, 'https://example.com/');
+             INSERT INTO moz_bookmarks VALUES
+                 (1, 2, NULL, 0, '', NULL, 'root________'),
+                 (2, 2, NULL, 1, 'menu', NULL, 'menu________'),
+                 (3, 2, NULL, 1, 'tags', NULL, 'tagsRoot________'),
+                 (4, 1, 1, 2, 'Example', 1577836800000000, 'a'),
+                 (5, 2, NULL, 3, 'web stuff', NULL, 'b'),
+                 (6, 1, 1, 5, NULL, NULL, 'c');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let sections = import(&path);
+        let _ = std::fs::remove_dir_all(dir.parent().unwrap());
+        assert_eq!(
+            idm::to_string(&sections.unwrap()).unwrap(),
+            "\
+Example
+  :uri https://example.com/
+  :tags web-stuff
+  :added 2020-01-01T00:00:00+0000
+"
+        );
+    }
+}
@@ -2,14 +2,15 @@
 //
 // https://goodreads.com/
 
-use crate::{LibraryEntry, Scrapeable};
-use base::{sym, Symbol, VagueDate};
+use base::{Rating, Result, Section, VagueDate};
+use indexmap::IndexMap;
 use serde::Deserialize;
-use std::convert::TryFrom;
-use std::error::Error;
+
+/// Exclusive shelf of books the user wants to read.
+const TO_READ_SHELF: &str = "to-read";
 
 #[derive(Debug, Deserialize)]
-pub struct GoodreadsEntry {
+struct GoodreadsEntry {
     #[serde(rename = "Title")]
     title: String,
     #[serde(rename = "Author")]
@@ -19,7 +20,7 @@ pub struct GoodreadsEntry {
     #[serde(rename = "ISBN13")]
     isbn13: String,
     #[serde(rename = "My Rating")]
-    my_rating: i32,
+    my_rating: u8,
     #[serde(rename = "Year Published")]
     year_published: String,
     #[serde(rename = "Date Read")]
@@ -34,29 +35,101 @@ pub struct GoodreadsEntry {
     notes: String,
 }
 
-#[derive(Debug)]
-pub struct Entries(pub Vec<GoodreadsEntry>);
-
-impl TryFrom<&Scrapeable> for Entries {
-    type Error = Box<dyn Error>;
-
-    fn try_from(s: &Scrapeable) -> Result<Entries, Self::Error> {
-        let mut rdr = csv::Reader::from_reader(s.as_bytes());
-        let mut ret = Vec::new();
-        for result in rdr.deserialize() {
-            let result: GoodreadsEntry = result?;
-            if result.title.is_empty() {
-                log::info!("Skipping invalid goodreads entry (no title): {:?}", result);
-            } else {
-                ret.push(result);
-            }
+/// Import books from the to-read shelf if `to_read` is set, otherwise
+/// from the other exclusive shelves.
+pub fn import(s: &str, to_read: bool) -> Result<Vec<Section>> {
+    let s = s.trim_start_matches('\u{feff}');
+    let mut rdr = csv::Reader::from_reader(s.as_bytes());
+    let mut ret = Vec::new();
+    for result in rdr.deserialize() {
+        let entry: GoodreadsEntry = result?;
+        if entry.title.is_empty() {
+            log::info!("Skipping Goodreads entry with no title: {:?}", entry);
+            continue;
+        }
+        if (entry.exclusive_shelf == TO_READ_SHELF) != to_read {
+            continue;
         }
-        Ok(Entries(ret))
+        ret.push(entry);
     }
+
+    // Sort by date
+    ret.sort_by_key(|e| {
+        date(&e.date_read)
+            .or_else(|| date(&e.date_added))
+            .unwrap_or(VagueDate::Year(0))
+    });
+
+    Ok(ret.into_iter().map(section).collect())
+}
+
+fn section(e: GoodreadsEntry) -> Section {
+    // The exclusive shelf is also listed in bookshelves, but it's covered
+    // by --to-read and the read date.
+    let tags: Vec<&str> = e
+        .bookshelves
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty() && *s != e.exclusive_shelf)
+        .collect();
+    let tags = tags.join(" ");
+
+    let mut attributes = IndexMap::new();
+    let mut set = |name: &str, value: String| {
+        if !value.is_empty() {
+            attributes.insert(name.to_string(), value);
+        }
+    };
+
+    // Spreadsheet-proofing makes the ISBN fields look like ="0123456789".
+    let isbn10 = e.isbn.replace(['"', '='], "");
+    let mut isbn13 = e.isbn13.replace(['"', '='], "");
+    if isbn13.is_empty() && !isbn10.is_empty() {
+        isbn13 = isbn_10_to_13(&isbn10);
+    }
+    if isbn13.is_empty() {
+        log::info!("{:?} has no ISBN value", e.title);
+    } else {
+        set("uri", format!("isbn:{}", isbn13));
+    }
+
+    set("author", e.author);
+    set("published", e.year_published);
+    set(
+        "added",
+        date(&e.date_added)
+            .map(|d| d.to_string())
+            .unwrap_or_default(),
+    );
+    set(
+        "read",
+        date(&e.date_read)
+            .map(|d| d.to_string())
+            .unwrap_or_default(),
+    );
+
+    set("tags", tags);
+    set(
+        "rating",
+        Rating::new(e.my_rating)
+            .map(|r| r.to_string())
+            .unwrap_or_default(),
+    );
+    set("via", "goodreads.com".into());
+
+    let ret = Section::new(e.title, attributes);
+    ret.set_body(e.notes.trim());
+    ret
+}
+
+/// Parse a Goodreads date, formatted as "2019/05/01".
+fn date(s: &str) -> Option<VagueDate> {
+    s.replace('/', "-").parse().ok()
 }
 
 fn normalize_isbn_13(isbn: &str) -> String {
-    let mut digits: Vec<u32> = isbn.chars().filter_map(|c| c.to_digit(10)).collect();
+    let mut digits: Vec<u32> =
+        isbn.chars().filter_map(|c| c.to_digit(10)).collect();
 
     // ISBN-13 check digit algorithm
     // https://en.wikipedia.org/wiki/ISBN#ISBN-13_check_digit_calculation
@@ -68,7 +141,7 @@ fn normalize_isbn_13(isbn: &str) -> String {
         .sum::<u32>()
         % 10;
     let idx = digits.len() - 1;
-    digits[idx] = 10 - sum;
+    digits[idx] = (10 - sum) % 10;
 
     digits
         .into_iter()
@@ -76,85 +149,55 @@ fn normalize_isbn_13(isbn: &str) -> String {
         .collect()
 }
 
-fn isbn_10_to_13(isbn10: &str) -> String {
-    normalize_isbn_13(&format!("978{}", isbn10))
-}
-
-impl From<GoodreadsEntry> for LibraryEntry {
-    fn from(e: GoodreadsEntry) -> LibraryEntry {
-        let mut ret = LibraryEntry::default();
-        ret.via = Some("goodreads.com".into());
-
-        let mut isbn13 = e.isbn13.replace("\"", "").replace("=", "");
-        let isbn10 = e.isbn.replace("\"", "").replace("=", "");
-
-        if isbn13.is_empty() && !isbn10.is_empty() {
-            isbn13 = isbn_10_to_13(&isbn10);
-        }
-
-        if !isbn13.is_empty() {
-            ret.uri = format!("isbn:{}", isbn13);
-        } else {
-            ret.uri = format!(
-                "title:{}",
-                parser::normalize_title(&format!("{} {}", e.title, e.author))
-            );
-            log::info!("{:?} has no ISBN value", e);
-            if e.title.is_empty() {
-                log::warn!("{:?} has no title!", e);
-            }
-        }
-
-        ret.title = Some(e.title);
-
-        if !e.author.is_empty() {
-            ret.author = Some(e.author);
-        }
-
-        if !e.year_published.is_empty() {
-            ret.published = e.year_published.parse().map(VagueDate::Year).ok();
-        }
-
-        if !e.date_added.is_empty() {
-            ret.added = e.date_added.replace("/", "-").parse().ok();
-        }
-
-        if !e.date_read.is_empty() {
-            ret.read = e.date_read.replace("/", "-").parse().ok();
-        }
-
-        ret.tags = e
-            .bookshelves
-            .split(", ")
-            .filter_map(|s| Symbol::new(s).ok())
-            .collect();
-
-        if let Ok(exclusive_shelf) = Symbol::new(e.exclusive_shelf) {
-            if !ret.tags.contains(&exclusive_shelf) {
-                ret.tags.insert(exclusive_shelf);
-            }
-        }
-
-        if e.my_rating != 0 {
-            ret.tags.insert(sym!("rating-{}", e.my_rating));
-        }
-
-        if !e.notes.is_empty() {
-            // TODO: Use pair-based IDM v0.3 thing
-            ret._contents = Some(e.notes);
-        }
-
-        ret
-    }
+pub(crate) fn isbn_10_to_13(isbn10: &str) -> String {
+    // The old check digit can be an X, it's replaced anyway.
+    let digits: String = isbn10
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .take(9)
+        .collect();
+    normalize_isbn_13(&format!("978{}0", digits))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const EXPORT: &str = "\
+Book Id,Title,Author,Author l-f,Additional Authors,ISBN,ISBN13,My Rating,Average Rating,Publisher,Binding,Number of Pages,Year Published,Original Publication Year,Date Read,Date Added,Bookshelves,Bookshelves with positions,Exclusive Shelf,My Review,Spoiler,Private Notes,Read Count,Owned Copies
+1,The Little Schemer,Daniel P. Friedman,\"Friedman, Daniel P.\",,\"=\"\"0262560992\"\"\",\"=\"\"\"\"\",4,4.2,MIT Press,Paperback,216,1995,1974,2019/05/01,2019/04/20,\"lisp, read\",,read,,,Good one,1,0
+2,Structure and Interpretation,Harold Abelson,\"Abelson, Harold\",,\"=\"\"\"\"\",\"=\"\"9780262510875\"\"\",0,4.5,MIT Press,Paperback,657,1996,1985,,2020/01/02,to-read,,to-read,,,,0,0
+";
+
     #[test]
     fn test_isbn_13() {
         assert_eq!(isbn_10_to_13("0262510871"), "9780262510875");
         assert_eq!(isbn_10_to_13("0465026567"), "9780465026562");
+        assert_eq!(isbn_10_to_13("080442957X"), "9780804429573");
+    }
+
+    #[test]
+    fn test_import() {
+        let read = import(EXPORT, false).unwrap();
+        assert_eq!(
+            idm::to_string(&read).unwrap(),
+            "\
+The Little Schemer
+  :uri isbn:9780262560993
+  :author Daniel P. Friedman
+  :published 1995
+  :added 2019-04-20
+  :read 2019-05-01
+  :tags lisp
+  :rating 4
+  :via goodreads.com
+  : Good one
+"
+        );
+
+        let to_read = import(EXPORT, true).unwrap();
+        assert_eq!(to_read.len(), 1);
+        assert_eq!(to_read[0].headline(), "Structure and Interpretation");
+        assert_eq!(to_read[0].uri().as_deref(), Some("isbn:9780262510875"));
     }
 }
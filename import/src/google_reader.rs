@@ -0,0 +1,83 @@
+//! Google Reader takeout JSON file
+//
+// Starred and shared items were exported as `starred.json` and
+// `shared.json` with the same structure.
+
+use base::{Result, Section, Symbol, VagueDate};
+use serde::Deserialize;
+
+use crate::Entry;
+
+#[derive(Debug, Deserialize)]
+struct Export {
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Item {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    published: Option<i64>,
+    #[serde(default)]
+    alternate: Vec<Link>,
+    #[serde(default)]
+    categories: Vec<String>,
+    #[serde(default)]
+    origin: Option<Origin>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Link {
+    href: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Origin {
+    #[serde(default)]
+    title: String,
+}
+
+pub fn import(s: &str) -> Result<Vec<Section>> {
+    let export: Export = serde_json::from_str(s)?;
+
+    let mut ret = Vec::new();
+    for item in export.items {
+        let Some(uri) = item.alternate.first().map(|l| l.href.clone()) else {
+            log::warn!("No URI in Google Reader item {:?}", item.title);
+            continue;
+        };
+
+        // User labels look like "user/123/label/foo", the rest are reader
+        // states like "user/123/state/com.google/starred".
+        let tags = item
+            .categories
+            .iter()
+            .filter_map(|c| c.split_once("/label/"))
+            .filter_map(|(_, label)| Symbol::new(label.replace(' ', "-")).ok())
+            .collect();
+
+        let added = item.published.map(VagueDate::from_timestamp);
+        let title = if item.title.trim().is_empty() {
+            uri.clone()
+        } else {
+            item.title.trim().to_string()
+        };
+
+        let section = Entry {
+            uri,
+            tags,
+            added,
+            via: item
+                .origin
+                .map(|o| o.title)
+                .filter(|t| !t.is_empty())
+                .or_else(|| Some("Google Reader".into())),
+        }
+        .into_section(title)?;
+        ret.push((added, section));
+    }
+
+    ret.sort_by_key(|(added, _)| *added);
+    Ok(ret.into_iter().map(|(_, s)| s).collect())
+}
@@ -0,0 +1,190 @@
+//! Converting HTML note content into outline lines.
+
+use std::collections::HashMap;
+
+use base::Section;
+use select::{document::Document, node::Node, predicate::Name};
+
+/// Convert HTML or ENML content into outline lines with Markdown style
+/// markup.
+///
+/// List items are nested under the preceding line. Evernote media elements
+/// become links to the paths that `attachments` maps their hashes to.
+pub(crate) fn html_to_outline(
+    html: &str,
+    attachments: &HashMap<String, String>,
+) -> Vec<Section> {
+    let doc = Document::from(html);
+    let Some(root) = doc
+        .find(Name("en-note"))
+        .next()
+        .or_else(|| doc.find(Name("body")).next())
+    else {
+        return Vec::new();
+    };
+
+    let mut writer = Writer::new(attachments);
+    writer.node(&root);
+    writer.flush(false);
+
+    // Drop blank lines from the ends and collapse runs of them.
+    let mut lines = writer.lines;
+    lines.dedup_by(|a, b| a.1.is_empty() && b.1.is_empty());
+    while lines.last().is_some_and(|(_, s)| s.is_empty()) {
+        lines.pop();
+    }
+    while lines.first().is_some_and(|(_, s)| s.is_empty()) {
+        lines.remove(0);
+    }
+
+    let mut ret: Vec<Section> = Vec::new();
+    let mut stack: Vec<Section> = Vec::new();
+    for (depth, line) in lines {
+        let section = Section::new(line, Default::default());
+        stack.truncate(depth);
+        match stack.last() {
+            Some(parent) => parent.append(section.clone()),
+            None => ret.push(section.clone()),
+        }
+        stack.push(section);
+    }
+    ret
+}
+
+struct Writer<'a> {
+    attachments: &'a HashMap<String, String>,
+    /// Finished lines with their nesting depths.
+    lines: Vec<(usize, String)>,
+    current: String,
+    depth: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn new(attachments: &'a HashMap<String, String>) -> Self {
+        Writer {
+            attachments,
+            lines: Vec::new(),
+            current: String::new(),
+            depth: 0,
+        }
+    }
+
+    /// End the current line.
+    ///
+    /// Empty lines are only kept if `keep_empty` is set.
+    fn flush(&mut self, keep_empty: bool) {
+        let line = self.current.trim().to_string();
+        self.current.clear();
+        if !line.is_empty() || keep_empty {
+            self.lines.push((self.depth, line));
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        // Collapse whitespace like a browser would.
+        let mut words = text.split_whitespace().peekable();
+        if text.starts_with(char::is_whitespace) && !self.current.is_empty() {
+            self.current.push(' ');
+        }
+        while let Some(word) = words.next() {
+            self.current.push_str(word);
+            if words.peek().is_some() || text.ends_with(char::is_whitespace) {
+                self.current.push(' ');
+            }
+        }
+    }
+
+    fn children(&mut self, node: &Node) {
+        for child in node.children() {
+            self.node(&child);
+        }
+    }
+
+    fn node(&mut self, node: &Node) {
+        if let Some(text) = node.as_text() {
+            self.text(text);
+            return;
+        }
+
+        match node.name().unwrap_or("") {
+            "script" | "style" | "head" | "en-crypt" => {}
+            "br" => self.flush(true),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = node.name().unwrap()[1..].parse().unwrap_or(1);
+                self.flush(false);
+                self.current.push_str(&"#".repeat(level));
+                self.current.push(' ');
+                self.children(node);
+                self.flush(false);
+            }
+            "ul" | "ol" => {
+                self.flush(false);
+                self.depth += 1;
+                self.children(node);
+                self.depth -= 1;
+            }
+            "li" => {
+                // List items are nested one level under the line before
+                // the list, even if the list element is missing.
+                self.flush(false);
+                let depth = self.depth;
+                self.depth = depth.max(1);
+                self.current.push_str("- ");
+                self.children(node);
+                self.flush(false);
+                self.depth = depth;
+            }
+            "en-todo" => {
+                if node.attr("checked") == Some("true") {
+                    self.current.push_str("[x] ");
+                } else {
+                    self.current.push_str("[ ] ");
+                }
+                // The HTML parser doesn't know ENML elements are empty and
+                // puts whatever follows inside them.
+                self.children(node);
+            }
+            "en-media" => {
+                if let Some(path) =
+                    node.attr("hash").and_then(|h| self.attachments.get(h))
+                {
+                    let name = path.rsplit('/').next().unwrap_or(path);
+                    if node
+                        .attr("type")
+                        .is_some_and(|t| t.starts_with("image/"))
+                    {
+                        self.current.push('!');
+                    }
+                    self.current.push_str(&format!("[{}]({})", name, path));
+                }
+                self.children(node);
+            }
+            "a" => {
+                let mut inner = Writer::new(self.attachments);
+                inner.children(node);
+                let text = inner.current.trim().to_string();
+                match node.attr("href") {
+                    Some(href) if text.is_empty() || text == href => {
+                        self.current.push_str(href)
+                    }
+                    Some(href) => {
+                        self.current.push_str(&format!("[{}]({})", text, href))
+                    }
+                    None => self.current.push_str(&text),
+                }
+            }
+            "p" | "div" | "blockquote" | "pre" | "tr" | "table" | "hr" => {
+                self.flush(false);
+                self.children(node);
+                self.flush(false);
+            }
+            "td" | "th" => {
+                if !self.current.is_empty() {
+                    self.current.push_str(" | ");
+                }
+                self.children(node);
+            }
+            _ => self.children(node),
+        }
+    }
+}
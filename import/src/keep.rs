@@ -0,0 +1,177 @@
+//! Google Keep notes from Google Takeout
+//
+// https://takeout.google.com/
+//
+// Takeout has a JSON file for every note in the `Keep` directory.
+
+use base::{
+    task::{State, Task},
+    Result, Section, Symbol, VagueDate,
+};
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+use crate::note_headline;
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Note {
+    title: String,
+    text_content: String,
+    list_content: Vec<ListItem>,
+    labels: Vec<Label>,
+    annotations: Vec<Annotation>,
+    is_pinned: bool,
+    is_trashed: bool,
+    created_timestamp_usec: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListItem {
+    text: String,
+    #[serde(default)]
+    is_checked: bool,
+}
+
+#[derive(Deserialize)]
+struct Label {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Annotation {
+    url: Option<String>,
+}
+
+/// Import a single note.
+///
+/// Returns nothing for notes that are in the Keep trash.
+pub fn import(s: &str) -> Result<Vec<Section>> {
+    let note: Note = serde_json::from_str(s)?;
+    if note.is_trashed {
+        return Ok(Vec::new());
+    }
+
+    let mut lines: Vec<&str> = note.text_content.lines().collect();
+
+    // Untitled notes are common in Keep, use the start of the text instead.
+    // A text line used as the title is left out of the body.
+    let mut title = note.title.trim().to_string();
+    if title.is_empty() {
+        if let Some(i) = lines.iter().position(|s| !s.trim().is_empty()) {
+            title = lines[i].trim().to_string();
+            lines.drain(..=i);
+        } else {
+            title = note
+                .list_content
+                .iter()
+                .map(|i| i.text.trim())
+                .find(|s| !s.is_empty())
+                .unwrap_or("Untitled")
+                .to_string();
+        }
+    }
+
+    let mut attributes = IndexMap::new();
+    let mut headline = note_headline(&title, &mut attributes);
+    if note.is_pinned {
+        headline.push_str(" *");
+    }
+
+    if let Some(uri) = note.annotations.iter().find_map(|a| a.url.as_ref()) {
+        attributes.insert("uri".to_string(), uri.clone());
+    }
+
+    let tags: Vec<String> = note
+        .labels
+        .iter()
+        .filter_map(|l| {
+            Symbol::new(l.name.split_whitespace().collect::<Vec<_>>().join("-"))
+                .ok()
+        })
+        .map(|t| t.to_string())
+        .collect();
+    if !tags.is_empty() {
+        attributes.insert("tags".to_string(), tags.join(" "));
+    }
+
+    if let Some(usec) = note.created_timestamp_usec {
+        attributes.insert(
+            "added".to_string(),
+            VagueDate::from_timestamp(usec / 1_000_000).to_string(),
+        );
+    }
+
+    let section = Section::new(headline, attributes);
+    for line in lines {
+        section.append(Section::new(
+            line.trim_end().to_string(),
+            Default::default(),
+        ));
+    }
+    // Checklists become task checkboxes.
+    for item in &note.list_content {
+        let task = Task {
+            state: if item.is_checked {
+                State::Done
+            } else {
+                State::Pending
+            },
+            percent: None,
+            title: item.text.trim().to_string(),
+        };
+        section.append(Section::new(task.to_string(), Default::default()));
+    }
+
+    Ok(vec![section])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        let note = r#"{
+  "color": "DEFAULT",
+  "isTrashed": false,
+  "isPinned": true,
+  "isArchived": false,
+  "title": "Shopping list",
+  "listContent": [
+    {"textHtml": "Milk", "text": "Milk", "isChecked": true},
+    {"textHtml": "Eggs", "text": "Eggs", "isChecked": false}
+  ],
+  "labels": [{"name": "home"}, {"name": "to buy"}],
+  "userEditedTimestampUsec": 1580472000000000,
+  "createdTimestampUsec": 1580472000000000
+}"#;
+
+        assert_eq!(
+            idm::to_string(&import(note).unwrap()).unwrap(),
+            "\
+ShoppingList *
+  :title Shopping list
+  :tags home to-buy
+  :added 2020-01-31T12:00:00+0000
+  [X] Milk
+  [_] Eggs
+"
+        );
+
+        let note = r#"{"textContent": "\nCall Bob\nAbout the car"}"#;
+        assert_eq!(
+            idm::to_string(&import(note).unwrap()).unwrap(),
+            "\
+CallBob
+  :title Call Bob
+  About the car
+"
+        );
+
+        assert!(import(r#"{"isTrashed": true, "textContent": "Old"}"#)
+            .unwrap()
+            .is_empty());
+    }
+}
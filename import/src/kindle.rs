@@ -0,0 +1,312 @@
+//! Kindle `My Clippings.txt` highlights and notes
+//
+// Each clipping is a block ending with a `==========` line:
+//
+//     Dune (Frank Herbert)
+//     - Your Highlight on page 12 | Location 180-182 | Added on ...
+//
+//     I must not fear.
+//     ==========
+//
+// Clippings are grouped into one section per book, with highlights as
+// `> `-quoted child sections. Notes go under the highlight they were made
+// on, or under the book if there isn't one. Bookmarks have no text and are
+// skipped.
+
+use std::collections::HashMap;
+
+use base::{Result, Section};
+use chrono::NaiveDateTime;
+use indexmap::IndexMap;
+
+const SEPARATOR: &str = "==========";
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Kind {
+    Highlight,
+    Note,
+    Bookmark,
+}
+
+/// A highlight or note with its location range in the book.
+struct Clipping {
+    section: Section,
+    start: u32,
+    end: u32,
+}
+
+pub fn import(s: &str) -> Result<Vec<Section>> {
+    let mut books: IndexMap<String, (Section, Vec<Clipping>)> = IndexMap::new();
+
+    for block in s.trim_start_matches('\u{feff}').split(SEPARATOR) {
+        let mut lines = block.trim_matches(['\r', '\n']).lines();
+        let Some(book) = lines.next().map(|s| s.trim_start_matches('\u{feff}'))
+        else {
+            continue;
+        };
+        if book.trim().is_empty() {
+            continue;
+        }
+        let Some(meta) = lines.next().and_then(|s| s.strip_prefix("- ")) else {
+            return Err(format!("Bad Kindle clipping for {:?}", book))?;
+        };
+        let text = lines
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut kind = Kind::Bookmark;
+        let mut attributes = IndexMap::new();
+        let (mut start, mut end) = (0, 0);
+        for part in meta.split(" | ") {
+            let lower = part.to_lowercase();
+            if lower.contains("highlight") {
+                kind = Kind::Highlight;
+            } else if lower.contains("note") {
+                kind = Kind::Note;
+            }
+            if let Some(page) = value_after(&lower, "page ") {
+                attributes.insert("page".to_string(), page.to_string());
+            }
+            if let Some(loc) = value_after(&lower, "location ")
+                .or_else(|| value_after(&lower, "loc. "))
+            {
+                attributes.insert("location".to_string(), loc.to_string());
+                (start, end) = range(loc);
+            }
+            if let Some(date) = part
+                .strip_prefix("Added on ")
+                .and_then(|s| s.split_once(", "))
+                .and_then(|(_weekday, date)| parse_date(date))
+            {
+                attributes.insert("added".to_string(), date);
+            }
+        }
+        if kind == Kind::Bookmark || text.is_empty() {
+            continue;
+        }
+
+        let (title, author) = title_and_author(book.trim());
+        let (book, clippings) =
+            books.entry(title.clone()).or_insert_with(|| {
+                let mut attributes = IndexMap::new();
+                if let Some(author) = author {
+                    attributes.insert("author".to_string(), author);
+                }
+                (Section::new(title, attributes), Vec::new())
+            });
+
+        match kind {
+            Kind::Highlight => {
+                let headline = format!("> {}", text);
+                // Edited highlights show up again with the same text.
+                if clippings.iter().any(|c| c.section.headline() == headline) {
+                    continue;
+                }
+                let section = Section::new(headline, attributes);
+                book.append(section.clone());
+                clippings.push(Clipping {
+                    section,
+                    start,
+                    end,
+                });
+            }
+            Kind::Note => {
+                // Notes are located at the end of their highlight.
+                let parent = clippings
+                    .iter()
+                    .rev()
+                    .find(|c| c.start <= start && start <= c.end)
+                    .map(|c| c.section.clone());
+                match parent {
+                    Some(parent) => {
+                        parent.append(Section::new(text, Default::default()))
+                    }
+                    None => book.append(Section::new(text, attributes)),
+                }
+            }
+            Kind::Bookmark => {}
+        }
+    }
+
+    Ok(books.into_values().map(|(book, _)| book).collect())
+}
+
+/// Give books without a uri the ISBN uri of a book with the same title and
+/// author in `existing`.
+///
+/// Clippings only have the title and author of the book, so this lets
+/// highlights be merged into books already in the collection.
+pub fn match_books(
+    books: &[Section],
+    existing: impl IntoIterator<Item = Section>,
+) {
+    let mut isbns: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    for section in existing {
+        let Some(uri) = section.uri().filter(|u| u.starts_with("isbn:")) else {
+            continue;
+        };
+        let attributes = section.borrow().attributes.clone();
+        let title = attributes
+            .get("title")
+            .cloned()
+            .unwrap_or_else(|| section.title());
+        let author = attributes.get("author").cloned().unwrap_or_default();
+        isbns
+            .entry(title_key(&title))
+            .or_default()
+            .push((author.to_lowercase(), uri));
+    }
+
+    for book in books {
+        if book.uri().is_some() {
+            continue;
+        }
+        let Some(candidates) = isbns.get(&title_key(&book.title())) else {
+            continue;
+        };
+        let author = book
+            .borrow()
+            .attributes
+            .get("author")
+            .map(|a| a.to_lowercase())
+            .unwrap_or_default();
+        // Books with the same title need at least one shared author name.
+        let found = candidates.iter().find(|(a, _)| {
+            a.is_empty()
+                || author.is_empty()
+                || author
+                    .split(|c: char| !c.is_alphanumeric())
+                    .any(|name| name.len() > 2 && a.contains(name))
+        });
+        if let Some((_, uri)) = found {
+            log::info!("Matched Kindle book {:?} to {}", book.title(), uri);
+            book.borrow_mut().attributes.shift_insert(
+                0,
+                "uri".to_string(),
+                uri.clone(),
+            );
+        }
+    }
+}
+
+/// Split "Title (Last, First;Other Author)" into the title and the authors
+/// as "First Last, Other Author".
+fn title_and_author(s: &str) -> (String, Option<String>) {
+    let Some(i) = s.strip_suffix(')').and_then(|s| s.rfind(" (")) else {
+        return (s.to_string(), None);
+    };
+    let author = s[i + 2..s.len() - 1]
+        .split(';')
+        .map(|name| match name.split_once(',') {
+            Some((last, first)) => format!("{} {}", first.trim(), last.trim()),
+            None => name.trim().to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    (s[..i].trim().to_string(), Some(author))
+}
+
+/// Key for matching titles, without subtitles and punctuation.
+fn title_key(title: &str) -> String {
+    let title = title.split([':', '(']).next().unwrap_or_default();
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Return the word after `prefix` in `s`.
+fn value_after<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let i = s.find(prefix)?;
+    s[i + prefix.len()..].split_whitespace().next()
+}
+
+/// Parse a location range like "180-182" or "1234-56".
+fn range(s: &str) -> (u32, u32) {
+    let (start, end) = s.split_once('-').unwrap_or((s, s));
+    let start_n: u32 = start.parse().unwrap_or(0);
+    // Older Kindles shorten the end to the digits that differ.
+    let end = if end.len() < start.len() {
+        format!("{}{}", &start[..start.len() - end.len()], end)
+    } else {
+        end.to_string()
+    };
+    (start_n, end.parse().unwrap_or(start_n))
+}
+
+/// Parse the date of an English language clipping, eg. "2 January 2023
+/// 10:00:00" or "January 2, 2023 10:00:00 AM".
+fn parse_date(s: &str) -> Option<String> {
+    ["%d %B %Y %H:%M:%S", "%B %d, %Y %I:%M:%S %p"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s.trim(), fmt).ok())
+        .map(|t| t.format("%Y-%m-%d").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        let clippings = "\u{feff}\
+Dune (Herbert, Frank)
+- Your Highlight on page 8 | Location 120-121 | Added on Monday, 2 January 2023 10:00:00
+
+I must not fear.
+==========
+Dune (Herbert, Frank)
+- Your Note on page 8 | Location 121 | Added on Monday, 2 January 2023 10:01:00
+
+The litany
+==========
+Dune (Herbert, Frank)
+- Your Bookmark on page 9 | Location 130 | Added on Monday, 2 January 2023 10:02:00
+
+
+==========
+Structure and Interpretation of Computer Programs: Second Edition (Harold Abelson;Gerald Jay Sussman)
+- Your Highlight on Location 1234-36 | Added on Tuesday, January 3, 2023 9:15:00 PM
+
+Programs must be written for people to read.
+==========
+";
+        let books = import(clippings).unwrap();
+
+        let existing: Vec<Section> = idm::from_str(
+            "\
+Structure and interpretation of computer programs
+  :uri isbn:9780262510875
+  :author Harold Abelson, Gerald Jay Sussman
+Dune
+  :uri isbn:9780441172719
+  :author Someone Else
+",
+        )
+        .unwrap();
+        match_books(&books, existing);
+
+        assert_eq!(
+            idm::to_string(&books).unwrap(),
+            "\
+Dune
+  :author Frank Herbert
+  > I must not fear.
+    :page 8
+    :location 120-121
+    :added 2023-01-02
+    The litany
+Structure and Interpretation of Computer Programs: Second Edition
+  :uri isbn:9780262510875
+  :author Harold Abelson, Gerald Jay Sussman
+  > Programs must be written for people to read.
+    :location 1234-36
+    :added 2023-01-03
+"
+        );
+    }
+}
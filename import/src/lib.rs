@@ -1 +1,341 @@
+//! Converting export files of other programs into collection entries.
+
+use std::{collections::BTreeSet, fs, path::Path};
+
+use base::{parse, Collection, Result, Section, Symbol, VagueDate};
+use indexmap::IndexMap;
+use serde::Serialize;
+
+pub mod apple_notes;
+pub mod bibtex;
+pub mod chrome;
+pub mod enex;
+pub mod firefox;
+pub mod goodreads;
+pub mod google_reader;
+mod html;
+pub mod keep;
+pub mod kindle;
+pub mod netscape_bookmarks;
 pub mod pocket;
+pub mod tiddlywiki;
+pub mod workflowy;
+
+/// Text export file formats that can be imported.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Format {
+    Pocket,
+    NetscapeBookmarks,
+    GoogleReader,
+    Goodreads,
+    ChromeBookmarks,
+    Evernote,
+    /// Single Google Keep note from Takeout.
+    GoogleKeep,
+    /// OPML outline, eg. from Workflowy, Dynalist or a feed reader.
+    Workflowy,
+    /// TiddlyWiki JSON tiddlers or single-file wiki.
+    TiddlyWiki,
+    /// BibTeX bibliography.
+    Bibtex,
+    /// Kindle `My Clippings.txt` highlights.
+    Kindle,
+}
+
+/// Directory under the collection root where imported attachments are
+/// saved.
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+/// Magic bytes at the start of SQLite databases.
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+impl Format {
+    /// Guess the format of an export file from its contents.
+    pub fn detect(s: &str) -> Option<Format> {
+        let s = s.trim_start_matches('\u{feff}').trim_start();
+        if s.starts_with("<!DOCTYPE NETSCAPE-Bookmark") {
+            Some(Format::NetscapeBookmarks)
+        } else if s.starts_with('<')
+            && s.contains("<title>Pocket Export</title>")
+        {
+            Some(Format::Pocket)
+        } else if (s.starts_with('<')
+            && s.contains("class=\"tiddlywiki-tiddler-store\""))
+            || (s.starts_with('[') && s.contains("\"title\""))
+        {
+            Some(Format::TiddlyWiki)
+        } else if s.starts_with('{') && s.contains("\"isPinned\"") {
+            // Check Keep notes first, the note text could contain anything.
+            Some(Format::GoogleKeep)
+        } else if s.starts_with('{') && s.contains("\"roots\"") {
+            Some(Format::ChromeBookmarks)
+        } else if s.starts_with('{') && s.contains("\"items\"") {
+            Some(Format::GoogleReader)
+        } else if s.starts_with("Book Id,Title,Author") {
+            Some(Format::Goodreads)
+        } else if s.starts_with('<') && s.contains("<en-export") {
+            Some(Format::Evernote)
+        } else if s.starts_with('<') && s.contains("<opml") {
+            Some(Format::Workflowy)
+        } else if s
+            .lines()
+            .nth(1)
+            .is_some_and(|line| line.starts_with("- ") && line.contains(" | "))
+            && s.contains("\n==========")
+        {
+            Some(Format::Kindle)
+        } else if s
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty() && !line.starts_with('%'))
+            .is_some_and(|line| {
+                line.starts_with('@') && line.contains(['{', '('])
+            })
+        {
+            Some(Format::Bibtex)
+        } else {
+            None
+        }
+    }
+}
+
+/// Import a file of any supported format.
+///
+/// Like `import`, but also reads binary formats like the Firefox bookmark
+/// database, and directories of notes like Google Keep and Apple Notes
+/// exports.
+pub fn import_file(
+    path: impl AsRef<Path>,
+    to_read: bool,
+    collection_root: &Path,
+) -> Result<Vec<Section>> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        return import_dir(path);
+    }
+    let data = fs::read(path)?;
+    if data.starts_with(SQLITE_HEADER) {
+        return firefox::import(path);
+    }
+    import(&String::from_utf8(data)?, to_read, collection_root)
+}
+
+/// Import every note file in a directory and its subdirectories.
+///
+/// JSON files are read as Google Keep notes, HTML and text files as Apple
+/// Notes notes. Keep exports have a HTML copy of every JSON note, those are
+/// skipped. Other files, like attachments, are ignored.
+fn import_dir(path: &Path) -> Result<Vec<Section>> {
+    let mut entries: Vec<_> = fs::read_dir(path)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    entries.sort();
+
+    let mut ret = Vec::new();
+    for path in entries {
+        if path.is_dir() {
+            ret.extend(import_dir(&path)?);
+            continue;
+        }
+
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let ext = path
+            .extension()
+            .map(|s| s.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let notes = match ext.as_str() {
+            "json" => {
+                let s = fs::read_to_string(&path)?;
+                if Format::detect(&s) != Some(Format::GoogleKeep) {
+                    continue;
+                }
+                keep::import(&s)
+            }
+            "html" | "htm" if !path.with_extension("json").exists() => {
+                apple_notes::import_html(&fs::read_to_string(&path)?, &stem)
+            }
+            "txt" | "md" => {
+                apple_notes::import_text(&fs::read_to_string(&path)?, &stem)
+            }
+            _ => continue,
+        };
+        ret.extend(notes.map_err(|e| format!("{:?}: {}", path, e))?);
+    }
+    Ok(ret)
+}
+
+/// Import an export file of any supported text format.
+///
+/// Pocket and Goodreads exports have separate read and unread items and
+/// `to_read` picks the unread ones. Other formats only have one set of
+/// items.
+///
+/// Attachments of imported notes are saved in `ATTACHMENTS_DIR` under
+/// `collection_root`. Kindle books are matched to the books in the
+/// collection there.
+pub fn import(
+    s: &str,
+    to_read: bool,
+    collection_root: &Path,
+) -> Result<Vec<Section>> {
+    match Format::detect(s) {
+        Some(Format::Pocket) => {
+            let items = if to_read {
+                pocket::import_to_read(s)?
+            } else {
+                pocket::import_read(s)?
+            };
+            items.iter().map(Section::from_data).collect()
+        }
+        Some(Format::NetscapeBookmarks) => netscape_bookmarks::import(s),
+        Some(Format::GoogleReader) => google_reader::import(s),
+        Some(Format::ChromeBookmarks) => chrome::import(s),
+        Some(Format::Evernote) => enex::import(s, collection_root),
+        Some(Format::GoogleKeep) => keep::import(s),
+        Some(Format::Workflowy) => workflowy::import(s),
+        Some(Format::TiddlyWiki) if s.trim_start().starts_with('[') => {
+            tiddlywiki::import(s)
+        }
+        Some(Format::TiddlyWiki) => tiddlywiki::import_html(s),
+        Some(Format::Goodreads) => goodreads::import(s, to_read),
+        Some(Format::Bibtex) => bibtex::import(s),
+        Some(Format::Kindle) => {
+            let books = kindle::import(s)?;
+            // Clippings only name their books, find the ISBNs from books
+            // already in the collection.
+            if collection_root.is_dir() {
+                let col = Collection::load_from(collection_root)?;
+                kindle::match_books(&books, col.iter());
+            }
+            Ok(books)
+        }
+        None => Err("Unknown import file format".into()),
+    }
+}
+
+/// Attributes of an imported link.
+#[derive(Debug, Default, Serialize)]
+struct Entry {
+    uri: String,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    tags: BTreeSet<Symbol>,
+    added: Option<VagueDate>,
+    via: Option<String>,
+}
+
+impl Entry {
+    fn into_section(self, title: String) -> Result<Section> {
+        Section::from_data(&((title,), ((self,), ())))
+    }
+}
+
+/// Make the headline for an imported note.
+///
+/// Notes become articles, which need WikiWord titles. The original title is
+/// kept in the `title` attribute if it had to be changed.
+fn note_headline(
+    title: &str,
+    attributes: &mut IndexMap<String, String>,
+) -> String {
+    match wiki_word_title(title) {
+        Some(wiki_title) => {
+            if wiki_title != title {
+                attributes.insert("title".to_string(), title.to_string());
+            }
+            wiki_title
+        }
+        None => title.to_string(),
+    }
+}
+
+/// Turn a note title into a WikiWord, eg. "Meeting notes" to
+/// "MeetingNotes".
+fn wiki_word_title(title: &str) -> Option<String> {
+    let mut ret = String::new();
+    for word in title.split(|c: char| !c.is_alphanumeric()) {
+        let mut chars = word.chars();
+        if let Some(c) = chars.next() {
+            ret.extend(c.to_uppercase());
+            ret.extend(chars.flat_map(|c| c.to_lowercase()));
+        }
+    }
+
+    parse::wiki_words(&ret)
+        .first()
+        .filter(|w| **w == ret)
+        .map(|w| w.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Format::detect("<!DOCTYPE NETSCAPE-Bookmark-file-1>\n<DL>"),
+            Some(Format::NetscapeBookmarks)
+        );
+        assert_eq!(
+            Format::detect("<html><head><title>Pocket Export</title>"),
+            Some(Format::Pocket)
+        );
+        assert_eq!(
+            Format::detect("{\"id\": \"x\", \"items\": []}"),
+            Some(Format::GoogleReader)
+        );
+        assert_eq!(
+            Format::detect("Book Id,Title,Author,Author l-f"),
+            Some(Format::Goodreads)
+        );
+        assert_eq!(
+            Format::detect("{\n \"checksum\": \"0\",\n \"roots\": {}}"),
+            Some(Format::ChromeBookmarks)
+        );
+        assert_eq!(
+            Format::detect("<?xml version=\"1.0\"?>\n<opml version=\"2.0\">"),
+            Some(Format::Workflowy)
+        );
+        assert_eq!(
+            Format::detect("<?xml version=\"1.0\"?>\n<en-export>"),
+            Some(Format::Evernote)
+        );
+        assert_eq!(
+            Format::detect("{\"color\": \"DEFAULT\", \"isPinned\": false}"),
+            Some(Format::GoogleKeep)
+        );
+        assert_eq!(
+            Format::detect("[{\"title\": \"FooBar\", \"text\": \"\"}]"),
+            Some(Format::TiddlyWiki)
+        );
+        assert_eq!(
+            Format::detect("% Exported\n@book{key,\n  title = {Foo}}"),
+            Some(Format::Bibtex)
+        );
+        assert_eq!(
+            Format::detect(
+                "Dune (Frank Herbert)\n\
+                 - Your Highlight on Location 12 | Added on Monday\n\
+                 \n\
+                 Fear.\n\
+                 =========="
+            ),
+            Some(Format::Kindle)
+        );
+        assert_eq!(Format::detect("plain text"), None);
+    }
+
+    #[test]
+    fn test_wiki_word_title() {
+        assert_eq!(
+            wiki_word_title("Meeting notes"),
+            Some("MeetingNotes".into())
+        );
+        assert_eq!(wiki_word_title("TODO list 2"), Some("TodoList2".into()));
+        assert_eq!(wiki_word_title("Groceries"), None);
+        assert_eq!(wiki_word_title("Café menu"), None);
+    }
+}
@@ -2,100 +2,78 @@
 //
 // http://fileformats.archiveteam.org/wiki/Netscape_bookmarks
 
-use crate::{LibraryEntry, Scrapeable};
-use base::{Symbol, VagueDate};
-use select::{document::Document, predicate::Name};
-use std::collections::BTreeSet;
-use std::convert::TryFrom;
-use std::error::Error;
+use base::{Result, Section, Symbol, VagueDate};
+use select::{document::Document, node::Node, predicate::Name};
 
-#[derive(Debug)]
-pub struct NetscapeBookmarksEntry {
-    pub title: String,
-    pub uri: String,
-    pub added: VagueDate,
-    pub tags: BTreeSet<Symbol>,
-    pub notes: Option<String>,
-}
-
-#[derive(Debug)]
-pub struct Entries(pub Vec<NetscapeBookmarksEntry>);
-
-impl TryFrom<&Scrapeable> for Entries {
-    type Error = Box<dyn Error>;
+use crate::Entry;
 
-    fn try_from(s: &Scrapeable) -> Result<Entries, Self::Error> {
-        if !s.starts_with("<!DOCTYPE NETSCAPE-Bookmark") {
-            return Err("not a bookmark file")?;
-        }
+pub fn import(s: &str) -> Result<Vec<Section>> {
+    if !s.trim_start().starts_with("<!DOCTYPE NETSCAPE-Bookmark") {
+        return Err("Not a bookmark file".into());
+    }
 
-        let doc = Document::from(s.as_ref());
+    let doc = Document::from(s);
 
-        let mut ret = Vec::new();
+    let mut ret = Vec::new();
+    for item in doc.find(Name("dt")) {
+        // Folders are items with a heading instead of a link.
+        let Some(a) = item.children().find(|n| n.name() == Some("a")) else {
+            continue;
+        };
+        let Some(uri) = a.attr("href") else {
+            log::warn!("No URI in bookmark item {}", a.html());
+            continue;
+        };
 
-        let mut node = doc.find(Name("dt")).next();
-        while let Some(item) = node {
-            if let Some("dt") = item.name() {
-                // TODO: Replace panicing unwraps with error handling.
-                let a = item.find(Name("a")).next().unwrap();
-                let title = a.text();
-                let uri = if let Some(uri) = a.attr("href") {
-                    uri.to_string()
-                } else {
-                    log::warn!("No URI in bookmark item");
-                    continue;
-                };
+        let added = a
+            .attr("add_date")
+            .and_then(|a| a.parse::<i64>().ok())
+            .map(VagueDate::from_timestamp);
+        let tags = a
+            .attr("tags")
+            .unwrap_or("")
+            .split(',')
+            .filter_map(|s| Symbol::new(s.trim()).ok())
+            .collect();
 
-                let added = a
-                    .attr("add_date")
-                    .and_then(|a| a.parse::<i64>().ok())
-                    .unwrap_or(0);
-                let added = VagueDate::from_timestamp(added);
-                let tags = a
-                    .attr("tags")
-                    .unwrap_or("")
-                    .split(",")
-                    .filter_map(|s| Symbol::new(s).ok())
-                    .filter(|s| !s.is_empty())
-                    .collect();
+        let mut title = a.text().trim().to_string();
+        if title.is_empty() {
+            title = uri.to_string();
+        }
 
-                ret.insert(
-                    0,
-                    NetscapeBookmarksEntry {
-                        title,
-                        uri,
-                        added,
-                        tags,
-                        notes: None,
-                    },
-                );
-            }
-            if let Some("dd") = item.name() {
-                if ret.is_empty() {
-                    log::warn!("Malformed bookmark file");
-                    continue;
-                }
-                ret[0].notes = Some(item.text());
-            }
-            node = item.next();
+        let section = Entry {
+            uri: uri.to_string(),
+            tags,
+            added,
+            via: None,
         }
+        .into_section(title)?;
 
-        ret.sort_by_key(|o| o.added);
+        // Description is in a DD element right after the item.
+        if let Some(notes) = description(&item) {
+            section.set_body(notes);
+        }
 
-        Ok(Entries(ret))
+        ret.push((added, section));
     }
+
+    ret.sort_by_key(|(added, _)| *added);
+    Ok(ret.into_iter().map(|(_, s)| s).collect())
 }
 
-impl From<NetscapeBookmarksEntry> for LibraryEntry {
-    fn from(e: NetscapeBookmarksEntry) -> LibraryEntry {
-        LibraryEntry {
-            uri: e.uri,
-            title: Some(e.title),
-            added: Some(e.added),
-            tags: e.tags,
-            notes: e.notes,
-            via: Some("Netscape bookmarks".into()),
-            ..Default::default()
+fn description(item: &Node) -> Option<String> {
+    let mut node = item.next();
+    while let Some(n) = node {
+        match n.name() {
+            Some("dd") => {
+                let text = n.text();
+                let text = text.split_whitespace().collect::<Vec<_>>();
+                return (!text.is_empty()).then(|| text.join(" "));
+            }
+            Some(_) => return None,
+            // Skip whitespace text.
+            None => node = n.next(),
         }
     }
+    None
 }
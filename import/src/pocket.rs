@@ -36,10 +36,10 @@ fn import(s: &str, title: &str) -> Result<Vec<Section>> {
     let doc = Document::from(s);
     if let Some(title) = doc.find(Name("title")).next() {
         if title.text() != "Pocket Export" {
-            return Err("Not a Pocket export file")?;
+            return Err("Not a Pocket export file".into());
         }
     } else {
-        return Err("Not a Pocket export file")?;
+        return Err("Not a Pocket export file".into());
     }
 
     parse_section(&doc, title)
@@ -0,0 +1,240 @@
+//! TiddlyWiki tiddlers
+//
+// https://tiddlywiki.com/#TiddlerFields
+//
+// Reads both JSON tiddler exports and single-file wikis, which keep their
+// tiddlers as JSON in script elements. Every tiddler becomes an article and
+// bracketed links are turned into WikiWords where the target title can be
+// one.
+
+use base::{Result, Section, Symbol, VagueDate};
+use indexmap::IndexMap;
+use select::{
+    document::Document,
+    predicate::{Attr, Name, Predicate},
+};
+use serde_json::{Map, Value};
+
+use crate::{note_headline, wiki_word_title};
+
+/// Tiddler fields that aren't turned into attributes.
+const SKIPPED_FIELDS: &[&str] = &[
+    "title", "text", "tags", "created", "modified", "type", "revision", "bag",
+    "creator", "modifier",
+];
+
+/// Import tiddlers from a JSON array.
+pub fn import(s: &str) -> Result<Vec<Section>> {
+    let tiddlers: Vec<Map<String, Value>> = serde_json::from_str(s)?;
+    Ok(tiddlers.iter().filter_map(tiddler).collect())
+}
+
+/// Import the tiddlers of a single-file wiki.
+pub fn import_html(s: &str) -> Result<Vec<Section>> {
+    let doc = Document::from(s);
+    let mut ret = Vec::new();
+    for store in
+        doc.find(Name("script").and(Attr("class", "tiddlywiki-tiddler-store")))
+    {
+        ret.extend(import(&store.text())?);
+    }
+    if ret.is_empty() {
+        return Err("No tiddlers found, is this a TiddlyWiki 5.2 or later \
+                    single-file wiki?"
+            .into());
+    }
+    Ok(ret)
+}
+
+fn field<'a>(tiddler: &'a Map<String, Value>, name: &str) -> &'a str {
+    tiddler.get(name).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+fn tiddler(tiddler: &Map<String, Value>) -> Option<Section> {
+    let title = field(tiddler, "title");
+    // Skip the wiki's own configuration and unfinished edits.
+    if title.is_empty()
+        || title.starts_with("$:/")
+        || tiddler.contains_key("draft.of")
+    {
+        return None;
+    }
+
+    let mut attributes = IndexMap::new();
+    let headline = note_headline(title, &mut attributes);
+
+    let tags: Vec<String> = tag_list(field(tiddler, "tags"))
+        .into_iter()
+        .filter_map(|t| {
+            Symbol::new(t.split_whitespace().collect::<Vec<_>>().join("-")).ok()
+        })
+        .map(|t| t.to_string())
+        .collect();
+    if !tags.is_empty() {
+        attributes.insert("tags".to_string(), tags.join(" "));
+    }
+
+    if let Some(added) = parse_date(field(tiddler, "created")) {
+        attributes.insert("added".to_string(), added.to_string());
+    }
+
+    let mut section = Section::new(headline, attributes);
+    for (name, value) in tiddler {
+        if SKIPPED_FIELDS.contains(&name.as_str()) {
+            continue;
+        }
+        if let Some(value) = value.as_str() {
+            section.set_raw_attr(name, value);
+        }
+    }
+
+    let text = field(tiddler, "text");
+    let mut lines: Vec<&str> = text.lines().map(|s| s.trim_end()).collect();
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    // Nest list items under the line before the list. Plain lines are at
+    // depth 1 and list items one deeper than their list level.
+    let mut stack = vec![(0, section.clone())];
+    for line in lines {
+        let (level, line) = list_item(line);
+        let depth = level + 1;
+        let line = Section::new(wiki_links(line), Default::default());
+        while stack.last().is_some_and(|(d, _)| *d >= depth) {
+            stack.pop();
+        }
+        stack
+            .last()
+            .expect("Shouldn't happen")
+            .1
+            .append(line.clone());
+        stack.push((depth, line));
+    }
+    Some(section)
+}
+
+/// Return the nesting depth and text of a wikitext list item line.
+///
+/// Lines that aren't list items have depth zero.
+fn list_item(line: &str) -> (usize, &str) {
+    let text = line.trim_start_matches(['*', '#']);
+    let depth = line.len() - text.len();
+    match text.strip_prefix(' ') {
+        Some(text) if depth > 0 => (depth, text.trim_start()),
+        _ => (0, line),
+    }
+}
+
+/// Split a TiddlyWiki tag list, tags with spaces are in double brackets.
+fn tag_list(s: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let (tag, tail) = match rest.strip_prefix("[[") {
+            Some(r) => match r.find("]]") {
+                Some(end) => (&r[..end], &r[end + 2..]),
+                None => (r, ""),
+            },
+            None => rest.split_at(rest.find(' ').unwrap_or(rest.len())),
+        };
+        ret.push(tag.to_string());
+        rest = tail.trim_start();
+    }
+    ret
+}
+
+/// Turn `[[Some title]]` and `[[label|Some title]]` links into WikiWords.
+///
+/// Links whose titles can't be made into WikiWords are left as they are.
+fn wiki_links(line: &str) -> String {
+    let mut ret = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("[[") {
+        let Some(len) = rest[start..].find("]]") else {
+            break;
+        };
+        let link = &rest[start + 2..start + len];
+        let (label, target) = match link.split_once('|') {
+            Some((label, target)) => (Some(label.trim()), target.trim()),
+            None => (None, link.trim()),
+        };
+
+        ret.push_str(&rest[..start]);
+        match (wiki_word_title(target), label) {
+            (Some(word), None) => ret.push_str(&word),
+            (Some(word), Some(label)) => {
+                ret.push_str(&format!("{} ({})", label, word))
+            }
+            (None, _) => ret.push_str(&rest[start..start + len + 2]),
+        }
+        rest = &rest[start + len + 2..];
+    }
+    ret.push_str(rest);
+    ret
+}
+
+/// Parse TiddlyWiki UTC timestamps like "20200131120000000".
+fn parse_date(s: &str) -> Option<VagueDate> {
+    if s.len() < 14 || !s.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    format!(
+        "{}-{}-{}T{}:{}:{}+0000",
+        &s[0..4],
+        &s[4..6],
+        &s[6..8],
+        &s[8..10],
+        &s[10..12],
+        &s[12..14]
+    )
+    .parse()
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        let json = r##"[
+  {
+    "title": "Travel plans",
+    "text": "See [[Packing list]] and [[the map|Map of Europe]].\n* One\n** Two\n# Three\n\n[[Paris]]\n* Four\n* Five",
+    "tags": "[[summer trip]] Planning",
+    "created": "20200131120000000",
+    "color": "#ff0000"
+  },
+  {"title": "$:/StoryList", "list": "Travel plans"},
+  {"title": "Draft of 'Paris'", "draft.of": "Paris", "text": ""}
+]"##;
+        assert_eq!(
+            idm::to_string(&import(json).unwrap()).unwrap(),
+            "\
+TravelPlans
+  :title Travel plans
+  :tags summer-trip Planning
+  :added 2020-01-31T12:00:00+0000
+  :color #ff0000
+  See PackingList and the map (MapOfEurope).
+    One
+      Two
+    Three
+
+  [[Paris]]
+    Four
+    Five
+"
+        );
+
+        let html = format!(
+            "<html><body><script class=\"tiddlywiki-tiddler-store\" \
+             type=\"application/json\">{}</script></body></html>",
+            r#"[{"title": "FooBar", "text": "a<b"}]"#
+        );
+        assert_eq!(
+            idm::to_string(&import_html(&html).unwrap()).unwrap(),
+            "FooBar\n  a<b\n"
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! OPML outlines from Workflowy, Dynalist, feed readers and otlbook
+//
+// Items are nested `outline` elements with the item text in the `text`
+// attribute. Workflowy puts notes in `_note` and marks completed items with
+// `_complete="true"`. Dynalist uses the same note attribute, but has
+// `checkbox="true"` for items with a checkbox and `checked="true"` for
+// checked ones. Links have the address in `url` and feed subscriptions in
+// `htmlUrl` and `xmlUrl`. The OPML `created` date is in RFC 822 format.
+//
+// otlbook's own OPML export writes section attributes with an `otl-`
+// prefix, they are read back as they were. Other attributes are kept as
+// section attributes too.
+
+use base::{Result, Section, VagueDate};
+use indexmap::IndexMap;
+
+/// Prefix of section attributes in otlbook's OPML export.
+const ATTR_PREFIX: &str = "otl-";
+
+/// Outline attributes that are either handled specially or only matter to
+/// the program that wrote the file.
+const SKIPPED_ATTRS: &[&str] = &[
+    "text",
+    "_note",
+    "_complete",
+    "checkbox",
+    "checked",
+    "created",
+    "type",
+    "url",
+    "htmlUrl",
+    "xmlUrl",
+    "title",
+    "collapsed",
+    "isComment",
+    "isBreakpoint",
+    "color",
+    "heading",
+];
+
+pub fn import(s: &str) -> Result<Vec<Section>> {
+    let doc = roxmltree::Document::parse(s)?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "opml" {
+        return Err("Not an OPML file".into());
+    }
+    let Some(body) = root.children().find(|n| n.has_tag_name("body")) else {
+        return Err("No body in OPML file".into());
+    };
+
+    Ok(outlines(body).map(item).collect())
+}
+
+fn outlines<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> {
+    node.children().filter(|n| n.has_tag_name("outline"))
+}
+
+fn item(node: roxmltree::Node) -> Section {
+    let is_set = |name| node.attribute(name) == Some("true");
+
+    let mut attributes = IndexMap::new();
+    if is_set("_complete") || is_set("checked") {
+        attributes.insert("status".to_string(), "done".to_string());
+    } else if is_set("checkbox") {
+        attributes.insert("status".to_string(), "todo".to_string());
+    }
+    if let Some(uri) = node.attribute("url").or(node.attribute("htmlUrl")) {
+        attributes.insert("uri".to_string(), uri.to_string());
+    }
+    if let Some(feed) = node.attribute("xmlUrl") {
+        attributes.insert("feed".to_string(), feed.to_string());
+    }
+    for attr in node.attributes() {
+        let name = attr.name();
+        if name.starts_with(ATTR_PREFIX) {
+            continue;
+        }
+        if !SKIPPED_ATTRS.contains(&name) && is_attr_name(name) {
+            attributes.insert(name.to_string(), attr.value().to_string());
+        }
+    }
+    // An outline from otlbook has all its section attributes under the
+    // prefix, use them as they are instead of guessing from the outline.
+    let own: Vec<(&str, &str)> = node
+        .attributes()
+        .filter_map(|a| Some((a.name().strip_prefix(ATTR_PREFIX)?, a.value())))
+        .filter(|(name, _)| !name.is_empty())
+        .collect();
+    if !own.is_empty() {
+        attributes = own
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+    }
+    if !attributes.contains_key("added") {
+        if let Some(created) = node.attribute("created").and_then(parse_date) {
+            attributes.insert("added".to_string(), created.to_string());
+        }
+    }
+
+    // Outline headlines are single lines, extra lines of text go in the
+    // body with the note.
+    let text = node.attribute("text").unwrap_or("");
+    let (headline, rest) = text.split_once('\n').unwrap_or((text, ""));
+    let section = Section::new(headline.trim_end().to_string(), attributes);
+
+    let note = node.attribute("_note").unwrap_or("");
+    let body: Vec<&str> = rest
+        .lines()
+        .chain(note.lines())
+        .map(|s| s.trim_end())
+        .collect();
+    section.set_body(body.join("\n").trim_matches('\n'));
+
+    for child in outlines(node) {
+        section.append(item(child));
+    }
+
+    section
+}
+
+/// Return whether an outline attribute name works as a section attribute
+/// name.
+fn is_attr_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Parse a RFC 822 date or an otlbook date.
+fn parse_date(s: &str) -> Option<VagueDate> {
+    if let Ok(date) = s.parse() {
+        return Some(date);
+    }
+    chrono::DateTime::parse_from_rfc2822(s)
+        .ok()
+        .map(VagueDate::DateTime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        let opml = r#"<?xml version="1.0"?>
+<opml version="2.0">
+  <head><ownerEmail>me@example.com</ownerEmail></head>
+  <body>
+    <outline text="Projects" _note="Things to do&#10;&#10;Soon">
+      <outline text="Write report" _complete="true" />
+      <outline text="Call Bob" checkbox="true" />
+    </outline>
+    <outline text="Ideas" />
+  </body>
+</opml>"#;
+
+        let sections = import(opml).unwrap();
+        assert_eq!(
+            idm::to_string(&sections).unwrap(),
+            "\
+Projects
+  : Things to do
+  :
+  : Soon
+  Write report
+    :status done
+  Call Bob
+    :status todo
+Ideas
+"
+        );
+
+        let opml = r#"<opml version="2.0"><body>
+    <outline text="Site" type="link" url="https://example.com/"
+      created="Fri, 01 May 2020 10:00:00 +0300" tags="web" x_y="no" />
+    <outline text="Blog" xmlUrl="https://blog.example/feed" />
+  </body></opml>"#;
+        assert_eq!(
+            idm::to_string(&import(opml).unwrap()).unwrap(),
+            "\
+Site
+  :uri https://example.com/
+  :tags web
+  :added 2020-05-01T10:00:00+0300
+Blog
+  :feed https://blog.example/feed
+"
+        );
+
+        assert!(import("<html></html>").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "\
+Reading
+  :tags books
+  : Things to read.
+  Dune
+    :uri https://example.com/dune
+    :added 2020-05-01
+";
+        let sections: Vec<Section> = idm::from_str(text).unwrap();
+        let opml = weave::opml::opml("Notes", &sections);
+        assert_eq!(idm::to_string(&import(&opml).unwrap()).unwrap(), text);
+
+        // Attributes with names that mean something in OPML survive.
+        let text = "\
+Title
+  :title Other title
+  :type book
+  :url https://example.com/
+  :text other
+  :created y
+";
+        let sections: Vec<Section> = idm::from_str(text).unwrap();
+        let opml = weave::opml::opml("Notes", &sections);
+        assert_eq!(idm::to_string(&import(&opml).unwrap()).unwrap(), text);
+    }
+}
@@ -0,0 +1,192 @@
+//! Shared HTTP client for operations that go through many URLs.
+
+use std::{collections::HashMap, future::Future, time::Duration};
+
+use base::Result;
+
+use crate::{
+    block_on,
+    nonblocking::{map_concurrent, Client, DEFAULT_CONCURRENCY},
+    LinkStatus,
+};
+
+/// Fetches many URLs concurrently with a single connection pool and cookie
+/// store.
+///
+/// Requests to the same host are made one at a time with a pause between
+/// them so that a collection with hundreds of links to one site doesn't
+/// hammer it. Failed requests are retried with increasing delays.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// let fetcher = scrape::Fetcher::default()
+///     .concurrency(16)
+///     .host_interval(Duration::from_secs(2));
+/// let titles = fetcher.titles(&["https://example.com".to_string()]);
+/// ```
+#[derive(Clone)]
+pub struct Fetcher {
+    client: Client,
+    concurrency: usize,
+    host_interval: Duration,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Default for Fetcher {
+    fn default() -> Self {
+        Fetcher {
+            client: Client::default(),
+            concurrency: DEFAULT_CONCURRENCY,
+            host_interval: base::Config::get().host_interval(),
+            retries: 2,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Fetcher {
+    /// Give up on requests that take longer than this.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.client = Client::with_timeout(timeout);
+        self
+    }
+
+    /// Number of hosts to fetch from at the same time.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Time to wait between requests to the same host.
+    pub fn host_interval(mut self, host_interval: Duration) -> Self {
+        self.host_interval = host_interval;
+        self
+    }
+
+    /// How many times to retry a failed request.
+    ///
+    /// The delay before a retry starts from one second and doubles on every
+    /// retry.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Download pages and parse their titles.
+    pub fn titles(&self, urls: &[String]) -> Vec<Result<Option<String>>> {
+        block_on(
+            self.map(
+                urls,
+                |c, url| async move { c.web_page_title(&url).await },
+            ),
+        )
+    }
+
+    /// Get the possibly redirected URLs of pages.
+    pub fn final_urls(&self, urls: &[String]) -> Vec<Result<String>> {
+        block_on(
+            self.map(urls, |c, url| async move { c.final_url(&url).await }),
+        )
+    }
+
+    /// Probe whether links still work.
+    pub fn check_links(&self, urls: &[String]) -> Vec<LinkStatus> {
+        block_on(
+            self.map(urls, |c, url| async move { c.check_link(&url).await }),
+        )
+        .into_iter()
+        .map(LinkStatus::from_result)
+        .collect()
+    }
+
+    /// Find the closest Wayback Machine snapshots of pages.
+    pub fn wayback_snapshots(
+        &self,
+        urls: &[String],
+    ) -> Vec<Result<Option<String>>> {
+        block_on(
+            self.map(
+                urls,
+                |c, url| async move { c.wayback_snapshot(&url).await },
+            ),
+        )
+    }
+
+    /// Run a request for every URL, results are in the order of the URLs.
+    async fn map<R, F, Fut>(&self, urls: &[String], f: F) -> Vec<Result<R>>
+    where
+        F: Fn(Client, String) -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        let mut hosts: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, url) in urls.iter().enumerate() {
+            let host = url::Url::parse(url)
+                .ok()
+                .and_then(|u| u.host_str().map(|h| h.to_string()))
+                .unwrap_or_default();
+            hosts.entry(host).or_default().push(i);
+        }
+
+        let f = &f;
+        let done = map_concurrent(
+            hosts.into_values(),
+            self.concurrency,
+            |indices| async move {
+                let mut ret = Vec::new();
+                for (n, i) in indices.into_iter().enumerate() {
+                    if n > 0 {
+                        tokio::time::sleep(self.host_interval).await;
+                    }
+                    ret.push((
+                        i,
+                        self.retry(|| f(self.client.clone(), urls[i].clone()))
+                            .await,
+                    ));
+                }
+                ret
+            },
+        )
+        .await;
+
+        let mut ret: Vec<Option<Result<R>>> =
+            (0..urls.len()).map(|_| None).collect();
+        for (i, result) in done.into_iter().flatten() {
+            ret[i] = Some(result);
+        }
+        ret.into_iter()
+            .map(|r| r.expect("URL was not fetched"))
+            .collect()
+    }
+
+    async fn retry<R, Fut>(&self, f: impl Fn() -> Fut) -> Result<R>
+    where
+        Fut: Future<Output = Result<R>>,
+    {
+        let mut delay = self.backoff;
+        let mut result = f().await;
+        for _ in 0..self.retries {
+            // No point in retrying if the network is down.
+            if result.is_ok() || crate::is_offline() {
+                break;
+            }
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            result = f().await;
+        }
+        result
+    }
+}
+
+impl LinkStatus {
+    fn from_result(result: Result<LinkStatus>) -> LinkStatus {
+        match result {
+            Ok(status) => status,
+            Err(e) => match e.downcast_ref::<reqwest::Error>() {
+                Some(e) if e.is_timeout() => LinkStatus::TimedOut,
+                _ => LinkStatus::Failed(e.to_string()),
+            },
+        }
+    }
+}
@@ -1,44 +1,170 @@
-use std::collections::HashMap;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    RwLock,
+};
 
 use base::Result;
-use serde::Deserialize;
+use select::{document::Document, predicate::Name};
+
+mod fetcher;
+pub use fetcher::Fetcher;
+
+pub mod nonblocking;
+use nonblocking::Client;
+pub use nonblocking::LinkStatus;
+
+mod proxy;
+pub use proxy::Proxies;
 
-// TODO: Make timeout configurable in CLI parameters.
 // Timeout is needed if you hit a weird site like http://robpike.io
+//
+// Used for connecting and for quick checks, the timeout for downloading a
+// page is the `http-timeout` user setting.
 const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
 
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Make all network operations fail immediately.
+///
+/// Offline mode is also enabled by setting the `OTLBOOK_OFFLINE`
+/// environment variable, and switched on automatically when a request fails
+/// to connect so that a batch of requests doesn't wait for every one of
+/// them to time out.
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+        || std::env::var_os("OTLBOOK_OFFLINE").is_some_and(|v| !v.is_empty())
+}
+
+static PROXIES: RwLock<Option<Proxies>> = RwLock::new(None);
+
+/// Set per-domain proxies used by clients created after this.
+///
+/// Without proxy rules the `HTTP_PROXY` and `HTTPS_PROXY` environment
+/// variables are used like in most other programs, and they're still used
+/// for hosts that no rule matches.
+pub fn set_proxies(proxies: Proxies) {
+    *PROXIES.write().unwrap() = Some(proxies);
+}
+
+fn proxies() -> Option<Proxies> {
+    PROXIES.read().unwrap().clone()
+}
+
+/// Run a future to completion on a throwaway runtime.
+///
+/// Must not be called from inside an async context.
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to start async runtime")
+        .block_on(future)
+}
+
 /// Try to download a web page from the internet.
 pub fn download_web_page(url: impl AsRef<str>) -> Result<String> {
-    let url: url::Url = url.as_ref().parse()?;
-    let agent = ureq::AgentBuilder::new()
-        .timeout_read(REQUEST_TIMEOUT)
-        .build();
-    Ok(agent.get(url.as_str()).call()?.into_string()?)
+    block_on(Client::default().download_web_page(url.as_ref()))
 }
 
 /// Get possibly redirected url.
 pub fn final_url(url: impl AsRef<str>) -> Result<String> {
-    let url: url::Url = url.as_ref().parse()?;
-    let agent = ureq::AgentBuilder::new()
-        .timeout_read(REQUEST_TIMEOUT)
-        .build();
-    Ok(agent.get(url.as_str()).call()?.get_url().into())
+    block_on(Client::default().final_url(url.as_ref()))
+}
+
+/// Metadata parsed from a web page.
+#[derive(Clone, Default, Debug, Eq, PartialEq)]
+pub struct PageInfo {
+    pub title: Option<String>,
+    /// ISO 639-1 code of the page language.
+    pub lang: Option<String>,
+    /// Paragraph text of the page separated by blank lines.
+    pub text: String,
+}
+
+impl PageInfo {
+    pub fn parse(html: &str) -> PageInfo {
+        let document = Document::from(html);
+        let text = parse_text(&document);
+        PageInfo {
+            title: parse_title(&document),
+            lang: parse_lang(&document, &text),
+            text,
+        }
+    }
+}
+
+/// Download a web page and parse its metadata.
+pub fn web_page_info(url: impl AsRef<str>) -> Result<PageInfo> {
+    block_on(Client::default().web_page_info(url.as_ref()))
 }
 
 /// Helper function for parsing the title only.
 ///
 /// A lot of the time you only want this.
 pub fn web_page_title(url: impl AsRef<str>) -> Result<Option<String>> {
-    use select::{document::Document, predicate::Name};
+    block_on(Client::default().web_page_title(url.as_ref()))
+}
+
+/// Fetch titles for many pages concurrently.
+///
+/// Results are in the same order as the urls. Unlike [`Fetcher::titles`],
+/// this makes a single attempt per page without pausing between requests to
+/// the same host, so that callers waiting for a handful of titles aren't
+/// held up.
+pub fn web_page_titles(
+    urls: impl IntoIterator<Item = impl AsRef<str>>,
+) -> Vec<Result<Option<String>>> {
+    let client = Client::default();
+    let urls: Vec<String> =
+        urls.into_iter().map(|u| u.as_ref().to_string()).collect();
+    block_on(nonblocking::map_concurrent(
+        urls.iter(),
+        nonblocking::DEFAULT_CONCURRENCY,
+        |url| client.web_page_title(url),
+    ))
+}
 
-    let content = download_web_page(url)?;
-    let document = Document::from(content.as_ref());
+/// Send text to a URL in a POST request.
+pub fn post_text(url: impl AsRef<str>, body: impl Into<String>) -> Result<()> {
+    block_on(Client::default().post_text(url.as_ref(), body.into()))
+}
+
+/// Send a request to a JSON web API and return the JSON response.
+pub fn call_api(
+    method: &str,
+    url: &str,
+    token: Option<&str>,
+    body: Option<&serde_json::Value>,
+    timeout: std::time::Duration,
+) -> Result<serde_json::Value> {
+    block_on(Client::default().call_api(method, url, token, body, timeout))
+}
 
+pub fn is_archived_on_wayback(url: impl AsRef<str>) -> Result<bool> {
+    block_on(Client::default().is_archived_on_wayback(url.as_ref()))
+}
+
+/// Find the URL of the closest Wayback Machine snapshot of a page.
+pub fn wayback_snapshot(url: impl AsRef<str>) -> Result<Option<String>> {
+    block_on(Client::default().wayback_snapshot(url.as_ref()))
+}
+
+/// Archive a page on the Wayback Machine and return the snapshot URL.
+pub fn save_to_wayback(url: impl AsRef<str>) -> Result<String> {
+    block_on(Client::default().save_to_wayback(url.as_ref()))
+}
+
+/// Parse the title out of a HTML page.
+fn parse_title(document: &Document) -> Option<String> {
     let title = document
         .find(Name("title"))
         .next()
         .map(|n| n.text())
-        .unwrap_or_else(Default::default);
+        .unwrap_or_default();
 
     // Correct for weird stuff like multi-line text block for
     // title.
@@ -47,49 +173,81 @@ pub fn web_page_title(url: impl AsRef<str>) -> Result<Option<String>> {
         .lines()
         .next()
         .map(|s| s.to_string())
-        .unwrap_or_else(Default::default);
+        .unwrap_or_default();
 
     if title.is_empty() {
-        Ok(None)
+        None
     } else {
-        Ok(Some(title))
+        Some(title)
     }
 }
 
-pub fn is_archived_on_wayback(url: impl AsRef<str>) -> Result<bool> {
-    #[derive(Deserialize)]
-    #[allow(dead_code)]
-    struct WaybackAvailable {
-        url: String,
-        archived_snapshots: HashMap<String, Snapshot>,
+/// Collect the paragraph text of a HTML page.
+fn parse_text(document: &Document) -> String {
+    let paragraphs: Vec<String> = document
+        .find(Name("p"))
+        .map(|n| n.text().split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|p| !p.is_empty())
+        .collect();
+    paragraphs.join("\n\n")
+}
+
+/// Detect the language of a HTML page.
+///
+/// Uses the language declared on the `html` element if there is one,
+/// otherwise guesses the language from the paragraph text.
+fn parse_lang(document: &Document, text: &str) -> Option<String> {
+    let html = document.find(Name("html")).next();
+    if let Some(lang) = html.and_then(|n| n.attr("lang")) {
+        // Only keep the primary language from tags like "en-US".
+        let lang = lang.split('-').next()?.trim().to_lowercase();
+        if !lang.is_empty() {
+            return Some(lang);
+        }
     }
 
-    #[derive(Deserialize)]
-    #[allow(dead_code)]
-    struct Snapshot {
-        status: String,
-        available: bool,
-        url: String,
-        timestamp: String,
+    let info = whatlang::detect(text)?;
+    if !info.is_reliable() {
+        return None;
     }
+    isolang::Language::from_639_3(info.lang().code())?
+        .to_639_1()
+        .map(|s| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_info() {
+        assert_eq!(
+            PageInfo::parse(
+                "<html lang=\"en-US\"><head><title>\n  Hello\n</title>\
+                 </head></html>"
+            ),
+            PageInfo {
+                title: Some("Hello".into()),
+                lang: Some("en".into()),
+                text: "".into(),
+            }
+        );
 
-    // Make sure the initial parameter looks like an URL, then throw this
-    // value away. It's only here to see if the parse succeeds.
-    let url: url::Url = url.as_ref().parse()?;
-
-    // Then make the actual URL that's querying wayback machine.
-    let url: url::Url =
-        format!("https://archive.org/wayback/available?url={}", url.as_ref())
-            .parse()?;
-
-    let agent = ureq::AgentBuilder::new()
-        .timeout_read(REQUEST_TIMEOUT)
-        .build();
-
-    let response: WaybackAvailable =
-        agent.get(url.as_str()).call()?.into_json()?;
-    Ok(response
-        .archived_snapshots
-        .get("closest")
-        .map_or(false, |e| e.available))
+        assert_eq!(
+            PageInfo::parse(
+                "<html><body><p>Tämä on suomenkielinen kappale, jossa \
+                 kerrotaan kuinka kissa istui pöydällä ja katseli ulos \
+                 ikkunasta koko pitkän iltapäivän.</p></body></html>"
+            )
+            .lang,
+            Some("fi".into())
+        );
+
+        assert_eq!(
+            PageInfo::parse("<p>One\n  two</p><p> </p><p>Three</p>").text,
+            "One two\n\nThree"
+        );
+
+        assert_eq!(PageInfo::parse("<html></html>"), PageInfo::default());
+    }
 }
@@ -0,0 +1,320 @@
+//! Async versions of the scraping functions.
+//!
+//! Use these when making lots of requests at once, the blocking functions
+//! at the crate root run one request at a time.
+
+use std::{collections::HashMap, future::Future, time::Duration};
+
+use base::Result;
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{PageInfo, REQUEST_TIMEOUT};
+
+/// How many requests batch operations keep in flight at once by default.
+pub const DEFAULT_CONCURRENCY: usize = 64;
+
+/// Archiving a page takes the Wayback Machine a while.
+const WAYBACK_SAVE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Result of probing a link.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LinkStatus {
+    Ok,
+    /// Server responded with an error status.
+    Broken(u16),
+    TimedOut,
+    /// Request failed some other way, eg. the host wasn't found or the
+    /// network is down.
+    Failed(String),
+}
+
+impl LinkStatus {
+    /// Return whether the link should be considered dead.
+    pub fn is_dead(&self) -> bool {
+        matches!(self, LinkStatus::Broken(_) | LinkStatus::TimedOut)
+    }
+}
+
+/// HTTP client for scraping.
+///
+/// Cloning the client is cheap and clones share the connection pool and the
+/// cookie store.
+#[derive(Clone)]
+pub struct Client(reqwest::Client);
+
+impl Default for Client {
+    fn default() -> Self {
+        Client::with_timeout(base::Config::get().http_timeout())
+    }
+}
+
+impl Client {
+    /// Make a client whose requests give up after the timeout.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        let mut builder = reqwest::Client::builder()
+            .cookie_store(true)
+            .connect_timeout(REQUEST_TIMEOUT.min(timeout))
+            .timeout(timeout);
+
+        if let Some(proxies) = crate::proxies().filter(|p| !p.is_empty()) {
+            builder = builder.proxy(reqwest::Proxy::custom(move |url| {
+                proxies
+                    .proxy_for(url.host_str()?)
+                    .and_then(|p| p.parse::<url::Url>().ok())
+            }));
+
+            // Setting any proxy turns off reqwest's own environment lookup,
+            // so add the environment proxies back as fallbacks.
+            let env_proxies = vec![
+                env_var("HTTP_PROXY")
+                    .and_then(|p| reqwest::Proxy::http(p).ok()),
+                env_var("HTTPS_PROXY")
+                    .and_then(|p| reqwest::Proxy::https(p).ok()),
+            ];
+            for proxy in env_proxies.into_iter().flatten() {
+                builder = builder.proxy(proxy);
+            }
+        }
+
+        Client(builder.build().expect("Failed to initialize HTTP client"))
+    }
+}
+
+/// Read an environment variable in either upper or lower case.
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .or_else(|_| std::env::var(name.to_lowercase()))
+        .ok()
+        .filter(|v| !v.is_empty())
+}
+
+impl Client {
+    /// Make a GET request unless in offline mode.
+    async fn get(&self, url: url::Url) -> Result<reqwest::Response> {
+        if crate::is_offline() {
+            return Err(format!("Offline, not fetching {}", url).into());
+        }
+
+        match self.0.get(url.clone()).send().await {
+            Ok(response) => Ok(response),
+            Err(e) if e.is_connect() && !network_is_up(&url).await => {
+                log::warn!("Network is down, switching to offline mode");
+                crate::set_offline(true);
+                Err(e.into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Try to download a web page from the internet.
+    pub async fn download_web_page(&self, url: &str) -> Result<String> {
+        let url: url::Url = url.parse()?;
+        Ok(self.get(url).await?.text().await?)
+    }
+
+    /// Get possibly redirected url.
+    pub async fn final_url(&self, url: &str) -> Result<String> {
+        let url: url::Url = url.parse()?;
+        Ok(self.get(url).await?.url().to_string())
+    }
+
+    /// Download a web page and parse its title.
+    pub async fn web_page_title(&self, url: &str) -> Result<Option<String>> {
+        Ok(self.web_page_info(url).await?.title)
+    }
+
+    /// Download a web page and parse its title and language.
+    pub async fn web_page_info(&self, url: &str) -> Result<PageInfo> {
+        let content = self.download_web_page(url).await?;
+        Ok(PageInfo::parse(&content))
+    }
+
+    /// Send text to a URL in a POST request.
+    pub async fn post_text(&self, url: &str, body: String) -> Result<()> {
+        let url: url::Url = url.parse()?;
+        if crate::is_offline() {
+            return Err(format!("Offline, not posting to {}", url).into());
+        }
+        self.0
+            .post(url)
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Send a request to a JSON web API and return the JSON response.
+    ///
+    /// The token is sent as a bearer token. The request can run for
+    /// `timeout` even if it's longer than the client's timeout, for APIs
+    /// that answer a long poll only when something happens.
+    pub async fn call_api(
+        &self,
+        method: &str,
+        url: &str,
+        token: Option<&str>,
+        body: Option<&Value>,
+        timeout: Duration,
+    ) -> Result<Value> {
+        let url: url::Url = url.parse()?;
+        if crate::is_offline() {
+            return Err(format!("Offline, not calling {}", url).into());
+        }
+
+        let mut request = self
+            .0
+            .request(reqwest::Method::from_bytes(method.as_bytes())?, url)
+            .timeout(timeout);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        Ok(request.send().await?.error_for_status()?.json().await?)
+    }
+
+    /// Try to fetch a page and report whether the server had it.
+    ///
+    /// Returns an error if the request didn't get a response at all.
+    pub async fn check_link(&self, url: &str) -> Result<LinkStatus> {
+        let url: url::Url = url.parse()?;
+        let status = self.get(url).await?.status();
+        if status.is_client_error() || status.is_server_error() {
+            Ok(LinkStatus::Broken(status.as_u16()))
+        } else {
+            Ok(LinkStatus::Ok)
+        }
+    }
+
+    pub async fn is_archived_on_wayback(&self, url: &str) -> Result<bool> {
+        Ok(self.wayback_snapshot(url).await?.is_some())
+    }
+
+    /// Find the URL of the closest Wayback Machine snapshot of a page.
+    pub async fn wayback_snapshot(&self, url: &str) -> Result<Option<String>> {
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct WaybackAvailable {
+            url: String,
+            archived_snapshots: HashMap<String, Snapshot>,
+        }
+
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Snapshot {
+            status: String,
+            available: bool,
+            url: String,
+            timestamp: String,
+        }
+
+        // Make sure the initial parameter looks like an URL, then throw this
+        // value away. It's only here to see if the parse succeeds.
+        let url: url::Url = url.parse()?;
+
+        // Then make the actual URL that's querying wayback machine.
+        let url: url::Url = format!(
+            "https://archive.org/wayback/available?url={}",
+            url.as_ref()
+        )
+        .parse()?;
+
+        let response: WaybackAvailable = self.get(url).await?.json().await?;
+        Ok(response
+            .archived_snapshots
+            .get("closest")
+            .filter(|e| e.available)
+            .map(|e| e.url.clone()))
+    }
+
+    /// Ask the Wayback Machine to archive a page and return the URL of the
+    /// new snapshot.
+    pub async fn save_to_wayback(&self, url: &str) -> Result<String> {
+        let url: url::Url = url.parse()?;
+        if crate::is_offline() {
+            return Err(format!("Offline, not archiving {}", url).into());
+        }
+
+        let response = self
+            .0
+            .post(format!("https://web.archive.org/save/{}", url))
+            .timeout(WAYBACK_SAVE_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // The save request redirects to the snapshot, some responses only
+        // point to it in a header.
+        if response.url().path().starts_with("/web/") {
+            return Ok(response.url().to_string());
+        }
+        match response
+            .headers()
+            .get(reqwest::header::CONTENT_LOCATION)
+            .and_then(|h| h.to_str().ok())
+        {
+            Some(path) if path.starts_with("/web/") => {
+                Ok(format!("https://web.archive.org{}", path))
+            }
+            _ => Err(format!("No snapshot location for {}", url).into()),
+        }
+    }
+}
+
+/// Check whether the network is reachable for a request to url.
+///
+/// Used to tell a dead site apart from being offline. If the request goes
+/// through a proxy, the proxy is probed instead of a well-known host, the
+/// network may only allow proxied connections.
+async fn network_is_up(url: &url::Url) -> bool {
+    // Cloudflare DNS, connect by address so name resolution isn't needed.
+    const PROBE: &str = "1.1.1.1:443";
+    let probe = proxy_address(url).unwrap_or_else(|| PROBE.to_string());
+    matches!(
+        tokio::time::timeout(
+            REQUEST_TIMEOUT,
+            tokio::net::TcpStream::connect(probe)
+        )
+        .await,
+        Ok(Ok(_))
+    )
+}
+
+/// Return the host and port of the proxy a request to url goes through.
+fn proxy_address(url: &url::Url) -> Option<String> {
+    let proxy = match crate::proxies()
+        .and_then(|p| p.proxy_for(url.host_str()?).map(|p| p.to_string()))
+    {
+        Some(proxy) => proxy,
+        None if url.scheme() == "https" => env_var("HTTPS_PROXY")?,
+        None => env_var("HTTP_PROXY")?,
+    };
+    let proxy: url::Url = proxy.parse().ok()?;
+    // SOCKS proxies have no default port known to the url crate.
+    let port = proxy.port_or_known_default().unwrap_or(1080);
+    Some(format!("{}:{}", proxy.host_str()?, port))
+}
+
+/// Run an async operation on every item with at most `concurrency`
+/// operations running at once.
+///
+/// Results are returned in the order of the input items.
+pub async fn map_concurrent<T, F, Fut, R>(
+    items: impl IntoIterator<Item = T>,
+    concurrency: usize,
+    f: F,
+) -> Vec<R>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = R>,
+{
+    stream::iter(items)
+        .map(f)
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
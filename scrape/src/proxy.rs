@@ -0,0 +1,86 @@
+//! Per-domain proxy settings.
+
+use std::str::FromStr;
+
+use indexmap::IndexMap;
+
+/// Proxy rules mapping domains to proxy URLs.
+///
+/// Written in IDM as lines of domain and proxy URL:
+///
+/// ```notrust
+/// onion socks5h://127.0.0.1:9050
+/// example.org socks5h://127.0.0.1:9050
+/// * http://proxy.local:3128
+/// ```
+///
+/// A domain matches itself and all its subdomains, `*` matches everything.
+/// The first matching rule is used. Use `socks5h` for Tor so that host
+/// names get resolved by the proxy.
+#[derive(Clone, Default, Debug)]
+pub struct Proxies(IndexMap<String, String>);
+
+impl FromStr for Proxies {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rules: IndexMap<String, String> = idm::from_str(s)?;
+        for proxy in rules.values() {
+            // Fail early on bad URLs instead of on first request.
+            reqwest::Proxy::all(proxy.as_str())?;
+        }
+        Ok(Proxies(rules))
+    }
+}
+
+impl Proxies {
+    /// Return proxy URL to use for host, if any.
+    pub fn proxy_for(&self, host: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(domain, _)| {
+                let domain = domain.trim_start_matches("*.");
+                domain == "*"
+                    || host == domain
+                    || host.ends_with(&format!(".{}", domain))
+            })
+            .map(|(_, proxy)| proxy.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_for() {
+        let proxies: Proxies = "\
+*.onion socks5h://127.0.0.1:9050
+example.org socks5h://127.0.0.1:9051
+* http://proxy.local:3128"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            proxies.proxy_for("xyz.onion"),
+            Some("socks5h://127.0.0.1:9050")
+        );
+        assert_eq!(
+            proxies.proxy_for("example.org"),
+            Some("socks5h://127.0.0.1:9051")
+        );
+        assert_eq!(
+            proxies.proxy_for("www.example.org"),
+            Some("socks5h://127.0.0.1:9051")
+        );
+        assert_eq!(
+            proxies.proxy_for("notexample.org"),
+            Some("http://proxy.local:3128")
+        );
+        assert!("x not-a-url".parse::<Proxies>().is_err());
+    }
+}
@@ -0,0 +1,477 @@
+//! Flashcards for Anki from cloze deletions in the collection.
+//!
+//! Any headline with `{braced}` parts is a cloze card source. Each braced
+//! part becomes a separate card that asks for it with the rest of the line
+//! shown as context. In cloze mode each headline becomes a single note of
+//! Anki's own cloze note type instead. Notes in the deck that no longer
+//! have a card are only deleted with `--delete`.
+//!
+//! The AnkiConnect endpoint, deck, note type and field names can be set in
+//! `anki.idm` in the collection's config directory and overridden with
+//! environment variables, see `AnkiConfig`. The `anki-deck` user setting is
+//! used if neither names a deck. Cards from sections with an
+//! inherited `lang` attribute can go to their own deck and note type set
+//! under `languages` in the settings file.
+//!
+//! Existing decks can be imported into the collection with `import-deck`.
+//! Cloze notes become lines with `{braced}` deletions and basic notes
+//! become lines with the question followed by the braced answer. The
+//! lines remember their note in an `anki-note` attribute so that pushing
+//! cards leaves the original note and its review history alone.
+
+use std::fs;
+
+use anki::{AnkiConfig, AnkiConnection, Card, NoteId, NoteInfo, UpdatePlan};
+use base::{parse, xdg, Collection, Section, Symbol};
+use indexmap::IndexMap;
+use structopt::StructOpt;
+
+use crate::OrDie;
+
+#[derive(StructOpt, Debug)]
+pub enum AnkiCmd {
+    #[structopt(
+        name = "import-deck",
+        about = "Import the notes of an Anki deck as cloze lines"
+    )]
+    ImportDeck {
+        #[structopt(about = "Name of the Anki deck")]
+        name: String,
+        #[structopt(
+            long = "under",
+            about = "Path to insert the lines under, the deck name by default"
+        )]
+        under: Option<String>,
+    },
+}
+
+pub fn run(cmd: AnkiCmd) {
+    match cmd {
+        AnkiCmd::ImportDeck { name, under } => import_deck(name, under),
+    }
+}
+
+/// Name of the Anki settings file in the config directory.
+const CONFIG_FILE: &str = "anki.idm";
+
+/// Attribute for the Anki note an imported line came from.
+const NOTE_ATTR: &str = "anki-note";
+
+fn note_id(section: &Section) -> Option<NoteId> {
+    section.attr(NOTE_ATTR).ok().flatten()
+}
+
+pub fn anki(
+    dump: bool,
+    dry_run: bool,
+    delete: bool,
+    deck: Option<String>,
+    cloze: bool,
+    lang: Option<String>,
+) {
+    let col = Collection::load().or_die();
+    let mut config = load_config(&col);
+    config.cloze |= cloze;
+
+    // Cards go to the deck and note type of their section's language.
+    let mut groups: Vec<(AnkiConfig, Vec<Card>)> = Vec::new();
+    if lang.is_none() {
+        groups.push((target(&config, None, &deck), Vec::new()));
+    }
+    for section in col.iter() {
+        let cards = cards(&section, config.cloze);
+        if cards.is_empty() {
+            continue;
+        }
+        let section_lang = section.lang();
+        if lang.is_some() && section_lang != lang {
+            continue;
+        }
+        let target = target(&config, section_lang.as_deref(), &deck);
+        match groups.iter_mut().find(|(c, _)| *c == target) {
+            Some((_, group)) => group.extend(cards),
+            None => groups.push((target, cards)),
+        }
+    }
+
+    if dump {
+        for card in groups.iter().flat_map(|(_, cards)| cards) {
+            println!("{}", card);
+        }
+        return;
+    }
+
+    let Some((first, _)) = groups.first() else {
+        eprintln!("No cards in language {}", lang.unwrap_or_default());
+        std::process::exit(1);
+    };
+    let mut conn = AnkiConnection::new(first.clone()).or_die();
+    let mut is_changed = false;
+    let mut kept = 0;
+    for (target, cards) in groups {
+        conn.set_config(target);
+        let mut plan = UpdatePlan::new(conn.current_cards().or_die(), cards);
+        // Only cards in one language were collected, the deck may have
+        // cards in other languages too.
+        if lang.is_some() {
+            plan.delete.clear();
+        }
+        // The deck can have notes made by hand, only delete on request so
+        // that their review history isn't lost.
+        if !delete {
+            kept += plan.delete.len();
+            plan.delete.clear();
+        }
+        if !plan.is_empty() {
+            if !dry_run {
+                conn.apply(&plan).or_die();
+            }
+            print!("{}", plan);
+            is_changed = true;
+        }
+    }
+    if !is_changed {
+        eprintln!("Anki deck is up to date");
+    }
+    if kept > 0 {
+        eprintln!(
+            "Kept {} notes that have no card in the collection, \
+             use --delete to delete them",
+            kept
+        );
+    }
+}
+
+/// Return the configuration for cards in a language, an explicitly given
+/// deck overrides the deck of the language.
+fn target(
+    config: &AnkiConfig,
+    lang: Option<&str>,
+    deck: &Option<String>,
+) -> AnkiConfig {
+    let mut ret = config.for_lang(lang);
+    if deck.is_some() {
+        ret.deck = deck.clone();
+    }
+    ret
+}
+
+fn import_deck(name: String, under: Option<String>) {
+    let mut col = Collection::load().or_die();
+    let config = load_config(&col);
+    let conn = AnkiConnection::new(config.clone()).or_die();
+    let notes = conn.deck_notes(&name).or_die();
+    if notes.is_empty() {
+        eprintln!("No notes found in deck {:?}", name);
+        std::process::exit(1);
+    }
+
+    crate::hooks::pre(&col, "anki-import");
+    let parent = col.find_or_create(under.as_ref().unwrap_or(&name)).or_die();
+    let mut count = 0;
+    for note in &notes {
+        let Some(line) = note_line(&config, note) else {
+            log::warn!("Skipping empty note {}", note.note_id);
+            continue;
+        };
+        // Don't duplicate lines from an earlier import.
+        if parent
+            .children()
+            .any(|c| c.title() == line || note_id(&c) == Some(note.note_id))
+        {
+            continue;
+        }
+
+        let tags: Vec<String> = note
+            .tags
+            .iter()
+            .filter_map(|t| Symbol::new(t.replace("::", "-")).ok())
+            .map(|t| t.to_string())
+            .collect();
+        let mut attributes = IndexMap::new();
+        if !tags.is_empty() {
+            attributes.insert("tags".to_string(), tags.join(" "));
+        }
+        attributes.insert(NOTE_ATTR.to_string(), note.note_id.to_string());
+        parent.append(Section::new(line, attributes));
+        count += 1;
+    }
+
+    if count > 0 {
+        crate::save_changes(&mut col, "anki-import", count);
+    }
+    eprintln!("Imported {} of {} notes", count, notes.len());
+}
+
+/// Turn an Anki note into a line with `{braced}` cloze deletions.
+///
+/// Cloze notes keep their deletions, for other notes the first field is the
+/// question and the second field is the deleted answer.
+fn note_line(config: &AnkiConfig, note: &NoteInfo) -> Option<String> {
+    let line = if let Some(text) = note.field(&config.text_field) {
+        from_anki_cloze(&plain_text(text))
+    } else {
+        let values = note.field_values();
+        let field = |name: &str, i: usize| {
+            plain_text(
+                note.field(name).or(values.get(i).copied()).unwrap_or(""),
+            )
+        };
+        let (front, back) =
+            (field(&config.front_field, 0), field(&config.back_field, 1));
+        match (front.is_empty(), back.is_empty()) {
+            (_, true) => front,
+            (true, false) => back,
+            (false, false) => format!("{} {{{}}}", front, back),
+        }
+    };
+    Some(line).filter(|s| !s.is_empty())
+}
+
+/// Convert Anki cloze markup like `{{c1::deleted::hint}}` into `{deleted}`.
+fn from_anki_cloze(text: &str) -> String {
+    let mut ret = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{c") {
+        let Some(end) = rest[start..].find("}}").map(|i| start + i) else {
+            break;
+        };
+        let inner = &rest[start + 3..end];
+        let Some((n, deletion)) = inner.split_once("::") else {
+            break;
+        };
+        if !n.chars().all(|c| c.is_ascii_digit()) {
+            break;
+        }
+        let deletion = deletion.split("::").next().unwrap_or("").trim();
+
+        ret.push_str(&rest[..start]);
+        ret.push('{');
+        ret.push_str(deletion);
+        ret.push('}');
+        rest = &rest[end + 2..];
+    }
+    ret.push_str(rest);
+    ret
+}
+
+/// Strip the HTML formatting of an Anki field into a single line of text.
+fn plain_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut tag: Option<String> = None;
+    for c in html.chars() {
+        match (&mut tag, c) {
+            (None, '<') => tag = Some(String::new()),
+            (Some(name), '>') => {
+                // Line breaks and block ends become spaces.
+                let name = name.trim_start_matches('/').to_lowercase();
+                if ["br", "div", "p", "li"]
+                    .iter()
+                    .any(|t| name.split([' ', '/']).next() == Some(t))
+                {
+                    text.push(' ');
+                }
+                tag = None;
+            }
+            (Some(name), c) => name.push(c),
+            (None, c) => text.push(c),
+        }
+    }
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub(crate) fn load_config(col: &Collection) -> AnkiConfig {
+    try_load_config(col).or_die()
+}
+
+/// Load the Anki settings, return an error if the settings file is broken.
+pub(crate) fn try_load_config(col: &Collection) -> base::Result<AnkiConfig> {
+    let path = xdg::path(col.root_path(), xdg::Dir::Config, CONFIG_FILE);
+    let config = if path.exists() {
+        idm::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("Bad Anki settings in {:?}: {}", path, e))?
+    } else {
+        AnkiConfig::default()
+    };
+    let mut config = config.with_env_overrides();
+    if config.deck.is_none() {
+        config.deck = base::Config::get().anki_deck.clone();
+    }
+    Ok(config)
+}
+
+/// Generate the cloze cards for a single section.
+///
+/// With `cloze` there's a single card with Anki cloze markup for the
+/// section, otherwise a basic card for each cloze deletion. The first card
+/// of a line imported from Anki stands for the original note.
+fn cards(section: &Section, cloze: bool) -> Vec<Card> {
+    let mut ret = section_cards(section, cloze);
+    if let Some(card) = ret.first_mut() {
+        card.note_id = note_id(section);
+    }
+    ret
+}
+
+fn section_cards(section: &Section, cloze: bool) -> Vec<Card> {
+    let title = section.title();
+    let segments = parse::cloze(&title);
+    let tags: Vec<String> = section.tags().into_iter().collect();
+    let is_japanese = section.lang().as_deref() == Some("ja");
+    let ruby = |text: &str| ruby_text(text, is_japanese);
+
+    if cloze {
+        if segments.is_empty() {
+            return Vec::new();
+        }
+        let mut n = 0;
+        let text: String = segments
+            .iter()
+            .map(|&(text, hidden)| {
+                if hidden {
+                    n += 1;
+                    format!("{{{{c{}::{}}}}}", n, text)
+                } else {
+                    ruby(text)
+                }
+            })
+            .collect();
+        return vec![Card::new(text, "", tags)];
+    }
+
+    let back: String = segments
+        .iter()
+        .map(|&(text, hidden)| {
+            if hidden {
+                format!("<b>{}</b>", text)
+            } else {
+                ruby(text)
+            }
+        })
+        .collect();
+
+    let mut ret = Vec::new();
+    for (i, _) in segments.iter().enumerate().filter(|(_, (_, h))| *h) {
+        let front: String = segments
+            .iter()
+            .enumerate()
+            .map(|(j, &(text, _))| {
+                if i == j {
+                    "[...]".to_string()
+                } else {
+                    ruby(text)
+                }
+            })
+            .collect();
+        ret.push(Card::new(front, back.clone(), tags.clone()));
+    }
+    ret
+}
+
+/// Convert `漢字{かんじ}` ruby annotations for Anki.
+///
+/// Japanese cards get Anki's ` 漢字[かんじ]` furigana format that is shown
+/// with a `{{furigana:Field}}` card template, other cards get HTML ruby
+/// elements.
+fn ruby_text(text: &str, is_japanese: bool) -> String {
+    let segments = parse::ruby(text);
+    if segments.is_empty() {
+        return text.to_string();
+    }
+    let mut ret = String::new();
+    for (text, reading) in segments {
+        match reading {
+            // The space marks where the annotated text starts.
+            Some(reading) if is_japanese => {
+                if !ret.is_empty() && !ret.ends_with(' ') {
+                    ret.push(' ');
+                }
+                ret.push_str(&format!("{}[{}]", text, reading))
+            }
+            Some(reading) => ret.push_str(&format!(
+                "<ruby>{}<rt>{}</rt></ruby>",
+                text, reading
+            )),
+            None => ret.push_str(text),
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(fields: &[(&str, &str)]) -> NoteInfo {
+        let fields: serde_json::Map<String, serde_json::Value> = fields
+            .iter()
+            .enumerate()
+            .map(|(i, (name, value))| {
+                (
+                    name.to_string(),
+                    serde_json::json!({ "value": value, "order": i }),
+                )
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "noteId": 1,
+            "modelName": "Basic",
+            "fields": fields,
+            "tags": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_note_line() {
+        let config = AnkiConfig::default();
+        assert_eq!(
+            note_line(&config, &note(&[("Front", "Cat"), ("Back", "猫")])),
+            Some("Cat {猫}".into())
+        );
+        assert_eq!(
+            note_line(&config, &note(&[("Front", "Only front"), ("Back", "")])),
+            Some("Only front".into())
+        );
+        // Fields with other names are read in order.
+        assert_eq!(
+            note_line(&config, &note(&[("Q", "Dog"), ("A", "犬")])),
+            Some("Dog {犬}".into())
+        );
+        assert_eq!(
+            note_line(
+                &config,
+                &note(&[("Text", "{{c1::Paris}} is in {{c2::France}}")])
+            ),
+            Some("{Paris} is in {France}".into())
+        );
+        assert_eq!(note_line(&config, &note(&[("Front", " ")])), None);
+    }
+
+    #[test]
+    fn test_from_anki_cloze() {
+        assert_eq!(from_anki_cloze("no deletions"), "no deletions");
+        assert_eq!(
+            from_anki_cloze("{{c1::Paris::city}} is in {{c12::France}}"),
+            "{Paris} is in {France}"
+        );
+        // Malformed markup is left alone.
+        assert_eq!(from_anki_cloze("{{cx::a}} b"), "{{cx::a}} b");
+        assert_eq!(from_anki_cloze("{{c1::a b"), "{{c1::a b");
+    }
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(plain_text("<b>bold</b> text"), "bold text");
+        assert_eq!(plain_text("one<br>two<div>three</div>"), "one two three");
+        assert_eq!(plain_text("a &amp; b&nbsp;&lt;c&gt;"), "a & b <c>");
+        assert_eq!(plain_text("  spread \n out  "), "spread out");
+    }
+}
@@ -0,0 +1,314 @@
+//! Chat bots for saving links and notes from a phone.
+//!
+//! A link sent to the bot is scraped and saved in the to-read queue like
+//! with `olt toread`, any other message is saved in the inbox as a note
+//! whose first line is the headline. The bot replies with the headline of
+//! the saved item. Messages from users not listed in the settings are
+//! refused with a reply that tells the user ID to add. The Matrix bot
+//! doesn't accept room invites, join its account to the rooms with another
+//! client.
+
+use std::{thread, time::Duration};
+
+use base::{
+    batch, journal::Journal, parse, route::Routes, shared::SharedCollection,
+    Collection, Config, Section, VagueDate,
+};
+use indexmap::IndexMap;
+use serde_json::{json, Value};
+use structopt::StructOpt;
+
+use crate::OrDie;
+
+/// How long the chat server may hold a poll request open.
+const POLL_SECS: u64 = 30;
+
+/// Wait before polling again after a failed request.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(StructOpt, Debug)]
+pub enum BotCmd {
+    #[structopt(name = "telegram", about = "Run a Telegram bot")]
+    Telegram,
+    #[structopt(name = "matrix", about = "Run a Matrix bot")]
+    Matrix,
+}
+
+pub fn run(cmd: BotCmd) {
+    let mut col = Collection::load().or_die();
+    crate::recover_journal(&mut col);
+    let col = SharedCollection::new(col);
+    match cmd {
+        BotCmd::Telegram => telegram(&col),
+        BotCmd::Matrix => matrix(&col),
+    }
+}
+
+/// Timeout for a poll request, a bit longer than the server holds it.
+fn poll_timeout() -> Duration {
+    Duration::from_secs(POLL_SECS) + Config::get().http_timeout()
+}
+
+/// Save a message and return the reply to send.
+fn handle(
+    col: &SharedCollection,
+    user: &str,
+    allowed: &[String],
+    text: &str,
+) -> String {
+    if !allowed.iter().any(|u| u == user) {
+        log::warn!("Refused message from {}", user);
+        return format!("Not allowed, add {} to the bot users setting", user);
+    }
+    let text = text.trim();
+    if text.is_empty() {
+        return "Nothing to save".into();
+    }
+
+    // Link scraping may have tripped offline mode earlier.
+    scrape::set_offline(false);
+
+    // Pick up edits made while the bot was waiting.
+    if let Err(e) = col.refresh() {
+        return format!("Failed to load collection: {}", e);
+    }
+
+    let (item, default_path, command) = if parse::only(parse::url)(text).is_ok()
+    {
+        let entry = crate::scrape_entry(text.to_string()).unwrap_or_else(|e| {
+            log::warn!("Failed to scrape {}: {}", text, e);
+            Section::new(
+                text.to_string(),
+                IndexMap::from([
+                    ("uri".to_string(), text.to_string()),
+                    ("added".to_string(), VagueDate::now().to_string()),
+                ]),
+            )
+        });
+        (entry, "ToRead", "toread")
+    } else {
+        let (headline, body) = text.split_once('\n').unwrap_or((text, ""));
+        let note = Section::new(
+            headline.trim().to_string(),
+            IndexMap::from([(
+                "added".to_string(),
+                VagueDate::now().to_string(),
+            )]),
+        );
+        note.set_body(body.trim());
+        (note, Config::get().inbox.as_str(), "insert")
+    };
+
+    let headline = item.headline();
+    col.write(|col| match save_item(col, item, default_path, command) {
+        Ok(0) => format!("Already saved: {}", headline),
+        Ok(_) => format!("Saved: {}", headline),
+        Err(e) => {
+            log::warn!("Failed to save {:?}: {}", headline, e);
+            format!("Failed to save {}: {}", headline, e)
+        }
+    })
+}
+
+/// Insert an item and save it, return the number of items saved.
+///
+/// Errors are returned instead of exiting so that the bot keeps running.
+fn save_item(
+    col: &mut Collection,
+    item: Section,
+    default_path: &str,
+    command: &str,
+) -> base::Result<usize> {
+    crate::hooks::try_pre(col, command)?;
+
+    // Route the item here so that the journal has where it went.
+    let routes = Routes::load(col.root_path())?;
+    let path = routes.target(&item).unwrap_or(default_path).to_string();
+
+    // Only journal the message once the hook has let it through.
+    let journal = Journal::new(col.root_path());
+    journal.record(&batch::Command::Insert {
+        under: Some(path.clone()),
+        idm: idm::to_string(&item)?,
+    })?;
+
+    let inserted = crate::try_insert_items(
+        col,
+        &[item],
+        Some(path),
+        default_path,
+        crate::conflict::OnConflict::Skip,
+    )
+    .and_then(|count| {
+        if count > 0 {
+            crate::try_save_changes(col, command, count)?;
+        }
+        Ok(count)
+    });
+    if inserted.is_err() {
+        // Leave nothing half done in memory, the journal goes too since
+        // the edit is reported as failed.
+        if let Err(e) = col.revert() {
+            log::warn!("Failed to revert collection: {}", e);
+        }
+    }
+    if let Err(e) = journal.clear() {
+        log::warn!("Failed to clear journal: {}", e);
+    }
+    inserted
+}
+
+fn telegram(col: &SharedCollection) {
+    let config = Config::get();
+    let Some(token) = &config.telegram_token else {
+        eprintln!(
+            "Set telegram-token in {:?}",
+            Config::file_path().unwrap_or_default()
+        );
+        std::process::exit(1);
+    };
+    let api = format!("https://api.telegram.org/bot{}", token);
+
+    eprintln!("Telegram bot running");
+    let mut offset = 0;
+    loop {
+        let updates = match scrape::call_api(
+            "GET",
+            &format!(
+                "{}/getUpdates?timeout={}&offset={}",
+                api, POLL_SECS, offset
+            ),
+            None,
+            None,
+            poll_timeout(),
+        ) {
+            Ok(updates) => updates,
+            Err(e) => {
+                // Errors have the URL in them, don't log the token.
+                let e = e.to_string().replace(token.as_str(), "<token>");
+                log::warn!("Telegram poll failed: {}", e);
+                thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        };
+
+        for update in updates["result"].as_array().into_iter().flatten() {
+            if let Some(id) = update["update_id"].as_i64() {
+                offset = offset.max(id + 1);
+            }
+            let message = &update["message"];
+            let (Some(chat), Some(user), Some(text)) = (
+                message["chat"]["id"].as_i64(),
+                message["from"]["id"].as_i64(),
+                message["text"].as_str(),
+            ) else {
+                continue;
+            };
+
+            let reply =
+                handle(col, &user.to_string(), &config.telegram_users, text);
+            if let Err(e) = scrape::call_api(
+                "POST",
+                &format!("{}/sendMessage", api),
+                None,
+                Some(&json!({"chat_id": chat, "text": reply})),
+                config.http_timeout(),
+            ) {
+                let e = e.to_string().replace(token.as_str(), "<token>");
+                log::warn!("Telegram reply failed: {}", e);
+            }
+        }
+    }
+}
+
+fn matrix(col: &SharedCollection) {
+    let config = Config::get();
+    let (Some(homeserver), Some(token)) =
+        (&config.matrix_homeserver, &config.matrix_token)
+    else {
+        eprintln!(
+            "Set matrix-homeserver and matrix-token in {:?}",
+            Config::file_path().unwrap_or_default()
+        );
+        std::process::exit(1);
+    };
+    let api = format!("{}/_matrix/client/v3", homeserver.trim_end_matches('/'));
+    let call = |method: &str, path: &str, body: Option<&Value>| {
+        scrape::call_api(
+            method,
+            &format!("{}{}", api, path),
+            Some(token),
+            body,
+            poll_timeout(),
+        )
+    };
+
+    let whoami = call("GET", "/account/whoami", None).or_die();
+    let own_id = whoami["user_id"].as_str().unwrap_or_default().to_string();
+    eprintln!("Matrix bot running as {}", own_id);
+
+    // Messages from before the bot started are skipped.
+    let mut since: Option<String> = None;
+    let started = chrono::Utc::now().timestamp_millis();
+    let mut txn = 0;
+    loop {
+        let mut path = format!("/sync?timeout={}", POLL_SECS * 1000);
+        if let Some(since) = &since {
+            path.push_str(&format!("&since={}", url_encode(since)));
+        }
+        let sync = match call("GET", &path, None) {
+            Ok(sync) => sync,
+            Err(e) => {
+                log::warn!("Matrix sync failed: {}", e);
+                thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        };
+        let is_first = since.is_none();
+        since = sync["next_batch"].as_str().map(|s| s.to_string());
+
+        let Some(rooms) = sync["rooms"]["join"].as_object() else {
+            continue;
+        };
+        for (room, data) in rooms {
+            for event in
+                data["timeline"]["events"].as_array().into_iter().flatten()
+            {
+                let (Some(sender), Some(text)) = (
+                    event["sender"].as_str(),
+                    event["content"]["body"].as_str(),
+                ) else {
+                    continue;
+                };
+                if is_first
+                    || sender == own_id
+                    || event["type"] != "m.room.message"
+                    || event["content"]["msgtype"] != "m.text"
+                {
+                    continue;
+                }
+
+                let reply = handle(col, sender, &config.matrix_users, text);
+                txn += 1;
+                // Transaction IDs must not repeat between runs either.
+                let path = format!(
+                    "/rooms/{}/send/m.room.message/olt{}-{}",
+                    url_encode(room),
+                    started,
+                    txn
+                );
+                if let Err(e) = call(
+                    "PUT",
+                    &path,
+                    Some(&json!({"msgtype": "m.text", "body": reply})),
+                ) {
+                    log::warn!("Matrix reply failed: {}", e);
+                }
+            }
+        }
+    }
+}
+
+fn url_encode(s: &str) -> String {
+    url::form_urlencoded::byte_serialize(s.as_bytes()).collect()
+}
@@ -0,0 +1,338 @@
+//! Handling inserted items that are already in the collection.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    str::FromStr,
+};
+
+use base::{EntityIdentifier, Section};
+use dialoguer::console::Term;
+
+use crate::OrDie;
+
+/// What to do when an inserted item has the same entity identifier as an
+/// existing section.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum OnConflict {
+    /// Leave the existing section alone and drop the item.
+    Skip,
+    /// Put the item in the existing section's place.
+    Replace,
+    /// Add the item's tags and missing attributes to the existing section.
+    Merge,
+    /// Merge, and also add the item's child sections that the existing
+    /// section doesn't have.
+    ///
+    /// For imports like Kindle highlights that add new child sections on
+    /// every run.
+    Append,
+    /// Show the differences and ask which of the above to do.
+    Ask,
+}
+
+impl FromStr for OnConflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(OnConflict::Skip),
+            "replace" => Ok(OnConflict::Replace),
+            "merge" => Ok(OnConflict::Merge),
+            "append" => Ok(OnConflict::Append),
+            "ask" => Ok(OnConflict::Ask),
+            _ => Err(format!(
+                "Bad conflict mode {:?}, \
+                 must be skip, replace, merge, append or ask",
+                s
+            )),
+        }
+    }
+}
+
+/// Resolve a conflict between an existing section and an incoming item.
+///
+/// Return the section that now holds the entity, or `None` if nothing
+/// changed.
+pub fn resolve(
+    mode: OnConflict,
+    existing: &Section,
+    item: &Section,
+) -> Option<Section> {
+    match mode {
+        OnConflict::Skip => None,
+        OnConflict::Replace if existing.parent().is_none() => {
+            // A file root has no place to put the item in.
+            eprintln!("{} is a whole file, can't replace it", existing.path());
+            None
+        }
+        OnConflict::Replace => {
+            let item = item.clone();
+            existing.insert_before(item.clone());
+            existing.detach();
+            Some(item)
+        }
+        OnConflict::Merge => {
+            merge(existing, item, false).then(|| existing.clone())
+        }
+        OnConflict::Append => {
+            merge(existing, item, true).then(|| existing.clone())
+        }
+        OnConflict::Ask => {
+            print_diff(existing, item);
+            resolve(ask(&Term::stderr()), existing, item)
+        }
+    }
+}
+
+/// Point the entities of a resolved conflict to where they are now.
+///
+/// Sections inside `old` may have been detached from the collection or
+/// new ones merged into it, so entities found under `old` before
+/// resolving are dropped and the ones under `new` are added.
+pub fn update_entities(
+    entities: &mut HashMap<EntityIdentifier, Section>,
+    old: &[EntityIdentifier],
+    new: &Section,
+) {
+    for id in old {
+        entities.remove(id);
+    }
+    for section in new.iter() {
+        if let Some(id) = section.entity_identifier() {
+            entities.insert(id, section);
+        }
+    }
+}
+
+/// Return the entity identifiers of a section and its descendants.
+pub fn entities(section: &Section) -> Vec<EntityIdentifier> {
+    section
+        .iter()
+        .filter_map(|s| s.entity_identifier())
+        .collect()
+}
+
+/// Add the tags, missing attributes and body of item to section, and its
+/// new child sections if `append` is set.
+///
+/// Return whether section changed.
+fn merge(section: &Section, item: &Section, append: bool) -> bool {
+    let mut changed = false;
+
+    let mut tags: BTreeSet<String> =
+        section.attr("tags").ok().flatten().unwrap_or_default();
+    let new_tags: BTreeSet<String> =
+        item.attr("tags").ok().flatten().unwrap_or_default();
+    if !new_tags.is_subset(&tags) {
+        tags.extend(new_tags);
+        section.clone().set_attr("tags", &tags).or_die();
+        changed = true;
+    }
+
+    for (name, value) in &item.borrow().attributes {
+        if !section.borrow().attributes.contains_key(name) {
+            section
+                .borrow_mut()
+                .attributes
+                .insert(name.clone(), value.clone());
+            changed = true;
+        }
+    }
+
+    if section.body().is_empty() && !item.body().is_empty() {
+        section.set_body(item.body());
+        changed = true;
+    }
+
+    if !append {
+        return changed;
+    }
+
+    let headlines: BTreeSet<String> =
+        section.children().map(|c| c.headline()).collect();
+    for child in item.children().collect::<Vec<_>>() {
+        if !headlines.contains(&child.headline()) {
+            section.append(child);
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+/// Print the headline and attribute differences between the existing
+/// section and an item.
+fn print_diff(existing: &Section, item: &Section) {
+    eprintln!("\n{} is already in the collection", existing.path());
+    if existing.headline() != item.headline() {
+        eprintln!("- {}", existing.headline());
+        eprintln!("+ {}", item.headline());
+    }
+
+    let old = existing.borrow().attributes.clone();
+    let new = item.borrow().attributes.clone();
+    for (name, value) in &old {
+        match new.get(name) {
+            Some(v) if v == value => {}
+            Some(v) => {
+                eprintln!("- :{} {}", name, value);
+                eprintln!("+ :{} {}", name, v);
+            }
+            None => eprintln!("  :{} {}", name, value),
+        }
+    }
+    for (name, value) in &new {
+        if !old.contains_key(name) {
+            eprintln!("+ :{} {}", name, value);
+        }
+    }
+}
+
+/// Ask what to do, skip if there's no terminal to ask from.
+fn ask(term: &Term) -> OnConflict {
+    if !term.is_term() {
+        eprintln!("Not a terminal, skipping");
+        return OnConflict::Skip;
+    }
+    eprintln!("[s]kip  [r]eplace  [m]erge  [a]ppend");
+    loop {
+        match term.read_char() {
+            Ok('s' | ' ') | Err(_) => return OnConflict::Skip,
+            Ok('r') => return OnConflict::Replace,
+            Ok('m') => return OnConflict::Merge,
+            Ok('a') => return OnConflict::Append,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outline(s: &str) -> Section {
+        let root = Section::new(String::new(), Default::default());
+        for item in idm::from_str::<Vec<Section>>(s).unwrap() {
+            root.append(item);
+        }
+        root
+    }
+
+    #[test]
+    fn test_resolve() {
+        let item = || {
+            outline(
+                "\
+Dune
+  :uri isbn:9780441013593
+  :tags scifi
+  :rating 5
+  Reread
+",
+            )
+            .child()
+            .unwrap()
+        };
+        let col = || {
+            outline(
+                "\
+Dune
+  :uri isbn:9780441013593
+  :tags classic
+  Notes
+",
+            )
+        };
+
+        let text = |s: &Section| idm::to_string(s).unwrap();
+
+        let root = col();
+        let existing = root.child().unwrap();
+        assert!(resolve(OnConflict::Skip, &existing, &item()).is_none());
+        assert_eq!(text(&root), text(&col()));
+
+        let root = col();
+        let existing = root.child().unwrap();
+        let item = item();
+        let replaced = resolve(OnConflict::Replace, &existing, &item).unwrap();
+        assert_eq!(text(&replaced), text(&item));
+        assert_eq!(root.children().count(), 1);
+        assert_eq!(text(&root.child().unwrap()), text(&item));
+        assert!(existing.parent().is_none());
+
+        // File roots stay in place.
+        let root = col();
+        assert!(resolve(OnConflict::Replace, &root, &item).is_none());
+        assert_eq!(text(&root), text(&col()));
+
+        let root = col();
+        let existing = root.child().unwrap();
+        let merged =
+            resolve(OnConflict::Merge, &existing, &item.clone()).unwrap();
+        assert_eq!(
+            idm::to_string(&merged).unwrap(),
+            "\
+Dune
+  :uri isbn:9780441013593
+  :tags classic scifi
+  :rating 5
+  Notes
+"
+        );
+        assert!(resolve(OnConflict::Merge, &existing, &item).is_none());
+
+        let root = col();
+        let existing = root.child().unwrap();
+        let appended =
+            resolve(OnConflict::Append, &existing, &item.clone()).unwrap();
+        assert_eq!(
+            idm::to_string(&appended).unwrap(),
+            "\
+Dune
+  :uri isbn:9780441013593
+  :tags classic scifi
+  :rating 5
+  Notes
+  Reread
+"
+        );
+        assert!(resolve(OnConflict::Append, &existing, &item).is_none());
+    }
+
+    #[test]
+    fn test_update_entities() {
+        let root = outline(
+            "\
+Dune
+  :uri isbn:9780441013593
+  Review
+    :uri https://example.com/review
+",
+        );
+        let existing = root.child().unwrap();
+        let item = outline(
+            "\
+Dune
+  :uri isbn:9780441013593
+  Interview
+    :uri https://example.com/interview
+",
+        )
+        .child()
+        .unwrap();
+
+        let mut map: HashMap<EntityIdentifier, Section> = root
+            .iter()
+            .filter_map(|s| Some((s.entity_identifier()?, s)))
+            .collect();
+        let old = entities(&existing);
+        let new = resolve(OnConflict::Replace, &existing, &item).unwrap();
+        update_entities(&mut map, &old, &new);
+
+        // Detached sections would have paths without the leading slash
+        // of the root.
+        let mut paths: Vec<String> = map.values().map(|s| s.path()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/Dune", "/Dune/Interview"]);
+    }
+}
@@ -0,0 +1,171 @@
+//! Diagnostics for the collection and the environment around it.
+
+use std::{fs, path::Path};
+
+use base::{search::Index, xdg, Collection, Config};
+
+/// Cache size above which clearing the cache is suggested.
+const LARGE_CACHE_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Result of a single check.
+enum Status {
+    Ok,
+    /// Something is missing or out of date, but things still work.
+    Warn,
+    /// Something is broken.
+    Error,
+}
+
+fn report(status: Status, msg: impl AsRef<str>, suggestion: Option<&str>) {
+    let label = match status {
+        Status::Ok => "ok",
+        Status::Warn => "warn",
+        Status::Error => "error",
+    };
+    println!("{:<6}{}", label, msg.as_ref());
+    if let Some(suggestion) = suggestion {
+        println!("      {}", suggestion);
+    }
+}
+
+pub fn doctor() {
+    match Config::load() {
+        Ok(_) => match Config::file_path().filter(|p| p.exists()) {
+            Some(path) => {
+                report(Status::Ok, format!("Settings from {:?}", path), None)
+            }
+            None => report(Status::Ok, "Using default settings", None),
+        },
+        Err(e) => report(
+            Status::Error,
+            e.to_string(),
+            Some("Fix the settings file, other commands won't run until it loads"),
+        ),
+    }
+
+    let root = match Collection::default_path() {
+        Ok(root) if root.is_dir() => root,
+        Ok(root) => {
+            report(
+                Status::Error,
+                format!("No collection directory at {:?}", root),
+                Some("Create the directory or set OTLBOOK_PATH to point to your notes"),
+            );
+            std::process::exit(1);
+        }
+        Err(e) => {
+            report(
+                Status::Error,
+                e.to_string(),
+                Some("Set OTLBOOK_PATH to point to your notes"),
+            );
+            std::process::exit(1);
+        }
+    };
+    report(Status::Ok, format!("Collection at {:?}", root), None);
+    let mut has_errors = false;
+
+    let col = match Collection::load() {
+        Ok(col) => {
+            report(
+                Status::Ok,
+                format!("{} files load without errors", col.files().count()),
+                None,
+            );
+            Some(col)
+        }
+        Err(e) => {
+            has_errors = true;
+            report(
+                Status::Error,
+                format!("Collection doesn't load: {}", e),
+                Some("Fix the file in an editor, most commands won't run until it loads"),
+            );
+            None
+        }
+    };
+
+    if root.join(".git").exists() {
+        report(Status::Ok, "Collection is a git repository", None);
+    } else {
+        report(
+            Status::Warn,
+            "Collection is not a git repository",
+            Some("Run git init in the collection directory to keep a history of changes"),
+        );
+    }
+
+    match col.as_ref().map(crate::anki::try_load_config) {
+        Some(Ok(config)) if anki::is_running(&config.endpoint) => report(
+            Status::Ok,
+            format!("AnkiConnect answers at {}", config.endpoint),
+            None,
+        ),
+        Some(Ok(config)) => report(
+            Status::Warn,
+            format!("AnkiConnect doesn't answer at {}", config.endpoint),
+            Some("Start Anki with the AnkiConnect add-on to use olt anki"),
+        ),
+        Some(Err(e)) => {
+            has_errors = true;
+            report(Status::Error, e.to_string(), None);
+        }
+        None => {}
+    }
+
+    let mut index = Index::load(&root);
+    match index.update(&root) {
+        Ok(0) => report(Status::Ok, "Search index is up to date", None),
+        Ok(n) => report(
+            Status::Warn,
+            format!("Search index is out of date for {} files", n),
+            Some("Run olt search to update it"),
+        ),
+        Err(e) => {
+            has_errors = true;
+            report(
+                Status::Error,
+                format!("Can't update search index: {}", e),
+                None,
+            );
+        }
+    }
+
+    // Without a cache base directory the cache lives in the legacy
+    // directory with the settings, so don't suggest deleting it.
+    if let Some(cache) = xdg::dir(&root, xdg::Dir::Cache) {
+        let bytes = dir_size(&cache);
+        let msg = format!(
+            "Cache at {:?} is {:.1} MB",
+            cache,
+            bytes as f64 / 1024.0 / 1024.0
+        );
+        if bytes > LARGE_CACHE_BYTES {
+            report(
+                Status::Warn,
+                msg,
+                Some("The cache is rebuilt as needed, delete the directory to free space"),
+            );
+        } else {
+            report(Status::Ok, msg, None);
+        }
+    }
+
+    if has_errors {
+        std::process::exit(1);
+    }
+}
+
+/// Total size of the files under a directory.
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| match e.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&e.path()),
+            _ => e.metadata().map(|m| m.len()).unwrap_or(0),
+        })
+        .sum()
+}
@@ -0,0 +1,106 @@
+//! Expense roll-ups from ledger-style lines in the collection.
+
+use std::collections::BTreeMap;
+
+use base::{
+    expense::{self, Amount, Expense},
+    Collection,
+};
+use structopt::StructOpt;
+
+use crate::OrDie;
+
+#[derive(StructOpt, Debug)]
+pub enum ExpensesCmd {
+    #[structopt(name = "report", about = "Sum expenses per category")]
+    Report {
+        #[structopt(long = "month", about = "Sum separately for each month")]
+        month: bool,
+        #[structopt(
+            long = "since",
+            about = "Only count expenses from this date on, eg. 2024-01"
+        )]
+        since: Option<base::VagueDate>,
+    },
+    #[structopt(name = "export", about = "Print all expenses as CSV")]
+    Export,
+}
+
+pub fn run(cmd: ExpensesCmd) {
+    match cmd {
+        ExpensesCmd::Report { month, since } => report(month, since),
+        ExpensesCmd::Export => export(),
+    }
+}
+
+fn load(since: Option<base::VagueDate>) -> Vec<Expense> {
+    let col = Collection::load().or_die();
+    let mut ret = expense::expenses(&col);
+    if let Some(since) = since {
+        ret.retain(|e| e.date >= since);
+    }
+    ret
+}
+
+fn report(by_month: bool, since: Option<base::VagueDate>) {
+    // (period, category, currency) -> sum
+    let mut sums: BTreeMap<(String, String, String), Amount> = BTreeMap::new();
+    let mut totals: BTreeMap<(String, String), Amount> = BTreeMap::new();
+    for e in load(since) {
+        let period = if by_month { e.month() } else { String::new() };
+        *sums
+            .entry((period.clone(), e.category.clone(), e.currency.clone()))
+            .or_default() += e.amount;
+        *totals.entry((period, e.currency)).or_default() += e.amount;
+    }
+
+    let width = sums.keys().map(|(_, c, _)| c.len()).fold(5, usize::max);
+    let mut current_period = None;
+    for ((period, category, currency), sum) in &sums {
+        if current_period != Some(period) {
+            if current_period.is_some() {
+                println!();
+            }
+            current_period = Some(period);
+        }
+        let prefix = if by_month {
+            format!("{}  ", period)
+        } else {
+            String::new()
+        };
+        println!("{}{:width$}  {:>12} {}", prefix, category, sum, currency);
+
+        // Print totals after the last category of the period.
+        let is_last = sums
+            .range((period.clone(), category.clone(), currency.clone())..)
+            .nth(1)
+            .is_none_or(|((p, _, _), _)| p != period);
+        if is_last {
+            for ((p, currency), total) in &totals {
+                if p == period {
+                    println!(
+                        "{}{:width$}  {:>12} {}",
+                        prefix, "total", total, currency
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn export() {
+    let mut wtr = csv::Writer::from_writer(std::io::stdout());
+    wtr.write_record(["date", "category", "amount", "currency", "description"])
+        .or_die();
+    for e in load(None) {
+        wtr.write_record([
+            e.date.to_string(),
+            e.category,
+            e.amount.to_string(),
+            e.currency,
+            e.description,
+        ])
+        .or_die();
+    }
+    wtr.flush().or_die();
+}
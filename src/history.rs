@@ -0,0 +1,111 @@
+//! Log of commands that changed the collection.
+//!
+//! Each mutating command appends a JSON line to `history.log` in the
+//! collection's state directory after it has saved its changes. The line
+//! lists the entities the command added, modified and removed, so sync
+//! tools can follow the log instead of comparing files.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use base::{changes::Change, xdg, Collection, VagueDate};
+use serde::{Deserialize, Serialize};
+
+use crate::OrDie;
+
+/// Name of the history log in the state directory.
+const HISTORY_FILE: &str = "history.log";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub time: VagueDate,
+    /// Command line arguments, not including the program name.
+    pub args: Vec<String>,
+    /// Number of entities the command changed.
+    pub affected: usize,
+    /// Entities the save changed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<Change>,
+}
+
+impl Entry {
+    /// Name of the subcommand that was run.
+    pub fn command(&self) -> Option<&str> {
+        self.args
+            .iter()
+            .find(|a| !a.starts_with('-'))
+            .map(|a| a.as_str())
+    }
+}
+
+fn history_path(col: &Collection) -> PathBuf {
+    xdg::path(col.root_path(), xdg::Dir::State, HISTORY_FILE)
+}
+
+/// Record the current command in the collection's history.
+pub fn record(col: &Collection, affected: usize) {
+    let entry = Entry {
+        time: VagueDate::now(),
+        args: std::env::args().skip(1).collect(),
+        affected,
+        changes: col.changes().to_vec(),
+    };
+
+    if let Err(e) = append(&history_path(col), &entry) {
+        // The changes are already saved at this point, don't fail the whole
+        // command over the log.
+        log::warn!("Failed to write history: {}", e);
+    }
+}
+
+fn append(path: &Path, entry: &Entry) -> base::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+fn load(path: &Path) -> base::Result<Vec<Entry>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut ret = Vec::new();
+    for line in BufReader::new(fs::File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        ret.push(serde_json::from_str(&line)?);
+    }
+    Ok(ret)
+}
+
+/// Print history entries, optionally filtered by date and subcommand.
+pub fn history(since: Option<VagueDate>, command: Option<String>) {
+    let col = Collection::load().or_die();
+
+    for entry in load(&history_path(&col)).or_die() {
+        if since.is_some_and(|since| entry.time < since) {
+            continue;
+        }
+        if command.is_some() && entry.command() != command.as_deref() {
+            continue;
+        }
+
+        println!(
+            "{}  {:>5}  {}",
+            entry.time,
+            entry.affected,
+            entry.args.join(" ")
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! User hook scripts run around collection changes.
+//!
+//! Hooks are executables in `hooks/` in the collection's config directory,
+//! named after the event, eg. `pre-save`, `post-save`, `pre-insert` or
+//! `post-insert`. They get a JSON description of the operation on stdin and
+//! run with the collection root as working directory. If a `pre-*` hook
+//! fails, the operation is aborted. The `post-*` hooks also get a `changes`
+//! list of the entities the save added, modified and removed. On Windows
+//! hooks can also have an `.exe`, `.bat` or `.cmd` extension.
+//!
+//! Filter hooks are different, they read data from stdin and print a
+//! result to stdout. The `summarize` hook gets the text of a scraped article
+//! and prints a summary of it, the `embed` hook gets a JSON list of texts
+//! and prints a JSON list of embedding vectors for them.
+
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use base::{xdg, Collection};
+use serde_json::json;
+
+use crate::OrDie;
+
+/// Name of the hooks directory in the config directory.
+const HOOKS_DIR: &str = "hooks";
+
+/// Longest article text in bytes passed to the summarize hook.
+const SUMMARY_INPUT_LIMIT: usize = 50_000;
+
+/// Longest summary in bytes that is kept.
+const SUMMARY_LIMIT: usize = 2_000;
+
+/// How long to wait for the summarize hook before giving up.
+const SUMMARY_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn hooks_dir(root: &Path) -> PathBuf {
+    xdg::path(root, xdg::Dir::Config, HOOKS_DIR)
+}
+
+fn hook_path(root: &Path, name: &str) -> PathBuf {
+    let path = hooks_dir(root).join(name);
+    // Windows only runs files with an executable extension.
+    if cfg!(windows) && !path.exists() {
+        for ext in ["exe", "bat", "cmd"] {
+            let exe = path.with_extension(ext);
+            if exe.exists() {
+                return exe;
+            }
+        }
+    }
+    path
+}
+
+/// Run the named hook with a JSON payload if the hook exists.
+fn run(
+    col: &Collection,
+    name: &str,
+    payload: &serde_json::Value,
+) -> base::Result<()> {
+    let path = hook_path(col.root_path(), name);
+    if !path.exists() {
+        return Ok(());
+    }
+
+    log::info!("Running hook {:?}", path);
+    let mut child = Command::new(&path)
+        .current_dir(col.root_path())
+        .env("OTLBOOK_PATH", col.root_path())
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run hook {}: {}", name, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // Hook may exit without reading its input, ignore broken pipes.
+        let _ = writeln!(stdin, "{}", payload);
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("Hook {} failed with {}", name, status).into());
+    }
+    Ok(())
+}
+
+fn payload(
+    col: &Collection,
+    hook: &str,
+    command: &str,
+    affected: Option<usize>,
+) -> serde_json::Value {
+    json!({
+        "hook": hook,
+        "command": command,
+        "args": std::env::args().skip(1).collect::<Vec<_>>(),
+        "collection": col.root_path(),
+        "affected": affected,
+    })
+}
+
+/// Run the `pre-<command>` hook, exit if it fails.
+pub fn pre(col: &Collection, command: &str) {
+    try_pre(col, command).or_die();
+}
+
+/// Run the `pre-<command>` hook, return an error if it fails.
+pub fn try_pre(col: &Collection, command: &str) -> base::Result<()> {
+    let hook = format!("pre-{}", command);
+    run(col, &hook, &payload(col, &hook, command, None))
+}
+
+/// Run a `pre-*` hook with a count of affected entities.
+pub fn pre_with_count(
+    col: &Collection,
+    hook: &str,
+    command: &str,
+    affected: usize,
+) -> base::Result<()> {
+    run(col, hook, &payload(col, hook, command, Some(affected)))
+}
+
+/// Run a `post-*` hook, only warn if it fails since the operation is
+/// already done.
+pub fn post(col: &Collection, hook: &str, command: &str, affected: usize) {
+    let mut payload = payload(col, hook, command, Some(affected));
+    payload["changes"] = json!(col.changes());
+    if let Err(e) = run(col, hook, &payload) {
+        eprintln!("{}", e);
+    }
+}
+
+/// Summarize article text with the `summarize` hook of the default
+/// collection.
+///
+/// Returns `None` if there is no hook or it fails, summaries are optional
+/// and failing to make one only gets a warning.
+pub fn summarize(text: &str) -> Option<String> {
+    let root = Collection::default_path().ok()?;
+    if text.trim().is_empty() {
+        return None;
+    }
+
+    let input = truncate(text, SUMMARY_INPUT_LIMIT);
+    match filter(&root, "summarize", input, SUMMARY_TIMEOUT).transpose()? {
+        Ok(summary) => {
+            let summary = truncate(summary.trim(), SUMMARY_LIMIT);
+            (!summary.is_empty()).then(|| summary.to_string())
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            None
+        }
+    }
+}
+
+/// Run a filter hook that reads text from stdin and writes text to stdout.
+///
+/// Returns `Ok(None)` if the collection has no such hook.
+pub fn filter(
+    root: &Path,
+    name: &str,
+    input: &str,
+    timeout: Duration,
+) -> base::Result<Option<String>> {
+    let path = hook_path(root, name);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    log::info!("Running hook {:?}", path);
+    let mut child = Command::new(&path)
+        .current_dir(root)
+        .env("OTLBOOK_PATH", root)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    // Feed and read the pipes from threads so a hook that doesn't read all
+    // its input or writes lots of output can't deadlock us.
+    let mut stdin = child.stdin.take().expect("No hook stdin");
+    let input = input.to_string();
+    thread::spawn(move || {
+        let _ = stdin.write_all(input.as_bytes());
+    });
+    let mut stdout = child.stdout.take().expect("No hook stdout");
+    let output = thread::spawn(move || {
+        let mut ret = String::new();
+        stdout.read_to_string(&mut ret).map(|_| ret)
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("Hook {} timed out", name).into());
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    if !status.success() {
+        return Err(format!("Hook {} failed with {}", name, status).into());
+    }
+    Ok(Some(output.join().expect("Hook output thread panicked")?))
+}
+
+/// Cut string to at most `len` bytes at a char boundary.
+pub fn truncate(s: &str, mut len: usize) -> &str {
+    if len >= s.len() {
+        return s;
+    }
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    &s[..len]
+}
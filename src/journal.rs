@@ -0,0 +1,114 @@
+//! Daily journal entries edited in `$EDITOR`.
+
+use base::{Collection, Config, Section, VagueDate};
+use dialoguer::{Confirm, Editor};
+
+use crate::OrDie;
+
+pub fn journal(date: Option<VagueDate>) {
+    let date =
+        date.unwrap_or_else(|| VagueDate::Date(VagueDate::now().first_day()));
+
+    let mut col = Collection::load().or_die();
+    crate::hooks::pre(&col, "journal");
+    let journal = col.find_or_create(&Config::get().journal).or_die();
+
+    let (entry, is_new) =
+        match journal.children().find(|c| c.headline_date() == Some(date)) {
+            Some(entry) => (entry, false),
+            None => (Section::new(date.to_string(), Default::default()), true),
+        };
+
+    let original = idm::to_string(&entry).or_die();
+    let mut text = original.clone();
+    let new_entry: Section = loop {
+        let Some(edited) = Editor::new().extension(".otl").edit(&text).or_die()
+        else {
+            eprintln!("Journal entry not saved");
+            return;
+        };
+        if edited == original {
+            eprintln!("Journal entry unchanged, nothing saved");
+            return;
+        }
+        if edited.trim().is_empty() {
+            eprintln!("Empty journal entry, nothing saved");
+            return;
+        }
+
+        match idm::from_str(&edited) {
+            Ok(section) => break section,
+            Err(e) => {
+                eprintln!("Bad outline: {}", e);
+                if !Confirm::new()
+                    .with_prompt("Edit again?")
+                    .default(true)
+                    .interact()
+                    .or_die()
+                {
+                    std::process::exit(1);
+                }
+                text = edited;
+            }
+        }
+    };
+
+    if is_new {
+        insert_by_date(&journal, new_entry);
+    } else {
+        entry.insert_before(new_entry);
+        entry.detach();
+    }
+    crate::save_changes(&mut col, "journal", 1);
+}
+
+/// Insert an entry before the first entry with a later date.
+fn insert_by_date(journal: &Section, entry: Section) {
+    let date = entry.headline_date();
+    match journal
+        .children()
+        .find(|c| date.is_some() && c.headline_date() > date)
+    {
+        Some(next) => next.insert_before(entry),
+        None => journal.append(entry),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_by_date() {
+        let journal: Section = idm::from_str(
+            "\
+Journal
+  2024-01-01
+  2024-01-03
+  Ideas
+",
+        )
+        .unwrap();
+        let entry = |s: &str| Section::new(s.to_string(), Default::default());
+
+        insert_by_date(&journal, entry("2024-01-02"));
+        insert_by_date(&journal, entry("2023-12-31"));
+        insert_by_date(&journal, entry("2024-01-04"));
+        insert_by_date(&journal, entry("Undated"));
+
+        let headlines: Vec<String> =
+            journal.children().map(|c| c.headline()).collect();
+        assert_eq!(
+            headlines,
+            vec![
+                "2023-12-31",
+                "2024-01-01",
+                "2024-01-02",
+                "2024-01-03",
+                "Ideas",
+                "2024-01-04",
+                "Undated"
+            ]
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! Library interface to otlbook collections.
+//!
+//! Use this crate instead of the internal workspace crates when writing
+//! tools that work on an otlbook collection. The items exported here follow
+//! semver, the `base`, `scrape` and `import` crates behind them may change
+//! between any releases.
+//!
+//! Building with `--no-default-features` leaves out the command line tool
+//! and the HTTP and HTML dependencies, the `scrape` and `import` features
+//! add the corresponding modules back.
+//!
+//! ```no_run
+//! use otlbook::{query::Query, Collection};
+//!
+//! let col = Collection::load().unwrap();
+//! let q: Query = "tag:rust".parse().unwrap();
+//! for section in col.iter().filter(|s| q.matches(s)) {
+//!     println!("{}", section.path());
+//! }
+//! ```
+
+pub use base::{
+    check, parse, query, route, split, trash, Collection, EntityIdentifier,
+    Result, Section, Sym, Symbol, Uri, VagueDate,
+};
+
+/// Fetching data from the web.
+#[cfg(feature = "scrape")]
+pub mod scrape {
+    pub use ::scrape::{
+        download_web_page, final_url, is_archived_on_wayback, web_page_title,
+    };
+}
+
+/// Importing collection entries from other formats.
+#[cfg(feature = "import")]
+pub mod import {
+    pub use ::import::{
+        chrome, firefox, google_reader, import, import_file,
+        netscape_bookmarks, pocket, Format,
+    };
+}
@@ -1,24 +1,247 @@
 use std::{
-    collections::{BTreeSet, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap},
     fs,
     io::{prelude::*, stdin},
     path::{Path, PathBuf},
 };
 
-use base::{Collection, Section, VagueDate};
+use base::{minhash::Signature, Collection, Section, VagueDate};
 use indexmap::IndexMap;
 use structopt::StructOpt;
 
+mod anki;
+mod bot;
+mod conflict;
+mod doctor;
+mod expenses;
+mod history;
+mod hooks;
+mod journal;
+mod notify;
+mod plugin;
+mod review;
+mod script;
+mod semantic;
+mod token;
+mod trash;
+mod triage;
+
+// Command output is stable: running the same command on an unchanged
+// collection prints the same lines in the same order, so the output can be
+// diffed between runs. Sections are listed in collection order, aggregates
+// are kept in ordered maps and ranked results break ties by name. Don't
+// iterate a HashMap when printing.
 #[derive(StructOpt, Debug)]
 #[structopt(name = "olt", about = "Outline file processing tool")]
+struct Opt {
+    #[structopt(
+        long = "offline",
+        global = true,
+        about = "Fail network operations immediately instead of trying them"
+    )]
+    offline: bool,
+    #[structopt(subcommand)]
+    cmd: Olt,
+}
+
+#[derive(StructOpt, Debug)]
 enum Olt {
+    #[structopt(
+        name = "aggregate",
+        about = "Combine quantity attribute values, eg. 'sum distance'"
+    )]
+    Aggregate {
+        #[structopt(about = "One of count, sum, mean, min or max")]
+        function: base::query::Aggregate,
+        #[structopt(about = "Attribute to combine")]
+        name: String,
+        #[structopt(
+            about = "Only use sections matching query, eg. 'tag:workout'",
+            long = "query"
+        )]
+        query: Option<base::query::Query>,
+    },
+    #[structopt(
+        name = "anki",
+        about = "Push cloze cards from the collection to Anki"
+    )]
+    Anki {
+        #[structopt(long = "dump", about = "Print cards instead of pushing")]
+        dump: bool,
+        #[structopt(
+            long = "dry-run",
+            about = "Print the changes to the deck without making them"
+        )]
+        dry_run: bool,
+        #[structopt(
+            long = "delete",
+            about = "Delete notes in the deck that have no card in the collection"
+        )]
+        delete: bool,
+        #[structopt(
+            long = "deck",
+            about = "Anki deck to update instead of the current one"
+        )]
+        deck: Option<String>,
+        #[structopt(
+            long = "cloze",
+            about = "Make one Anki cloze note per line instead of basic cards"
+        )]
+        cloze: bool,
+        #[structopt(
+            long = "lang",
+            about = "Only push cards in the given language, eg. 'ja'"
+        )]
+        lang: Option<String>,
+        #[structopt(subcommand)]
+        cmd: Option<anki::AnkiCmd>,
+    },
+    #[structopt(
+        name = "archive",
+        about = "Archive linked pages on the Wayback Machine"
+    )]
+    Archive {
+        #[structopt(
+            long = "missing-only",
+            about = "Only archive pages with no existing Wayback snapshot"
+        )]
+        missing_only: bool,
+    },
+    #[structopt(
+        name = "batch",
+        about = "Apply newline-delimited JSON edit commands read from stdin"
+    )]
+    Batch,
+    #[structopt(
+        name = "bot",
+        about = "Run a chat bot that saves links and notes sent to it"
+    )]
+    Bot(bot::BotCmd),
+    #[structopt(
+        name = "chart",
+        about = "Plot attribute values under a path as a SVG chart"
+    )]
+    Chart {
+        #[structopt(about = "Path of the section, eg. Health/Weight")]
+        path: String,
+        #[structopt(long = "x", about = "Attribute or column for the x axis")]
+        x: String,
+        #[structopt(long = "y", about = "Attribute or column for the y axis")]
+        y: String,
+        #[structopt(
+            long = "bar",
+            about = "Draw a bar chart instead of a line"
+        )]
+        bar: bool,
+        #[structopt(
+            long = "svg",
+            parse(from_os_str),
+            about = "File to write the chart to instead of stdout"
+        )]
+        svg: Option<PathBuf>,
+    },
+    #[structopt(
+        name = "check",
+        about = "Check collection for broken links and other problems"
+    )]
+    Check {
+        #[structopt(
+            about = "Warn about files larger than this many bytes",
+            long = "max-bytes",
+            default_value = "1000000"
+        )]
+        max_bytes: u64,
+        #[structopt(
+            about = "Warn about files with more than this many sections",
+            long = "max-sections",
+            default_value = "10000"
+        )]
+        max_sections: usize,
+    },
+    #[structopt(
+        name = "commit",
+        about = "Commit all changes in a git repository collection"
+    )]
+    Commit {
+        #[structopt(short = "m", long = "message", about = "Commit message")]
+        message: Option<String>,
+    },
+    #[structopt(
+        name = "copy",
+        about = "Copy a section and its children under another section"
+    )]
+    Copy {
+        #[structopt(about = "Section to copy")]
+        path: String,
+        #[structopt(about = "Section to copy it under, created if missing")]
+        dest: String,
+        #[structopt(
+            long = "title",
+            about = "New headline for the copy, required for articles with \
+                     WikiWord titles"
+        )]
+        title: Option<String>,
+        #[structopt(
+            long = "keep-uris",
+            about = "Keep uri attributes instead of removing them"
+        )]
+        keep_uris: bool,
+        #[structopt(
+            long = "keep-dates",
+            about = "Keep added and created dates instead of setting them to now"
+        )]
+        keep_dates: bool,
+    },
+    #[structopt(
+        name = "digest",
+        about = "Make a HTML review of the past day or week, or email it"
+    )]
+    Digest {
+        #[structopt(long = "weekly", about = "Review the past week")]
+        weekly: bool,
+        #[structopt(
+            long = "mailto",
+            about = "Email the review to this address instead of printing it"
+        )]
+        mailto: Option<String>,
+    },
+    #[structopt(
+        name = "doctor",
+        about = "Diagnose problems with the collection and its environment"
+    )]
+    Doctor,
     #[structopt(
         name = "dump",
-        about = "Dump all articles in JSON for external tools"
+        about = "Dump all articles in JSON or the collection in OPML"
     )]
-    Dump,
+    Dump {
+        #[structopt(
+            long = "format",
+            default_value = "json",
+            about = "Output format: json, jsonl or opml"
+        )]
+        format: String,
+        #[structopt(
+            long = "full",
+            about = "With jsonl, write every top-level section with its \
+                     children instead of articles"
+        )]
+        full: bool,
+    },
     #[structopt(name = "dupes", about = "List duplicate entries")]
-    Dupes,
+    Dupes {
+        #[structopt(
+            long = "content",
+            about = "Also list sections with nearly identical text"
+        )]
+        content: bool,
+        #[structopt(
+            long = "threshold",
+            default_value = "0.8",
+            about = "Word overlap from 0 to 1 for near-duplicate text"
+        )]
+        threshold: f64,
+    },
     #[structopt(
         name = "uri-exists",
         about = "Check if URI is saved in collection"
@@ -31,9 +254,100 @@ enum Olt {
         #[structopt(parse(from_str))]
         uri: String,
     },
+    #[structopt(
+        name = "expenses",
+        about = "Report expenses from ledger-style lines"
+    )]
+    Expenses(expenses::ExpensesCmd),
+    #[structopt(
+        name = "export",
+        about = "Export articles to another format and print to stdout"
+    )]
+    Export {
+        #[structopt(
+            long = "format",
+            required_unless = "gemini",
+            about = "Output format: tiddlywiki, freemind or opml-mindmap"
+        )]
+        format: Option<weave::Format>,
+        #[structopt(
+            long = "gemini",
+            parse(from_os_str),
+            conflicts_with_all = &["format", "template", "paths"],
+            about = "Write the collection as gemtext pages to a directory"
+        )]
+        gemini: Option<PathBuf>,
+        #[structopt(
+            long = "template",
+            parse(from_os_str),
+            about = "Empty single-file TiddlyWiki to add the articles to"
+        )]
+        template: Option<PathBuf>,
+        #[structopt(
+            required_unless = "gemini",
+            about = "Articles or sections containing articles to export"
+        )]
+        paths: Vec<String>,
+    },
+    #[structopt(
+        name = "export-bib",
+        about = "Print articles with a tag as a BibTeX bibliography"
+    )]
+    ExportBib {
+        #[structopt(long = "tag", about = "Tag of the articles to export")]
+        tag: String,
+    },
+    #[structopt(
+        name = "export-html",
+        about = "Write the collection as a static HTML site"
+    )]
+    ExportHtml {
+        #[structopt(parse(from_os_str), about = "Directory to write pages to")]
+        outdir: PathBuf,
+    },
+    #[structopt(
+        name = "export-md",
+        about = "Print an article or section as Markdown"
+    )]
+    ExportMd {
+        #[structopt(about = "Path of the article, eg. Notes/WikiWord")]
+        article: String,
+        #[structopt(
+            long = "recursive",
+            about = "Write out articles inside the section instead of listing them"
+        )]
+        recursive: bool,
+    },
+    #[structopt(
+        name = "fix-case",
+        about = "Unify WikiWords spelled with different letter case or digits"
+    )]
+    FixCase {
+        #[structopt(
+            long = "yes",
+            about = "Use the suggested spelling without asking"
+        )]
+        yes: bool,
+    },
+    #[structopt(
+        name = "history",
+        about = "Show commands that have changed the collection"
+    )]
+    History {
+        #[structopt(
+            about = "Only show commands run since date",
+            long = "since"
+        )]
+        since: Option<VagueDate>,
+        #[structopt(
+            about = "Only show runs of the given subcommand",
+            long = "command"
+        )]
+        command: Option<String>,
+    },
     #[structopt(
         name = "import",
-        about = "Import entries from other formats and print to stdout"
+        about = "Import entries from an export file or a URL and print to stdout"
     )]
     Import {
         #[structopt(parse(from_str), required = true)]
@@ -43,6 +357,11 @@ enum Olt {
             long = "to-read"
         )]
         to_read: bool,
+        #[structopt(
+            about = "Skip entries that are already in the collection",
+            long = "new"
+        )]
+        new: bool,
     },
     #[structopt(
         name = "insert",
@@ -54,12 +373,129 @@ enum Olt {
             long = "under"
         )]
         under: Option<String>,
+        #[structopt(
+            long = "on-conflict",
+            default_value = "skip",
+            about = "What to do with items already in the collection: \
+                     skip, replace, merge, append or ask"
+        )]
+        on_conflict: conflict::OnConflict,
+    },
+    #[structopt(
+        name = "journal",
+        about = "Edit the journal entry of a day, today by default"
+    )]
+    Journal {
+        #[structopt(about = "Date of the entry, eg. 2024-01-31")]
+        date: Option<VagueDate>,
+    },
+    #[structopt(
+        name = "log",
+        about = "Show git history of the file containing an article, or \
+                 list sections by a date attribute"
+    )]
+    Log {
+        #[structopt(required_unless = "attr")]
+        article: Option<String>,
+        #[structopt(
+            long = "attr",
+            conflicts_with = "article",
+            about = "List sections with this date attribute, eg. read, \
+                    oldest first"
+        )]
+        attr: Option<String>,
+        #[structopt(
+            long = "from",
+            requires = "attr",
+            about = "Earliest date to list, eg. 2023-01"
+        )]
+        from: Option<VagueDate>,
+        #[structopt(
+            long = "to",
+            requires = "attr",
+            about = "Latest date to list, eg. 2023-06"
+        )]
+        to: Option<VagueDate>,
+    },
+    #[structopt(
+        name = "linkcheck",
+        about = "Check links in the collection and tag dead ones with dead-link"
+    )]
+    Linkcheck {
+        #[structopt(
+            long = "timeout",
+            about = "Seconds to wait for a page before calling it dead, \
+                     http-timeout setting by default"
+        )]
+        timeout: Option<u64>,
+        #[structopt(
+            long = "host-interval",
+            about = "Seconds to wait between requests to the same host, \
+                     host-interval setting by default"
+        )]
+        host_interval: Option<u64>,
+    },
+    #[structopt(
+        name = "lint",
+        about = "Report inconsistencies in the notebook"
+    )]
+    Lint {
+        #[structopt(
+            long = "fix",
+            about = "Fix the problems that can be fixed"
+        )]
+        fix: bool,
     },
     #[structopt(
         name = "normalize",
         about = "Load and rewrite entire notebook in normal form"
     )]
-    Normalize,
+    Normalize {
+        #[structopt(
+            long = "file",
+            about = "Only rewrite this file or the files in this directory, \
+                    also when the fidelity setting keeps them from being \
+                    rewritten otherwise"
+        )]
+        file: Option<PathBuf>,
+        #[structopt(
+            long = "indent",
+            about = "Convert the files to tabs or spaces:N indentation"
+        )]
+        indent: Option<base::Indent>,
+    },
+    #[structopt(
+        name = "notify",
+        about = "Report new bookmarks, dead links and due tasks since last run"
+    )]
+    Notify,
+    #[structopt(
+        name = "present",
+        about = "Print an article as a HTML slideshow"
+    )]
+    Present {
+        #[structopt(about = "Title of the article to present")]
+        article: String,
+    },
+    #[structopt(name = "query", about = "List sections matching a query")]
+    Query {
+        #[structopt(
+            about = "Query, eg. 'tags:rust AND published>=2020 AND read:none'"
+        )]
+        query: base::query::Query,
+        #[structopt(
+            long = "sort",
+            allow_hyphen_values = true,
+            about = "Attribute to sort by, '-' prefix for descending order, \
+                     eg. -rating"
+        )]
+        sort: Option<String>,
+    },
+    #[structopt(
+        name = "rate",
+        about = "Rate the entry with an URI from 1 to 5"
+    )]
+    Rate { uri: String, rating: base::Rating },
     #[structopt(
         name = "reinsert",
         about = "Rewrite existing entities in notebook read from stdin, insert other items that are not existing entities"
@@ -71,122 +507,882 @@ enum Olt {
         )]
         under: Option<String>,
     },
+    #[structopt(
+        name = "retitle",
+        about = "Replace titles that are just the link URL with page titles"
+    )]
+    Retitle {
+        #[structopt(flatten)]
+        selection: Selection,
+    },
+    #[structopt(
+        name = "reurl",
+        about = "Update links that redirect to their new URLs"
+    )]
+    Reurl {
+        #[structopt(flatten)]
+        selection: Selection,
+    },
+    #[structopt(
+        name = "review",
+        about = "Review due cloze cards in the terminal without Anki"
+    )]
+    Review {
+        #[structopt(long = "limit", about = "Most cards to review")]
+        limit: Option<usize>,
+    },
+    #[structopt(
+        name = "rm",
+        about = "Move sections matching a query to the trash"
+    )]
+    Rm {
+        #[structopt(
+            about = "Query for sections to remove, eg. 'tag:junk AND added<2015'",
+            long = "query"
+        )]
+        query: base::query::Query,
+        #[structopt(about = "Don't ask for confirmation", long = "yes")]
+        yes: bool,
+    },
     #[structopt(
         name = "scrape",
         about = "Fetch data from URL and print IDM entry to stdout"
     )]
     Scrape { url: String },
+    #[structopt(
+        name = "script",
+        about = "Run a Rhai script against the collection"
+    )]
+    Script {
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+        #[structopt(about = "Arguments passed to the script as `args`")]
+        args: Vec<String>,
+        #[structopt(long = "dry-run", about = "Don't save changes")]
+        dry_run: bool,
+    },
+    #[structopt(
+        name = "search",
+        about = "Find sections containing all the given words"
+    )]
+    Search {
+        #[structopt(
+            required = true,
+            about = "Words to search for, 'word*' matches prefixes"
+        )]
+        words: Vec<String>,
+    },
+    #[structopt(
+        name = "semantic-search",
+        about = "Find articles similar in meaning to the query"
+    )]
+    SemanticSearch {
+        query: String,
+        #[structopt(
+            long = "count",
+            default_value = "10",
+            about = "Number of results to show"
+        )]
+        count: usize,
+    },
+    #[structopt(
+        name = "split",
+        about = "Split a large collection file into multiple files"
+    )]
+    Split {
+        #[structopt(
+            parse(from_str),
+            about = "Collection file to split, relative to collection root"
+        )]
+        path: PathBuf,
+        #[structopt(
+            about = "How to split the file (year|toplevel)",
+            long = "by",
+            default_value = "toplevel"
+        )]
+        by: base::split::SplitBy,
+    },
+    #[structopt(
+        name = "sql",
+        about = "Run a read-only SQL query on the collection database"
+    )]
+    Sql {
+        #[structopt(about = "SQL query, eg. \"SELECT * FROM links\"")]
+        query: String,
+    },
     #[structopt(name = "tagged", about = "List items with given tags")]
     Tagged {
-        #[structopt(parse(from_str), required = true)]
+        #[structopt(parse(from_str), required_unless = "lang")]
         tags: Vec<String>,
+        #[structopt(
+            long = "lang",
+            about = "Only list items in the given language, eg. 'fi'"
+        )]
+        lang: Option<String>,
     },
     #[structopt(name = "tags", about = "Show tag cloud")]
     Tags,
+    #[structopt(
+        name = "timeline",
+        about = "Print the dated sections under a path as a SVG timeline"
+    )]
+    Timeline {
+        #[structopt(about = "Path of the section, eg. Article/Plan")]
+        path: String,
+    },
+    #[structopt(
+        name = "todo",
+        about = "List [_] and [X] tasks or mark a task done or pending"
+    )]
+    Todo {
+        #[structopt(long = "pending", about = "Only list pending tasks")]
+        pending: bool,
+        #[structopt(
+            long = "done",
+            conflicts_with = "pending",
+            about = "Only list done tasks"
+        )]
+        done: bool,
+        #[structopt(long = "under", about = "Only list tasks under a path")]
+        under: Option<String>,
+        #[structopt(
+            long = "toggle",
+            about = "Flip the task at a path between pending and done"
+        )]
+        toggle: Option<String>,
+    },
     #[structopt(name = "toread", about = "Save a link in the to-read queue")]
     ToRead { uri: String },
+    #[structopt(
+        name = "token",
+        about = "Manage API tokens for the webserver's ingest endpoint"
+    )]
+    Token(token::TokenCmd),
+    #[structopt(name = "trash", about = "Manage deleted sections")]
+    Trash(trash::TrashCmd),
+    #[structopt(
+        name = "triage",
+        about = "Interactively file, tag or delete items in the InBox"
+    )]
+    Triage,
+    #[structopt(
+        name = "view",
+        about = "Print the sections of a named view, or list the views"
+    )]
+    View {
+        #[structopt(about = "Name of the view")]
+        name: Option<String>,
+    },
     #[structopt(
         name = "webserver",
         about = "Run the otlbook web server for the current collection"
     )]
     Webserver {
-        #[structopt(default_value = "8080")]
-        port: u32,
+        #[structopt(
+            about = "Port to listen on, webserver-port setting by default"
+        )]
+        port: Option<u32>,
+    },
+    #[structopt(
+        name = "x",
+        about = "Run an olt-<name> plugin command found on PATH"
+    )]
+    X {
+        name: String,
+        #[structopt(about = "Arguments passed to the plugin")]
+        args: Vec<String>,
     },
 }
 
-fn main() {
-    env_logger::init();
+/// Which sections a bulk edit command works on.
+#[derive(StructOpt, Debug)]
+struct Selection {
+    #[structopt(
+        long = "under",
+        about = "Only change sections under this path"
+    )]
+    under: Option<String>,
+    #[structopt(long = "tag", about = "Only change sections with this tag")]
+    tag: Option<String>,
+    #[structopt(long = "dry-run", about = "Show changes without saving them")]
+    dry_run: bool,
+}
+
+impl Selection {
+    fn sections(&self, col: &Collection) -> Vec<Section> {
+        let sections: Vec<Section> = match &self.under {
+            Some(path) => col
+                .find(path)
+                .ok_or_else(|| format!("Section {:?} not found", path))
+                .or_die()
+                .iter()
+                .skip(1)
+                .collect(),
+            None => col
+                .files()
+                .filter(|(path, _)| !base::trash::is_trash(path))
+                .flat_map(|(_, root)| root.iter())
+                .collect(),
+        };
+        match &self.tag {
+            Some(tag) => sections
+                .into_iter()
+                .filter(|s| s.tags().contains(tag))
+                .collect(),
+            None => sections,
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let opt = Opt::from_args();
+    if opt.offline {
+        // Set through the environment so hooks and plugins see it too.
+        std::env::set_var("OTLBOOK_OFFLINE", "1");
+    }
+    // Report a broken settings file before anything uses the settings,
+    // doctor reports it along with other problems.
+    if !matches!(opt.cmd, Olt::Doctor) {
+        base::Config::load().or_die();
+    }
+    load_proxies();
+
+    match opt.cmd {
+        Olt::Aggregate {
+            function,
+            name,
+            query,
+        } => aggregate(function, name, query),
+        Olt::Anki { cmd: Some(cmd), .. } => anki::run(cmd),
+        Olt::Anki {
+            dump,
+            dry_run,
+            delete,
+            deck,
+            cloze,
+            lang,
+            ..
+        } => anki::anki(dump, dry_run, delete, deck, cloze, lang),
+        Olt::Archive { missing_only } => archive(missing_only),
+        Olt::Batch => batch(),
+        Olt::Bot(cmd) => bot::run(cmd),
+        Olt::Chart {
+            path,
+            x,
+            y,
+            bar,
+            svg,
+        } => chart(path, x, y, bar, svg),
+        Olt::Check {
+            max_bytes,
+            max_sections,
+        } => check(max_bytes, max_sections),
+        Olt::Commit { message } => commit(message),
+        Olt::Copy {
+            path,
+            dest,
+            title,
+            keep_uris,
+            keep_dates,
+        } => copy(path, dest, title, keep_uris, keep_dates),
+        Olt::Digest { weekly, mailto } => notify::review(weekly, mailto),
+        Olt::Doctor => doctor::doctor(),
+        Olt::Dump { format, full } => dump(format, full),
+        Olt::Dupes { content, threshold } => dupes(content, threshold),
+        Olt::Exists { uri } => exists(uri),
+        Olt::Expenses(cmd) => expenses::run(cmd),
+        Olt::Export {
+            format,
+            gemini,
+            template,
+            paths,
+        } => match (format, gemini) {
+            (_, Some(outdir)) => export_gemini(outdir),
+            (Some(format), None) => export(format, template, paths),
+            (None, None) => unreachable!(),
+        },
+        Olt::ExportBib { tag } => export_bib(tag),
+        Olt::ExportHtml { outdir } => export_html(outdir),
+        Olt::ExportMd { article, recursive } => export_md(article, recursive),
+        Olt::FixCase { yes } => fix_case(yes),
+        Olt::History { since, command } => history::history(since, command),
+        Olt::Import {
+            path,
+            to_read: to_reads,
+            new,
+        } => import(path, to_reads, new),
+        Olt::Insert { under, on_conflict } => insert(under, on_conflict),
+        Olt::Linkcheck {
+            timeout,
+            host_interval,
+        } => linkcheck(timeout, host_interval),
+        Olt::Lint { fix } => lint(fix),
+        Olt::Journal { date } => journal::journal(date),
+        Olt::Log {
+            article,
+            attr,
+            from,
+            to,
+        } => match attr {
+            Some(attr) => log_dates(attr, from, to),
+            None => log(article.expect("article is required without --attr")),
+        },
+        Olt::Normalize { file, indent } => normalize(file, indent),
+        Olt::Notify => notify::notify(),
+        Olt::Present { article } => present(article),
+        Olt::Query { query, sort } => query_search(query, sort),
+        Olt::Rate { uri, rating } => rate(uri, rating),
+        Olt::Reinsert { under } => reinsert(under),
+        Olt::Retitle { selection } => retitle(selection),
+        Olt::Reurl { selection } => reurl(selection),
+        Olt::Review { limit } => review::review(limit),
+        Olt::Rm { query, yes } => rm(query, yes),
+        Olt::Scrape { url } => scrape(url),
+        Olt::Script {
+            path,
+            args,
+            dry_run,
+        } => script::run(&path, args, dry_run),
+        Olt::Search { words } => search(words),
+        Olt::SemanticSearch { query, count } => {
+            semantic::semantic_search(query, count)
+        }
+        Olt::Split { path, by } => split(path, by),
+        Olt::Sql { query } => sql(query),
+        Olt::Tagged { tags, lang } => tag_search(tags, lang),
+        Olt::Tags => tag_histogram(),
+        Olt::Timeline { path } => timeline(path),
+        Olt::Todo {
+            pending,
+            done,
+            under,
+            toggle,
+        } => todo(pending, done, under, toggle),
+        Olt::ToRead { uri } => save_to_read(uri),
+        Olt::Token(cmd) => token::run(cmd),
+        Olt::Trash(cmd) => trash::run(cmd),
+        Olt::Triage => triage::triage(),
+        Olt::View { name } => view(name),
+        Olt::Webserver { port } => {
+            let port = port.unwrap_or(base::Config::get().webserver_port);
+            let mut col = Collection::load().or_die();
+            recover_journal(&mut col);
+            webserver::run(port, col, try_save_changes)
+        }
+        Olt::X { name, args } => plugin::run(name, args),
+    }
+}
+
+fn aggregate(
+    function: base::query::Aggregate,
+    name: String,
+    query: Option<base::query::Query>,
+) {
+    let col = Collection::load().or_die();
+    let sections = col
+        .iter()
+        .filter(|s| query.as_ref().is_none_or(|q| q.matches(s)));
+    match base::query::aggregate(sections, &name, function).or_die() {
+        Some(value) => println!("{}", value),
+        None => eprintln!("No sections with {}", name),
+    }
+}
+
+/// How many links `archive` updates between saves.
+///
+/// Archiving a large collection takes hours, and saving every now and then
+/// keeps an interrupted run from losing its work.
+const ARCHIVE_SAVE_INTERVAL: usize = 20;
+
+fn archive(missing_only: bool) {
+    let mut col = Collection::load().or_die();
+
+    let sections: Vec<Section> = col
+        .files()
+        .filter(|(path, _)| !base::trash::is_trash(path))
+        .flat_map(|(_, root)| root.iter())
+        .filter(|s| {
+            // Only web pages can go in the Wayback Machine.
+            s.uri().is_some_and(|uri| uri.starts_with("http"))
+                && !s.borrow().attributes.contains_key("wayback")
+        })
+        .collect();
+
+    let uris: Vec<String> = sections.iter().filter_map(|s| s.uri()).collect();
+    let snapshots = if missing_only {
+        scrape::Fetcher::default().wayback_snapshots(&uris)
+    } else {
+        Vec::new()
+    };
+
+    hooks::pre(&col, "archive");
+    let mut count = 0;
+    let mut saved = 0;
+    let mut first_save = true;
+    for (i, (mut section, uri)) in sections.into_iter().zip(uris).enumerate() {
+        if count - saved >= ARCHIVE_SAVE_INTERVAL {
+            save_changes(&mut col, "archive", count - saved);
+            saved = count;
+        }
+
+        if scrape::is_offline() {
+            eprintln!("Offline, stopping");
+            break;
+        }
+
+        if let Some(snapshot) = snapshots.get(i) {
+            match snapshot {
+                Ok(Some(snapshot)) => {
+                    eprintln!("{} already archived", uri);
+                    section.set_attr("wayback", snapshot).or_die();
+                    count += 1;
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!(
+                        "\x1b[1;31mFailed to check {}: {}\x1b[0m",
+                        uri, e
+                    );
+                    continue;
+                }
+            }
+        }
+
+        if !first_save {
+            // Stay under the Wayback Machine's rate limit.
+            std::thread::sleep(base::Config::get().wayback_interval());
+        }
+        first_save = false;
+        match scrape::save_to_wayback(&uri) {
+            Ok(snapshot) => {
+                eprintln!("{} -> {}", uri, snapshot);
+                section.set_attr("wayback", &snapshot).or_die();
+                count += 1;
+            }
+            Err(e) => {
+                eprintln!("\x1b[1;31mFailed to archive {}: {}\x1b[0m", uri, e)
+            }
+        }
+    }
+
+    save_changes(&mut col, "archive", count - saved);
+}
+
+fn batch() {
+    let mut input = String::new();
+    stdin().read_to_string(&mut input).or_die();
+
+    let mut col = Collection::load().or_die();
+    hooks::pre(&col, "batch");
+
+    let report = base::batch::apply(&mut col, input.lines()).or_die();
+    for result in &report.results {
+        println!("{}", result);
+    }
+
+    // The batch is applied as a whole or not at all.
+    if report.failed {
+        eprintln!("Batch had errors, no changes saved");
+        std::process::exit(1);
+    }
+    save_changes(&mut col, "batch", report.changes);
+}
+
+fn chart(path: String, x: String, y: String, bar: bool, svg: Option<PathBuf>) {
+    let col = Collection::load_subset(&path).or_die();
+    let section = col
+        .find(&path)
+        .ok_or_else(|| format!("Section {:?} not found", path))
+        .or_die();
+    let kind = if bar {
+        webserver::ChartKind::Bar
+    } else {
+        webserver::ChartKind::Line
+    };
+    let chart = webserver::chart(&section, &webserver::Chart { x, y, kind });
+    match svg {
+        Some(svg) => fs::write(svg, chart).or_die(),
+        None => print!("{}", chart),
+    }
+}
+
+fn check(max_bytes: u64, max_sections: usize) {
+    let col = Collection::load().or_die();
+
+    // Collect (file, message) pairs from all checks so they can be reported
+    // grouped by file.
+    let mut problems: Vec<(PathBuf, String)> = Vec::new();
+
+    log::info!("Start file size check");
+    for file in base::check::large_files(&col, max_bytes, max_sections) {
+        problems.push((
+            file.path.clone(),
+            format!("{}, consider running olt split", file),
+        ));
+    }
+
+    log::info!("Start broken link check");
+    for link in base::check::broken_links(&col) {
+        problems.push((link.path.clone(), link.to_string()));
+    }
+
+    log::info!("Start rating check");
+    for value in base::check::bad_ratings(&col) {
+        problems.push((value.path.clone(), value.to_string()));
+    }
+
+    log::info!("Start WikiWord spelling check");
+    let variants = base::check::wiki_word_variants(&col);
+    log::info!("Finished checks");
+
+    let file_count = print_problems(&mut problems);
+
+    // Spelling variants are spread over the whole collection, list them
+    // after the per-file problems.
+    for v in &variants {
+        println!("{}", v);
+    }
+    if !variants.is_empty() {
+        println!("Run olt fix-case to pick one spelling for each");
+    }
+
+    if problems.is_empty() && variants.is_empty() {
+        println!("No problems found");
+    } else {
+        println!(
+            "{} problems in {} files",
+            problems.len() + variants.len(),
+            file_count
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Print problems grouped by file, return the number of files.
+fn print_problems(problems: &mut [(PathBuf, String)]) -> usize {
+    // Stable sort, keep the order of problems within a file.
+    problems.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut current_file = None;
+    let mut file_count = 0;
+    for (path, msg) in problems.iter() {
+        if current_file != Some(path) {
+            current_file = Some(path);
+            file_count += 1;
+            println!("{}", path.to_string_lossy());
+        }
+        println!("  {}", msg);
+    }
+    file_count
+}
+
+fn commit(message: Option<String>) {
+    let root = Collection::default_path().or_die();
+    if !base::vcs::is_repo(&root) {
+        eprintln!("Collection at {:?} is not a git repository", root);
+        std::process::exit(1);
+    }
+    let message = message.unwrap_or_else(|| "Update notebook".into());
+    if !base::vcs::commit_all(&root, &message).or_die() {
+        eprintln!("Nothing to commit");
+    }
+}
+
+/// Attributes that identify the entity a section is about.
+const IDENTIFIER_ATTRS: &[&str] = &["uri", "wayback"];
+
+/// Attributes for when a section was made.
+const CREATION_ATTRS: &[&str] = &["added", "created"];
+
+fn copy(
+    path: String,
+    dest: String,
+    title: Option<String>,
+    keep_uris: bool,
+    keep_dates: bool,
+) {
+    let mut col = Collection::load().or_die();
+    let section = col
+        .find(&path)
+        .ok_or_else(|| format!("Section {:?} not found", path))
+        .or_die();
+    // Two articles with the same WikiWord title would be ambiguous.
+    if let (None, Some(wiki_title)) = (&title, section.wiki_title()) {
+        Err::<(), _>(format!(
+            "{} is a WikiWord title, give the copy a new one with --title",
+            wiki_title
+        ))
+        .or_die();
+    }
+
+    hooks::pre(&col, "copy");
+    let copy = section.deep_clone();
+    if let Some(title) = title {
+        copy.set_headline(title);
+    }
+
+    let now = VagueDate::now().to_string();
+    let mut count = 0;
+    for mut s in copy.iter() {
+        count += 1;
+        if !keep_uris {
+            for name in IDENTIFIER_ATTRS {
+                s.remove_attr(name);
+            }
+        }
+        if !keep_dates {
+            for name in CREATION_ATTRS {
+                if s.borrow().attributes.contains_key(*name) {
+                    s.set_raw_attr(name, &now);
+                }
+            }
+        }
+    }
+
+    col.find_or_create(&dest).or_die().append(copy);
+    save_changes(&mut col, "copy", count);
+    eprintln!("Copied {} sections", count);
+}
+
+fn export(
+    format: weave::Format,
+    template: Option<PathBuf>,
+    paths: Vec<String>,
+) {
+    let col = match paths.as_slice() {
+        [path] => Collection::load_subset(path),
+        _ => Collection::load(),
+    }
+    .or_die();
+    let sections: Vec<Section> = paths
+        .iter()
+        .map(|path| {
+            col.find(path)
+                .ok_or_else(|| format!("Section {:?} not found", path))
+                .or_die()
+        })
+        .collect();
+
+    if format.is_mindmap() && sections.len() != 1 {
+        Err::<(), _>("A mind map is made from a single section").or_die();
+    }
+    let articles: Vec<Section> =
+        sections.iter().flat_map(weave::articles).collect();
+
+    match format {
+        weave::Format::TiddlyWiki => match template {
+            Some(template) => print!(
+                "{}",
+                weave::tiddlywiki::single_file(
+                    &fs::read_to_string(template).or_die(),
+                    &articles
+                )
+                .or_die()
+            ),
+            None => println!("{}", weave::tiddlywiki::tiddlers(&articles)),
+        },
+        weave::Format::FreeMind => {
+            print!("{}", weave::mindmap::freemind(&sections[0]))
+        }
+        weave::Format::OpmlMindmap => {
+            print!("{}", weave::mindmap::opml(&sections[0]))
+        }
+    }
+}
+
+fn export_bib(tag: String) {
+    let col = Collection::load().or_die();
+    let articles: Vec<Section> = col
+        .iter()
+        .filter(|s| s.is_article() && s.tags().contains(&tag))
+        .collect();
+    print!("{}", weave::bibtex::bibtex(&articles));
+}
+
+fn export_gemini(outdir: PathBuf) {
+    let col = Collection::load().or_die();
+    let site = base::publish::Site::new(&col);
+    fs::create_dir_all(&outdir).or_die();
+    for page in &site.pages {
+        fs::write(
+            outdir.join(format!("{}.gmi", page.name)),
+            weave::gemini::page(page, &site),
+        )
+        .or_die();
+    }
+    fs::write(
+        outdir.join(format!("{}.gmi", base::publish::INDEX_NAME)),
+        weave::gemini::index(&site),
+    )
+    .or_die();
+    eprintln!("Wrote {} articles to {:?}", site.pages.len(), outdir);
+}
+
+fn export_html(outdir: PathBuf) {
+    let col = Collection::load().or_die();
+    let count = webserver::export_site(&col, &outdir).or_die();
+    eprintln!("Wrote {} articles to {:?}", count, outdir);
+}
+
+fn export_md(article: String, recursive: bool) {
+    let col = Collection::load_subset(&article).or_die();
+    let Some(section) = col.find(&article) else {
+        eprintln!("Section {:?} not found", article);
+        std::process::exit(1);
+    };
+    print!("{}", weave::markdown::markdown(&section, recursive));
+}
+
+fn fix_case(yes: bool) {
+    let mut col = Collection::load().or_die();
+    hooks::pre(&col, "fix-case");
 
-    match Olt::from_args() {
-        Olt::Dump => dump(),
-        Olt::Dupes => dupes(),
-        Olt::Exists { uri } => exists(uri),
-        Olt::Import {
-            path,
-            to_read: to_reads,
-        } => import(path, to_reads),
-        Olt::Insert { under } => insert(under),
-        Olt::Normalize => normalize(),
-        Olt::Reinsert { under } => reinsert(under),
-        Olt::Scrape { url } => scrape(url),
-        Olt::Tagged { tags } => tag_search(tags),
-        Olt::Tags => tag_histogram(),
-        Olt::ToRead { uri } => save_to_read(uri),
-        Olt::Webserver { port } => {
-            webserver::run(port, Collection::load().or_die())
+    let mut changed = 0;
+    for v in base::check::wiki_word_variants(&col) {
+        let canonical = if yes {
+            0
+        } else {
+            let mut items: Vec<String> = v
+                .spellings
+                .iter()
+                .map(|(w, n)| format!("{} ({} uses)", w, n))
+                .collect();
+            items.push("Skip".into());
+            let choice = dialoguer::Select::new()
+                .with_prompt("Pick the spelling to use everywhere")
+                .items(&items)
+                .default(0)
+                .interact()
+                .or_die();
+            if choice == v.spellings.len() {
+                continue;
+            }
+            choice
+        };
+
+        let to = &v.spellings[canonical].0;
+        for (from, _) in &v.spellings {
+            if from != to {
+                let n = base::rename::rename_word(&col, from, to);
+                eprintln!("Renamed {} to {} in {} section(s)", from, to, n);
+                changed += n;
+            }
         }
     }
-}
 
-fn dump() {
-    use serde_json::{Map, Value};
+    if changed > 0 {
+        save_changes(&mut col, "fix-case", changed);
+    } else {
+        eprintln!("Nothing to fix");
+    }
+}
 
+fn dump(format: String, full: bool) {
     let col = Collection::load().or_die();
 
-    let mut array = Vec::new();
-    for article in col.iter().filter(|a| a.is_article()) {
-        let mut entry = Map::default();
-        // Initially override title with the headline.
-        //
-        // Currently headline will just be thrown out if the title is
-        // redefined, might put it in a separate field in the future in the
-        // case the values redefine title.
-        entry.insert("title".into(), article.title().into());
-
-        // Tags can be inherited from parent nodes, so add them explicitly.
-        entry.insert(
-            "tags".into(),
-            Value::Array(
-                article.tags().into_iter().map(|a| a.into()).collect(),
-            ),
-        );
+    match format.as_str() {
+        "json" => {
+            let array: Vec<serde_json::Value> = col
+                .iter()
+                .filter(|a| a.is_article())
+                .map(|a| a.to_json())
+                .collect();
 
-        for (key, val) in article.borrow().attributes.iter() {
-            if key == "tags" {
-                // Skip tags when processing the remaining attrs
-                continue;
+            print!("{}", serde_json::to_string_pretty(&array).or_die());
+        }
+        "jsonl" => {
+            // Write one line at a time so the whole dump is never in
+            // memory.
+            let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+            let mut write = |mut value: serde_json::Value,
+                             section: &Section| {
+                value["path"] = section.path().into();
+                writeln!(out, "{}", value).or_die();
+            };
+            if full {
+                let tops = col
+                    .files()
+                    .filter(|(path, _)| !base::trash::is_trash(path))
+                    .flat_map(|(_, root)| root.children());
+                for section in tops {
+                    write(section.to_json_tree(), &section);
+                }
             } else {
-                entry.insert(key.into(), val.clone().into());
+                for article in col.iter().filter(|a| a.is_article()) {
+                    write(article.to_json(), &article);
+                }
             }
         }
-        array.push(entry);
+        "opml" => {
+            let roots: Vec<Section> = col
+                .files()
+                .filter(|(path, _)| !base::trash::is_trash(path))
+                .map(|(_, root)| root)
+                .collect();
+            let title = col.root_path().display().to_string();
+            print!("{}", weave::opml::opml(&title, &roots));
+        }
+        _ => {
+            eprintln!(
+                "Unknown dump format {:?}, must be json, jsonl or opml",
+                format
+            );
+            std::process::exit(1);
+        }
     }
-
-    print!("{}", serde_json::to_string_pretty(&array).or_die());
 }
 
-fn dupes() {
+fn dupes(content: bool, threshold: f64) {
     let col = Collection::load().or_die();
-    let mut count = HashMap::new();
 
-    log::info!("Start WikiTitle crawl");
-    for section in col.iter() {
-        if let Some(title) = section.wiki_title() {
-            *count.entry(title).or_insert(0) += 1;
-        }
+    let sections: Vec<Section> = col.iter().collect();
+    for dupe in base::lint::duplicates(&sections) {
+        println!("{} dupes: {}", dupe.kind, dupe.key);
     }
-    log::info!("Finished WikiTitle crawl, {} titles", count.len());
 
-    for (t, &n) in &count {
-        if n > 1 {
-            println!("WikiWord dupes: {}", t);
-        }
+    if content {
+        content_dupes(&col, threshold);
     }
+}
 
-    log::info!("Start uri crawl");
-    let mut count = HashMap::new();
-    for section in col.iter() {
-        if let Some(uri) = section.uri() {
-            *count.entry(uri).or_insert(0) += 1;
+/// Shortest text in words that is checked for near-duplicates.
+const MIN_DUPE_WORDS: usize = 5;
+
+/// List articles and notes with nearly identical text.
+fn content_dupes(col: &Collection, threshold: f64) {
+    log::info!("Start content crawl");
+    let mut sections = Vec::new();
+    let mut signatures = Vec::new();
+    for (path, root) in col.files() {
+        if base::trash::is_trash(path) {
+            continue;
+        }
+        for section in root.iter().skip(1) {
+            if section.is_alias()
+                || !(section.is_article() || section.child().is_some())
+            {
+                continue;
+            }
+            let text: Vec<String> = section.iter().map(|s| s.title()).collect();
+            let text = text.join("\n");
+            if text.split_whitespace().count() >= MIN_DUPE_WORDS {
+                signatures.push(Signature::new(&text));
+                sections.push(section);
+            }
         }
     }
-    log::info!("Finished uri crawl, {} items;", count.len());
+    log::info!("Finished content crawl, {} sections", sections.len());
 
-    for (t, &n) in &count {
-        if n > 1 {
-            println!("uri dupes: {}", t);
+    for (i, j) in base::minhash::near_duplicates(&signatures, threshold) {
+        let (a, b) = (&sections[i], &sections[j]);
+        // A section with a single child is trivially similar to the child.
+        if a.contains(b) || b.contains(a) {
+            continue;
         }
+        println!("content dupes: {} | {}", a.path(), b.path());
     }
 }
 
@@ -209,22 +1405,49 @@ fn exists(uri: String) {
     std::process::exit(1);
 }
 
-fn import(path: impl AsRef<Path>, import_to_reads: bool) {
-    let text = fs::read_to_string(path).or_die();
-    // TODO 2022-10-01 Support other types than Pocket (eg. Goodreads)
-
-    let collection = if import_to_reads {
-        import::pocket::import_to_read(&text).or_die()
-    } else {
-        import::pocket::import_read(&text).or_die()
-    };
+fn import(path: impl AsRef<Path>, import_to_reads: bool, only_new: bool) {
+    // A plain URL is scraped into a single entry.
+    let arg = path.as_ref().to_string_lossy();
+    let mut sections =
+        if arg.starts_with("http://") || arg.starts_with("https://") {
+            vec![scrape_entry(arg.to_string()).or_die()]
+        } else {
+            let root = Collection::default_path().or_die();
+            import::import_file(&path, import_to_reads, &root).or_die()
+        };
+
+    if only_new {
+        let existing = existing_entities(&Collection::load().or_die());
+        sections.retain(|s| {
+            s.entity_identifier()
+                .is_none_or(|id| !existing.contains_key(&id))
+        });
+    }
 
-    print!("{}", idm::to_string(&collection).or_die());
+    print!("{}", idm::to_string(&sections).or_die());
 }
 
-fn insert(under: Option<String>) {
+fn insert(under: Option<String>, on_conflict: conflict::OnConflict) {
     let mut col = Collection::load().or_die();
+    let items = read_stdin_sections();
+
+    hooks::pre(&col, "insert");
+    let count = insert_items(
+        &mut col,
+        &items,
+        under,
+        &base::Config::get().inbox,
+        on_conflict,
+    );
+    save_changes(&mut col, "insert", count);
 
+    if count > 0 {
+        eprintln!("Inserted or updated {} items", count);
+    }
+}
+
+/// Read IDM sections from standard input.
+fn read_stdin_sections() -> Vec<Section> {
     let mut buf = String::new();
     stdin().read_to_string(&mut buf).or_die();
     // TODO: Trim-if-multiple-lines helper function
@@ -236,59 +1459,347 @@ fn insert(under: Option<String>) {
     } else {
         &buf
     };
-    let items: Vec<Section> = idm::from_str(buf).or_die();
+    idm::from_str(buf).or_die()
+}
 
-    let mut existing_entities = col
-        .iter()
-        .filter_map(|s| s.entity_identifier())
-        .collect::<HashSet<_>>();
+/// Collect the entity identifiers of the sections in the collection.
+fn existing_entities(
+    col: &Collection,
+) -> HashMap<base::EntityIdentifier, Section> {
+    // Things in the trash are free to be inserted again.
+    col.files()
+        .filter(|(path, _)| !base::trash::is_trash(path))
+        .flat_map(|(_, root)| root.iter())
+        .filter_map(|s| Some((s.entity_identifier()?, s)))
+        .collect()
+}
 
-    let path = if let Some(path) = under {
-        path
-    } else {
-        "InBox".to_string()
-    };
+/// Insert items into the collection.
+///
+/// Items go under `under` if it's given, otherwise to the path of the first
+/// matching collection route, or to `default_path` if no route matches.
+/// Items that are existing entities are handled as `on_conflict` says.
+///
+/// Return the number of inserted and changed items.
+fn insert_items(
+    col: &mut Collection,
+    items: &[Section],
+    under: Option<String>,
+    default_path: &str,
+    on_conflict: conflict::OnConflict,
+) -> usize {
+    try_insert_items(col, items, under, default_path, on_conflict).or_die()
+}
 
-    let parent = col.find_or_create(&path).or_die();
+/// Like `insert_items`, but return an error instead of exiting if the
+/// routes can't be read or a target path can't be made.
+fn try_insert_items(
+    col: &mut Collection,
+    items: &[Section],
+    under: Option<String>,
+    default_path: &str,
+    on_conflict: conflict::OnConflict,
+) -> base::Result<usize> {
+    let routes = base::route::Routes::load(col.root_path())?;
+    let mut existing_entities = existing_entities(col);
+
+    let mut parents = HashMap::new();
 
     let mut count = 0;
-    for sec in &items {
+    for sec in items {
         if let Some(id) = sec.entity_identifier() {
-            if existing_entities.contains(&id) {
-                eprintln!("{:?} already present, skipping", id);
+            if let Some(existing) = existing_entities.get(&id) {
+                let old = conflict::entities(existing);
+                match conflict::resolve(on_conflict, existing, sec) {
+                    Some(section) => {
+                        eprintln!("{:?} already present, updated", id);
+                        conflict::update_entities(
+                            &mut existing_entities,
+                            &old,
+                            &section,
+                        );
+                        count += 1;
+                    }
+                    None => eprintln!("{:?} already present, skipping", id),
+                }
                 continue;
             }
-            existing_entities.insert(id);
+            existing_entities.insert(id, sec.clone());
         }
+
+        let path = match &under {
+            Some(path) => path.as_str(),
+            None => routes.target(sec).unwrap_or(default_path),
+        }
+        .to_string();
+
+        let parent = match parents.get(&path) {
+            Some(parent) => Section::clone(parent),
+            None => {
+                let parent = col.find_or_create(&path)?;
+                parents.insert(path, parent.clone());
+                parent
+            }
+        };
+
         count += 1;
         parent.append(sec.clone());
     }
 
-    col.save().or_die();
+    Ok(count)
+}
 
-    if count > 0 {
-        eprintln!("Inserted {} new items", count);
+/// Tag for sections whose links no longer work.
+const DEAD_LINK_TAG: &str = "dead-link";
+
+fn lint(fix: bool) {
+    let mut col = Collection::load().or_die();
+    if fix {
+        hooks::pre(&col, "lint");
+    }
+    let mut problems = base::lint::lint(&col);
+
+    if fix {
+        for p in &problems {
+            if p.fix == Some(base::lint::Fix::Rewrite) {
+                col.allow_rewrite(&p.path).or_die();
+            }
+        }
+        let count = base::lint::fix(&problems);
+        if count > 0 {
+            save_changes(&mut col, "lint", count);
+            println!("Fixed {} problems", count);
+        }
+        problems.retain(|p| p.fix.is_none());
+    }
+
+    let mut problems: Vec<(PathBuf, String)> = problems
+        .iter()
+        .map(|p| (p.path.clone(), p.to_string()))
+        .collect();
+    let file_count = print_problems(&mut problems);
+    if problems.is_empty() {
+        println!("No problems found");
+    } else {
+        println!("{} problems in {} files", problems.len(), file_count);
+        std::process::exit(1);
     }
 }
 
-fn normalize() {
+fn linkcheck(timeout: Option<u64>, host_interval: Option<u64>) {
+    use std::time::Duration;
+
+    let config = base::Config::get();
+    let timeout = timeout.unwrap_or(config.http_timeout);
+    let host_interval = host_interval.unwrap_or(config.host_interval);
+
     let mut col = Collection::load().or_die();
-    for root in col.roots() {
-        root.taint();
+    let sections: Vec<(Section, String)> = col
+        .files()
+        .filter(|(path, _)| !base::trash::is_trash(path))
+        .flat_map(|(_, root)| root.iter())
+        .filter_map(|s| Some((s.clone(), s.uri()?)))
+        .filter(|(_, uri)| uri.starts_with("http"))
+        .collect();
+    let uris: Vec<String> = sections.iter().map(|(_, u)| u.clone()).collect();
+
+    eprintln!("Checking {} links", uris.len());
+    let results = scrape::Fetcher::default()
+        .timeout(Duration::from_secs(timeout))
+        .host_interval(Duration::from_secs(host_interval))
+        .check_links(&uris);
+
+    hooks::pre(&col, "linkcheck");
+    let (mut dead, mut revived, mut failed, mut changed) = (0, 0, 0, 0);
+    for ((section, uri), status) in sections.iter().zip(&results) {
+        let mut tags: BTreeSet<String> =
+            section.attr("tags").ok().flatten().unwrap_or_default();
+        match status {
+            scrape::LinkStatus::Ok => {
+                if tags.remove(DEAD_LINK_TAG) {
+                    println!("revived {}", uri);
+                    revived += 1;
+                } else {
+                    continue;
+                }
+            }
+            scrape::LinkStatus::Failed(e) => {
+                println!("failed {}: {}", uri, e);
+                failed += 1;
+                continue;
+            }
+            scrape::LinkStatus::Broken(code) => {
+                println!("{} {}", code, uri);
+                dead += 1;
+                if !tags.insert(DEAD_LINK_TAG.into()) {
+                    continue;
+                }
+            }
+            scrape::LinkStatus::TimedOut => {
+                println!("timeout {}", uri);
+                dead += 1;
+                if !tags.insert(DEAD_LINK_TAG.into()) {
+                    continue;
+                }
+            }
+        }
+        section.clone().set_attr("tags", &tags).or_die();
+        changed += 1;
+    }
+
+    save_changes(&mut col, "linkcheck", changed);
+    eprintln!(
+        "{} links: {} ok, {} dead, {} failed, {} revived",
+        uris.len(),
+        uris.len() - dead - failed,
+        dead,
+        failed,
+        revived
+    );
+}
+
+fn log(article: String) {
+    let col = Collection::load().or_die();
+    if !base::vcs::is_repo(col.root_path()) {
+        eprintln!("Collection is not a git repository");
+        std::process::exit(1);
     }
-    col.save().or_die();
+    let section = col
+        .iter()
+        .find(|s| s.title() == article)
+        .ok_or_else(|| format!("Article {:?} not found", article))
+        .or_die();
+    let path = col
+        .file_path(&section)
+        .ok_or("Article is not in a collection file")
+        .or_die();
+    print!("{}", base::vcs::log(col.root_path(), path).or_die());
+}
+
+fn log_dates(attr: String, from: Option<VagueDate>, to: Option<VagueDate>) {
+    let col = Collection::load().or_die();
+    let sections: Vec<Section> = col
+        .files()
+        .filter(|(path, _)| !base::trash::is_trash(path))
+        .flat_map(|(_, root)| root.iter().collect::<Vec<_>>())
+        .collect();
+    for (date, section) in
+        base::query::by_date(sections, &attr, from.as_ref(), to.as_ref())
+    {
+        println!("{}\t{}", date, section.title());
+    }
+}
+
+fn normalize(file: Option<PathBuf>, indent: Option<base::Indent>) {
+    let mut col = Collection::load().or_die();
+    hooks::pre(&col, "normalize");
+    let prefix = file.map(|path| {
+        path.strip_prefix(col.root_path())
+            .map(Path::to_owned)
+            .unwrap_or(path)
+    });
+    let paths: Vec<PathBuf> = col
+        .files()
+        .map(|(path, _)| path.to_owned())
+        .filter(|path| prefix.as_ref().is_none_or(|p| path.starts_with(p)))
+        .collect();
+    if paths.is_empty() {
+        eprintln!("No collection files at {:?}", prefix.unwrap_or_default());
+        std::process::exit(1);
+    }
+
+    // Asking for a rewrite is what the fidelity setting waits for.
+    for path in &paths {
+        col.allow_rewrite(path).or_die();
+        if let Some(indent) = indent {
+            col.set_style(path, indent.0).or_die();
+        }
+        if let Some(root) = col.root(path) {
+            root.taint();
+        }
+    }
+    save_changes(&mut col, "normalize", paths.len());
+}
+
+fn present(article: String) {
+    let col = Collection::load().or_die();
+    let section = col
+        .iter()
+        .find(|s| s.title() == article)
+        .ok_or_else(|| format!("Article {:?} not found", article))
+        .or_die();
+    print!("{}", webserver::present(&section));
 }
 
 fn reinsert(under: Option<String>) {
-    todo!();
+    let mut col = Collection::load().or_die();
+    let items = read_stdin_sections();
+
+    let mut existing = existing_entities(&col);
+
+    hooks::pre(&col, "reinsert");
+
+    let mut replaced = 0;
+    let mut new_items = Vec::new();
+    for sec in items {
+        match sec.entity_identifier().and_then(|id| existing.remove(&id)) {
+            Some(old) if old.parent().is_none() => {
+                // A file root has no place to put the new version in.
+                eprintln!(
+                    "{} is a whole file, can't replace it with {:?}",
+                    old.path(),
+                    sec.headline()
+                );
+            }
+            Some(old) => {
+                // Put the new version in the old one's place.
+                old.insert_before(sec);
+                old.detach();
+                replaced += 1;
+            }
+            None => new_items.push(sec),
+        }
+    }
+
+    let inserted = insert_items(
+        &mut col,
+        &new_items,
+        under,
+        &base::Config::get().inbox,
+        conflict::OnConflict::Skip,
+    );
+    save_changes(&mut col, "reinsert", replaced + inserted);
+
+    if replaced > 0 {
+        eprintln!("Replaced {} existing items", replaced);
+    }
+    if inserted > 0 {
+        eprintln!("Inserted {} new items", inserted);
+    }
+}
+
+fn split(path: PathBuf, by: base::split::SplitBy) {
+    let mut col = Collection::load().or_die();
+
+    // Accept paths both with and without the .otl extension.
+    let path = path.with_extension("otl");
+    hooks::pre(&col, "split");
+    let created = base::split::split_file(&mut col, &path, by).or_die();
+    save_changes(&mut col, "split", created.len());
+
+    for p in &created {
+        eprintln!("Created {}", p.to_string_lossy());
+    }
+    eprintln!("Split {:?} into {} files", path, created.len());
 }
 
-fn tag_search(tags: Vec<String>) {
+fn tag_search(tags: Vec<String>, lang: Option<String>) {
     let tags = tags.into_iter().collect::<BTreeSet<_>>();
     let col = Collection::load().or_die();
 
     fn crawl(
         search_tags: &BTreeSet<String>,
+        lang: Option<&str>,
         inherited_tags: &BTreeSet<String>,
         current: &Section,
     ) {
@@ -297,35 +1808,38 @@ fn tag_search(tags: Vec<String>) {
                 .attr::<BTreeSet<String>>("tags")
                 .ok()
                 .flatten()
-                .unwrap_or_else(|| Default::default())
+                .unwrap_or_default()
                 .union(inherited_tags)
                 .cloned()
                 .collect::<BTreeSet<String>>();
 
-            if search_tags.is_subset(&tags) {
+            let lang_matches =
+                lang.is_none_or(|lang| current.lang().as_deref() == Some(lang));
+
+            if search_tags.is_subset(&tags) && lang_matches {
                 // Found!
                 print!("{}", idm::to_string(&current).or_die());
             }
 
             for sec in current.children() {
-                crawl(search_tags, &tags, &sec);
+                crawl(search_tags, lang, &tags, &sec);
             }
         } else {
             for sec in current.children() {
-                crawl(search_tags, inherited_tags, &sec);
+                crawl(search_tags, lang, inherited_tags, &sec);
             }
         }
     }
 
     for root in col.roots() {
-        crawl(&tags, &BTreeSet::new(), &root);
+        crawl(&tags, lang.as_deref(), &BTreeSet::new(), &root);
     }
 }
 
 fn tag_histogram() {
     let col = Collection::load().or_die();
 
-    let mut hist = HashMap::new();
+    let mut hist = BTreeMap::new();
     log::info!("Start URI search");
     for section in col.iter() {
         if let Ok(Some(ts)) = section.attr::<BTreeSet<String>>("tags") {
@@ -338,44 +1852,129 @@ fn tag_histogram() {
     // Sort by largest first
     for (n, t) in &hist
         .into_iter()
-        .map(|(t, n)| (-(n as i32), t))
+        .map(|(t, n): (String, i32)| (-n, t))
         .collect::<BTreeSet<_>>()
     {
         println!("{}  {}", t, -n);
     }
 }
 
-fn retitle() {
+fn timeline(path: String) {
+    let col = Collection::load_subset(&path).or_die();
+    let section = col
+        .find(&path)
+        .ok_or_else(|| format!("Section {:?} not found", path))
+        .or_die();
+    print!("{}", webserver::timeline(&section));
+}
+
+fn todo(
+    pending: bool,
+    done: bool,
+    under: Option<String>,
+    toggle: Option<String>,
+) {
+    use base::task;
+
     let mut col = Collection::load().or_die();
 
-    for mut item in col.iter() {
-        if let Ok(Some(uri)) = item.attr::<String>("uri") {
-            let title = item.title();
-            if title == uri {
-                if let Ok(Some(title)) = scrape::web_page_title(uri.clone()) {
-                    eprintln!("{} -> {}", uri, title);
-                    item.set_title(title);
-                } else {
-                    eprintln!("\x1b[1;31mFailed to improve {}\x1b[0m", uri);
-                }
+    if let Some(path) = toggle {
+        hooks::pre(&col, "todo");
+        let section = col
+            .find(&path)
+            .ok_or_else(|| format!("Section {:?} not found", path))
+            .or_die();
+        if !task::toggle(&section) {
+            eprintln!("{:?} is not a [_] or [X] task", path);
+            std::process::exit(1);
+        }
+        println!("{}", section.headline());
+        save_changes(&mut col, "todo", 1);
+        return;
+    }
+
+    let roots: Vec<Section> = match under {
+        Some(path) => vec![col
+            .find(&path)
+            .ok_or_else(|| format!("Section {:?} not found", path))
+            .or_die()],
+        None => col
+            .files()
+            .filter(|(path, _)| !base::trash::is_trash(path))
+            .map(|(_, root)| root)
+            .collect(),
+    };
+
+    for root in roots {
+        for (section, task) in task::tasks(&root) {
+            if (pending && task.is_done()) || (done && !task.is_done()) {
+                continue;
             }
+            println!("{}", section.path());
+        }
+    }
+}
+
+fn view(name: Option<String>) {
+    let col = Collection::load().or_die();
+    let Some(name) = name else {
+        for view in base::view::views(&col) {
+            println!("{}\t{}", view.name, view.title);
+        }
+        return;
+    };
+
+    let view = base::view::find(&col, &name)
+        .ok_or_else(|| format!("View {:?} not found", name))
+        .or_die();
+    print!("{}", idm::to_string(&view.apply(&col)).or_die());
+}
+
+fn retitle(selection: Selection) {
+    let mut col = Collection::load().or_die();
+
+    let items: Vec<(Section, String)> = selection
+        .sections(&col)
+        .into_iter()
+        .filter_map(|item| {
+            let uri = item.attr::<String>("uri").ok()??;
+            (item.title() == uri).then_some((item, uri))
+        })
+        .collect();
+    let uris: Vec<String> = items.iter().map(|(_, u)| u.clone()).collect();
+    let titles = scrape::Fetcher::default().titles(&uris);
+
+    if !selection.dry_run {
+        hooks::pre(&col, "retitle");
+    }
+    let mut count = 0;
+    for ((mut item, uri), title) in items.into_iter().zip(titles) {
+        if let Ok(Some(title)) = title {
+            eprintln!("{} -> {}", uri, title);
+            item.set_title(title);
+            count += 1;
+        } else {
+            eprintln!("\x1b[1;31mFailed to improve {}\x1b[0m", uri);
         }
     }
 
-    col.save().or_die();
+    if !selection.dry_run {
+        save_changes(&mut col, "retitle", count);
+    }
 }
 
-fn reurl() {
+fn reurl(selection: Selection) {
     let mut col = Collection::load().or_die();
 
-    for mut item in col.iter() {
+    let mut items = Vec::new();
+    for item in selection.sections(&col) {
         if let Ok(Some(_)) = item.attr::<String>("mirror") {
             // Assume items with a mirror attribute are known to be dead.
             continue;
         }
 
         if let Ok(Some(tags)) = item.attr::<BTreeSet<String>>("tags") {
-            if tags.contains(&"dead-link".to_owned()) {
+            if tags.contains(DEAD_LINK_TAG) {
                 // Assume link is known to be dead and mirror-less.
                 continue;
             }
@@ -393,77 +1992,298 @@ fn reurl() {
                 continue;
             }
 
-            if let Ok(new_url) = scrape::final_url(uri.clone()) {
-                if new_url != uri {
-                    eprintln!("{:?} -> {:?}", uri, new_url);
-                    item.set_attr("uri", &new_url).or_die();
-                }
+            items.push((item, uri));
+        }
+    }
+
+    let uris: Vec<String> = items.iter().map(|(_, u)| u.clone()).collect();
+    let new_urls = scrape::Fetcher::default().final_urls(&uris);
+
+    if !selection.dry_run {
+        hooks::pre(&col, "reurl");
+    }
+    let mut count = 0;
+    for ((mut item, uri), new_url) in items.into_iter().zip(new_urls) {
+        if let Ok(new_url) = new_url {
+            if new_url != uri {
+                eprintln!("{:?} -> {:?}", uri, new_url);
+                item.set_attr("uri", &new_url).or_die();
+                count += 1;
+            }
+        } else {
+            eprintln!("\x1b[1;31mFailed to scan {}\x1b[0m", uri);
+        }
+    }
+
+    if !selection.dry_run {
+        save_changes(&mut col, "reurl", count);
+    }
+}
+
+/// Find the topmost sections outside the trash that match a query.
+///
+/// Children of a matching section aren't listed separately, they go along
+/// with the parent.
+fn query_matches(col: &Collection, query: &base::query::Query) -> Vec<Section> {
+    fn crawl(
+        query: &base::query::Query,
+        current: &Section,
+        output: &mut Vec<Section>,
+    ) {
+        for sec in current.children() {
+            if query.matches(&sec) {
+                output.push(sec);
             } else {
-                eprintln!("\x1b[1;31mFailed to scan {}\x1b[0m", uri);
+                crawl(query, &sec, output);
+            }
+        }
+    }
+
+    let mut ret = Vec::new();
+    for (path, root) in col.files() {
+        if !base::trash::is_trash(path) {
+            crawl(query, &root, &mut ret);
+        }
+    }
+    ret
+}
+
+fn query_search(query: base::query::Query, sort: Option<String>) {
+    let col = Collection::load().or_die();
+    let mut matches = query_matches(&col, &query);
+    if let Some(sort) = sort {
+        match sort.strip_prefix('-') {
+            Some(name) => {
+                base::query::sort_by_attribute(&mut matches, name, true)
             }
+            None => base::query::sort_by_attribute(&mut matches, &sort, false),
         }
     }
+    for sec in matches {
+        print!("{}", idm::to_string(&sec).or_die());
+    }
+}
+
+fn rate(uri: String, rating: base::Rating) {
+    let mut col = Collection::load().or_die();
+
+    let matches: Vec<Section> = col
+        .iter()
+        .filter(|s| s.uri().as_deref() == Some(uri.as_str()))
+        .collect();
+    if matches.is_empty() {
+        eprintln!("No entry with URI {}", uri);
+        std::process::exit(1);
+    }
+
+    hooks::pre(&col, "rate");
+    for mut section in matches.iter().cloned() {
+        section.set_attr("rating", &rating.to_string()).or_die();
+        println!("{} rated {}", section.title(), rating);
+    }
+    save_changes(&mut col, "rate", matches.len());
+}
+
+fn rm(query: base::query::Query, yes: bool) {
+    let mut col = Collection::load().or_die();
+
+    let matches = query_matches(&col, &query);
+
+    if matches.is_empty() {
+        eprintln!("No sections match {}", query);
+        return;
+    }
+
+    for sec in &matches {
+        println!("{}", sec.headline());
+    }
+
+    if !yes
+        && !dialoguer::Confirm::new()
+            .with_prompt(format!("Move {} sections to trash?", matches.len()))
+            .default(false)
+            .interact()
+            .or_die()
+    {
+        return;
+    }
+
+    hooks::pre(&col, "rm");
+    for sec in &matches {
+        base::trash::move_to_trash(&mut col, sec).or_die();
+    }
+    save_changes(&mut col, "rm", matches.len());
+    eprintln!("Moved {} sections to trash", matches.len());
+}
+
+fn search(words: Vec<String>) {
+    use base::search::Index;
+
+    let root = Collection::default_path().or_die();
+    let mut index = Index::load(&root);
+    if index.update(&root).or_die() > 0 {
+        index.save(&root).or_die();
+    }
+
+    for path in index.search(&words.join(" ")) {
+        println!("{}", path);
+    }
+}
+
+fn sql(query: String) {
+    let root = Collection::default_path().or_die();
+    let storage = base::Config::get().storage;
+    let Some(path) = storage.database_path(&root) else {
+        eprintln!("No database, set storage to mirror or sqlite in settings");
+        std::process::exit(1);
+    };
+    // Opening the storage brings a mirror database up to date.
+    storage.open(&root).or_die();
 
-    col.save().or_die();
+    let (columns, rows) = base::sqlite::query(path, &query).or_die();
+    println!("{}", columns.join("\t"));
+    for row in rows {
+        println!("{}", row.join("\t"));
+    }
 }
 
 fn scrape(uri: String) {
+    let node = scrape_entry(uri).or_die();
+    print!("{}", idm::to_string(&node).or_die());
+}
+
+/// Build a new collection entry for an URI.
+fn scrape_entry(uri: String) -> base::Result<Section> {
     if uri.starts_with("isbn:") {
         todo!("Book scraping");
     }
 
-    let mut title = uri.clone();
+    let info = scrape::web_page_info(&uri)?;
+    let title = info.title.unwrap_or_else(|| uri.clone());
 
-    if let Some(page_title) = scrape::web_page_title(title.clone()).or_die() {
-        title = page_title;
+    let mut attributes = IndexMap::from([
+        ("uri".to_string(), uri),
+        ("added".to_string(), VagueDate::now().to_string()),
+    ]);
+    if let Some(lang) = info.lang {
+        attributes.insert("lang".to_string(), lang);
     }
 
-    let node = Section::new(
-        title,
-        IndexMap::from([
-            ("uri".to_string(), uri),
-            ("added".to_string(), VagueDate::now().to_string()),
-        ]),
-    );
+    let ret = Section::new(title, attributes);
+    if let Some(summary) = hooks::summarize(&info.text) {
+        let lines: Vec<&str> = summary
+            .lines()
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .collect();
+        ret.set_body(lines.join("\n"));
+    }
 
-    print!("{}", idm::to_string(&node).or_die());
+    Ok(ret)
 }
 
-fn save_bookmark(uri: String) {
+#[allow(dead_code)]
+fn save_bookmark(_uri: String) {
     todo!();
 }
 
 fn save_to_read(uri: String) {
-    todo!();
-    /*
     let mut col = Collection::load().or_die();
 
-    let section_data = scrape(uri).or_die();
-    let scraped_uri = &section_data.1 .0.uri;
+    let entry = scrape_entry(uri).or_die();
+    hooks::pre(&col, "toread");
+    if insert_items(
+        &mut col,
+        &[entry],
+        None,
+        "ToRead",
+        conflict::OnConflict::Skip,
+    ) > 0
+    {
+        save_changes(&mut col, "toread", 1);
+    } else {
+        std::process::exit(1);
+    }
+}
 
-    // TODO 2022-10-01 See insert for a more up to date way to do this...
-    log::info!("Start URI search");
-    for section in col.iter() {
-        if let Ok(Some(u)) = section.attr::<String>("uri") {
-            if &u == scraped_uri {
-                log::info!("URI search successful");
-                eprintln!(
-                    "Uri {:?} already present in collection.",
-                    scraped_uri
-                );
-                std::process::exit(1);
-            }
-        }
+/// Name of the per-domain proxy rules file in the config directory.
+const PROXIES_FILE: &str = "proxies.idm";
+
+/// Set up scraping proxies from the collection's proxy rules if there are
+/// any.
+fn load_proxies() {
+    let Ok(root) = Collection::default_path() else {
+        return;
+    };
+    let path = base::xdg::path(root, base::xdg::Dir::Config, PROXIES_FILE);
+    if !path.exists() {
+        return;
     }
+    let proxies = fs::read_to_string(&path)
+        .or_die()
+        .parse()
+        .map_err(|e| format!("Bad proxy rules in {:?}: {}", path, e))
+        .or_die();
+    scrape::set_proxies(proxies);
+}
 
-    log::info!("URI not found, scraping new entry");
-    let entry = Section::from_data(&section_data).or_die();
+/// Save changes made by a command.
+///
+/// Runs the save hooks and the post-command hook, and records the command
+/// in collection history.
+fn save_changes(col: &mut Collection, command: &str, affected: usize) {
+    try_save_changes(col, command, affected).or_die();
+}
 
-    let to_read = col.find_or_create("ToRead");
-    to_read.append(entry);
+/// Like `save_changes`, but return an error instead of exiting if a hook
+/// refuses the save or saving fails.
+fn try_save_changes(
+    col: &mut Collection,
+    command: &str,
+    affected: usize,
+) -> base::Result<()> {
+    hooks::pre_with_count(col, "pre-save", command, affected)?;
+    col.set_track_changes(true);
+    col.save()?;
+    hooks::post(col, "post-save", command, affected);
+    hooks::post(col, &format!("post-{}", command), command, affected);
+    history::record(col, affected);
+
+    // Commit the files that were written if the collection is kept in git.
+    if base::vcs::is_repo(col.root_path()) && !col.saved_paths().is_empty() {
+        let message = format!("olt {}: {} changes", command, affected);
+        if let Err(e) =
+            base::vcs::commit(col.root_path(), col.saved_paths(), &message)
+        {
+            eprintln!("Failed to commit changes: {}", e);
+        }
+    }
+    Ok(())
+}
 
-    col.save().or_die();
-    */
+/// Apply and save edits that a server or bot left in the journal when it
+/// stopped before saving them.
+///
+/// If saving fails, the edits are undone and kept in the journal for the
+/// next try.
+fn recover_journal(col: &mut Collection) {
+    let journal = base::journal::Journal::new(col.root_path());
+    let saved = match journal.recover(col) {
+        Ok(0) => Ok(()),
+        Ok(n) => try_save_changes(col, "recover", n)
+            .map(|_| println!("Recovered {} unsaved edits", n)),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = saved {
+        eprintln!("Failed to recover unsaved edits: {}", e);
+        if let Err(e) = col.revert() {
+            eprintln!("Failed to undo recovered edits: {}", e);
+        }
+        return;
+    }
+    if let Err(e) = journal.clear() {
+        log::warn!("Failed to clear journal: {}", e);
+    }
 }
 
 /// Trait for top-level error handling.
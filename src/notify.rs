@@ -0,0 +1,351 @@
+//! Digests of what changed in the collection since the last run.
+//!
+//! `olt notify` is meant to be run from cron. It remembers the bookmarks and
+//! dead links it has seen in `notify-snapshot.json` in the collection's state
+//! directory and reports new bookmarks, newly dead links and tasks whose
+//! `due` date has come.
+//!
+//! The digest is printed to stdout unless `notify.idm` in the collection's
+//! config directory sets up other ways to send it:
+//!
+//! ```notrust
+//! email-command mail -s "otlbook digest" me@example.com
+//! ntfy https://ntfy.sh/my-otlbook
+//! ```
+//!
+//! The email command is run with the shell and gets the digest on stdin,
+//! the ntfy URL gets the digest POSTed to it.
+//!
+//! `olt digest` makes a HTML review of a past day or week instead: the
+//! bookmarks added and the items read in that time, due tasks and a random
+//! older article to look at again. With `--mailto` it's sent as an email
+//! with the `sendmail-command` from `notify.idm`, `sendmail -t` by default,
+//! which reads the recipient from the message headers.
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use base::{xdg, Collection, Section, VagueDate};
+use chrono::NaiveDate;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::OrDie;
+
+/// Name of the notification settings file in the config directory.
+const CONFIG_FILE: &str = "notify.idm";
+
+/// Name of the file for the state from the previous run in the state
+/// directory.
+const SNAPSHOT_FILE: &str = "notify-snapshot.json";
+
+#[derive(Clone, Default, Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct NotifyConfig {
+    email_command: Option<String>,
+    ntfy: Option<String>,
+    sendmail_command: Option<String>,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+struct Snapshot {
+    time: Option<VagueDate>,
+    bookmarks: BTreeSet<String>,
+    dead_links: BTreeSet<String>,
+}
+
+impl Snapshot {
+    fn new(col: &Collection) -> Snapshot {
+        let mut ret = Snapshot {
+            time: Some(VagueDate::now()),
+            ..Default::default()
+        };
+        for section in sections(col) {
+            if let Some(uri) = section.uri() {
+                if section.tags().contains(crate::DEAD_LINK_TAG) {
+                    ret.dead_links.insert(uri.clone());
+                }
+                ret.bookmarks.insert(uri);
+            }
+        }
+        ret
+    }
+
+    fn path(col: &Collection) -> PathBuf {
+        xdg::path(col.root_path(), xdg::Dir::State, SNAPSHOT_FILE)
+    }
+
+    fn load(col: &Collection) -> Option<Snapshot> {
+        let path = Snapshot::path(col);
+        if !path.exists() {
+            return None;
+        }
+        Some(
+            serde_json::from_str(&fs::read_to_string(&path).or_die())
+                .map_err(|e| format!("Bad snapshot {:?}: {}", path, e))
+                .or_die(),
+        )
+    }
+
+    fn save(&self, col: &Collection) {
+        let path = Snapshot::path(col);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).or_die();
+        }
+        fs::write(&path, serde_json::to_string(self).or_die()).or_die();
+    }
+}
+
+/// Sections outside the trash.
+fn sections(col: &Collection) -> impl Iterator<Item = Section> + '_ {
+    col.files()
+        .filter(|(path, _)| !base::trash::is_trash(path))
+        .flat_map(|(_, root)| root.iter())
+}
+
+/// Tasks that are due by today and not done.
+fn due_tasks(col: &Collection) -> Vec<(VagueDate, Section)> {
+    let today = VagueDate::now().first_day();
+    let mut ret: Vec<(VagueDate, Section)> = sections(col)
+        .filter_map(|s| Some((s.attr::<VagueDate>("due").ok()??, s)))
+        .filter(|(due, _)| due.first_day() <= today)
+        .filter(|(_, s)| {
+            s.attr::<String>("status").ok().flatten().as_deref() != Some("done")
+        })
+        .collect();
+    ret.sort_by_key(|(due, _)| *due);
+    ret
+}
+
+/// Write the digest of changes since the previous snapshot.
+fn digest(col: &Collection, previous: Option<&Snapshot>) -> String {
+    let mut ret = String::new();
+
+    // Everything would be new without a previous snapshot, so only report
+    // the changes from the second run on.
+    if let Some(previous) = previous {
+        let mut new_bookmarks = Vec::new();
+        let mut dead_links = Vec::new();
+        for section in sections(col) {
+            let Some(uri) = section.uri() else {
+                continue;
+            };
+            if !previous.bookmarks.contains(&uri) {
+                new_bookmarks.push((section.title(), uri.clone()));
+            }
+            if section.tags().contains(crate::DEAD_LINK_TAG)
+                && !previous.dead_links.contains(&uri)
+            {
+                dead_links.push((section.title(), uri));
+            }
+        }
+
+        for (heading, items) in [
+            ("New bookmarks", new_bookmarks),
+            ("Newly dead links", dead_links),
+        ] {
+            if items.is_empty() {
+                continue;
+            }
+            let _ = writeln!(ret, "{}:", heading);
+            for (title, uri) in items {
+                let _ = writeln!(ret, "  {} <{}>", title, uri);
+            }
+            ret.push('\n');
+        }
+    }
+
+    let due = due_tasks(col);
+    if !due.is_empty() {
+        let _ = writeln!(ret, "Due tasks:");
+        for (date, task) in due {
+            let _ = writeln!(ret, "  {} {}", date, task.title());
+        }
+        ret.push('\n');
+    }
+
+    ret
+}
+
+fn load_config(col: &Collection) -> NotifyConfig {
+    let path = xdg::path(col.root_path(), xdg::Dir::Config, CONFIG_FILE);
+    if !path.exists() {
+        return NotifyConfig::default();
+    }
+    idm::from_str(&fs::read_to_string(&path).or_die())
+        .map_err(|e| format!("Bad notify settings in {:?}: {}", path, e))
+        .or_die()
+}
+
+/// Run a shell command with text on its stdin.
+fn send_email(command: &str, digest: &str) -> base::Result<()> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("Child has no stdin")
+        .write_all(digest.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("Email command failed: {}", status).into());
+    }
+    Ok(())
+}
+
+pub fn notify() {
+    let col = Collection::load().or_die();
+    let config = load_config(&col);
+    let previous = Snapshot::load(&col);
+
+    let digest = digest(&col, previous.as_ref());
+    if !digest.is_empty() {
+        if config.email_command.is_none() && config.ntfy.is_none() {
+            print!("{}", digest);
+        }
+        if let Some(command) = &config.email_command {
+            send_email(command, &digest).or_die();
+        }
+        if let Some(url) = &config.ntfy {
+            scrape::post_text(url, digest).or_die();
+        }
+    }
+
+    // Only move on to the new snapshot once the digest went through.
+    Snapshot::new(&col).save(&col);
+}
+
+/// Write the review of the days after `since` as a HTML page.
+fn review_html(col: &Collection, title: &str, since: NaiveDate) -> String {
+    let is_recent = |s: &Section, name: &str| {
+        s.attr::<VagueDate>(name)
+            .ok()
+            .flatten()
+            .is_some_and(|d| d.first_day() > since)
+    };
+    let articles: Vec<Section> = sections(col)
+        .filter(|s| s.is_article() && !s.headline().trim().is_empty())
+        .collect();
+
+    let new_bookmarks: Vec<&Section> = articles
+        .iter()
+        .filter(|s| s.uri().is_some() && is_recent(s, "added"))
+        .collect();
+    let read: Vec<&Section> =
+        articles.iter().filter(|s| is_recent(s, "read")).collect();
+    let due = due_tasks(col);
+    // Something from before the review period to look at again.
+    let older: Vec<&Section> = articles
+        .iter()
+        .filter(|s| !is_recent(s, "added") && !is_recent(s, "read"))
+        .collect();
+    let resurfaced = older.choose(&mut rand::thread_rng());
+
+    let mut body = String::new();
+    for (heading, items) in [("New bookmarks", new_bookmarks), ("Read", read)] {
+        if items.is_empty() {
+            continue;
+        }
+        let _ = writeln!(body, "<h2>{}</h2>\n<ul>", heading);
+        for item in items {
+            let _ = writeln!(body, "<li>{}</li>", item_html(item));
+        }
+        let _ = writeln!(body, "</ul>");
+    }
+    if !due.is_empty() {
+        let _ = writeln!(body, "<h2>Due tasks</h2>\n<ul>");
+        for (date, task) in due {
+            let _ =
+                writeln!(body, "<li>{} {}</li>", date, escape(&task.title()));
+        }
+        let _ = writeln!(body, "</ul>");
+    }
+    if let Some(article) = resurfaced {
+        let _ = writeln!(
+            body,
+            "<h2>From the archive</h2>\n<p>{}</p>",
+            item_html(article)
+        );
+        let text = article.body();
+        if !text.is_empty() {
+            let _ = writeln!(
+                body,
+                "<p style=\"white-space: pre-wrap\">{}</p>",
+                escape(&text)
+            );
+        }
+    }
+    if body.is_empty() {
+        body.push_str("<p>Nothing new.</p>\n");
+    }
+
+    format!(
+        "\
+<!DOCTYPE html>
+<html>
+<head>
+  <meta charset='utf-8'/>
+  <title>{title}</title>
+</head>
+<body>
+<h1>{title}</h1>
+{body}</body>
+</html>
+",
+        title = escape(title)
+    )
+}
+
+/// Section title, linked to its URI if it has one.
+fn item_html(section: &Section) -> String {
+    match section.uri() {
+        Some(uri) => format!(
+            "<a href='{}'>{}</a>",
+            escape(&uri),
+            escape(&section.title())
+        ),
+        None => escape(&section.title()),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('\'', "&#39;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+pub fn review(weekly: bool, mailto: Option<String>) {
+    let col = Collection::load().or_die();
+    let today = VagueDate::now().first_day();
+    let (days, name) = if weekly { (7, "Weekly") } else { (1, "Daily") };
+    let title = format!("{} review {}", name, today);
+    let html = review_html(&col, &title, today - chrono::Days::new(days));
+
+    let Some(address) = mailto else {
+        print!("{}", html);
+        return;
+    };
+    let message = format!(
+        "To: {}\r\nSubject: {}\r\nMIME-Version: 1.0\r\n\
+         Content-Type: text/html; charset=utf-8\r\n\r\n{}",
+        address, title, html
+    );
+    let config = load_config(&col);
+    let command = config.sendmail_command.as_deref().unwrap_or("sendmail -t");
+    send_email(command, &message).or_die();
+}
@@ -0,0 +1,198 @@
+//! External command plugins.
+//!
+//! `olt x <name> [args]` runs an `olt-<name>` executable found on PATH,
+//! git-style. The plugin gets the collection location in the `OTLBOOK_PATH`
+//! environment variable and can talk to olt using JSON-RPC 2.0: it writes
+//! one request per line to its stdout and reads one response per line from
+//! its stdin. Plugins should write anything meant for the user to stderr.
+//!
+//! Methods:
+//!
+//! * `list {query?}`: headlines, paths and attributes of sections matching
+//!   an optional query, or all articles.
+//! * `get {path}`: section at path with its children.
+//! * `set_attr {path, name, value}`: set an attribute, remove it if value is
+//!   null.
+//! * `insert {under, idm}`: append sections given as IDM text under path.
+//!
+//! Changes are saved when the plugin exits successfully, and discarded if
+//! it fails.
+
+use std::{
+    env,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use base::{query::Query, Collection, Section};
+use serde_json::{json, Map, Value};
+
+use crate::OrDie;
+
+/// Find `olt-<name>` executable on PATH.
+fn find_plugin(name: &str) -> Option<PathBuf> {
+    let exe = format!("olt-{}{}", name, env::consts::EXE_SUFFIX);
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&exe))
+        .find(|p| p.is_file())
+}
+
+pub fn run(name: String, args: Vec<String>) {
+    let Some(exe) = find_plugin(&name) else {
+        eprintln!("Plugin olt-{} not found on PATH", name);
+        std::process::exit(1);
+    };
+
+    let mut col = Collection::load().or_die();
+    crate::hooks::pre(&col, "x");
+
+    let mut child = Command::new(&exe)
+        .args(&args)
+        .env("OTLBOOK_PATH", col.root_path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .or_die();
+
+    let mut responses = child.stdin.take().expect("No plugin stdin");
+    let requests = BufReader::new(child.stdout.take().expect("No stdout"));
+
+    let mut changes = 0;
+    for line in requests.lines() {
+        let line = line.or_die();
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => {
+                let id = request.get("id").cloned().unwrap_or(Value::Null);
+                let method = request["method"].as_str().unwrap_or("");
+                match call(&mut col, method, &request["params"]) {
+                    Ok((result, changed)) => {
+                        changes += changed;
+                        json!({"jsonrpc": "2.0", "id": id, "result": result})
+                    }
+                    Err(e) => error(id, -32000, &e.to_string()),
+                }
+            }
+            Err(e) => error(Value::Null, -32700, &e.to_string()),
+        };
+
+        if writeln!(responses, "{}", response).is_err() {
+            // Plugin closed its input, it's not going to ask for more.
+            break;
+        }
+    }
+    drop(responses);
+
+    let status = child.wait().or_die();
+    if !status.success() {
+        eprintln!("Plugin olt-{} failed with {}", name, status);
+        std::process::exit(1);
+    }
+
+    if changes > 0 {
+        crate::save_changes(&mut col, "x", changes);
+    }
+}
+
+fn error(id: Value, code: i32, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": code, "message": message},
+    })
+}
+
+/// Handle a request, return result value and number of changes made.
+fn call(
+    col: &mut Collection,
+    method: &str,
+    params: &Value,
+) -> base::Result<(Value, usize)> {
+    let param = |name: &str| -> base::Result<&str> {
+        Ok(params[name]
+            .as_str()
+            .ok_or_else(|| format!("Missing parameter {:?}", name))?)
+    };
+    let lookup = |col: &Collection, path: &str| -> base::Result<Section> {
+        Ok(col
+            .find(path)
+            .ok_or_else(|| format!("Section {:?} not found", path))?)
+    };
+
+    match method {
+        "list" => {
+            let query: Option<Query> = match params["query"].as_str() {
+                Some(q) => Some(q.parse()?),
+                None => None,
+            };
+            let sections = col
+                .iter()
+                .filter(|s| match &query {
+                    Some(q) => q.matches(s),
+                    None => s.is_article(),
+                })
+                .map(|s| {
+                    let mut ret = section_summary(&s);
+                    ret.insert("path".into(), s.path().into());
+                    Value::Object(ret)
+                })
+                .collect();
+            Ok((Value::Array(sections), 0))
+        }
+        "get" => {
+            let section = lookup(col, param("path")?)?;
+            Ok((section_tree(&section), 0))
+        }
+        "set_attr" => {
+            let mut section = lookup(col, param("path")?)?;
+            let name = param("name")?;
+            match params["value"].as_str() {
+                Some(value) => section.set_raw_attr(name, value),
+                None => section.remove_attr(name),
+            }
+            Ok((Value::Null, 1))
+        }
+        "insert" => {
+            let parent = col.find_or_create(param("under")?)?;
+            let items: Vec<Section> = idm::from_str(param("idm")?)?;
+            let count = items.len();
+            for item in items {
+                parent.append(item);
+            }
+            Ok((count.into(), count))
+        }
+        _ => Err(format!("Unknown method {:?}", method))?,
+    }
+}
+
+/// Headline and attributes of a section as a JSON object.
+fn section_summary(section: &Section) -> Map<String, Value> {
+    let mut ret = Map::new();
+    ret.insert("headline".into(), section.headline().into());
+    ret.insert(
+        "attributes".into(),
+        Value::Object(
+            section
+                .borrow()
+                .attributes
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone().into()))
+                .collect(),
+        ),
+    );
+    ret
+}
+
+/// Section with all its children as a JSON object.
+fn section_tree(section: &Section) -> Value {
+    let mut ret = section_summary(section);
+    ret.insert(
+        "children".into(),
+        Value::Array(section.children().map(|c| section_tree(&c)).collect()),
+    );
+    Value::Object(ret)
+}
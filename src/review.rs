@@ -0,0 +1,71 @@
+//! Terminal flashcard review, see `base::srs`.
+
+use base::{srs, Collection, VagueDate};
+use dialoguer::console::Term;
+
+use crate::OrDie;
+
+pub fn review(limit: Option<usize>) {
+    let col = Collection::load().or_die();
+    let root = col.root_path().to_owned();
+    let today = VagueDate::now().first_day();
+
+    let mut state = srs::State::load(&root).or_die();
+    let mut cards = state.due(srs::collection_cards(&col), today);
+    if let Some(limit) = limit {
+        cards.truncate(limit);
+    }
+    if cards.is_empty() {
+        println!("No cards due");
+        return;
+    }
+
+    let term = Term::stdout();
+    let mut reviewed = 0;
+    for (i, card) in cards.iter().enumerate() {
+        println!("\n[{}/{}]", i + 1, cards.len());
+        println!("{}", card.front);
+        println!("[space] show answer  [q]uit");
+        if !wait_for_answer(&term) {
+            break;
+        }
+
+        println!("{}", card.back);
+        println!("Grade 0 (forgot) to {} (easy), [q]uit", srs::MAX_GRADE);
+        let Some(grade) = ask_grade(&term) else {
+            break;
+        };
+        state.review(card, grade, today);
+        // Save after every card so quitting halfway keeps the reviews.
+        state.save(&root).or_die();
+        reviewed += 1;
+    }
+
+    println!("Reviewed {} cards", reviewed);
+}
+
+/// Wait until the answer is asked for, return false for quit.
+fn wait_for_answer(term: &Term) -> bool {
+    loop {
+        match term.read_char().or_die() {
+            ' ' | '\n' => return true,
+            'q' => return false,
+            _ => {}
+        }
+    }
+}
+
+fn ask_grade(term: &Term) -> Option<u32> {
+    loop {
+        match term.read_char().or_die() {
+            'q' => return None,
+            c => {
+                if let Some(grade) =
+                    c.to_digit(10).filter(|&g| g <= srs::MAX_GRADE)
+                {
+                    return Some(grade);
+                }
+            }
+        }
+    }
+}
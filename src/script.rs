@@ -0,0 +1,149 @@
+//! Rhai scripting for one-off collection transformations.
+//!
+//! Scripts run with `olt script file.rhai [args]` see the command line
+//! arguments as `args` and can use these functions:
+//!
+//! * `sections()`: every section in the collection.
+//! * `query(q)`: sections matching a query string.
+//! * `find(path)`: section at path or `()`.
+//! * `find_or_create(path)`: section at path, created if missing.
+//!
+//! Sections have `headline` (settable), `title`, `path`, `uri` and `tags`
+//! properties and `attr(name)`, `set_attr(name, value)`,
+//! `remove_attr(name)`, `children()`, `parent()`, `is_article()` and
+//! `append(headline)` methods.
+//!
+//! Changes are saved when the script finishes without errors.
+
+use std::{cell::RefCell, path::Path, rc::Rc};
+
+use base::{query::Query, Collection, Section};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope};
+
+use crate::OrDie;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+pub fn run(path: &Path, args: Vec<String>, dry_run: bool) {
+    let col = Rc::new(RefCell::new(Collection::load().or_die()));
+    // Count of mutating calls made by the script.
+    let changes = Rc::new(RefCell::new(0));
+
+    let mut engine = Engine::new();
+    register_section(&mut engine, &changes);
+
+    {
+        let col = col.clone();
+        engine.register_fn("sections", move || -> Array {
+            col.borrow().iter().map(Dynamic::from).collect()
+        });
+    }
+    {
+        let col = col.clone();
+        engine.register_fn("query", move |q: &str| -> ScriptResult<Array> {
+            let q: Query = q.parse()?;
+            Ok(col
+                .borrow()
+                .iter()
+                .filter(|s| q.matches(s))
+                .map(Dynamic::from)
+                .collect())
+        });
+    }
+    {
+        let col = col.clone();
+        engine.register_fn("find", move |path: &str| -> Dynamic {
+            col.borrow().find(path).map_or(Dynamic::UNIT, Dynamic::from)
+        });
+    }
+    {
+        let col = col.clone();
+        let changes = changes.clone();
+        engine.register_fn(
+            "find_or_create",
+            move |path: &str| -> ScriptResult<Section> {
+                let mut col = col.borrow_mut();
+                if let Some(section) = col.find(path) {
+                    return Ok(section);
+                }
+                *changes.borrow_mut() += 1;
+                Ok(col.find_or_create(path).map_err(|e| e.to_string())?)
+            },
+        );
+    }
+
+    let mut scope = Scope::new();
+    let args: Array = args.into_iter().map(Dynamic::from).collect();
+    scope.push("args", args);
+
+    crate::hooks::pre(&col.borrow(), "script");
+    if let Err(e) = engine.run_file_with_scope(&mut scope, path.into()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    let changes = *changes.borrow();
+    if changes == 0 {
+        return;
+    }
+    if dry_run {
+        eprintln!("Dry run, not saving {} change(s)", changes);
+        return;
+    }
+    crate::save_changes(&mut col.borrow_mut(), "script", changes);
+}
+
+fn register_section(engine: &mut Engine, changes: &Rc<RefCell<usize>>) {
+    engine
+        .register_type_with_name::<Section>("Section")
+        .register_get("headline", |s: &mut Section| s.headline())
+        .register_get("title", |s: &mut Section| s.title())
+        .register_get("path", |s: &mut Section| s.path())
+        .register_get("uri", |s: &mut Section| {
+            s.uri().map_or(Dynamic::UNIT, Dynamic::from)
+        })
+        .register_get("tags", |s: &mut Section| -> Array {
+            s.tags().into_iter().map(Dynamic::from).collect()
+        })
+        .register_fn("attr", |s: &mut Section, name: &str| {
+            s.borrow()
+                .attributes
+                .get(name)
+                .cloned()
+                .map_or(Dynamic::UNIT, Dynamic::from)
+        })
+        .register_fn("children", |s: &mut Section| -> Array {
+            s.children().map(Dynamic::from).collect()
+        })
+        .register_fn("parent", |s: &mut Section| {
+            s.parent().map_or(Dynamic::UNIT, Dynamic::from)
+        })
+        .register_fn("is_article", |s: &mut Section| s.is_article())
+        .register_fn("to_string", |s: &mut Section| s.headline());
+
+    let c = changes.clone();
+    engine.register_set("headline", move |s: &mut Section, h: &str| {
+        *c.borrow_mut() += 1;
+        s.set_headline(h);
+    });
+    let c = changes.clone();
+    engine.register_fn(
+        "set_attr",
+        move |s: &mut Section, name: &str, value: &str| {
+            *c.borrow_mut() += 1;
+            s.set_raw_attr(name, value);
+        },
+    );
+    let c = changes.clone();
+    engine.register_fn("remove_attr", move |s: &mut Section, name: &str| {
+        *c.borrow_mut() += 1;
+        s.remove_attr(name);
+    });
+    let c = changes.clone();
+    engine.register_fn("append", move |s: &mut Section, headline: &str| {
+        *c.borrow_mut() += 1;
+        let child = Section::new(headline.into(), Default::default());
+        s.append(child.clone());
+        child
+    });
+}
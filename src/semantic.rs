@@ -0,0 +1,146 @@
+//! Semantic search using embedding vectors.
+//!
+//! Embeddings are computed by the collection's `embed` hook, which reads a
+//! JSON list of texts from stdin and prints a JSON list of vectors, one per
+//! text. This lets the user wire up whatever local model or API they like.
+//! Computed vectors are cached in `embeddings.json` in the collection's
+//! cache directory keyed by a
+//! hash of the embedded text, so only new and changed articles need to be
+//! embedded again.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::PathBuf,
+    time::Duration,
+};
+
+use base::{xdg, Collection, Section};
+
+use crate::OrDie;
+
+/// Name of the embedding cache in the cache directory.
+const EMBEDDINGS_FILE: &str = "embeddings.json";
+
+/// How many texts are sent to the embed hook at once.
+const BATCH_SIZE: usize = 64;
+
+/// Longest text in bytes embedded for a single article.
+const TEXT_LIMIT: usize = 8_000;
+
+/// How long to wait for the embed hook to process one batch.
+const EMBED_TIMEOUT: Duration = Duration::from_secs(300);
+
+type Embeddings = BTreeMap<String, Vec<f32>>;
+
+pub fn semantic_search(query: String, count: usize) {
+    let col = Collection::load().or_die();
+
+    let articles: Vec<(Section, String)> = col
+        .iter()
+        .filter(|s| s.is_article())
+        .map(|s| {
+            let text = embed_text(&s);
+            let key = format!("{:x}", md5::compute(&text));
+            (s, key)
+        })
+        .collect();
+
+    let mut cache = load(&col);
+    let mut texts: Vec<(String, String)> = Vec::new();
+    let mut seen = HashSet::new();
+    for (s, key) in &articles {
+        if !cache.contains_key(key) && seen.insert(key) {
+            texts.push((key.clone(), embed_text(s)));
+        }
+    }
+
+    if !texts.is_empty() {
+        eprintln!("Embedding {} new or changed article(s)", texts.len());
+        for batch in texts.chunks(BATCH_SIZE) {
+            let vectors = embed(
+                &col,
+                &batch.iter().map(|(_, t)| t.as_str()).collect::<Vec<_>>(),
+            )
+            .or_die();
+            for ((key, _), v) in batch.iter().zip(vectors) {
+                cache.insert(key.clone(), v);
+            }
+        }
+    }
+
+    // Drop vectors for articles that are gone or have changed.
+    cache.retain(|k, _| articles.iter().any(|(_, key)| key == k));
+    save(&col, &cache).or_die();
+
+    let query = embed(&col, &[&query]).or_die().pop().unwrap_or_default();
+
+    let mut results: Vec<(f32, &Section)> = articles
+        .iter()
+        .filter_map(|(s, key)| Some((similarity(&query, cache.get(key)?), s)))
+        .collect();
+    results.sort_by(|a, b| {
+        b.0.total_cmp(&a.0)
+            .then_with(|| a.1.path().cmp(&b.1.path()))
+    });
+
+    for (score, s) in results.into_iter().take(count) {
+        println!("{:.3}  {}", score, s.path());
+    }
+}
+
+/// Text used to represent an article.
+fn embed_text(section: &Section) -> String {
+    let text = format!("{}\n{}", section.title(), section.body_string());
+    crate::hooks::truncate(&text, TEXT_LIMIT).to_string()
+}
+
+fn embed(col: &Collection, texts: &[&str]) -> base::Result<Vec<Vec<f32>>> {
+    let input = serde_json::to_string(texts)?;
+    let output =
+        crate::hooks::filter(col.root_path(), "embed", &input, EMBED_TIMEOUT)?
+            .ok_or("Semantic search needs an embed hook")?;
+
+    let vectors: Vec<Vec<f32>> = serde_json::from_str(&output)?;
+    if vectors.len() != texts.len() {
+        return Err(format!(
+            "Embed hook returned {} vectors for {} texts",
+            vectors.len(),
+            texts.len()
+        )
+        .into());
+    }
+    Ok(vectors)
+}
+
+/// Cosine similarity of two vectors.
+fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+fn cache_path(col: &Collection) -> PathBuf {
+    xdg::path(col.root_path(), xdg::Dir::Cache, EMBEDDINGS_FILE)
+}
+
+fn load(col: &Collection) -> Embeddings {
+    fs::read_to_string(cache_path(col))
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn save(col: &Collection, cache: &Embeddings) -> base::Result<()> {
+    let path = cache_path(col);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string(cache)?)?;
+    Ok(())
+}
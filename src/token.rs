@@ -0,0 +1,48 @@
+//! API tokens for the webserver's ingest endpoint.
+
+use base::{token::Tokens, Collection};
+use structopt::StructOpt;
+
+use crate::OrDie;
+
+#[derive(StructOpt, Debug)]
+pub enum TokenCmd {
+    #[structopt(
+        name = "new",
+        about = "Make a token and print it, replacing one with the same name"
+    )]
+    New {
+        #[structopt(about = "Name for the token, eg. the device using it")]
+        name: String,
+    },
+    #[structopt(name = "list", about = "List the names of the tokens")]
+    List,
+    #[structopt(name = "revoke", about = "Remove a token")]
+    Revoke { name: String },
+}
+
+pub fn run(cmd: TokenCmd) {
+    let col = Collection::load().or_die();
+    let root = col.root_path();
+    let mut tokens = Tokens::load(root).or_die();
+
+    match cmd {
+        TokenCmd::New { name } => {
+            let token = tokens.generate(&name).or_die();
+            tokens.save(root).or_die();
+            println!("{}", token);
+        }
+        TokenCmd::List => {
+            for name in tokens.names() {
+                println!("{}", name);
+            }
+        }
+        TokenCmd::Revoke { name } => {
+            if !tokens.revoke(&name) {
+                eprintln!("No token named {:?}", name);
+                std::process::exit(1);
+            }
+            tokens.save(root).or_die();
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Listing, restoring and emptying the trash.
+
+use base::{trash, Collection, VagueDate};
+use structopt::StructOpt;
+
+use crate::OrDie;
+
+#[derive(StructOpt, Debug)]
+pub enum TrashCmd {
+    #[structopt(name = "list", about = "List sections in the trash")]
+    List,
+    #[structopt(
+        name = "restore",
+        about = "Move a section from the trash back to where it was deleted from"
+    )]
+    Restore {
+        #[structopt(about = "Trash entry number from olt trash list")]
+        id: usize,
+    },
+    #[structopt(name = "empty", about = "Permanently delete trash contents")]
+    Empty {
+        #[structopt(
+            about = "Only delete entries older than this, eg. 90d, 4w or 1y",
+            long = "older-than",
+            parse(try_from_str = parse_age)
+        )]
+        older_than: Option<chrono::Duration>,
+        #[structopt(about = "Don't ask for confirmation", long = "yes")]
+        yes: bool,
+    },
+}
+
+pub fn run(cmd: TrashCmd) {
+    match cmd {
+        TrashCmd::List => list(),
+        TrashCmd::Restore { id } => restore(id),
+        TrashCmd::Empty { older_than, yes } => empty(older_than, yes),
+    }
+}
+
+fn list() {
+    let col = Collection::load().or_die();
+
+    for (i, entry) in trash::entries(&col).iter().enumerate() {
+        let deleted = trash::deleted(entry)
+            .map(|d| d.to_string()[..10].to_string())
+            .unwrap_or_else(|| "?".into());
+        let from = entry
+            .attr::<String>("trashed-from")
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| "?".into());
+        println!("{:>4}  {}  {}  {}", i + 1, deleted, from, entry.headline());
+    }
+}
+
+fn restore(id: usize) {
+    let mut col = Collection::load().or_die();
+
+    let entries = trash::entries(&col);
+    let Some(entry) = id.checked_sub(1).and_then(|i| entries.get(i)) else {
+        eprintln!("No trash entry {}", id);
+        std::process::exit(1);
+    };
+
+    crate::hooks::pre(&col, "trash-restore");
+    let path = trash::restore(&mut col, entry).or_die();
+    crate::save_changes(&mut col, "trash-restore", 1);
+    eprintln!("Restored {:?} to {}", entry.headline(), path);
+}
+
+fn empty(older_than: Option<chrono::Duration>, yes: bool) {
+    let mut col = Collection::load().or_die();
+
+    let before = older_than.map(|age| {
+        let cutoff = chrono::Local::now()
+            .checked_sub_signed(age)
+            .ok_or("Bad age, too far in the past")
+            .or_die();
+        VagueDate::DateTime(cutoff.fixed_offset())
+    });
+
+    if !yes
+        && !dialoguer::Confirm::new()
+            .with_prompt("Permanently delete trash contents?")
+            .default(false)
+            .interact()
+            .or_die()
+    {
+        return;
+    }
+
+    crate::hooks::pre(&col, "trash-empty");
+    let count = trash::empty(&mut col, before);
+    crate::save_changes(&mut col, "trash-empty", count);
+    eprintln!("Deleted {} trash entries", count);
+}
+
+/// Parse ages like `90d`, `4w` or `1y`.
+///
+/// Ages reaching further back than dates can go are errors.
+fn parse_age(s: &str) -> Result<chrono::Duration, String> {
+    let err = || format!("Bad age {:?}, use eg. 90d, 4w or 1y", s);
+
+    let (i, _) = s.char_indices().last().ok_or_else(err)?;
+    let (n, unit) = s.split_at(i);
+    // The integer parser would accept a leading +.
+    if !n.chars().all(|c| c.is_ascii_digit()) {
+        return Err(err());
+    }
+    let n = i64::from(n.parse::<u32>().map_err(|_| err())?);
+    let age = match unit {
+        "d" => chrono::Duration::try_days(n),
+        "w" => chrono::Duration::try_weeks(n),
+        "y" => n.checked_mul(365).and_then(chrono::Duration::try_days),
+        _ => None,
+    }
+    .ok_or_else(err)?;
+    match chrono::Local::now().checked_sub_signed(age) {
+        Some(_) => Ok(age),
+        None => Err(err()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("90d"), Ok(chrono::Duration::days(90)));
+        assert_eq!(parse_age("4w"), Ok(chrono::Duration::weeks(4)));
+        assert_eq!(parse_age("1y"), Ok(chrono::Duration::days(365)));
+        assert!(parse_age("-1d").is_err());
+        assert!(parse_age("+1d").is_err());
+        assert!(parse_age("99999999999y").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("").is_err());
+        assert!(parse_age("1m").is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Interactive processing of the InBox.
+
+use std::collections::BTreeSet;
+
+use base::{Collection, Section};
+use dialoguer::{console::Term, Confirm, Input};
+
+use crate::OrDie;
+
+/// Things that can be done to an InBox item.
+enum Action {
+    Move(String),
+    Tag(Vec<String>),
+    Delete,
+    Skip,
+    Quit,
+}
+
+pub fn triage() {
+    let mut col = Collection::load().or_die();
+    let inbox = col.find_or_create(&base::Config::get().inbox).or_die();
+
+    let items: Vec<Section> = inbox.children().collect();
+    if items.is_empty() {
+        eprintln!("InBox is empty");
+        return;
+    }
+
+    crate::hooks::pre(&col, "triage");
+
+    let term = Term::stdout();
+    let mut changes = 0;
+
+    'items: for (i, item) in items.iter().enumerate() {
+        loop {
+            println!("\n[{}/{}]", i + 1, items.len());
+            print!("{}", idm::to_string(item).or_die());
+            println!("[m]ove  [t]ag  [r]ead later  [d]elete to trash  [s]kip  [q]uit");
+
+            match ask(&term) {
+                Action::Move(path) => {
+                    col.find_or_create(&path).or_die().append(item.clone());
+                    changes += 1;
+                    continue 'items;
+                }
+                Action::Tag(tags) => {
+                    add_tags(item, tags);
+                    changes += 1;
+                    // Keep showing the same item, tagging usually goes
+                    // together with another action.
+                }
+                Action::Delete => {
+                    base::trash::move_to_trash(&mut col, item).or_die();
+                    changes += 1;
+                    continue 'items;
+                }
+                Action::Skip => continue 'items,
+                Action::Quit => break 'items,
+            }
+        }
+    }
+
+    if changes == 0 {
+        return;
+    }
+
+    if Confirm::new()
+        .with_prompt(format!("Save {} changes?", changes))
+        .default(true)
+        .interact()
+        .or_die()
+    {
+        crate::save_changes(&mut col, "triage", changes);
+    }
+}
+
+fn ask(term: &Term) -> Action {
+    loop {
+        match term.read_char().or_die() {
+            'm' => {
+                let path: String = Input::new()
+                    .with_prompt("Move under")
+                    .interact_text()
+                    .or_die();
+                return Action::Move(path);
+            }
+            't' => {
+                let tags: String =
+                    Input::new().with_prompt("Tags").interact_text().or_die();
+                return Action::Tag(
+                    tags.split_whitespace().map(|s| s.to_string()).collect(),
+                );
+            }
+            'r' => return Action::Move("ToRead".into()),
+            'd' => return Action::Delete,
+            's' | ' ' => return Action::Skip,
+            'q' => return Action::Quit,
+            _ => {}
+        }
+    }
+}
+
+fn add_tags(item: &Section, new_tags: Vec<String>) {
+    let mut tags: BTreeSet<String> =
+        item.attr("tags").ok().flatten().unwrap_or_default();
+    tags.extend(new_tags);
+    item.clone().set_attr("tags", &tags).or_die();
+}
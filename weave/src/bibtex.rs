@@ -0,0 +1,169 @@
+//! BibTeX export
+//!
+//! Sections become `@book` entries if their uri is an ISBN, `@article`
+//! entries if they have a journal and `@misc` entries otherwise. The
+//! `cite-key` attribute is used as the citation key, sections without one
+//! get a key from the first author's last name, the year and the first
+//! word of the title.
+
+use std::{collections::BTreeSet, fmt::Write};
+
+use base::Section;
+
+/// Write sections as a BibTeX bibliography.
+pub fn bibtex(sections: &[Section]) -> String {
+    let mut ret = String::new();
+    let mut keys = BTreeSet::new();
+    for section in sections {
+        let attr = |name: &str| {
+            section
+                .borrow()
+                .attributes
+                .get(name)
+                .filter(|v| !v.is_empty())
+                .cloned()
+        };
+
+        let title = attr("title").unwrap_or_else(|| section.title());
+        let uri = section.uri();
+        let kind = if uri.as_ref().is_some_and(|u| u.starts_with("isbn:")) {
+            "book"
+        } else if attr("journal").is_some() {
+            "article"
+        } else {
+            "misc"
+        };
+
+        let key = attr("cite-key").unwrap_or_else(|| {
+            make_key(attr("author").as_deref(), attr("published"), &title)
+        });
+        // Keys must be unique within a bibliography.
+        let mut unique = key.clone();
+        let mut n = b'a';
+        while keys.contains(&unique) && n <= b'z' {
+            unique = format!("{}{}", key, n as char);
+            n += 1;
+        }
+        keys.insert(unique.clone());
+
+        let mut fields = vec![("title", title)];
+        if let Some(author) = attr("author") {
+            let authors: Vec<&str> = author.split(", ").collect();
+            fields.push(("author", authors.join(" and ")));
+        }
+        if let Some(year) = attr("published") {
+            // Published dates can be full dates, BibTeX only wants years.
+            fields.push(("year", year.chars().take(4).collect()));
+        }
+        for name in ["publisher", "journal"] {
+            if let Some(value) = attr(name) {
+                fields.push((name, value));
+            }
+        }
+        match uri {
+            Some(uri) if uri.starts_with("isbn:") => {
+                fields.push(("isbn", uri["isbn:".len()..].to_string()))
+            }
+            Some(uri) if uri.starts_with("https://doi.org/") => {
+                fields.push(("doi", uri["https://doi.org/".len()..].into()))
+            }
+            Some(uri) => fields.push(("url", uri)),
+            None => {}
+        }
+        if let Some(tags) = attr("tags") {
+            let tags: Vec<&str> = tags.split_whitespace().collect();
+            fields.push(("keywords", tags.join(", ")));
+        }
+
+        let _ = writeln!(ret, "@{}{{{},", kind, unique);
+        for (name, value) in fields {
+            // URLs are read verbatim, escaping would break them.
+            let value = if name == "url" { value } else { escape(&value) };
+            let _ = writeln!(ret, "  {} = {{{}}},", name, value);
+        }
+        ret.push_str("}\n\n");
+    }
+
+    let len = ret.trim_end().len();
+    ret.truncate(len);
+    if !ret.is_empty() {
+        ret.push('\n');
+    }
+    ret
+}
+
+fn make_key(author: Option<&str>, year: Option<String>, title: &str) -> String {
+    let last_name = author
+        .and_then(|a| a.split(", ").next())
+        .and_then(|a| a.split_whitespace().last())
+        .unwrap_or_default();
+    let word = title
+        .split_whitespace()
+        .find(|w| w.len() > 3)
+        .or_else(|| title.split_whitespace().next())
+        .unwrap_or_default();
+    let year: String = year.unwrap_or_default().chars().take(4).collect();
+    format!("{}{}{}", last_name, year, word)
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Escape characters that are special in TeX.
+fn escape(s: &str) -> String {
+    let mut ret = String::new();
+    for c in s.chars() {
+        if "&%$#_{}".contains(c) {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bibtex() {
+        let sections: Vec<Section> = idm::from_str(
+            "\
+Structure and Interpretation of Computer Programs
+  :uri isbn:9780262510875
+  :author Harold Abelson, Gerald Jay Sussman
+  :published 1996
+  :tags lisp computer-science
+Literate Programming
+  :uri https://doi.org/10.1093/comjnl/27.2.97
+  :author Donald E. Knuth
+  :published 1984-05
+  :journal The Computer Journal
+  :cite-key knuth84
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            bibtex(&sections),
+            "\
+@book{abelson1996structure,
+  title = {Structure and Interpretation of Computer Programs},
+  author = {Harold Abelson and Gerald Jay Sussman},
+  year = {1996},
+  isbn = {9780262510875},
+  keywords = {lisp, computer-science},
+}
+
+@article{knuth84,
+  title = {Literate Programming},
+  author = {Donald E. Knuth},
+  year = {1984},
+  journal = {The Computer Journal},
+  doi = {10.1093/comjnl/27.2.97},
+}
+"
+        );
+    }
+}
@@ -0,0 +1,299 @@
+//! Gemtext export for publishing in Geminispace
+//!
+//! Gemtext has no inline links, so the WikiWords and URLs of each block of
+//! text are listed as link lines after the block. WikiWords only get a link
+//! if their article has a page. Gemtext has three header levels and no
+//! nested lists, deeper sections are flat list items.
+
+use std::fmt::Write;
+
+use base::{
+    parse::{self, only},
+    publish::{Page, Site, INDEX_NAME},
+    Section,
+};
+
+use crate::walk::{self, Emitter};
+
+/// Deepest gemtext header level.
+const MAX_HEADER: usize = 3;
+
+/// Write the page of an article as gemtext.
+pub fn page(page: &Page, site: &Site) -> String {
+    let mut output = Output::new(site);
+    let article = &page.article;
+
+    output.line(&format!("# {}", article.title()));
+    output.blank();
+    output.attributes(article);
+    output.text(&article.body());
+    walk::children(article, 2, &mut output);
+
+    if !page.tags.is_empty() {
+        let tags: Vec<&str> = page.tags.iter().map(|t| t.as_str()).collect();
+        output.line(&format!("Tags: {}", tags.join(" ")));
+        output.blank();
+    }
+    output.flush_links();
+    output.line(&format!("=> {}.gmi Index", INDEX_NAME));
+    output.finish()
+}
+
+/// Write the index page listing the articles of each file.
+pub fn index(site: &Site) -> String {
+    let mut output = Output::new(site);
+    output.line("# Index");
+    for (path, titles) in &site.files {
+        let pages: Vec<&Page> =
+            titles.iter().filter_map(|t| site.page(t)).collect();
+        if pages.is_empty() {
+            continue;
+        }
+        output.blank();
+        output.line(&format!("## {}", path));
+        output.blank();
+        for page in pages {
+            output.line(&format!(
+                "=> {}.gmi {}",
+                link_path(&page.name),
+                page.article.title()
+            ));
+        }
+    }
+    output.finish()
+}
+
+struct Output<'a> {
+    site: &'a Site,
+    text: String,
+    /// Links from the current block of text.
+    links: Vec<(String, String)>,
+}
+
+impl<'a> Output<'a> {
+    fn new(site: &'a Site) -> Self {
+        Output {
+            site,
+            text: String::new(),
+            links: Vec::new(),
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        let _ = writeln!(self.text, "{}", line);
+    }
+
+    /// Write a line of text, keeping text that looks like gemtext markup
+    /// from being read as markup.
+    fn text_line(&mut self, line: &str) {
+        self.find_links(line);
+        if line.starts_with(['#', '>', '*']) || line.starts_with("=>") {
+            self.line(&format!(" {}", line));
+        } else {
+            self.line(line);
+        }
+    }
+
+    fn blank(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with("\n\n") {
+            self.text.push('\n');
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        for line in text.lines() {
+            self.text_line(line);
+        }
+        self.blank();
+        self.flush_links();
+    }
+
+    fn attributes(&mut self, section: &Section) {
+        let attributes = section.borrow().attributes.clone();
+        if attributes.is_empty() {
+            return;
+        }
+        for (name, value) in &attributes {
+            if name == "uri" {
+                self.line(&format!("=> {} {}", value, value));
+            } else {
+                // Gemtext lines can't be continued.
+                let value = value.replace('\n', " ");
+                self.find_links(&value);
+                self.line(&format!("* {}: {}", name, value));
+            }
+        }
+        self.blank();
+        self.flush_links();
+    }
+
+    /// Write a section and its contents as flat list items.
+    fn write_list_item(&mut self, section: &Section) {
+        let title = section.title();
+        self.find_links(&title);
+        self.line(&format!("* {}", title));
+        for (name, value) in &section.borrow().attributes {
+            let value = value.replace('\n', " ");
+            self.find_links(&value);
+            self.line(&format!("* {}: {}", name, value));
+        }
+        for line in section.body().lines() {
+            self.find_links(line);
+            self.line(&format!("* {}", line));
+        }
+        for child in section.children() {
+            if !walk::is_blank(&child) {
+                self.write_list_item(&child);
+            }
+        }
+    }
+
+    /// Collect links to published articles and URLs in text.
+    fn find_links(&mut self, text: &str) {
+        for word in text.split_whitespace() {
+            let word = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+            let url = word.trim_end_matches(|c: char| {
+                matches!(
+                    c,
+                    '.' | ',' | ';' | ':' | '!' | '?' | ')' | '\'' | '"'
+                )
+            });
+            let link = if only(parse::url)(url).is_ok() {
+                (url.to_string(), url.to_string())
+            } else if let Ok((_, wiki_word)) = parse::wiki_word(word) {
+                let Some(page) = self.site.page(wiki_word) else {
+                    continue;
+                };
+                (format!("{}.gmi", link_path(&page.name)), wiki_word.into())
+            } else {
+                continue;
+            };
+            if !self.links.contains(&link) {
+                self.links.push(link);
+            }
+        }
+    }
+
+    fn flush_links(&mut self) {
+        if self.links.is_empty() {
+            return;
+        }
+        for (target, text) in std::mem::take(&mut self.links) {
+            self.line(&format!("=> {} {}", target, text));
+        }
+        self.blank();
+    }
+
+    fn finish(mut self) -> String {
+        self.flush_links();
+        let len = self.text.trim_end().len();
+        self.text.truncate(len);
+        self.text.push('\n');
+        self.text
+    }
+}
+
+impl Emitter for Output<'_> {
+    fn is_list_item(&self, section: &Section, depth: usize) -> bool {
+        depth > MAX_HEADER
+            || section.child().is_none() && section.body().is_empty()
+    }
+
+    fn header(&mut self, section: &Section, depth: usize) {
+        self.flush_links();
+        self.line(&format!("{} {}", "#".repeat(depth), section.title()));
+        self.blank();
+        self.attributes(section);
+        self.text(&section.body());
+    }
+
+    fn list_item(&mut self, section: &Section) {
+        self.write_list_item(section);
+    }
+
+    fn end_list(&mut self) {
+        self.blank();
+        self.flush_links();
+    }
+
+    fn code_line(&mut self, line: &str) {
+        // Alt text after the fence is allowed in gemtext.
+        self.line(line);
+    }
+
+    fn end_code(&mut self) {
+        self.line("```");
+        self.blank();
+    }
+}
+
+/// Escape a page name for a relative link.
+fn link_path(name: &str) -> String {
+    name.replace('%', "%25").replace(' ', "%20")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gemini() {
+        let articles: Vec<Section> = idm::from_str(
+            "\
+TravelPlans
+  :uri https://example.com/trip
+  :tags trip
+  Pack
+    Passport
+  Script
+    ```sh
+    ls
+    ```
+  See PackingList and OtherPlace
+PackingList
+  Socks
+SecretPlans
+  :tags private
+  Nothing here
+",
+        )
+        .unwrap();
+        let root = Section::new(String::new(), Default::default());
+        for a in articles {
+            root.append(a);
+        }
+        let site = Site::from_files(vec![("trips.otl".into(), root)]);
+
+        assert!(site.page("SecretPlans").is_none());
+        assert_eq!(
+            page(site.page("TravelPlans").unwrap(), &site),
+            "\
+# TravelPlans
+
+=> https://example.com/trip https://example.com/trip
+* tags: trip
+
+## Pack
+
+* Passport
+
+## Script
+
+```sh
+ls
+```
+
+* See PackingList and OtherPlace
+
+=> PackingList.gmi PackingList
+
+Tags: trip
+
+=> index.gmi Index
+"
+        );
+    }
+}
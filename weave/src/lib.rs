@@ -1 +1,76 @@
+//! Formatting otlbook articles into documents for other programs.
 
+use std::{fmt, str::FromStr};
+
+use base::Section;
+
+pub mod bibtex;
+pub mod gemini;
+pub mod markdown;
+pub mod mindmap;
+pub mod opml;
+pub mod tiddlywiki;
+mod walk;
+
+/// Document formats that articles can be exported to.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Format {
+    TiddlyWiki,
+    FreeMind,
+    OpmlMindmap,
+}
+
+impl Format {
+    /// Return whether the format is a mind map of a single section instead
+    /// of a collection of articles.
+    pub fn is_mindmap(self) -> bool {
+        matches!(self, Format::FreeMind | Format::OpmlMindmap)
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tiddlywiki" => Ok(Format::TiddlyWiki),
+            "freemind" => Ok(Format::FreeMind),
+            "opml-mindmap" => Ok(Format::OpmlMindmap),
+            _ => Err(format!("Unknown export format {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::TiddlyWiki => write!(f, "tiddlywiki"),
+            Format::FreeMind => write!(f, "freemind"),
+            Format::OpmlMindmap => write!(f, "opml-mindmap"),
+        }
+    }
+}
+
+/// Collect the articles in a section.
+///
+/// An article section is its own only article, for other sections the
+/// topmost articles under them are collected. A section without any
+/// articles is exported as is.
+pub fn articles(section: &Section) -> Vec<Section> {
+    fn collect(section: &Section, output: &mut Vec<Section>) {
+        if section.is_article() {
+            output.push(section.clone());
+        } else {
+            for child in section.children() {
+                collect(&child, output);
+            }
+        }
+    }
+
+    let mut ret = Vec::new();
+    collect(section, &mut ret);
+    if ret.is_empty() {
+        ret.push(section.clone());
+    }
+    ret
+}
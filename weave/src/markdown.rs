@@ -0,0 +1,218 @@
+//! Markdown export
+//!
+//! The exported section becomes a document whose title is the section
+//! title and whose attributes are YAML front matter. Sections with children
+//! become headers by depth and leaf sections become list items. Code
+//! between two ```` ``` ```` headlines and under a ```` ``` ```` headline is
+//! written as is.
+
+use std::fmt::Write;
+
+use base::Section;
+
+use crate::walk::{self, Emitter};
+
+/// Deepest Markdown header level, sections below this are nested lists.
+const MAX_HEADER: usize = 6;
+
+/// Write a section and its children as a Markdown document.
+///
+/// Articles inside the section are only written as their headline unless
+/// `recursive` is set.
+pub fn markdown(section: &Section, recursive: bool) -> String {
+    let mut output = Markdown {
+        text: String::new(),
+        recursive,
+    };
+
+    let attributes = section.borrow().attributes.clone();
+    if !attributes.is_empty() {
+        output.text.push_str("---\n");
+        for (name, value) in &attributes {
+            let _ = writeln!(output.text, "{}: {}", name, yaml_value(value));
+        }
+        output.text.push_str("---\n\n");
+    }
+    let _ = writeln!(output.text, "# {}\n", escape(&section.title()));
+    output.write_text(&section.body());
+    walk::children(section, 2, &mut output);
+
+    // Leave just one newline at the end.
+    let mut ret = output.text;
+    let len = ret.trim_end().len();
+    ret.truncate(len);
+    ret.push('\n');
+    ret
+}
+
+struct Markdown {
+    text: String,
+    recursive: bool,
+}
+
+impl Emitter for Markdown {
+    fn is_list_item(&self, section: &Section, depth: usize) -> bool {
+        depth > MAX_HEADER
+            || section.child().is_none() && section.body().is_empty()
+            || self.is_cut(section)
+    }
+
+    fn header(&mut self, section: &Section, depth: usize) {
+        let _ = writeln!(
+            self.text,
+            "{} {}\n",
+            "#".repeat(depth),
+            escape(&section.title())
+        );
+        if section.has_attributes() {
+            self.write_attributes(section, "");
+            self.text.push('\n');
+        }
+        self.write_text(&section.body());
+    }
+
+    fn list_item(&mut self, section: &Section) {
+        self.write_list_item(section, 0);
+    }
+
+    fn end_list(&mut self) {
+        // Blank line ends the list.
+        self.text.push('\n');
+    }
+
+    fn code_line(&mut self, line: &str) {
+        let _ = writeln!(self.text, "{}", line);
+    }
+
+    fn end_code(&mut self) {
+        self.text.push_str("```\n\n");
+    }
+}
+
+impl Markdown {
+    /// Return whether a section is an article that is only written as its
+    /// headline.
+    fn is_cut(&self, section: &Section) -> bool {
+        !self.recursive && section.is_article()
+    }
+
+    /// Write a section as a list item with its contents as a nested list.
+    ///
+    /// An article that isn't written out is a link to its URI if it has
+    /// one.
+    fn write_list_item(&mut self, section: &Section, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let title = escape(&section.title());
+        let is_cut = self.is_cut(section);
+        match section.uri() {
+            Some(uri) if is_cut => {
+                let _ = writeln!(self.text, "{}- [{}]({})", indent, title, uri);
+            }
+            _ => {
+                let _ = writeln!(self.text, "{}- {}", indent, title);
+            }
+        }
+        if is_cut {
+            return;
+        }
+
+        let inner = "  ".repeat(depth + 1);
+        self.write_attributes(section, &inner);
+        for line in section.body().lines() {
+            let _ = writeln!(self.text, "{}{}", inner, line);
+        }
+        for child in section.children() {
+            if !walk::is_blank(&child) {
+                self.write_list_item(&child, depth + 1);
+            }
+        }
+    }
+
+    /// Write attributes as list items.
+    fn write_attributes(&mut self, section: &Section, indent: &str) {
+        for (name, value) in &section.borrow().attributes {
+            // Continue multi-line values with hard line breaks.
+            let value = value.replace('\n', &format!("  \n{}  ", indent));
+            let _ = writeln!(self.text, "{}- *{}:* {}", indent, name, value);
+        }
+    }
+
+    /// Write body text as paragraphs.
+    fn write_text(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.text.push_str(text);
+            self.text.push_str("\n\n");
+        }
+    }
+}
+
+/// Escape text that would start a Markdown block structure.
+fn escape(s: &str) -> String {
+    if s.starts_with(['#', '>', '-', '+', '*', '=']) {
+        format!("\\{}", s)
+    } else {
+        s.to_string()
+    }
+}
+
+/// Quote a front matter value if it isn't a plain YAML string.
+fn yaml_value(value: &str) -> String {
+    let is_plain = value.starts_with(|c: char| c.is_alphanumeric())
+        && !value.contains([':', '#', '"', '\'', '\n']);
+    if is_plain {
+        value.to_string()
+    } else {
+        // JSON strings are YAML strings.
+        serde_json::to_string(value).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown() {
+        let section: Section = idm::from_str(
+            "\
+TravelPlans
+  :tags trip summer
+  Pack
+    Passport
+      :note expires soon
+  Script
+    ```sh
+    ls
+      -l
+    ```
+  See PackingList
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            markdown(&section, false),
+            "\
+---
+tags: trip summer
+---
+
+# TravelPlans
+
+## Pack
+
+- Passport
+  - *note:* expires soon
+
+## Script
+
+```sh
+ls
+    -l
+```
+
+- See PackingList
+"
+        );
+    }
+}
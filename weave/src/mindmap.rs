@@ -0,0 +1,188 @@
+//! Mind map export
+//
+// FreeMind: http://freemind.sourceforge.net/wiki/index.php/File_format
+//
+// Most other mind map programs, like Freeplane, XMind and MindNode, open
+// either FreeMind files or OPML outlines.
+
+use std::fmt::Write;
+
+use base::Section;
+
+/// Nodes this deep or deeper start out folded in FreeMind so big outlines
+/// are readable when first opened.
+const FOLD_DEPTH: usize = 2;
+
+/// Write a section and its children as a FreeMind `.mm` file.
+///
+/// Section URIs become node links and other attributes become node
+/// attributes.
+pub fn freemind(section: &Section) -> String {
+    fn write_node(section: &Section, depth: usize, output: &mut String) {
+        let indent = " ".repeat(depth);
+        let _ = write!(
+            output,
+            "{}<node TEXT=\"{}\"",
+            indent,
+            escape(&section.title())
+        );
+        if let Some(uri) = section.uri() {
+            let _ = write!(output, " LINK=\"{}\"", escape(&uri));
+        }
+        let children: Vec<Section> = children(section).collect();
+        if depth >= FOLD_DEPTH && !children.is_empty() {
+            output.push_str(" FOLDED=\"true\"");
+        }
+        output.push_str(">\n");
+
+        if section.is_important() {
+            let _ = writeln!(
+                output,
+                "{} <font BOLD=\"true\" NAME=\"SansSerif\" SIZE=\"12\"/>",
+                indent
+            );
+        }
+        for (name, value) in &section.borrow().attributes {
+            if name == "uri" {
+                continue;
+            }
+            let _ = writeln!(
+                output,
+                "{} <attribute NAME=\"{}\" VALUE=\"{}\"/>",
+                indent,
+                escape(name),
+                escape(value)
+            );
+        }
+        for child in children {
+            write_node(&child, depth + 1, output);
+        }
+        let _ = writeln!(output, "{}</node>", indent);
+    }
+
+    let mut ret = String::from("<map version=\"1.0.1\">\n");
+    write_node(section, 1, &mut ret);
+    ret.push_str("</map>\n");
+    ret
+}
+
+/// Write a section and its children as an OPML outline.
+///
+/// Attributes are written into the `_note` field of the outline items, the
+/// way Workflowy and Dynalist keep notes.
+pub fn opml(section: &Section) -> String {
+    fn write_outline(section: &Section, depth: usize, output: &mut String) {
+        let indent = "  ".repeat(depth);
+        let _ = write!(
+            output,
+            "{}<outline text=\"{}\"",
+            indent,
+            escape(&section.title())
+        );
+        let note: Vec<String> = section
+            .borrow()
+            .attributes
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect();
+        if !note.is_empty() {
+            let _ = write!(output, " _note=\"{}\"", escape(&note.join("\n")));
+        }
+
+        let children: Vec<Section> = children(section).collect();
+        if children.is_empty() {
+            output.push_str("/>\n");
+            return;
+        }
+        output.push_str(">\n");
+        for child in children {
+            write_outline(&child, depth + 1, output);
+        }
+        let _ = writeln!(output, "{}</outline>", indent);
+    }
+
+    let mut ret = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    ret.push_str("<opml version=\"2.0\">\n");
+    let _ = writeln!(
+        ret,
+        "  <head><title>{}</title></head>",
+        escape(&section.title())
+    );
+    ret.push_str("  <body>\n");
+    write_outline(section, 2, &mut ret);
+    ret.push_str("  </body>\n</opml>\n");
+    ret
+}
+
+/// Children that aren't blank lines.
+pub(crate) fn children(section: &Section) -> impl Iterator<Item = Section> {
+    section
+        .children()
+        .filter(|c| !c.headline().trim().is_empty() || c.child().is_some())
+}
+
+/// Escape text for an XML attribute value.
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mindmap() {
+        let section: Section = idm::from_str(
+            "\
+Reading
+  :tags books
+  Fiction *
+    Dune
+      :uri https://example.com/dune
+
+    A & B
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            freemind(&section),
+            "\
+<map version=\"1.0.1\">
+ <node TEXT=\"Reading\">
+  <attribute NAME=\"tags\" VALUE=\"books\"/>
+  <node TEXT=\"Fiction\" FOLDED=\"true\">
+   <font BOLD=\"true\" NAME=\"SansSerif\" SIZE=\"12\"/>
+   <node TEXT=\"Dune\" LINK=\"https://example.com/dune\">
+   </node>
+   <node TEXT=\"A &amp; B\">
+   </node>
+  </node>
+ </node>
+</map>
+"
+        );
+
+        assert_eq!(
+            opml(&section),
+            "\
+<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<opml version=\"2.0\">
+  <head><title>Reading</title></head>
+  <body>
+    <outline text=\"Reading\" _note=\"tags: books\">
+      <outline text=\"Fiction\">
+        <outline text=\"Dune\" _note=\"uri: https://example.com/dune\"/>
+        <outline text=\"A &amp; B\"/>
+      </outline>
+    </outline>
+  </body>
+</opml>
+"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! OPML outline export
+//
+// Unlike the mind map OPML, this is meant to be read back. Headlines go in
+// `text`, body text in `_note` like Workflowy and Dynalist keep it, and
+// section attributes become outline attributes with an `otl-` prefix, so
+// that they can't clash with attributes other programs give a meaning,
+// like `type` or `url`. The `added` date is also written as `created` for
+// other outliners.
+
+use std::fmt::Write;
+
+use base::{Section, VagueDate};
+
+use crate::mindmap::{children, escape};
+
+/// Prefix of outline attributes that hold section attributes.
+const ATTR_PREFIX: &str = "otl-";
+
+/// Write sections and their children as an OPML document.
+pub fn opml(title: &str, sections: &[Section]) -> String {
+    fn write_outline(section: &Section, depth: usize, output: &mut String) {
+        let indent = "  ".repeat(depth);
+        let _ = write!(
+            output,
+            "{}<outline text=\"{}\"",
+            indent,
+            escape(&section.headline())
+        );
+        let body = section.body();
+        if !body.is_empty() {
+            let _ = write!(output, " _note=\"{}\"", escape(&body));
+        }
+        if let Some(added) = section.attr::<VagueDate>("added").ok().flatten() {
+            let _ = write!(
+                output,
+                " created=\"{}\"",
+                added.first_day().format("%a, %d %b %Y 00:00:00 +0000")
+            );
+        }
+        for (name, value) in &section.borrow().attributes {
+            let _ = write!(
+                output,
+                " {}{}=\"{}\"",
+                ATTR_PREFIX,
+                name,
+                escape(value)
+            );
+        }
+
+        let children: Vec<Section> = children(section).collect();
+        if children.is_empty() {
+            output.push_str("/>\n");
+            return;
+        }
+        output.push_str(">\n");
+        for child in children {
+            write_outline(&child, depth + 1, output);
+        }
+        let _ = writeln!(output, "{}</outline>", indent);
+    }
+
+    let mut ret = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    ret.push_str("<opml version=\"2.0\">\n");
+    let _ = writeln!(ret, "  <head><title>{}</title></head>", escape(title));
+    ret.push_str("  <body>\n");
+    for section in sections {
+        write_outline(section, 2, &mut ret);
+    }
+    ret.push_str("  </body>\n</opml>\n");
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opml() {
+        let section: Section = idm::from_str(
+            "\
+Reading
+  :tags books
+  : Things to read.
+  Dune
+    :uri https://example.com/dune
+    :added 2020-05-01
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            opml("Notes", &[section]),
+            "\
+<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<opml version=\"2.0\">
+  <head><title>Notes</title></head>
+  <body>
+    <outline text=\"Reading\" _note=\"Things to read.\" otl-tags=\"books\">
+      <outline text=\"Dune\" created=\"Fri, 01 May 2020 00:00:00 +0000\" \
+otl-uri=\"https://example.com/dune\" otl-added=\"2020-05-01\"/>
+    </outline>
+  </body>
+</opml>
+"
+        );
+    }
+}
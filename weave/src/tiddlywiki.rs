@@ -0,0 +1,146 @@
+//! TiddlyWiki export
+//
+// https://tiddlywiki.com/#TiddlerFields
+//
+// Every article becomes a tiddler. The outline body is written as a nested
+// wikitext list, TiddlyWiki links CamelCase words by itself so WikiWords
+// keep working. Attributes become tiddler fields.
+
+use base::{Section, VagueDate};
+use serde_json::{Map, Value};
+
+/// Class of the script elements that hold tiddlers in TiddlyWiki 5.2 and
+/// later single-file wikis.
+const STORE_CLASS: &str = "tiddlywiki-tiddler-store";
+
+/// Tiddler fields that attributes must not overwrite.
+const RESERVED_FIELDS: &[&str] = &["title", "text", "type"];
+
+/// Convert articles into a JSON array of tiddlers.
+///
+/// The JSON file can be imported into any TiddlyWiki by dragging it onto
+/// the wiki.
+pub fn tiddlers(articles: &[Section]) -> String {
+    let tiddlers: Vec<Value> = articles.iter().map(tiddler).collect();
+    serde_json::to_string_pretty(&tiddlers).expect("Shouldn't happen")
+}
+
+/// Add articles into a single-file TiddlyWiki.
+///
+/// The template should be an empty wiki downloaded from tiddlywiki.com, the
+/// result is a complete wiki that works in a web browser without any other
+/// files.
+pub fn single_file(
+    template: &str,
+    articles: &[Section],
+) -> Result<String, String> {
+    let open_tag = format!("<script class=\"{}\"", STORE_CLASS);
+    let start = template
+        .find(&open_tag)
+        .ok_or("Template is not a TiddlyWiki 5.2 or later single-file wiki")?;
+    let end = template[start..]
+        .find("</script>")
+        .map(|i| start + i + "</script>".len())
+        .ok_or("Unterminated tiddler store in template")?;
+
+    // Escape '<' like TiddlyWiki does so text can't close the script
+    // element.
+    let json = tiddlers(articles).replace('<', "\\u003C");
+    Ok(format!(
+        "{}\n{} type=\"application/json\">{}</script>{}",
+        &template[..end],
+        open_tag,
+        json,
+        &template[end..]
+    ))
+}
+
+fn tiddler(article: &Section) -> Value {
+    let mut fields = Map::new();
+    fields.insert("title".into(), article.title().into());
+
+    let mut text = article.body();
+    if !text.is_empty() {
+        text.push_str("\n\n");
+    }
+    write_list(article, 1, &mut text);
+    fields.insert("text".into(), text.into());
+    fields.insert("type".into(), "text/vnd.tiddlywiki".into());
+
+    for (name, value) in &article.borrow().attributes {
+        // Tags are symbols without spaces, so the attribute is a valid
+        // TiddlyWiki tag list as is.
+        if !RESERVED_FIELDS.contains(&name.as_str()) {
+            fields.insert(name.clone(), value.clone().into());
+        }
+    }
+
+    if let Some(added) = article.attr::<VagueDate>("added").ok().flatten() {
+        fields.insert("created".into(), timestamp(&added).into());
+    }
+
+    Value::Object(fields)
+}
+
+/// Write the children of a section as a wikitext list.
+fn write_list(section: &Section, depth: usize, output: &mut String) {
+    for child in section.children() {
+        let headline = child.headline();
+        if !headline.trim().is_empty() {
+            output.push_str(&"*".repeat(depth));
+            output.push(' ');
+            output.push_str(&headline);
+            output.push('\n');
+        }
+        for (name, value) in &child.borrow().attributes {
+            output.push_str(&"*".repeat(depth + 1));
+            output.push_str(&format!(" ''{}'' {}\n", name, value));
+        }
+        write_list(&child, depth + 1, output);
+    }
+}
+
+/// Format a date as a TiddlyWiki UTC timestamp.
+fn timestamp(date: &VagueDate) -> String {
+    date.first_day().format("%Y%m%d000000000").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiddlers() {
+        let article: Section = idm::from_str(
+            "\
+TravelPlans
+  :tags trip summer
+  :added 2020-05-01
+  Pack
+    Passport
+      :note expires soon
+  See PackingList
+",
+        )
+        .unwrap();
+
+        let json: Value = serde_json::from_str(&tiddlers(&[article])).unwrap();
+        assert_eq!(json[0]["title"], "TravelPlans");
+        assert_eq!(json[0]["tags"], "trip summer");
+        assert_eq!(json[0]["created"], "20200501000000000");
+        assert_eq!(
+            json[0]["text"],
+            "* Pack\n** Passport\n*** ''note'' expires soon\n* See PackingList\n"
+        );
+
+        let template =
+            "<html><body><script class=\"tiddlywiki-tiddler-store\" \
+            type=\"application/json\">[]</script><script>boot()</script>\
+            </body></html>";
+        let article: Section = idm::from_str("FooBar\n  a<b\n").unwrap();
+        let wiki = single_file(template, &[article]).unwrap();
+        assert!(wiki.contains("a\\u003Cb"));
+        assert!(wiki.find("a\\u003Cb").unwrap() < wiki.find("boot()").unwrap());
+        assert!(single_file("<html></html>", &[]).is_err());
+    }
+}
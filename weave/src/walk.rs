@@ -0,0 +1,93 @@
+//! Walking sections into text documents with headers, lists and code
+//!
+//! Markdown and gemtext exports lay out sections the same way: sections
+//! with contents become headers by depth, leaf sections become list items
+//! and code between two ```` ``` ```` headlines or under a ```` ``` ````
+//! headline is written as is. The formats only differ in how they write
+//! each of these.
+
+use base::Section;
+
+/// Writes the parts of a document in some format.
+pub(crate) trait Emitter {
+    /// Return whether a section at depth is written as a list item instead
+    /// of a header.
+    fn is_list_item(&self, section: &Section, depth: usize) -> bool;
+
+    /// Write the header of a section and its text, but not its children.
+    fn header(&mut self, section: &Section, depth: usize);
+
+    /// Write a section and all its contents as a list item.
+    fn list_item(&mut self, section: &Section);
+
+    /// Called after the last list item in a row of them.
+    fn end_list(&mut self);
+
+    /// Write a line of fenced code, including the opening fence.
+    fn code_line(&mut self, line: &str);
+
+    /// Close a fenced code block.
+    fn end_code(&mut self);
+}
+
+/// Write the children of a section at header depth.
+pub(crate) fn children(
+    section: &Section,
+    depth: usize,
+    emitter: &mut impl Emitter,
+) {
+    let mut children = section.children().peekable();
+    while let Some(child) = children.next() {
+        if is_blank(&child) {
+            continue;
+        }
+
+        let headline = child.headline();
+        if headline.starts_with("```") {
+            // Fenced code written as sibling headlines, or as the children
+            // of the opening fence without a closing one.
+            emitter.code_line(&headline);
+            code(&child, 0, emitter);
+            for line in children.by_ref() {
+                if line.headline().starts_with("```") {
+                    break;
+                }
+                emitter.code_line(&line.headline());
+                code(&line, 1, emitter);
+            }
+            emitter.end_code();
+            continue;
+        }
+
+        if emitter.is_list_item(&child, depth) {
+            emitter.list_item(&child);
+            if !children
+                .peek()
+                .is_some_and(|c| emitter.is_list_item(c, depth))
+            {
+                emitter.end_list();
+            }
+            continue;
+        }
+
+        emitter.header(&child, depth);
+        self::children(&child, depth + 1, emitter);
+    }
+}
+
+/// Return whether a section is an empty line that isn't written.
+pub(crate) fn is_blank(section: &Section) -> bool {
+    section.headline().trim().is_empty() && section.child().is_none()
+}
+
+/// Write the children of a code line with their indentation.
+fn code(section: &Section, depth: usize, emitter: &mut impl Emitter) {
+    for child in section.children() {
+        emitter.code_line(&format!(
+            "{}{}",
+            "    ".repeat(depth),
+            child.headline()
+        ));
+        code(&child, depth + 1, emitter);
+    }
+}
@@ -0,0 +1,255 @@
+use std::{fmt::Write, str::FromStr};
+
+use base::{quantity::Quantity, Section, VagueDate};
+use chrono::Datelike;
+
+use crate::html::escape;
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 300.0;
+
+/// Space left around the plot area for axis labels.
+const MARGIN: f64 = 50.0;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ChartKind {
+    Line,
+    Bar,
+}
+
+impl FromStr for ChartKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "line" => Ok(ChartKind::Line),
+            "bar" => Ok(ChartKind::Bar),
+            _ => Err(format!("Unknown chart kind {:?}", s)),
+        }
+    }
+}
+
+/// What to plot from a section.
+///
+/// Written as the value of a `chart` attribute, eg. `:chart date weight` or
+/// `:chart month distance bar`, to show the chart in the web view.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Chart {
+    pub x: String,
+    pub y: String,
+    pub kind: ChartKind,
+}
+
+impl FromStr for Chart {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let kind = match words.get(2) {
+            Some(kind) => kind.parse()?,
+            None => ChartKind::Line,
+        };
+        match words[..] {
+            [x, y] | [x, y, _] => Ok(Chart {
+                x: x.into(),
+                y: y.into(),
+                kind,
+            }),
+            _ => Err(format!("Bad chart {:?}, expected 'x y [line|bar]'", s)),
+        }
+    }
+}
+
+/// Read (x, y) pairs from the section's children.
+///
+/// The values are either attributes of the children, or columns of a table
+/// written as child lines like `| date | weight |`, where the first row has
+/// the column names.
+fn points(section: &Section, x: &str, y: &str) -> Vec<(String, String)> {
+    let rows: Vec<Vec<String>> = section
+        .children()
+        .filter_map(|c| {
+            let line = c.headline();
+            let line = line.trim().strip_prefix('|')?;
+            let line = line.strip_suffix('|').unwrap_or(line);
+            Some(line.split('|').map(|s| s.trim().to_string()).collect())
+        })
+        .collect();
+
+    if let Some((header, rows)) = rows.split_first() {
+        let col = |name| header.iter().position(|h| h == name);
+        if let (Some(x), Some(y)) = (col(x), col(y)) {
+            return rows
+                .iter()
+                .filter_map(|r| Some((r.get(x)?.clone(), r.get(y)?.clone())))
+                .collect();
+        }
+    }
+
+    section
+        .children()
+        .filter_map(|c| {
+            let c = c.borrow();
+            Some((c.attributes.get(x)?.clone(), c.attributes.get(y)?.clone()))
+        })
+        .collect()
+}
+
+/// Position of a x value on a numeric axis, if it has one.
+fn x_position(s: &str) -> Option<f64> {
+    if let Ok(x) = s.parse::<f64>() {
+        return Some(x);
+    }
+    let date = s.parse::<VagueDate>().ok()?;
+    Some(date.first_day().num_days_from_ce() as f64)
+}
+
+/// Plot values of children of a section as a SVG line or bar chart.
+///
+/// Y values are numbers or quantities in units that convert to each other.
+/// Line charts place x values that are all numbers or dates on a numeric
+/// axis, other x values are spaced evenly in the order they appear.
+pub fn chart(section: &Section, chart: &Chart) -> String {
+    let mut unit = None;
+    let mut data: Vec<(String, f64)> = Vec::new();
+    for (x, y) in points(section, &chart.x, &chart.y) {
+        let Ok(y) = y.parse::<Quantity>() else {
+            log::warn!("Bad {} value {:?} in chart", chart.y, y);
+            continue;
+        };
+        let unit = unit.get_or_insert_with(|| y.unit.clone());
+        match y.convert_to(unit) {
+            Some(y) => data.push((x, y.value)),
+            None => log::warn!("Can't convert {} to {} in chart", y, unit),
+        }
+    }
+
+    let mut ret = format!(
+        "<svg xmlns='http://www.w3.org/2000/svg' width='{WIDTH}' \
+         height='{HEIGHT}' font-family='sans-serif' font-size='12'>\n"
+    );
+    if data.is_empty() {
+        ret.push_str("</svg>\n");
+        return ret;
+    }
+
+    let positions: Option<Vec<f64>> = if chart.kind == ChartKind::Line {
+        data.iter().map(|(x, _)| x_position(x)).collect()
+    } else {
+        None
+    };
+    let xs = match positions {
+        Some(xs) => {
+            // Sort by position so the line doesn't double back.
+            let mut pairs: Vec<_> = xs.into_iter().zip(data).collect();
+            pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let xs = pairs.iter().map(|(x, _)| *x).collect();
+            data = pairs.into_iter().map(|(_, d)| d).collect();
+            xs
+        }
+        None => (0..data.len()).map(|i| i as f64).collect::<Vec<_>>(),
+    };
+
+    let (x_min, x_max) = (xs[0], xs[xs.len() - 1]);
+    // Bars start from zero, lines only need to show the range of values.
+    let (low, high) = match chart.kind {
+        ChartKind::Bar => (0.0, 0.0),
+        ChartKind::Line => (f64::INFINITY, f64::NEG_INFINITY),
+    };
+    let y_min = data.iter().map(|d| d.1).fold(low, f64::min);
+    let y_max = data.iter().map(|d| d.1).fold(high, f64::max);
+
+    let plot_w = WIDTH - 2.0 * MARGIN;
+    let plot_h = HEIGHT - 2.0 * MARGIN;
+    let px = |x: f64| match chart.kind {
+        ChartKind::Bar => MARGIN + (x + 0.5) * plot_w / data.len() as f64,
+        ChartKind::Line if x_max > x_min => {
+            MARGIN + (x - x_min) * plot_w / (x_max - x_min)
+        }
+        ChartKind::Line => MARGIN + plot_w / 2.0,
+    };
+    let py = |y: f64| {
+        if y_max > y_min {
+            HEIGHT - MARGIN - (y - y_min) * plot_h / (y_max - y_min)
+        } else {
+            HEIGHT - MARGIN
+        }
+    };
+    let fmt_y = |y: f64| Quantity::new(y, unit.clone().unwrap_or_default());
+
+    // Axes with the value range.
+    let _ = writeln!(
+        ret,
+        "<path d='M{m} {m} V{b} H{r}' fill='none' stroke='#888'/>\n\
+         <text x='{}' y='{m}' text-anchor='end'>{}</text>\n\
+         <text x='{}' y='{b}' text-anchor='end'>{}</text>",
+        MARGIN - 5.0,
+        fmt_y(y_max),
+        MARGIN - 5.0,
+        fmt_y(y_min),
+        m = MARGIN,
+        b = HEIGHT - MARGIN,
+        r = WIDTH - MARGIN,
+    );
+
+    match chart.kind {
+        ChartKind::Line => {
+            let line: Vec<String> = xs
+                .iter()
+                .zip(&data)
+                .map(|(x, (_, y))| format!("{:.1},{:.1}", px(*x), py(*y)))
+                .collect();
+            let _ = writeln!(
+                ret,
+                "<polyline points='{}' fill='none' stroke='#48a' \
+                 stroke-width='2'/>",
+                line.join(" ")
+            );
+            // Label the ends of the x axis.
+            let _ = writeln!(
+                ret,
+                "<text x='{}' y='{y}' text-anchor='start'>{}</text>\n\
+                 <text x='{}' y='{y}' text-anchor='end'>{}</text>",
+                px(xs[0]),
+                escape(&data[0].0),
+                px(xs[xs.len() - 1]),
+                escape(&data[data.len() - 1].0),
+                y = HEIGHT - MARGIN + 15.0,
+            );
+            for (x, (label, y)) in xs.iter().zip(&data) {
+                let _ = writeln!(
+                    ret,
+                    "<circle cx='{:.1}' cy='{:.1}' r='3' fill='#48a'>\
+                     <title>{}: {}</title></circle>",
+                    px(*x),
+                    py(*y),
+                    escape(label),
+                    fmt_y(*y)
+                );
+            }
+        }
+        ChartKind::Bar => {
+            let w = plot_w / data.len() as f64 * 0.8;
+            for (x, (label, y)) in xs.iter().zip(&data) {
+                let (top, bottom) = (py(y.max(0.0)), py(y.min(0.0)));
+                let _ = writeln!(
+                    ret,
+                    "<rect x='{:.1}' y='{top:.1}' width='{w:.1}' \
+                     height='{:.1}' fill='#8ab'>\
+                     <title>{}: {}</title></rect>\n\
+                     <text x='{:.1}' y='{}' text-anchor='middle'>{}</text>",
+                    px(*x) - w / 2.0,
+                    (bottom - top).max(1.0),
+                    escape(label),
+                    fmt_y(*y),
+                    px(*x),
+                    HEIGHT - MARGIN + 15.0,
+                    escape(label),
+                );
+            }
+        }
+    }
+
+    ret.push_str("</svg>\n");
+    ret
+}
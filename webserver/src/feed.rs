@@ -0,0 +1,186 @@
+use std::fmt::Write;
+
+use base::{Collection, Section, VagueDate};
+
+use crate::html::{escape, url_encode};
+
+/// Number of newest items in the feed.
+const FEED_LENGTH: usize = 50;
+
+/// Feed item, a section that was added or read at some date.
+struct Item {
+    section: Section,
+    date: VagueDate,
+    is_read: bool,
+}
+
+impl Item {
+    /// Date the item was read at, or added at if it hasn't been read.
+    fn new(section: Section) -> Option<Item> {
+        let date = |name| section.attr::<VagueDate>(name).ok().flatten();
+        let (date, is_read) = match (date("added"), date("read")) {
+            (_, Some(read)) => (read, true),
+            (Some(added), None) => (added, false),
+            (None, None) => return None,
+        };
+        Some(Item {
+            section,
+            date,
+            is_read,
+        })
+    }
+}
+
+/// Generate an Atom feed of the most recently added and read items.
+///
+/// Items without an URI link to the page of the article they're in on the
+/// server at `base_url`.
+pub fn atom(collection: &Collection, base_url: &str) -> String {
+    // Deleted items stay out of the feed.
+    feed(
+        collection
+            .files()
+            .filter(|(path, _)| !base::trash::is_trash(path))
+            .flat_map(|(_, root)| root.iter()),
+        base_url,
+    )
+}
+
+fn feed(sections: impl IntoIterator<Item = Section>, base_url: &str) -> String {
+    let mut items: Vec<Item> = sections
+        .into_iter()
+        .filter(|s| !s.title().is_empty())
+        .filter_map(Item::new)
+        .collect();
+    items.sort_by_key(|i| std::cmp::Reverse(i.date));
+    items.truncate(FEED_LENGTH);
+
+    let updated = items
+        .first()
+        .map(|i| timestamp(&i.date))
+        .unwrap_or_else(|| timestamp(&VagueDate::now()));
+
+    let mut ret = String::new();
+    let self_link = escape(&format!("{}/feed.xml", base_url));
+    let _ = write!(
+        ret,
+        "\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<feed xmlns=\"http://www.w3.org/2005/Atom\">
+  <title>otlbook</title>
+  <id>{self_link}</id>
+  <link rel=\"self\" href=\"{self_link}\"/>
+  <updated>{updated}</updated>
+  <author><name>otlbook</name></author>
+"
+    );
+    for item in items {
+        let title = item.section.title();
+        let (link, id) = links(&item.section, base_url);
+        let summary = if item.is_read { "Read" } else { "Added" };
+        let _ = write!(
+            ret,
+            "  <entry>
+    <title>{}</title>
+    <id>{}</id>
+    <link href=\"{}\"/>
+    <updated>{}</updated>
+    <summary>{} {}</summary>
+  </entry>
+",
+            escape(&title),
+            escape(&id),
+            escape(&link),
+            timestamp(&item.date),
+            summary,
+            item.date
+        );
+    }
+    ret.push_str("</feed>\n");
+    ret
+}
+
+/// Return the link and the unique id of a feed item.
+fn links(section: &Section, base_url: &str) -> (String, String) {
+    if let Some(uri) = section.uri() {
+        return (uri.clone(), uri);
+    }
+
+    let article = std::iter::successors(Some(section.clone()), |s| s.parent())
+        .find(|s| s.is_article())
+        .unwrap_or_else(|| section.clone());
+    let link = format!("{}/a/{}", base_url, url_encode(&article.title()));
+    if section.is_article() {
+        (link.clone(), link)
+    } else {
+        // Several items can be in the same article.
+        let id = format!("{}#{}", link, url_encode(&section.path()));
+        (link, id)
+    }
+}
+
+/// Format a date as an Atom timestamp, dates without time are at midnight
+/// UTC.
+fn timestamp(date: &VagueDate) -> String {
+    match date {
+        VagueDate::DateTime(dt) => dt.to_rfc3339(),
+        _ => date.first_day().format("%Y-%m-%dT00:00:00Z").to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feed() {
+        let root: Section = idm::from_str(
+            "\
+Reading
+  Dune
+    :uri isbn:9780441013593
+    :read 2024-01-02
+  GardenPlans
+    :added 2024-01-01
+    Buy seeds
+      :added 2024-01-03
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            feed(root.iter(), "http://localhost:8080"),
+            "\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<feed xmlns=\"http://www.w3.org/2005/Atom\">
+  <title>otlbook</title>
+  <id>http://localhost:8080/feed.xml</id>
+  <link rel=\"self\" href=\"http://localhost:8080/feed.xml\"/>
+  <updated>2024-01-03T00:00:00Z</updated>
+  <author><name>otlbook</name></author>
+  <entry>
+    <title>Buy seeds</title>
+    <id>http://localhost:8080/a/GardenPlans#Reading%2FGardenPlans%2FBuy%20seeds</id>
+    <link href=\"http://localhost:8080/a/GardenPlans\"/>
+    <updated>2024-01-03T00:00:00Z</updated>
+    <summary>Added 2024-01-03</summary>
+  </entry>
+  <entry>
+    <title>Dune</title>
+    <id>isbn:9780441013593</id>
+    <link href=\"isbn:9780441013593\"/>
+    <updated>2024-01-02T00:00:00Z</updated>
+    <summary>Read 2024-01-02</summary>
+  </entry>
+  <entry>
+    <title>GardenPlans</title>
+    <id>http://localhost:8080/a/GardenPlans</id>
+    <link href=\"http://localhost:8080/a/GardenPlans\"/>
+    <updated>2024-01-01T00:00:00Z</updated>
+    <summary>Added 2024-01-01</summary>
+  </entry>
+</feed>
+"
+        );
+    }
+}
@@ -1,6 +1,12 @@
-use std::{fmt, str::FromStr};
+use std::{collections::BTreeSet, fmt, str::FromStr};
 
-use base::{Section, Uri};
+use base::{
+    parse::{self, only},
+    Section, Uri,
+};
+use chrono::{DateTime, Local};
+
+use crate::chart::{chart, Chart};
 
 /// Display a value as HTML.
 pub trait HtmlFmt {
@@ -35,7 +41,19 @@ impl<T: HtmlFmt> HtmlFmt for Vec<T> {
 
 impl HtmlFmt for Section {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fn write(
+        HtmlFmt::fmt(&Folded(self.clone(), BTreeSet::new()), f)
+    }
+}
+
+/// Article with some of its sections folded.
+///
+/// Sections with children are shown as foldable and sections whose headline
+/// paths relative to the article are in the set start out folded.
+pub struct Folded(pub Section, pub BTreeSet<String>);
+
+impl HtmlFmt for Folded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write_headline(
             elt: &Section,
             tag: &str,
             f: &mut fmt::Formatter<'_>,
@@ -48,13 +66,20 @@ impl HtmlFmt for Section {
                 write!(f, "<strong>")?;
             }
 
-            write!(f, "{text}")?;
+            write!(f, "{}", linkify(&text))?;
 
             if is_important {
                 write!(f, "</strong>")?;
             }
-            writeln!(f, "</{tag}>")?;
+            writeln!(f, "</{tag}>")
+        }
 
+        fn write_body(
+            elt: &Section,
+            path: &str,
+            folded: &BTreeSet<String>,
+            f: &mut fmt::Formatter<'_>,
+        ) -> fmt::Result {
             // Print attributes
             {
                 let elt = elt.borrow();
@@ -73,9 +98,16 @@ impl HtmlFmt for Section {
                                     "<tr><td>{name}</td><td>{val}</td></tr>"
                                 )?;
                             }
+                            _ if val.contains('\n') => writeln!(
+                                f,
+                                "<tr><td>{name}</td>\
+                                 <td style=\"white-space: pre-wrap\">{}</td></tr>",
+                                linkify(val)
+                            )?,
                             _ => writeln!(
                                 f,
-                                "<tr><td>{name}</td><td>{val}</td></tr>"
+                                "<tr><td>{name}</td><td>{}</td></tr>",
+                                linkify(val)
                             )?,
                         }
                     }
@@ -83,19 +115,337 @@ impl HtmlFmt for Section {
                 }
             }
 
+            // Body text
+            let body = elt.body();
+            if !body.is_empty() {
+                writeln!(
+                    f,
+                    "<p style=\"white-space: pre-wrap\">{}</p>",
+                    linkify(&body)
+                )?;
+            }
+
+            // Chart directive
+            if let Some(spec) = elt.attr::<String>("chart").ok().flatten() {
+                match spec.parse::<Chart>() {
+                    Ok(c) => writeln!(f, "{}", chart(elt, &c))?,
+                    Err(e) => log::warn!("{}", e),
+                }
+            }
+
             writeln!(f, "<ul>")?;
             let mut child = elt.child();
             while let Some(ref node) = child {
                 write!(f, "<li>")?;
-                write(node, "div", f)?;
+                let path = if path.is_empty() {
+                    node.headline()
+                } else {
+                    format!("{}/{}", path, node.headline())
+                };
+
+                if node.child().is_some()
+                    || node.has_attributes()
+                    || !node.borrow().body.is_empty()
+                {
+                    let open =
+                        if folded.contains(&path) { "" } else { " open" };
+                    write!(
+                        f,
+                        "<details data-path='{}'{open}><summary>",
+                        escape(&path)
+                    )?;
+                    write_headline(node, "span", f)?;
+                    write!(f, "</summary>")?;
+                    write_body(node, &path, folded, f)?;
+                    write!(f, "</details>")?;
+                } else {
+                    write_headline(node, "div", f)?;
+                }
                 writeln!(f, "</li>")?;
                 child = node.sibling();
             }
             writeln!(f, "</ul>")
         }
 
-        write(self, "h1", f)
+        write_headline(&self.0, "h1", f)?;
+        write_body(&self.0, "", &self.1, f)
+    }
+}
+
+/// Article shown as slides, one for each top-level child.
+pub struct Slides(pub Section);
+
+impl HtmlFmt for Slides {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write_bullets(
+            elt: &Section,
+            f: &mut fmt::Formatter<'_>,
+        ) -> fmt::Result {
+            if elt.child().is_none() {
+                return Ok(());
+            }
+            writeln!(f, "<ul>")?;
+            for node in elt.children() {
+                write!(f, "<li>{}", linkify(&node.title()))?;
+                write_bullets(&node, f)?;
+                writeln!(f, "</li>")?;
+            }
+            writeln!(f, "</ul>")
+        }
+
+        for slide in self.0.children() {
+            writeln!(f, "<section class='slide'>")?;
+            writeln!(f, "<h1>{}</h1>", linkify(&slide.title()))?;
+            write_bullets(&slide, f)?;
+            writeln!(f, "</section>")?;
+        }
+        Ok(())
+    }
+}
+
+/// Article's children shown as a kanban board with a column for each
+/// `status` attribute value.
+pub struct Board(pub Section);
+
+/// Board columns that are shown even when empty. Children without a status
+/// go in the first one.
+pub const BOARD_COLUMNS: [&str; 3] = ["todo", "doing", "done"];
+
+impl HtmlFmt for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut columns: Vec<(String, Vec<Section>)> = BOARD_COLUMNS
+            .iter()
+            .map(|s| (s.to_string(), Vec::new()))
+            .collect();
+
+        for node in self.0.children() {
+            let status = node
+                .attr::<String>("status")
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| BOARD_COLUMNS[0].into());
+            match columns.iter_mut().find(|(s, _)| *s == status) {
+                Some((_, cards)) => cards.push(node),
+                None => columns.push((status, vec![node])),
+            }
+        }
+
+        writeln!(f, "<h1>{}</h1>", escape(&self.0.title()))?;
+        writeln!(f, "<div class='board'>")?;
+        for (status, cards) in columns {
+            writeln!(
+                f,
+                "<div class='column' data-status='{}'><h2>{}</h2>",
+                escape(&status),
+                escape(&status)
+            )?;
+            for card in cards {
+                writeln!(
+                    f,
+                    "<div class='card' draggable='true' data-path='{}'>{}</div>",
+                    escape(&format!(
+                        "{}/{}",
+                        self.0.headline(),
+                        card.headline()
+                    )),
+                    linkify(&card.title())
+                )?;
+            }
+            writeln!(f, "</div>")?;
+        }
+        writeln!(f, "</div>")
+    }
+}
+
+/// Articles found by a search, each with a snippet of the matching text.
+pub struct Results(pub Vec<(Section, String)>);
+
+impl HtmlFmt for Results {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return writeln!(f, "<p>No results.</p>");
+        }
+        writeln!(f, "<ul class='results'>")?;
+        for (article, snippet) in &self.0 {
+            write!(f, "<li>{}", article_link(article))?;
+            if !snippet.is_empty() {
+                write!(f, "<div class='snippet'>{}</div>", escape(snippet))?;
+            }
+            writeln!(f, "</li>")?;
+        }
+        writeln!(f, "</ul>")
+    }
+}
+
+/// Front page with the articles of each collection file and an
+/// alphabetical listing of all articles.
+///
+/// Files are given as their collection path, modification time and
+/// articles.
+pub struct ArticleIndex(
+    pub Vec<(String, Option<DateTime<Local>>, Vec<Section>)>,
+);
+
+impl HtmlFmt for ArticleIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut all: Vec<(&Section, &str)> = self
+            .0
+            .iter()
+            .flat_map(|(file, _, articles)| {
+                articles.iter().map(move |a| (a, file.as_str()))
+            })
+            .collect();
+        all.sort_by_cached_key(|(a, _)| a.title().to_lowercase());
+
+        writeln!(f, "<p>{} articles in {} files</p>", all.len(), self.0.len())?;
+
+        writeln!(f, "<h2>Files</h2>")?;
+        for (file, modified, articles) in &self.0 {
+            write!(
+                f,
+                "<details><summary>{} ({} articles",
+                escape(file),
+                articles.len()
+            )?;
+            if let Some(modified) = modified {
+                write!(f, ", modified {}", modified.format("%Y-%m-%d %H:%M"))?;
+            }
+            writeln!(f, ")</summary>")?;
+            writeln!(f, "<ul>")?;
+            for article in articles {
+                writeln!(f, "<li>{}</li>", article_link(article))?;
+            }
+            writeln!(f, "</ul></details>")?;
+        }
+
+        writeln!(f, "<h2>All articles</h2>")?;
+        writeln!(f, "<ul>")?;
+        for (article, file) in all {
+            writeln!(
+                f,
+                "<li>{} <span class='file'>{}</span></li>",
+                article_link(article),
+                escape(file)
+            )?;
+        }
+        writeln!(f, "</ul>")
+    }
+}
+
+/// Link to the page of an article.
+fn article_link(article: &Section) -> String {
+    let title = article.title();
+    format!(
+        "<a href='/a/{}'>{}</a>",
+        escape(&url_encode(&title)),
+        escape(&title)
+    )
+}
+
+/// Return the innermost article the section is in.
+pub(crate) fn article_of(section: &Section) -> Option<Section> {
+    let mut node = Some(section.clone());
+    while let Some(n) = node {
+        if n.is_article() {
+            return Some(n);
+        }
+        node = n.parent();
     }
+    None
+}
+
+/// Return the start of the text of a section for showing in search results.
+///
+/// For an article, the text is its body, for other sections it starts from
+/// the section's own headline.
+pub(crate) fn snippet(section: &Section) -> String {
+    const MAX_SNIPPET_LEN: usize = 200;
+
+    let mut lines = Vec::new();
+    if !section.is_article() {
+        lines.push(section.title());
+    }
+    lines.extend(
+        section
+            .iter()
+            .skip(1)
+            .map(|s| s.title())
+            .filter(|s| !s.trim().is_empty())
+            .take(10),
+    );
+    let text = lines.join(" / ");
+    match text.char_indices().nth(MAX_SNIPPET_LEN) {
+        Some((i, _)) => format!("{}...", &text[..i]),
+        None => text,
+    }
+}
+
+/// Percent-encode text for use in an URL path.
+pub(crate) fn url_encode(s: &str) -> String {
+    let mut ret = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            ret.push(b as char);
+        } else {
+            ret.push_str(&format!("%{:02X}", b));
+        }
+    }
+    ret
+}
+
+/// Escape text for use in a HTML or SVG attribute value or text.
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('\'', "&#39;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+/// Escape text and turn the WikiWords and URLs in it into links.
+pub(crate) fn linkify(text: &str) -> String {
+    let mut ret = String::new();
+    for word in text.split_inclusive(char::is_whitespace) {
+        // Leave out punctuation around the word, like in "(see FooBar)."
+        let start = word.find(char::is_alphanumeric).unwrap_or(word.len());
+        let (prefix, word) = word.split_at(start);
+        ret.push_str(&escape(prefix));
+
+        let url = word.trim_end().trim_end_matches(|c: char| {
+            matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | ')' | '\'' | '"')
+        });
+        if only(parse::url)(url).is_ok() {
+            let rest = &word[url.len()..];
+            let url = escape(url);
+            ret.push_str(&format!("<a href='{url}'>{url}</a>"));
+            ret.push_str(&escape(rest));
+        } else if let Ok((rest, wiki_word)) = parse::wiki_word(word) {
+            ret.push_str(&format!("<a href='/a/{wiki_word}'>{wiki_word}</a>"));
+            ret.push_str(&escape(rest));
+        } else {
+            ret.push_str(&ruby(word));
+        }
+    }
+    ret
+}
+
+/// Escape text and show its `漢字{かんじ}` annotations as ruby text.
+fn ruby(text: &str) -> String {
+    let segments = parse::ruby(text);
+    if segments.is_empty() {
+        return escape(text);
+    }
+    let mut ret = String::new();
+    for (text, reading) in segments {
+        match reading {
+            Some(reading) => ret.push_str(&format!(
+                "<ruby>{}<rt>{}</rt></ruby>",
+                escape(text),
+                escape(reading)
+            )),
+            None => ret.push_str(&escape(text)),
+        }
+    }
+    ret
 }
 
 impl HtmlFmt for Uri {
@@ -116,3 +466,25 @@ impl HtmlFmt for Uri {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_board_escape() {
+        let board: Section = idm::from_str(
+            "\
+<b>Plans</b>
+  <script>card</script>
+    :status <i>doing</i>
+",
+        )
+        .unwrap();
+        let html = Html(Board(board)).to_string();
+        assert!(!html.contains("<b>"));
+        assert!(!html.contains("<i>"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<h1>&lt;b>Plans&lt;/b></h1>"));
+    }
+}
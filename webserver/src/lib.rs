@@ -1,45 +1,921 @@
-use std::str::FromStr;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeSet, HashMap},
+    fs,
+    hash::{Hash, Hasher},
+    io::Read,
+    path::Path,
+    str::FromStr,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
 
-use crate::{html::Html, resolver::Command};
-use base::Collection;
+use crate::{
+    html::{
+        article_of, escape, snippet, url_encode, ArticleIndex, Board, Folded,
+        Html, Results, Slides,
+    },
+    resolver::Command,
+};
+use base::{
+    batch, fold::FoldState, journal::Journal, search::Index,
+    shared::SharedCollection, token::Tokens, Collection, Section,
+};
+use chrono::{DateTime, Local};
+use notify::{RecursiveMode, Watcher};
+use rand::Rng;
 use rouille::{Request, Response};
 
+mod chart;
+mod feed;
 mod html;
 mod resolver;
+mod site;
+mod timeline;
+
+pub use chart::{chart, Chart, ChartKind};
+pub use site::export_site;
+pub use timeline::timeline;
 
 const CSS: &str = include_str!("../../assets/style.css");
 
-pub fn run(port: u32, collection: Collection) -> ! {
+/// Script that reports fold changes back to the server.
+const FOLD_JS: &str = "
+document.querySelectorAll('details').forEach(d => d.addEventListener('toggle', () => {
+  const folded = [...document.querySelectorAll('details:not([open])')]
+    .map(e => e.dataset.path);
+  fetch('/fold' + location.pathname.replace(/^\\/a\\//, '/'),
+    {method: 'POST', body: folded.join('\\n')});
+}));
+";
+
+/// Style that shows one slide at a time filling the window.
+const SLIDES_CSS: &str = "
+.slide { display: none; min-height: 90vh; font-size: 2em; }
+.slide.current { display: block; }
+.slide h1 { margin-top: 1em; }
+";
+
+/// Script for moving between slides with the keyboard or by clicking.
+const SLIDES_JS: &str = "
+const slides = [...document.querySelectorAll('.slide')];
+let current = Math.max(0, Number(location.hash.slice(1)) || 0);
+function show(i) {
+  current = Math.min(Math.max(i, 0), slides.length - 1);
+  slides.forEach((s, j) => s.classList.toggle('current', j == current));
+  history.replaceState(null, '', '#' + current);
+}
+document.addEventListener('keydown', e => {
+  if (['ArrowRight', 'PageDown', ' '].includes(e.key)) show(current + 1);
+  if (['ArrowLeft', 'PageUp', 'Backspace'].includes(e.key)) show(current - 1);
+  if (e.key == 'Home') show(0);
+  if (e.key == 'End') show(slides.length - 1);
+});
+document.addEventListener('click', () => show(current + 1));
+show(current);
+";
+
+const BOARD_CSS: &str = "
+body { max-width: none; }
+.board { display: flex; gap: 1em; align-items: flex-start; }
+.column { flex: 1; min-height: 10em; padding: 0.5em; background: #eee; }
+.column h2 { text-align: center; }
+.card { margin: 0.5em 0; padding: 0.5em; background: white; cursor: move; }
+";
+
+/// Script for moving cards between columns by dragging.
+///
+/// A dropped card's new status is sent to the server as an attribute
+/// change.
+const BOARD_JS: &str = "
+let dragged = null;
+document.querySelectorAll('.card').forEach(c =>
+  c.addEventListener('dragstart', () => dragged = c));
+document.querySelectorAll('.column').forEach(col => {
+  col.addEventListener('dragover', e => e.preventDefault());
+  col.addEventListener('drop', e => {
+    e.preventDefault();
+    if (!dragged || dragged.parentNode === col) return;
+    col.appendChild(dragged);
+    const path = dragged.dataset.path.split('/')
+      .map(encodeURIComponent).join('/');
+    fetch('/attr/' + path + '?name=status',
+      {method: 'PATCH', body: col.dataset.status});
+  });
+});
+";
+
+/// Function that saves the collection after an edit, given the name of
+/// the command and the number of changes.
+///
+/// The olt binary passes one that runs the save hooks and commits to git
+/// like its own commands do.
+pub type SaveFn = fn(&mut Collection, &str, usize) -> base::Result<()>;
+
+/// Cookie that identifies the viewer for remembering fold state.
+const SESSION_COOKIE: &str = "otlbook-session";
+
+/// Return the viewer's session id and whether it is new.
+///
+/// Viewers without a session cookie get a new random id, which the
+/// response must set with `set_session`.
+fn session(request: &Request) -> (String, bool) {
+    if let Some((_, value)) = rouille::input::cookies(request)
+        .find(|(name, _)| *name == SESSION_COOKIE)
+    {
+        return (value.to_string(), false);
+    }
+    let bytes: [u8; 16] = rand::thread_rng().gen();
+    (bytes.iter().map(|b| format!("{:02x}", b)).collect(), true)
+}
+
+/// Make the browser remember a new session id.
+fn set_session(response: Response, session: &str) -> Response {
+    response.with_additional_header(
+        "Set-Cookie",
+        format!(
+            "{}={}; Path=/; HttpOnly; SameSite=Lax",
+            SESSION_COOKIE, session
+        ),
+    )
+}
+
+/// Render an article as a standalone HTML slideshow.
+///
+/// Each top-level child of the article is a slide with the child's headline
+/// as the title and the nested content as bullets.
+pub fn present(article: &Section) -> String {
+    format!(
+        "\
+<!DOCTYPE html>
+<html>
+<head>
+  <meta charset='utf-8'/>
+  <title>{}</title>
+  <style>
+{CSS}
+{SLIDES_CSS}
+  </style>
+</head>
+<body>
+{}
+<script>{SLIDES_JS}</script>
+</body>
+</html>",
+        escape(&article.title()),
+        Html(Slides(article.clone()))
+    )
+}
+
+/// Return a token that changes when the outline text of a section changes.
+fn text_version(section: &Section) -> String {
+    let mut hasher = DefaultHasher::new();
+    idm::to_string(section)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Render a form for editing the outline text of an article.
+fn edit_page(
+    title: &str,
+    text: &str,
+    version: &str,
+    error: Option<&str>,
+) -> String {
+    let error = error
+        .map(|e| format!("<p class='error'>{}</p>", escape(e)))
+        .unwrap_or_default();
+    format!(
+        "\
+<!DOCTYPE html>
+<html>
+<head>
+  <meta charset='utf-8'/>
+  <title>Edit {title}</title>
+  <style>
+{CSS}
+textarea {{ width: 100%; height: 80vh; font-family: monospace; }}
+.error {{ color: red; }}
+  </style>
+</head>
+<body>
+<h1>Edit {title}</h1>
+{error}
+<form method='post'>
+<textarea name='text'>{}</textarea>
+<input type='hidden' name='version' value='{}'/>
+<p><input type='submit' value='Save'/> <a href='/a/{}'>Cancel</a></p>
+</form>
+</body>
+</html>",
+        escape(text),
+        escape(version),
+        escape(&url_encode(title)),
+        title = escape(title)
+    )
+}
+
+/// Return the articles under a section in document order.
+fn articles(section: &Section) -> Vec<Section> {
+    let mut ret = Vec::new();
+    for child in section.children() {
+        if child.is_article() {
+            ret.push(child.clone());
+        }
+        ret.extend(articles(&child));
+    }
+    ret
+}
+
+/// Render a page of search results with a search box.
+fn results_page(
+    title: &str,
+    query: &str,
+    hits: Vec<(Section, String)>,
+) -> String {
+    format!(
+        "\
+<!DOCTYPE html>
+<html>
+<head>
+  <meta charset='utf-8'/>
+  <title>{title}</title>
+  <style>
+{CSS}
+.snippet {{ color: #666; font-size: 0.9em; }}
+  </style>
+</head>
+<body>
+<h1>{title}</h1>
+<form action='/search'><input name='q' value='{}'/></form>
+{}
+</body>
+</html>",
+        escape(query),
+        Html(Results(hits)),
+        title = escape(title)
+    )
+}
+
+/// Shared collection and an index of its sections by title.
+struct Notebook {
+    collection: SharedCollection,
+    /// First section with each title, in the order `Collection::iter`
+    /// visits them, and the collection version it was built from.
+    /// WikiWord titles are found by the word.
+    titles: Mutex<(u64, Arc<HashMap<String, Section>>)>,
+    /// Full-text search index, kept up to date by `watch`.
+    search: Arc<Mutex<Index>>,
+}
+
+impl Notebook {
+    fn new(collection: Collection) -> Notebook {
+        let mut index = Index::load(collection.root_path());
+        update_index(&mut index, collection.root_path());
+        Notebook {
+            collection: SharedCollection::new(collection),
+            titles: Mutex::new((u64::MAX, Default::default())),
+            search: Arc::new(Mutex::new(index)),
+        }
+    }
+
+    /// Return the title index, rebuilding it if the collection has changed.
+    ///
+    /// Call inside a `read` or `write` of the collection.
+    fn titles(&self, collection: &Collection) -> Arc<HashMap<String, Section>> {
+        let version = self.collection.version();
+        let mut titles = self.titles.lock().unwrap();
+        if titles.0 != version {
+            let mut index = HashMap::new();
+            for section in collection.iter() {
+                index.entry(section.title()).or_insert(section);
+            }
+            *titles = (version, Arc::new(index));
+        }
+        titles.1.clone()
+    }
+}
+
+/// Reindex changed files and save the search index if anything changed.
+fn update_index(index: &mut Index, root: &Path) {
+    match index.update(root) {
+        Ok(n) if n > 0 => {
+            if let Err(e) = index.save(root) {
+                log::warn!("Failed to save search index: {}", e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => log::warn!("Failed to update search index: {}", e),
+    }
+}
+
+/// Reload collection files and update the search index when files are
+/// changed outside the server.
+fn watch(collection: SharedCollection, search: Arc<Mutex<Index>>) {
+    let root = collection.read(|col| col.root_path().to_owned());
+    // Event paths are canonical, so the root must be too for them to be
+    // turned into collection paths.
+    let root = fs::canonicalize(&root).unwrap_or(root);
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(tx) {
+        Ok(w) => w,
+        Err(e) => {
+            log::warn!("Can't watch collection for changes: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = watcher.watch(&root, RecursiveMode::Recursive) {
+        log::warn!("Can't watch collection for changes: {}", e);
+        return;
+    }
+
+    thread::spawn(move || {
+        // Keep the watcher alive as long as events are being handled.
+        let _watcher = watcher;
+        for event in rx {
+            let Ok(event) = event else {
+                continue;
+            };
+            let is_otl = event.paths.iter().any(|path| {
+                let Ok(path) = path.strip_prefix(&root) else {
+                    return false;
+                };
+                // Skip hidden directories like .git like when loading.
+                let is_hidden = path
+                    .components()
+                    .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+                !is_hidden && path.extension() == Some("otl".as_ref())
+            });
+            // Saves by the server are seen as events too, refresh only
+            // reloads files that differ from what was saved.
+            if is_otl {
+                if let Err(e) = collection.refresh() {
+                    log::warn!("Failed to reload collection: {}", e);
+                }
+                update_index(&mut search.lock().unwrap(), &root);
+            }
+        }
+    });
+}
+
+/// Serve the collection.
+///
+/// Recover the edits a previous run left in the journal before calling
+/// this. Edits are saved with `save`.
+pub fn run(port: u32, collection: Collection, save: SaveFn) -> ! {
     let addr = format!("localhost:{}", port);
+    let folds = Mutex::new(FoldState::load(collection.root_path()));
+    let notebook = Notebook::new(collection);
+    watch(notebook.collection.clone(), notebook.search.clone());
     println!("Starting server at http://{}", addr);
     rouille::start_server(addr, move |request| {
-        match Command::from_str(&request.url()) {
-            Ok(Command::ViewArticle(a)) => {
-                // The crappiest selector
-                for section in collection.iter() {
-                    if section.title() == a {
-                        return Response::html(format!(
-                            "\
+        let Ok(command) = Command::from_str(&request.url()) else {
+            return Response::empty_404();
+        };
+        let is_write = matches!(
+            (&command, request.method()),
+            (Command::Edit(_), "POST")
+                | (Command::SetAttribute(_), "PATCH")
+                | (Command::Ingest, "POST")
+        );
+        let (session, is_new_session) = session(request);
+        let response = if is_write {
+            notebook.collection.write(|collection| {
+                let titles = notebook.titles(collection);
+                write_request(request, command, collection, &titles, save)
+            })
+        } else {
+            notebook.collection.read(|collection| {
+                let titles = notebook.titles(collection);
+                read_request(
+                    request,
+                    command,
+                    collection,
+                    &titles,
+                    &notebook.search,
+                    &folds,
+                    &session,
+                )
+            })
+        };
+        if is_new_session {
+            set_session(response, &session)
+        } else {
+            response
+        }
+    })
+}
+
+/// Respond to a request that only looks at the collection.
+fn read_request(
+    request: &Request,
+    command: Command,
+    collection: &Collection,
+    titles: &HashMap<String, Section>,
+    search: &Mutex<Index>,
+    folds: &Mutex<FoldState>,
+    session: &str,
+) -> Response {
+    match command {
+        Command::Index => {
+            let files = collection
+                .files()
+                .map(|(path, root)| {
+                    let modified =
+                        fs::metadata(collection.root_path().join(path))
+                            .and_then(|m| m.modified())
+                            .ok()
+                            .map(DateTime::<Local>::from);
+                    (path.display().to_string(), modified, articles(&root))
+                })
+                .collect();
+            Response::html(format!(
+                "\
+<!DOCTYPE html>
+<html>
+<head>
+  <meta charset='utf-8'/>
+  <title>Index</title>
+  <link rel='alternate' type='application/atom+xml' href='/feed.xml'/>
+  <style>
+{CSS}
+.file {{ color: #666; font-size: 0.9em; }}
+  </style>
+</head>
+<body>
+<h1>Index</h1>
+<form action='/search'><input name='q'/></form>
+{}
+</body>
+</html>",
+                Html(ArticleIndex(files))
+            ))
+        }
+        Command::ViewArticle(a) => match titles.get(&a).cloned() {
+            Some(section) => {
+                let folded = folds.lock().unwrap().folded(session, &a);
+                Response::html(format!(
+                    "\
+<!DOCTYPE html>
+<html>
+<head>
+  <meta charset='utf-8'/>
+  <style>
+{CSS}
+  </style>
+</head>
+<body>
+{}
+<p><a href='/edit/{}'>Edit</a></p>
+<script>{FOLD_JS}</script>
+</body>
+</html>",
+                    Html(Folded(section, folded)),
+                    escape(&url_encode(&a))
+                ))
+            }
+            None => Response::empty_404(),
+        },
+        Command::Feed => {
+            let host = request.header("Host").unwrap_or("localhost");
+            Response::from_data(
+                "application/atom+xml",
+                feed::atom(collection, &format!("http://{}", host)),
+            )
+        }
+        Command::Present(a) => match titles.get(&a).cloned() {
+            Some(section) => Response::html(present(&section)),
+            None => Response::empty_404(),
+        },
+        Command::Raw(a) => match titles.get(&a).cloned() {
+            Some(section) => match idm::to_string(&section) {
+                Ok(text) => Response::text(text),
+                Err(e) => Response::text(e.to_string()).with_status_code(500),
+            },
+            None => Response::empty_404(),
+        },
+        Command::Json(a) => match titles.get(&a).cloned() {
+            Some(section) => Response::json(&section.to_json()),
+            None => Response::empty_404(),
+        },
+        Command::Board(a) => match titles.get(&a).cloned() {
+            Some(section) => Response::html(format!(
+                "\
 <!DOCTYPE html>
 <html>
 <head>
   <meta charset='utf-8'/>
   <style>
 {CSS}
+{BOARD_CSS}
   </style>
 </head>
 <body>
 {}
+<script>{BOARD_JS}</script>
 </body>
 </html>",
-                            Html(section)
-                        ));
-                    }
+                Html(Board(section))
+            )),
+            None => Response::empty_404(),
+        },
+        Command::Timeline(a) => match titles.get(&a).cloned() {
+            Some(section) => Response::html(format!(
+                "\
+<!DOCTYPE html>
+<html>
+<head>
+  <meta charset='utf-8'/>
+  <style>
+{CSS}
+body {{ max-width: none; }}
+  </style>
+</head>
+<body>
+<h1>{}</h1>
+{}
+</body>
+</html>",
+                escape(&section.title()),
+                timeline(&section)
+            )),
+            None => Response::empty_404(),
+        },
+        Command::View(name) => {
+            let Some(view) = base::view::find(collection, &name) else {
+                return Response::empty_404();
+            };
+            let article = Section::new(view.title.clone(), Default::default());
+            for section in view.apply(collection) {
+                article.append(section);
+            }
+            Response::html(format!(
+                "\
+<!DOCTYPE html>
+<html>
+<head>
+  <meta charset='utf-8'/>
+  <style>
+{CSS}
+  </style>
+</head>
+<body>
+{}
+</body>
+</html>",
+                Html(article)
+            ))
+        }
+        Command::Search => {
+            let query = request.get_param("q").unwrap_or_default();
+            let paths = search.lock().unwrap().search(&query);
+
+            let mut hits: Vec<(Section, String)> = Vec::new();
+            for path in paths {
+                let Some(section) = collection.find(&path) else {
+                    continue;
+                };
+                let Some(article) = article_of(&section) else {
+                    continue;
+                };
+                // Only show the first match in each article.
+                if hits.iter().any(|(a, _)| a.path() == article.path()) {
+                    continue;
                 }
-                Response::empty_404()
+                hits.push((article, snippet(&section)));
             }
-            Ok(cmd) => Response::text(format!("TODO: {:?}", cmd)),
-            Err(_) => Response::empty_404(),
+            Response::html(results_page(
+                &format!("Search: {}", query),
+                &query,
+                hits,
+            ))
         }
-    })
+        Command::Tag(tag) => {
+            // Tags are inherited from parent sections.
+            let hits: Vec<(Section, String)> = collection
+                .iter()
+                .filter(|s| s.is_article() && s.tags().contains(&tag))
+                .map(|s| {
+                    let snippet = snippet(&s);
+                    (s, snippet)
+                })
+                .collect();
+            Response::html(results_page(&format!("Tag: {}", tag), "", hits))
+        }
+        Command::Edit(a) => match titles.get(&a).cloned() {
+            Some(section) => Response::html(edit_page(
+                &a,
+                &idm::to_string(&section).unwrap_or_default(),
+                &text_version(&section),
+                None,
+            )),
+            None => Response::empty_404(),
+        },
+        Command::SetFolds(a) if request.method() == "POST" => {
+            let mut body = String::new();
+            if let Some(mut data) = request.data() {
+                if data.read_to_string(&mut body).is_err() {
+                    return Response::empty_400();
+                }
+            }
+            let folded: BTreeSet<String> = body
+                .lines()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect();
+
+            let mut folds = folds.lock().unwrap();
+            folds.set(session, &a, folded);
+            if let Err(e) = folds.save(collection.root_path()) {
+                log::warn!("Failed to save fold state: {}", e);
+            }
+            Response::empty_204()
+        }
+        cmd => Response::text(format!("TODO: {:?}", cmd)),
+    }
+}
+
+/// Respond to a request that changes the collection.
+fn write_request(
+    request: &Request,
+    command: Command,
+    collection: &mut Collection,
+    titles: &HashMap<String, Section>,
+    save_fn: SaveFn,
+) -> Response {
+    let journal = Journal::new(collection.root_path());
+    match command {
+        Command::Edit(a) => {
+            let Some(section) = titles.get(&a).cloned() else {
+                return Response::empty_404();
+            };
+            let Ok(input) =
+                rouille::input::post::raw_urlencoded_post_input(request)
+            else {
+                return Response::empty_400();
+            };
+            let field = |name: &str| {
+                input.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+            };
+            let (Some(text), Some(version)) = (field("text"), field("version"))
+            else {
+                return Response::empty_400();
+            };
+            // Browsers send textarea contents with CRLF line endings.
+            let text = text.replace("\r\n", "\n");
+
+            // A file root can't be swapped for another section.
+            if section.parent().is_none() {
+                return Response::html(edit_page(
+                    &a,
+                    &text,
+                    version,
+                    Some("Whole files can't be edited, edit their sections."),
+                ))
+                .with_status_code(400);
+            }
+
+            // Don't overwrite changes made after the editor was opened.
+            let is_changed_on_disk = collection
+                .file_path(&section)
+                .is_some_and(|p| collection.is_changed_on_disk(p));
+            if is_changed_on_disk || *version != text_version(&section) {
+                return Response::html(edit_page(
+                    &a,
+                    &text,
+                    version,
+                    Some(
+                        "The article was changed after you started \
+                         editing it. Copy your text and reload the page \
+                         to edit the current version.",
+                    ),
+                ))
+                .with_status_code(409);
+            }
+
+            let new_section = match idm::from_str::<Section>(&text) {
+                Ok(s) => s,
+                Err(e) => {
+                    return Response::html(edit_page(
+                        &a,
+                        &text,
+                        version,
+                        Some(&e.to_string()),
+                    ))
+                    .with_status_code(400)
+                }
+            };
+            if let Some(error) = record(
+                &journal,
+                &batch::Command::Replace {
+                    path: section.path(),
+                    idm: text,
+                },
+            ) {
+                return error;
+            }
+            section.insert_before(new_section.clone());
+            section.detach();
+
+            if let Some(error) = save(collection, &journal, save_fn, "edit", 1)
+            {
+                return error;
+            }
+            Response::redirect_303(format!(
+                "/a/{}",
+                url_encode(&new_section.title())
+            ))
+        }
+        Command::SetAttribute(path) => {
+            let Some(name) = request.get_param("name") else {
+                return Response::empty_400();
+            };
+            let mut value = String::new();
+            if let Some(mut data) = request.data() {
+                if data.read_to_string(&mut value).is_err() {
+                    return Response::empty_400();
+                }
+            }
+            let Some(mut section) = collection.find(&path) else {
+                return Response::empty_404();
+            };
+
+            // Empty value removes the attribute.
+            let value = value.trim().to_string();
+            if let Some(error) = record(
+                &journal,
+                &batch::Command::SetAttr {
+                    path,
+                    name: name.clone(),
+                    value: (!value.is_empty()).then(|| value.clone()),
+                },
+            ) {
+                return error;
+            }
+            if section.set_attr(&name, &value).is_err() {
+                return Response::empty_400();
+            }
+
+            if let Some(error) =
+                save(collection, &journal, save_fn, "set-attr", 1)
+            {
+                return error;
+            }
+            Response::empty_204()
+        }
+        Command::Ingest => {
+            let tokens = match Tokens::load(collection.root_path()) {
+                Ok(tokens) => tokens,
+                Err(e) => {
+                    log::warn!("{}", e);
+                    return Response::text(e.to_string()).with_status_code(500);
+                }
+            };
+            let Some(client) = request
+                .header("Authorization")
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .and_then(|t| tokens.check(t.trim()))
+            else {
+                return Response::text("Bad API token").with_status_code(401);
+            };
+
+            let mut body = String::new();
+            if let Some(mut data) = request.data() {
+                if data.read_to_string(&mut body).is_err() {
+                    return Response::empty_400();
+                }
+            }
+            if let Err(e) = journal.record_lines(body.lines()) {
+                log::warn!("Failed to write journal: {}", e);
+                return Response::text(e.to_string()).with_status_code(500);
+            }
+            let report = match batch::apply(collection, body.lines()) {
+                Ok(report) => report,
+                Err(e) => {
+                    return Response::text(e.to_string()).with_status_code(500)
+                }
+            };
+            let results: String =
+                report.results.iter().map(|r| format!("{}\n", r)).collect();
+
+            // Like with olt batch, nothing is saved if a command fails.
+            if report.failed {
+                if let Err(e) = collection.revert() {
+                    log::warn!("Failed to revert collection: {}", e);
+                }
+                if let Err(e) = journal.clear() {
+                    log::warn!("Failed to clear journal: {}", e);
+                }
+                return Response::from_data("application/x-ndjson", results)
+                    .with_status_code(400);
+            }
+            if let Some(error) =
+                save(collection, &journal, save_fn, "ingest", report.changes)
+            {
+                return error;
+            }
+            log::info!("{} ingested {} changes", client, report.changes);
+            Response::from_data("application/x-ndjson", results)
+        }
+        _ => Response::empty_404(),
+    }
+}
+
+/// Write an edit to the journal, return an error response if it fails.
+fn record(journal: &Journal, command: &batch::Command) -> Option<Response> {
+    if let Err(e) = journal.record(command) {
+        log::warn!("Failed to write journal: {}", e);
+        return Some(Response::text(e.to_string()).with_status_code(500));
+    }
+    None
+}
+
+/// Save the collection with `save_fn` and clear the journal, return an
+/// error response if saving fails.
+fn save(
+    collection: &mut Collection,
+    journal: &Journal,
+    save_fn: SaveFn,
+    command: &str,
+    changes: usize,
+) -> Option<Response> {
+    if let Err(e) = save_fn(collection, command, changes) {
+        // The journal keeps the edits until a save goes through.
+        log::warn!("Failed to save collection: {}", e);
+        return Some(Response::text(e.to_string()).with_status_code(500));
+    }
+    if let Err(e) = journal.clear() {
+        log::warn!("Failed to clear journal: {}", e);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_file_root() {
+        let root = std::env::temp_dir()
+            .join(format!("otlbook-webserver-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("Notes.otl"), "Chapter\n").unwrap();
+
+        let mut collection = Collection::load_from(&root).unwrap();
+        let titles: HashMap<String, Section> =
+            collection.iter().map(|s| (s.title(), s)).collect();
+        let version = text_version(&titles["Notes"]);
+        let request = Request::fake_http(
+            "POST",
+            "/edit/Notes",
+            vec![(
+                "Content-Type".to_string(),
+                "application/x-www-form-urlencoded".to_string(),
+            )],
+            format!("text=Other%0A&version={}", version).into_bytes(),
+        );
+        let response = write_request(
+            &request,
+            Command::Edit("Notes".into()),
+            &mut collection,
+            &titles,
+            |col, _, _| col.save(),
+        );
+        let text = std::fs::read_to_string(root.join("Notes.otl"));
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(response.status_code, 400);
+        assert_eq!(text.unwrap(), "Chapter\n");
+    }
+
+    #[test]
+    fn test_session() {
+        let request = |cookie: Option<&str>| {
+            let headers = cookie
+                .map(|c| vec![("Cookie".to_string(), c.to_string())])
+                .unwrap_or_default();
+            Request::fake_http("GET", "/a/Notes", headers, Vec::new())
+        };
+
+        let (a, is_new) = session(&request(None));
+        assert!(is_new);
+        let (b, _) = session(&request(None));
+        assert_ne!(a, b, "New viewers share a session");
+
+        let response = set_session(Response::empty_204(), &a);
+        let (_, cookie) = response
+            .headers
+            .iter()
+            .find(|(name, _)| name == "Set-Cookie")
+            .unwrap();
+        assert!(cookie.contains("HttpOnly"));
+        let cookie = cookie.split(';').next().unwrap();
+        assert_eq!(session(&request(Some(cookie))), (a.clone(), false));
+
+        let mut folds = FoldState::default();
+        folds.set(&a, "Notes", ["Chapter".to_string()].into());
+        assert_eq!(folds.folded(&a, "Notes").len(), 1);
+        assert!(folds.folded(&b, "Notes").is_empty());
+    }
 }
@@ -4,9 +4,37 @@ use base::parse::{self, only};
 
 #[derive(Eq, PartialEq, Debug)]
 pub enum Command {
+    /// List all the articles.
+    Index,
+    /// Atom feed of recently added and read items.
+    Feed,
     ViewArticle(String),
+    /// Store the folded sections of an article.
+    SetFolds(String),
+    /// Show an article as a slideshow.
+    Present(String),
+    /// Show an article as a kanban board.
+    Board(String),
+    /// Show the dated sections of an article on a timeline.
+    Timeline(String),
+    /// Edit the outline text of an article.
+    Edit(String),
+    /// Serve the outline text of an article.
+    Raw(String),
+    /// Serve an article as JSON.
+    Json(String),
+    /// Change an attribute of the section at a path.
+    SetAttribute(String),
+    /// Show the sections of a named view.
+    View(String),
+    /// Full-text search, the query is in the `q` parameter.
+    Search,
+    /// List the articles with a tag.
+    Tag(String),
     SaveToRead(String),
     SaveBookmark(String),
+    /// Apply a batch of edit commands sent by another program.
+    Ingest,
 }
 
 impl FromStr for Command {
@@ -15,6 +43,14 @@ impl FromStr for Command {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         use Command::*;
 
+        if s == "/" {
+            return Ok(Index);
+        }
+
+        if s == "/feed.xml" {
+            return Ok(Feed);
+        }
+
         // Special case, starting with upper case letter points directly to
         // article.
         if let Some(s) = s.strip_prefix("/") {
@@ -27,6 +63,54 @@ impl FromStr for Command {
             return Ok(ViewArticle(s.into()));
         }
 
+        if let Some(s) = s.strip_prefix("/attr/") {
+            return Ok(SetAttribute(s.into()));
+        }
+
+        if let Some(s) = s.strip_prefix("/board/") {
+            return Ok(Board(s.into()));
+        }
+
+        if let Some(s) = s.strip_prefix("/edit/") {
+            return Ok(Edit(s.into()));
+        }
+
+        if let Some(s) = s.strip_prefix("/fold/") {
+            return Ok(SetFolds(s.into()));
+        }
+
+        if let Some(s) = s.strip_prefix("/json/") {
+            return Ok(Json(s.into()));
+        }
+
+        if let Some(s) = s.strip_prefix("/present/") {
+            return Ok(Present(s.into()));
+        }
+
+        if let Some(s) = s.strip_prefix("/timeline/") {
+            return Ok(Timeline(s.into()));
+        }
+
+        if s == "/api/ingest" {
+            return Ok(Ingest);
+        }
+
+        if s == "/search" {
+            return Ok(Search);
+        }
+
+        if let Some(s) = s.strip_prefix("/tag/") {
+            return Ok(Tag(s.into()));
+        }
+
+        if let Some(s) = s.strip_prefix("/view/") {
+            return Ok(View(s.into()));
+        }
+
+        if let Some(s) = s.strip_prefix("/raw/") {
+            return Ok(Raw(s.into()));
+        }
+
         if let Some(s) = s.strip_prefix("/read/") {
             return Ok(SaveToRead(s.into()));
         }
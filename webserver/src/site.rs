@@ -0,0 +1,187 @@
+//! Static HTML export of a whole collection.
+//!
+//! The pages are rendered like the server renders them, but links between
+//! them point to files in the output directory. What gets published is
+//! decided by `base::publish`.
+
+use std::{collections::BTreeSet, fs, path::Path};
+
+use base::{
+    publish::{page_name, Site},
+    Collection, Result,
+};
+
+use crate::{
+    html::{escape, snippet, url_encode, ArticleIndex, Folded, Html, Results},
+    CSS,
+};
+
+/// Name of the stylesheet file in the output directory.
+const STYLE_FILE: &str = "style.css";
+
+/// Write every published article of the collection as a page in `outdir`
+/// along with an index page, a page for each tag and the stylesheet.
+///
+/// Return the number of article pages written.
+pub fn export_site(col: &Collection, outdir: &Path) -> Result<usize> {
+    fs::create_dir_all(outdir)?;
+    let site = Site::new(col);
+
+    for page in &site.pages {
+        let body = format!(
+            "{}\n{}<p><a href='index.html'>Index</a></p>",
+            Html(Folded(page.article.clone(), Default::default())),
+            tag_links(&page.tags)
+        );
+        write_page(
+            outdir,
+            &format!("{}.html", page.name),
+            &page.article.title(),
+            &body,
+            &site,
+        )?;
+    }
+
+    let tags = site.tags();
+    for tag in &tags {
+        let hits = site
+            .pages
+            .iter()
+            .filter(|p| p.tags.contains(tag))
+            .map(|p| (p.article.clone(), snippet(&p.article)))
+            .collect::<Vec<_>>();
+        let title = format!("Tag: {}", tag);
+        let body = format!(
+            "<h1>{}</h1>\n{}\n<p><a href='index.html'>Index</a></p>",
+            escape(&title),
+            Html(Results(hits))
+        );
+        write_page(outdir, &tag_page(tag), &title, &body, &site)?;
+    }
+
+    let files = site
+        .files
+        .iter()
+        .map(|(path, titles)| {
+            let articles = titles
+                .iter()
+                .filter_map(|t| site.page(t))
+                .map(|p| p.article.clone())
+                .collect();
+            (path.clone(), None, articles)
+        })
+        .collect();
+    let body = format!(
+        "<h1>Index</h1>\n{}{}",
+        tag_links(&tags),
+        Html(ArticleIndex(files))
+    );
+    write_page(outdir, "index.html", "Index", &body, &site)?;
+
+    fs::write(outdir.join(STYLE_FILE), CSS)?;
+
+    Ok(site.pages.len())
+}
+
+fn write_page(
+    outdir: &Path,
+    file: &str,
+    title: &str,
+    body: &str,
+    site: &Site,
+) -> Result<()> {
+    let html = format!(
+        "\
+<!DOCTYPE html>
+<html>
+<head>
+  <meta charset='utf-8'/>
+  <title>{}</title>
+  <link rel='stylesheet' href='{STYLE_FILE}'/>
+</head>
+<body>
+{}
+</body>
+</html>",
+        escape(title),
+        static_links(body, site)
+    );
+    fs::write(outdir.join(file), html)?;
+    Ok(())
+}
+
+fn tag_page(tag: &str) -> String {
+    format!("tag-{}.html", page_name(tag))
+}
+
+/// Paragraph of links to tag pages, empty if there are no tags.
+fn tag_links(tags: &BTreeSet<String>) -> String {
+    if tags.is_empty() {
+        return String::new();
+    }
+    let links: Vec<String> = tags
+        .iter()
+        .map(|t| {
+            format!(
+                "<a href='{}'>{}</a>",
+                escape(&url_encode(&tag_page(t))),
+                escape(t)
+            )
+        })
+        .collect();
+    format!("<p class='tags'>Tags: {}</p>\n", links.join(" "))
+}
+
+/// Point the server's article links in HTML to exported pages.
+///
+/// Links to articles that have no page are replaced with their text.
+fn static_links(html: &str, site: &Site) -> String {
+    const LINK: &str = "<a href='/a/";
+
+    let mut ret = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find(LINK) {
+        ret.push_str(&rest[..start]);
+        rest = &rest[start + LINK.len()..];
+
+        let (Some(end), Some(close)) = (rest.find("'>"), rest.find("</a>"))
+        else {
+            // Not a link the server made, leave it alone.
+            ret.push_str(LINK);
+            continue;
+        };
+        let title = url_decode(&rest[..end]);
+        let text = &rest[end + 2..close];
+        match site.page(&title) {
+            Some(page) => ret.push_str(&format!(
+                "<a href='{}.html'>{}</a>",
+                escape(&url_encode(&page.name)),
+                text
+            )),
+            None => ret.push_str(text),
+        }
+        rest = &rest[close + "</a>".len()..];
+    }
+    ret.push_str(rest);
+    ret
+}
+
+/// Decode a percent-encoded URL path.
+fn url_decode(s: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|h| std::str::from_utf8(h).ok());
+        match hex.map(|h| u8::from_str_radix(h, 16)) {
+            Some(Ok(c)) if b == b'%' => {
+                bytes.push(c);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,119 @@
+use std::fmt::Write;
+
+use base::{Section, VagueDate};
+
+use crate::html::escape;
+
+/// Width of the drawing area in pixels.
+const WIDTH: i64 = 1000;
+
+/// Height of one timeline row.
+const ROW_HEIGHT: i64 = 24;
+
+/// Space reserved for the year axis at the top.
+const AXIS_HEIGHT: i64 = 30;
+
+/// Section with a date range.
+struct Span {
+    title: String,
+    start: VagueDate,
+    end: VagueDate,
+}
+
+impl Span {
+    /// Read the range from `start` and `end` attributes, or from a `date`
+    /// attribute for a single date.
+    ///
+    /// A missing end date means the range covers only the start date, so
+    /// eg. `start: 2006` is the whole year 2006.
+    fn new(section: &Section) -> Option<Span> {
+        let date = |name| match section.attr::<VagueDate>(name) {
+            Ok(date) => date,
+            Err(e) => {
+                log::warn!("Bad {} in {:?}: {}", name, section.headline(), e);
+                None
+            }
+        };
+
+        let start = date("start").or_else(|| date("date"))?;
+        let end = date("end").filter(|e| *e >= start).unwrap_or(start);
+        Some(Span {
+            title: section.title(),
+            start,
+            end,
+        })
+    }
+}
+
+/// Lay out the dated sections under an article as a SVG timeline.
+///
+/// Sections are dated with `start` and `end` or `date` attributes. Each
+/// dated section gets a row and a bar covering its date range.
+pub fn timeline(article: &Section) -> String {
+    let mut spans: Vec<Span> = article
+        .iter()
+        .skip(1)
+        .filter_map(|s| Span::new(&s))
+        .collect();
+    spans.sort_by_key(|s| s.start);
+
+    let height = AXIS_HEIGHT + ROW_HEIGHT * spans.len() as i64 + ROW_HEIGHT;
+    let mut ret = format!(
+        "<svg xmlns='http://www.w3.org/2000/svg' width='{}' height='{}' \
+         font-family='sans-serif' font-size='12'>\n",
+        WIDTH, height
+    );
+
+    let (Some(first), Some(last)) = (
+        spans.iter().map(|s| s.start.year()).min(),
+        spans.iter().map(|s| s.end.year()).max(),
+    ) else {
+        ret.push_str("</svg>\n");
+        return ret;
+    };
+
+    let origin = VagueDate::Year(first).first_day();
+    let days = (VagueDate::Year(last).last_day() - origin).num_days() + 1;
+    let x = |days_from_origin: i64| days_from_origin * WIDTH / days;
+
+    // Year axis, with a label on at most about 20 years.
+    let step = ((last - first + 1) as usize).div_ceil(20).max(1);
+    for year in (first..=last).step_by(step) {
+        let pos = x((VagueDate::Year(year).first_day() - origin).num_days());
+        let _ = writeln!(
+            ret,
+            "<line x1='{pos}' y1='{AXIS_HEIGHT}' x2='{pos}' y2='{height}' \
+             stroke='#ddd'/>\n\
+             <text x='{}' y='{}'>{year}</text>",
+            pos + 2,
+            AXIS_HEIGHT - 10,
+        );
+    }
+
+    for (i, span) in spans.iter().enumerate() {
+        let x1 = x((span.start.first_day() - origin).num_days());
+        let x2 = x((span.end.last_day() - origin).num_days() + 1);
+        let y = AXIS_HEIGHT + ROW_HEIGHT * i as i64 + 2;
+        let range = if span.start == span.end {
+            span.start.to_string()
+        } else {
+            format!("{} to {}", span.start, span.end)
+        };
+        let _ = writeln!(
+            ret,
+            "<rect x='{x1}' y='{y}' width='{}' height='{}' fill='#8ab'>\
+             <title>{} {}</title></rect>\n\
+             <text x='{}' y='{}'>{}</text>",
+            (x2 - x1).max(2),
+            ROW_HEIGHT - 4,
+            escape(&span.title),
+            range,
+            x1 + 4,
+            y + ROW_HEIGHT - 10,
+            escape(&span.title),
+        );
+    }
+
+    ret.push_str("</svg>\n");
+    ret
+}